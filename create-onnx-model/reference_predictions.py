@@ -0,0 +1,36 @@
+"""
+Writes the predictions of the CREPE Python package for a clip of raw 16 kHz 16 bit samples, which
+the tests compare the predictions of the exported model with.
+"""
+
+import argparse
+import crepe
+import numpy as np
+from pathlib import Path
+
+SAMPLE_RATE = 16000
+
+
+def write_reference_predictions(args: argparse.Namespace) -> None:
+    audio = np.fromfile(args.clip, dtype='<i2')
+    time, frequency, confidence, _ = crepe.predict(audio, SAMPLE_RATE, model_capacity=args.size, viterbi=False,
+                                                   center=True, step_size=10, verbose=0)
+
+    with open(args.output, 'w') as file:
+        for values in zip(time, frequency, confidence):
+            file.write('{:.3f} {:.4f} {:.5f}\n'.format(*values))
+
+
+def main() -> None:
+    parser = argparse.ArgumentParser()
+    parser.add_argument('--size', default='full', choices=crepe.core.models.keys(), type=str,
+                        help='Which CREPE model to predict with.')
+    parser.add_argument('--clip', default='../testdata/harmonic-220hz.i16', type=Path,
+                        help='The clip of raw little-endian 16 bit samples at 16 kHz.')
+    parser.add_argument('--output', default='../testdata/harmonic-220hz.reference.txt', type=Path,
+                        help='The file that a "time frequency confidence" line is written to for every frame.')
+    write_reference_predictions(parser.parse_args())
+
+
+if __name__ == '__main__':
+    main()
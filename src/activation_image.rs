@@ -0,0 +1,150 @@
+//! Rendering of CREPE activations to images, e.g. to document or debug what the model detected.
+//!
+//! Higher bins are drawn at the top like in the heatmap, with every C labeled in a margin on the
+//! left and a legend of the colormap from 0 on the left to 1 on the right below the activations.
+use std::path::Path;
+use image::{ImageFormat, ImageResult, Rgb, RgbImage};
+use crate::colormap::Colormap;
+use crate::crepe::{frequency_to_bin, Activation, ACTIVATION_BINS};
+use crate::notes::midi_to_frequency;
+
+/// The width in pixels of the margin left of the activations, which labels every C.
+pub const LABEL_MARGIN: u32 = 12;
+
+/// The height in pixels of the colormap legend below the activations, including the gap above it.
+pub const LEGEND_HEIGHT: u32 = 8;
+
+/// The width in pixels of a single exported activation, a single column would be hard to see.
+pub const SINGLE_ACTIVATION_WIDTH: u32 = 32;
+
+const BACKGROUND_COLOR: Rgb<u8> = Rgb([0, 0, 0]);
+const LABEL_COLOR: Rgb<u8> = Rgb([255, 255, 255]);
+
+/// The rows of the 3x5 pixel glyphs of the labels from top to bottom, with the leftmost pixel in
+/// the highest bit.
+const C_GLYPH: [u8; 5] = [0b111, 0b100, 0b100, 0b100, 0b111];
+const DIGIT_GLYPHS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+const GLYPH_HEIGHT: u32 = 5;
+
+fn pixel_color(colormap: &dyn Colormap, t: f32) -> Rgb<u8> {
+    let color = colormap.map(t);
+
+    Rgb([color.r(), color.g(), color.b()])
+}
+
+fn draw_glyph(image: &mut RgbImage, glyph: &[u8; 5], left: u32, top: u32) {
+    for (row, bits) in glyph.iter().enumerate() {
+        for column in 0..3 {
+            if bits & (0b100 >> column) != 0 {
+                image.put_pixel(left + column, top + row as u32, LABEL_COLOR);
+            }
+        }
+    }
+}
+
+/// Renders activations as columns of `column_width` pixels from left to right with `colormap`,
+/// labeling the octaves relative to `a4_hz`.
+pub fn render_activations(activations: &[Activation], column_width: u32, a4_hz: f32, colormap: &dyn Colormap) -> RgbImage {
+    let bins = ACTIVATION_BINS as u32;
+    let plot_width = activations.len() as u32 * column_width;
+    let mut image = RgbImage::from_pixel(LABEL_MARGIN + plot_width, bins + LEGEND_HEIGHT, BACKGROUND_COLOR);
+
+    for (column, activation) in activations.iter().enumerate() {
+        for (bin, value) in activation.iter().enumerate() {
+            let color = pixel_color(colormap, *value);
+            for x in 0..column_width {
+                image.put_pixel(LABEL_MARGIN + column as u32 * column_width + x, bins - 1 - bin as u32, color);
+            }
+        }
+    }
+
+    for (octave, digit) in DIGIT_GLYPHS.iter().enumerate() {
+        let bin = frequency_to_bin(midi_to_frequency(12.0 * (octave + 1) as f32, a4_hz)).round();
+        if !(0.0..ACTIVATION_BINS as f32).contains(&bin) {
+            continue;
+        }
+        let y = bins - 1 - bin as u32;
+        for x in LABEL_MARGIN - 3..LABEL_MARGIN {
+            image.put_pixel(x, y, LABEL_COLOR);
+        }
+        // The label is centered on the tick unless that would cut it off at the bottom.
+        let top = y.saturating_sub(GLYPH_HEIGHT / 2).min(bins - GLYPH_HEIGHT);
+        draw_glyph(&mut image, &C_GLYPH, 0, top);
+        draw_glyph(&mut image, digit, 4, top);
+    }
+
+    for x in 0..plot_width {
+        let color = pixel_color(colormap, x as f32 / plot_width.saturating_sub(1).max(1) as f32);
+        for y in bins + 2..bins + LEGEND_HEIGHT {
+            image.put_pixel(LABEL_MARGIN + x, y, color);
+        }
+    }
+
+    image
+}
+
+/// Renders activations like [`render_activations`] and writes them to a PNG file.
+pub fn save_activations(path: &Path, activations: &[Activation], column_width: u32, a4_hz: f32, colormap: &dyn Colormap) -> ImageResult<()> {
+    render_activations(activations, column_width, a4_hz, colormap).save_with_format(path, ImageFormat::Png)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::activation_image::*;
+    use crate::colormap::{BlueRed, VIRIDIS};
+
+    /// The brightness of a pixel as the value of its color.
+    fn value(pixel: &Rgb<u8>) -> u8 {
+        pixel.0.into_iter().max().unwrap()
+    }
+
+    #[test]
+    fn test_exported_activation() {
+        let mut activation = [0.0; ACTIVATION_BINS];
+        activation[100] = 1.0;
+        activation[99] = 0.6;
+        activation[101] = 0.6;
+        activation[250] = 0.3;
+
+        let image = render_activations(&[activation], SINGLE_ACTIVATION_WIDTH, 440.0, &BlueRed);
+        assert_eq!(image.dimensions(), (LABEL_MARGIN + SINGLE_ACTIVATION_WIDTH, ACTIVATION_BINS as u32 + LEGEND_HEIGHT));
+
+        for x in [LABEL_MARGIN, LABEL_MARGIN + SINGLE_ACTIVATION_WIDTH - 1] {
+            let brightest = (0..ACTIVATION_BINS as u32).max_by_key(|y| value(image.get_pixel(x, *y))).unwrap();
+            // Higher bins are at the top.
+            assert_eq!(brightest, ACTIVATION_BINS as u32 - 1 - 100);
+        }
+        // Every C is marked with a tick next to its bin.
+        let c4_bin = frequency_to_bin(midi_to_frequency(60.0, 440.0)).round() as u32;
+        assert_eq!(*image.get_pixel(LABEL_MARGIN - 1, ACTIVATION_BINS as u32 - 1 - c4_bin), LABEL_COLOR);
+        assert_eq!(*image.get_pixel(LABEL_MARGIN - 1, ACTIVATION_BINS as u32 - 2 - c4_bin), BACKGROUND_COLOR);
+    }
+
+    #[test]
+    fn test_exported_heatmap() {
+        let activations = (0..10).map(|column| {
+            let mut activation = [0.0; ACTIVATION_BINS];
+            activation[column * 20] = 1.0;
+            activation
+        }).collect::<Vec<Activation>>();
+
+        let image = render_activations(&activations, 2, 440.0, &VIRIDIS);
+        assert_eq!(image.dimensions(), (LABEL_MARGIN + 20, ACTIVATION_BINS as u32 + LEGEND_HEIGHT));
+        assert_eq!(*image.get_pixel(LABEL_MARGIN + 2 * 3 + 1, ACTIVATION_BINS as u32 - 1 - 60), pixel_color(&VIRIDIS, 1.0));
+        // The legend goes from the lowest to the highest activation.
+        let legend_y = image.height() - 1;
+        assert_eq!(*image.get_pixel(LABEL_MARGIN, legend_y), pixel_color(&VIRIDIS, 0.0));
+        assert_eq!(*image.get_pixel(image.width() - 1, legend_y), pixel_color(&VIRIDIS, 1.0));
+    }
+}
@@ -0,0 +1,137 @@
+use crate::crepe::rms;
+
+/// The highest gain that is applied, so that noise in near silence is not amplified into something
+/// that passes the noise floor.
+const MAX_GAIN: f32 = 100.0;
+
+/// The default level that frames are scaled toward, -20 dBFS.
+pub const DEFAULT_TARGET_RMS: f32 = i16::MAX as f32 / 10.0;
+
+/// Converts a gain in decibels into a factor that samples are multiplied with.
+pub fn db_to_gain(gain_db: f32) -> f32 {
+    10f32.powf(gain_db / 20.0)
+}
+
+/// Multiplies samples by `gain`, clipping them to the range of `i16` instead of overflowing.
+pub fn apply_gain(frame: &mut [i16], gain: f32) {
+    for sample in frame {
+        *sample = (*sample as f32 * gain).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+    }
+}
+
+/// Automatic gain control that scales frames of audio toward a target level, so that quiet sources
+/// do not drop below the noise floor and loud ones do not clip.
+#[derive(Debug, Clone)]
+pub struct Agc {
+    target_rms: f32,
+    attack: f32,
+    release: f32,
+    gain: f32,
+}
+
+impl Agc {
+    /// Creates a gain control that scales frames toward an RMS of `target_rms` in sample units.
+    ///
+    /// `attack` is the fraction of the way to the desired gain that the gain moves per frame when
+    /// it has to decrease because the audio got louder, `release` when it has to increase.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `attack` or `release` are not greater than zero and at most one.
+    pub fn new(target_rms: f32, attack: f32, release: f32) -> Self {
+        assert!(attack > 0.0 && attack <= 1.0, "AGC attack must be in (0, 1], got {}", attack);
+        assert!(release > 0.0 && release <= 1.0, "AGC release must be in (0, 1], got {}", release);
+
+        Agc {
+            target_rms,
+            attack,
+            release,
+            gain: 1.0,
+        }
+    }
+
+    /// The gain that was applied to the most recent frame.
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    /// Updates the gain from the level of `frame` and applies it to the frame.
+    ///
+    /// Silent frames keep the current gain.
+    pub fn process(&mut self, frame: &mut [i16]) {
+        let level = rms(frame);
+        if level > 0.0 {
+            let desired_gain = (self.target_rms / level).min(MAX_GAIN);
+            let rate = if desired_gain < self.gain { self.attack } else { self.release };
+            self.gain += (desired_gain - self.gain) * rate;
+        }
+
+        apply_gain(frame, self.gain);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use crate::agc::*;
+
+    fn sine_frame(amplitude: f32) -> Vec<i16> {
+        (0..1024)
+            .map(|i| ((i as f32 * 0.1).sin() * amplitude) as i16)
+            .collect()
+    }
+
+    #[test]
+    fn test_quiet_frame_is_amplified_toward_target() {
+        let mut agc = Agc::new(DEFAULT_TARGET_RMS, 0.5, 0.5);
+        let quiet_rms = rms(&sine_frame(300.0));
+
+        let mut levels = Vec::new();
+        for _ in 0..10 {
+            let mut frame = sine_frame(300.0);
+            agc.process(&mut frame);
+            levels.push(rms(&frame));
+        }
+        assert!(levels[0] > quiet_rms);
+        assert!(levels.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_relative_eq!(*levels.last().unwrap(), DEFAULT_TARGET_RMS, epsilon = DEFAULT_TARGET_RMS * 0.01);
+    }
+
+    #[test]
+    fn test_amplified_peaks_clip_instead_of_overflowing() {
+        let mut agc = Agc::new(DEFAULT_TARGET_RMS, 1.0, 1.0);
+        let mut frame = vec![10; 1024];
+        frame[0] = 20_000;
+        frame[1] = -20_000;
+
+        agc.process(&mut frame);
+        assert!(agc.gain() > 1.0);
+        assert_eq!(frame[0], i16::MAX);
+        assert_eq!(frame[1], i16::MIN);
+        assert!(frame[2..].iter().all(|sample| *sample > 10));
+    }
+
+    #[test]
+    fn test_loud_frame_is_attenuated() {
+        let mut agc = Agc::new(DEFAULT_TARGET_RMS, 1.0, 1.0);
+        let mut frame = sine_frame(30_000.0);
+
+        agc.process(&mut frame);
+        assert_relative_eq!(rms(&frame), DEFAULT_TARGET_RMS, epsilon = DEFAULT_TARGET_RMS * 0.01);
+    }
+
+    #[test]
+    fn test_silence_keeps_gain() {
+        let mut agc = Agc::new(DEFAULT_TARGET_RMS, 1.0, 1.0);
+
+        agc.process(&mut [0; 1024]);
+        assert_eq!(agc.gain(), 1.0);
+    }
+
+    #[test]
+    fn test_db_to_gain() {
+        assert_relative_eq!(db_to_gain(0.0), 1.0);
+        assert_relative_eq!(db_to_gain(20.0), 10.0, epsilon = 1e-4);
+        assert_relative_eq!(db_to_gain(-6.0), 0.501, epsilon = 1e-3);
+    }
+}
@@ -1,17 +1,50 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::{Arc, RwLock};
-use std::time::Duration;
-use cpal::{BufferSize, Device, SampleRate, Stream, StreamConfig, StreamInstant};
+use std::time::{Duration, Instant};
+use cpal::{BufferSize, Device, FromSample, OutputCallbackInfo, SampleFormat, SampleRate, SizedSample, Stream, StreamConfig, StreamError, StreamInstant};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use eframe::egui::{Align2, Color32, Context, Label, Rgba, RichText, ViewportCommand, WindowLevel};
-use eframe::{egui, Frame, Storage};
+use eframe::egui::{pos2, vec2, Align2, Color32, ColorImage, Context, FontId, Label, Rect, Rgba, RichText, Sense, Stroke, TextureHandle, TextureOptions, Ui, ViewportCommand, WindowLevel};
+use eframe::{egui, glow, Frame, Storage};
 use eframe::egui::color_picker::Alpha;
-use egui_plot::{HLine, Line, Plot, PlotBounds, PlotPoints};
+use egui_plot::{uniform_grid_spacer, Bar, BarChart, HLine, Line, Plot, PlotBounds, PlotPoint, PlotPoints, Polygon, Text};
 use serde::{Deserialize, Serialize};
-use crate::crepe;
-use crate::crepe::CrepeModel;
+use pitch_overlay::activation_image::{save_activations, SINGLE_ACTIVATION_WIDTH};
+use pitch_overlay::agc::{apply_gain, db_to_gain, Agc, DEFAULT_TARGET_RMS};
+use pitch_overlay::autocorr::YinDetector;
+use pitch_overlay::channels::{downmix, ChannelMode};
+use pitch_overlay::colormap::{Colormap, ColormapKind};
+use pitch_overlay::crepe;
+use pitch_overlay::crepe::{frequency_to_bin, Activation, PitchDetector, PredictError, Prediction, ACTIVATION_BINS, DEFAULT_LOCAL_AVERAGE_HALF_WIDTH};
+use pitch_overlay::events::OnsetDetector;
+use pitch_overlay::filter::{correct_octaves, gate_frequency_range, AdaptiveSmoother, ConfidenceWeightedEma, EmaSmoother, Gate, MedianFilter, OnsetConfirmer, Smoother};
+use pitch_overlay::hold::{HeldPitch, PitchHold};
+use pitch_overlay::inference::InferenceWorker;
+use pitch_overlay::intonation::{IntonationSession, ScaleTemplate};
+use pitch_overlay::logging::CsvLogger;
+use pitch_overlay::metronome::{BeatCapture, BeatReading, BeatScheduler, ClickGenerator, DEFAULT_BPM};
+use pitch_overlay::notes::{beat_frequency, frequency_to_midi, frequency_to_note, frequency_to_note_checked, hz_to_cents, midi_to_frequency, midi_to_note, note_gridlines, shift_by_cents, Note, NoteSpec, DEFAULT_A4_HZ};
+use pitch_overlay::preemphasis::{PreEmphasis, DEFAULT_PRE_EMPHASIS};
+use pitch_overlay::reconnect::{reconnect_target, select_device, Reconnector};
+use pitch_overlay::resample::ChunkCollector;
+use pitch_overlay::snapshots::SnapshotList;
+use pitch_overlay::spectrum::{bin_frequency, Spectrum, SpectrumAnalyzer, SPECTRUM_BINS};
+use pitch_overlay::stats::{CentsHistogram, NoteMode, NoteModeTracker, PitchStats, StatsCollector, Tendency, CENTS_BINS, CENTS_BIN_WIDTH};
+use pitch_overlay::tone::{SineGenerator, ToneControl};
+use pitch_overlay::vibrato::{Vibrato, VibratoAnalyzer};
+use pitch_overlay::wav::WavRecorder;
+#[cfg(feature = "websocket")]
+use pitch_overlay::websocket::PredictionServer;
 
 pub(crate) const SETTINGS_STORAGE_KEY: &str = "settings";
 
+/// The size of the window in points when no valid size was stored.
+pub(crate) const DEFAULT_WINDOW_SIZE: [f32; 2] = [320.0, 240.0];
+
+/// The smallest stored window size that is restored, smaller sizes are likely corrupt.
+const MIN_WINDOW_SIZE: f32 = 50.0;
+
 /// The number of CREPE predictions to combine into a single averaged pitch value.
 ///
 /// By default, CREPE takes 64 millis of audio which results in really fast predictions that are all
@@ -22,352 +55,4347 @@ const STEPS_PER_DISPLAY: usize = 2;
 
 const MIN_SAMPLES_PER_DISPLAY: usize = STEPS_PER_DISPLAY * crepe::SAMPLES_PER_STEP;
 
+/// The duration of audio in seconds that is used for a single prediction.
+const STEP_SECONDS: f64 = crepe::SAMPLES_PER_STEP as f64 / crepe::SAMPLE_RATE as f64;
+
+/// The fraction of the way to the desired gain that automatic gain control moves per frame when
+/// the audio gets louder, which is fast to avoid clipping.
+const AGC_ATTACK: f32 = 0.5;
+
+/// The fraction of the way to the desired gain that automatic gain control moves per frame when
+/// the audio gets quieter, which is slow so that pauses do not immediately amplify the noise.
+const AGC_RELEASE: f32 = 0.05;
+
+/// The number of frames that can wait to be analyzed before new ones are dropped, about half a second of audio.
+const INFERENCE_QUEUE_FRAMES: usize = 8;
+
+/// The highest rate at which the window is redrawn for new predictions by default, the rate at
+/// which the predictions arrive, so that none is skipped without drawing more often than needed.
+const DEFAULT_MAX_UI_FPS: u32 = crepe::SAMPLE_RATE.div_ceil(crepe::SAMPLES_PER_STEP as u32);
+
+/// The time to wait before redrawing for a new prediction, so that predictions arriving in quick
+/// succession cause a single redraw and the window is redrawn at most `max_ui_fps` times per second.
+fn repaint_interval(max_ui_fps: u32) -> Duration {
+    Duration::from_secs_f64(1.0 / max_ui_fps.max(1) as f64)
+}
+
 const CONFIG: StreamConfig = StreamConfig {
     channels: 1,
     sample_rate: SampleRate(crepe::SAMPLE_RATE),
     buffer_size: BufferSize::Fixed(MIN_SAMPLES_PER_DISPLAY as u32),
 };
 
-struct WindowState {
-    is_always_on_top: bool,
-    are_settings_open: bool,
-    error_message: Option<String>,
+/// Picks the sample rate to capture audio from a device at.
+///
+/// The model's sample rate is preferred since it avoids resampling, otherwise the device's default
+/// rate is used and the audio is resampled before prediction.
+pub(crate) fn capture_sample_rate(device: &Device, channels: u16) -> u32 {
+    let supports_model_rate = device.supported_input_configs()
+        .map(|mut configs| configs.any(|config| {
+            config.channels() == channels
+                && config.min_sample_rate().0 <= crepe::SAMPLE_RATE && crepe::SAMPLE_RATE <= config.max_sample_rate().0
+        }))
+        .unwrap_or(false);
+    if supports_model_rate {
+        return crepe::SAMPLE_RATE;
+    }
+
+    device.default_input_config()
+        .map(|config| config.sample_rate().0)
+        .unwrap_or(crepe::SAMPLE_RATE)
 }
 
-impl Default for WindowState {
-    fn default() -> Self {
-        WindowState {
-            is_always_on_top: false,
-            are_settings_open: false,
-            error_message: None,
+/// Picks the number of channels to capture audio from a device with, which is the device's default.
+pub(crate) fn capture_channels(device: &Device) -> u16 {
+    device.default_input_config()
+        .map(|config| config.channels())
+        .unwrap_or(1)
+}
+
+/// Where the analyzed audio is captured from.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub(crate) enum CaptureSource {
+    /// Audio recorded by an input device such as a microphone.
+    #[default]
+    Input,
+    /// Audio played back by an output device, which is only supported by WASAPI on Windows.
+    Loopback,
+}
+
+impl CaptureSource {
+    fn is_supported(self) -> bool {
+        match self {
+            CaptureSource::Input => true,
+            CaptureSource::Loopback => cfg!(target_os = "windows"),
+        }
+    }
+
+    /// Lists the devices that audio can be captured from.
+    pub(crate) fn devices(self) -> Result<Vec<Device>, cpal::DevicesError> {
+        let host = cpal::default_host();
+        match self {
+            CaptureSource::Input => Ok(host.input_devices()?.collect()),
+            // Building an input stream on an output device captures its playback with WASAPI.
+            CaptureSource::Loopback => Ok(host.output_devices()?.collect()),
+        }
+    }
+
+    fn default_device(self) -> Option<Device> {
+        let host = cpal::default_host();
+        match self {
+            CaptureSource::Input => host.default_input_device(),
+            CaptureSource::Loopback => host.default_output_device(),
+        }
+    }
+
+    /// Picks the sample rate and number of channels to capture audio from a device with.
+    ///
+    /// Loopback capture has to use the format that the device plays back in.
+    fn capture_format(self, device: &Device) -> (u32, u16) {
+        match self {
+            CaptureSource::Input => {
+                let channels = capture_channels(device);
+                (capture_sample_rate(device, channels), channels)
+            }
+            CaptureSource::Loopback => device.default_output_config()
+                .map(|config| (config.sample_rate().0, config.channels()))
+                .unwrap_or((crepe::SAMPLE_RATE, 1)),
         }
     }
 }
 
-/// Settings of the application which are persisted between sessions.
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
-pub(crate) struct Settings {
-    display_range: (u32, u32),
-    target_range: (u32, u32),
-    confidence_threshold: f32,
-    target_color: Rgba,
-    label_color: Rgba,
-    // TODO: uncomment and implement restoring last device on open if selected
-    //restore_last_device: bool,
-    //last_device_id: ???
+/// The longest pitch history in seconds that can be shown, older history is discarded.
+const MAX_HISTORY_SECONDS: f64 = 60.0;
+
+/// The height of the onset markers as a fraction of the height of the pitch plot.
+const ONSET_TICK_FRACTION: f64 = 0.1;
+
+const PITCH_LINE_COLOR: Color32 = Color32::LIGHT_BLUE;
+
+/// The largest number of activation columns that can be shown by the heatmap, older ones are discarded.
+const MAX_HEATMAP_COLUMNS: usize = 500;
+
+fn colormap_name(kind: ColormapKind) -> &'static str {
+    match kind {
+        ColormapKind::BlueRed => "Blue-red",
+        ColormapKind::Viridis => "Viridis",
+        ColormapKind::Magma => "Magma",
+        ColormapKind::Grayscale => "Grayscale",
+    }
 }
 
-impl Default for Settings {
-    fn default() -> Self {
-        Settings {
-            display_range: (50, 500),
-            target_range: (185, 300),
-            confidence_threshold: 0.5,
-            target_color: Rgba::from(Color32::LIGHT_GREEN),
-            label_color: Rgba::from(Color32::WHITE),
+/// The highest frequency shown by the spectrogram, higher bins rarely contain anything but the
+/// upper harmonics of a voice.
+const SPECTROGRAM_MAX_HZ: f32 = 4000.0;
+
+/// Draws the most recent `columns` spectra as a spectrogram with the newest one on the right,
+/// coloring levels from `db_range.0` to `db_range.1` with `colormap`.
+fn spectrogram(
+    ui: &mut Ui,
+    texture: &mut Option<TextureHandle>,
+    spectra: &VecDeque<Spectrum>,
+    columns: usize,
+    db_range: (f32, f32),
+    colormap: &dyn Colormap,
+) {
+    let (rect, _) = ui.allocate_exact_size(ui.available_size(), Sense::hover());
+    let painter = ui.painter_at(rect);
+
+    let bins = (0..SPECTRUM_BINS).take_while(|bin| bin_frequency(*bin) <= SPECTROGRAM_MAX_HZ).count();
+    let db_to_color = |db: f32| colormap.map((db - db_range.0) / (db_range.1 - db_range.0).max(1.0));
+    let mut image = ColorImage::new([columns, bins], colormap.map(0.0));
+    let skipped_columns = spectra.len().saturating_sub(columns);
+    let first_column = columns.saturating_sub(spectra.len());
+    for (column, spectrum) in spectra.iter().skip(skipped_columns).enumerate() {
+        for (bin, db) in spectrum.iter().take(bins).enumerate() {
+            image[(first_column + column, bins - 1 - bin)] = db_to_color(*db);
         }
     }
-}
+    let texture = match texture {
+        Some(texture) => {
+            texture.set(image, TextureOptions::NEAREST);
+            texture
+        }
+        None => texture.insert(ui.ctx().load_texture("spectrogram", image, TextureOptions::NEAREST)),
+    };
+    painter.image(texture.id(), rect, Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)), Color32::WHITE);
 
-struct AudioState {
-    first_audio_instant: Option<StreamInstant>,
-    // Temporary store for any audio data that was less than 1024 samples long.
-    // Some audio backends output less than 1024 samples per callback, so we need to aggregate
-    // some values until we have those 1024 entries.
-    recent_audio: Vec<i16>,
-    last_valid_frequency: Option<f32>,
-    pitch_points: Vec<[f64; 2]>,
+    let label_color = ui.visuals().text_color();
+    for frequency in (1000..SPECTROGRAM_MAX_HZ as u32).step_by(1000) {
+        let y = rect.bottom() - rect.height() * frequency as f32 / SPECTROGRAM_MAX_HZ;
+        painter.hline(rect.x_range(), y, Stroke::new(1.0, label_color.gamma_multiply(0.3)));
+        painter.text(pos2(rect.left() + 2.0, y), Align2::LEFT_BOTTOM, format!("{} kHz", frequency / 1000), FontId::proportional(10.0), label_color);
+    }
 }
 
-impl Default for AudioState {
-    fn default() -> Self {
-        AudioState {
-            first_audio_instant: None,
-            recent_audio: vec![],
-            last_valid_frequency: None,
-            pitch_points: vec![],
+/// Draws the most recent `columns` activations as a heatmap with the newest one on the right,
+/// showing the bins between the frequencies of `display_range` with a line at every C.
+fn heatmap(
+    ui: &mut Ui,
+    texture: &mut Option<TextureHandle>,
+    activations: &VecDeque<Activation>,
+    columns: usize,
+    display_range: (u32, u32),
+    a4_hz: f32,
+    colormap: &dyn Colormap,
+) {
+    let (rect, _) = ui.allocate_exact_size(ui.available_size(), Sense::hover());
+    let painter = ui.painter_at(rect);
+
+    // Columns that have no activation yet stay empty so that the newest column is always on the right.
+    let mut image = ColorImage::new([columns, ACTIVATION_BINS], colormap.map(0.0));
+    let skipped_columns = activations.len().saturating_sub(columns);
+    let first_column = columns.saturating_sub(activations.len());
+    for (column, activation) in activations.iter().skip(skipped_columns).enumerate() {
+        for (bin, value) in activation.iter().enumerate() {
+            // Higher bins are drawn at the top.
+            image[(first_column + column, ACTIVATION_BINS - 1 - bin)] = colormap.map(*value);
+        }
+    }
+    let texture = match texture {
+        Some(texture) => {
+            texture.set(image, TextureOptions::NEAREST);
+            texture
         }
+        None => texture.insert(ui.ctx().load_texture("heatmap", image, TextureOptions::NEAREST)),
+    };
+
+    let min_bin = frequency_to_bin(display_range.0.max(1) as f32).clamp(0.0, ACTIVATION_BINS as f32);
+    let max_bin = frequency_to_bin(display_range.1 as f32).clamp(min_bin + 1.0, ACTIVATION_BINS as f32);
+    let bin_to_uv_y = |bin: f32| 1.0 - bin / ACTIVATION_BINS as f32;
+    let uv = Rect::from_x_y_ranges(0.0..=1.0, bin_to_uv_y(max_bin)..=bin_to_uv_y(min_bin));
+    painter.image(texture.id(), rect, uv, Color32::WHITE);
+
+    let min_midi = frequency_to_midi(display_range.0.max(1) as f32, a4_hz).ceil() as i32;
+    let max_midi = frequency_to_midi(display_range.1 as f32, a4_hz).floor() as i32;
+    for midi in (min_midi..=max_midi).filter(|midi| midi.rem_euclid(12) == 0) {
+        let bin = frequency_to_bin(midi_to_frequency(midi as f32, a4_hz));
+        let y = rect.bottom() - (bin - min_bin) / (max_bin - min_bin) * rect.height();
+        painter.hline(rect.x_range(), y, Stroke::new(1.0, Color32::from_white_alpha(80)));
+
+        let note = midi_to_note(midi);
+        painter.text(pos2(rect.left() + 2.0, y), Align2::LEFT_BOTTOM, format!("{}{}", note.name, note.octave), FontId::proportional(10.0), Color32::WHITE);
     }
 }
 
-pub(crate) struct PitchOverlayApp {
-    current_stream: Option<Stream>,
-    current_device_index: Option<usize>,
-    available_input_devices: Vec<Device>,
+/// Maps a confidence to a color that goes from red at the lower threshold over yellow halfway
+/// between the thresholds to green at the upper threshold.
+fn confidence_color(confidence: f32, thresholds: (f32, f32)) -> Color32 {
+    let (low, high) = thresholds;
+    let t = ((confidence - low) / (high - low).max(f32::EPSILON)).clamp(0.0, 1.0);
+    let lerp = |from: u8, to: u8, t: f32| (from as f32 + (to as f32 - from as f32) * t).round() as u8;
 
-    audio_state: Arc<RwLock<AudioState>>,
-    crepe_model: Arc<CrepeModel>,
-    settings: Settings,
+    let (from, to, t) = if t < 0.5 {
+        (Color32::RED, Color32::YELLOW, t * 2.0)
+    } else {
+        (Color32::YELLOW, Color32::GREEN, t * 2.0 - 1.0)
+    };
+    Color32::from_rgb(lerp(from.r(), to.r(), t), lerp(from.g(), to.g(), t), lerp(from.b(), to.b(), t))
+}
 
-    window_state: WindowState,
+/// The width of the readout panel, which fits its longest line so that the panel does not change
+/// its size as the values update.
+const READOUT_WIDTH: f32 = 90.0;
+
+/// The highest number of decimals the frequency in the readout can be shown with.
+const MAX_FREQ_DECIMALS: usize = 3;
+
+fn format_hz(frequency: f32, decimals: usize) -> String {
+    format!("{:.*} Hz", decimals.min(MAX_FREQ_DECIMALS), frequency)
 }
 
-impl PitchOverlayApp {
-    pub(crate) fn new(input_devices: Vec<Device>, crepe_model: CrepeModel, settings: Settings) -> Self {
-        Self {
-            current_stream: None,
-            current_device_index: None,
-            available_input_devices: input_devices,
+fn format_note(note: Note) -> String {
+    format!("{}{}", note.name, note.octave)
+}
 
-            audio_state: Arc::new(RwLock::new(AudioState::default())),
-            crepe_model: Arc::new(crepe_model),
-            settings,
+fn format_midi(midi: i32) -> String {
+    format!("MIDI {}", midi)
+}
 
-            window_state: WindowState::default(),
+/// Formats an offset in cents with its sign and one decimal, without the unit.
+fn format_signed_cents(cents: f32) -> String {
+    // Adding zero turns negative zero into zero, which would otherwise be shown as -0.0.
+    format!("{:+.1}", cents + 0.0)
+}
+
+fn format_cents(cents: f32) -> String {
+    format!("{} ¢", format_signed_cents(cents))
+}
+
+/// The MIDI number and nearest note of `frequency`, with the cents offset rounded to the one decimal
+/// the readout shows.
+///
+/// The note is the nearest one before rounding, and the rounded offset is clamped to ±50.0 cents,
+/// so a pitch just below halfway to the next note is shown as +50.0 cents above its own note
+/// instead of jumping to the next one.
+fn readout_note(frequency: f32, a4_hz: f32) -> Option<(i32, Note)> {
+    frequency_to_note_checked(frequency, a4_hz)?;
+
+    let midi = frequency_to_midi(frequency, a4_hz);
+    let nearest = midi.round() as i32;
+    let cents = (((midi - nearest as f32) * 1000.0).round() / 10.0).clamp(-50.0, 50.0);
+
+    Some((nearest, Note {
+        cents_offset: cents,
+        ..midi_to_note(nearest)
+    }))
+}
+
+/// The lines of the readout that are enabled in `settings` for `frequency`, with placeholders while
+/// there is no pitch so that lines do not appear and disappear.
+fn readout_lines(frequency: Option<f32>, settings: &Settings) -> Vec<String> {
+    let note = frequency.and_then(|frequency| readout_note(frequency, settings.a4_hz));
+    let frequency = frequency.filter(|_| note.is_some());
+
+    [
+        (settings.show_hz, frequency.map(|frequency| format_hz(frequency, settings.freq_decimals)), "-- Hz"),
+        (settings.show_note, note.map(|(_, note)| format_note(note)), "--"),
+        (settings.show_midi, note.map(|(midi, _)| format_midi(midi)), "MIDI --"),
+        (settings.show_cents, note.map(|(_, note)| format_cents(note.cents_offset)), "-- ¢"),
+    ]
+        .into_iter()
+        .filter(|(is_shown, _, _)| *is_shown)
+        .map(|(_, value, placeholder)| value.unwrap_or(placeholder.to_owned()))
+        .collect()
+}
+
+/// The number of cents a note may be off by while still being shown as in tune by the tuner.
+const IN_TUNE_CENTS: f32 = 5.0;
+
+/// Draws a tuner that shows the name of `note` and a needle for how many cents it is off by.
+///
+/// The tuner is grayed out if `is_active` is false, e.g. if the current pitch is not confident.
+fn tuner(ui: &mut Ui, note: Option<Note>, is_active: bool, label_color: Color32) {
+    let (rect, _) = ui.allocate_exact_size(ui.available_size(), Sense::hover());
+    let painter = ui.painter_at(rect);
+    let color = if is_active { label_color } else { Color32::GRAY };
+    let stroke = Stroke::new(1.0, color);
+
+    let scale_y = rect.top() + rect.height() * 0.7;
+    let half_scale_width = rect.width() * 0.45;
+    let cents_to_x = |cents: f32| rect.center().x + cents.clamp(-50.0, 50.0) / 50.0 * half_scale_width;
+
+    let zone_color = if is_active { Color32::DARK_GREEN } else { Color32::from_gray(60) };
+    painter.rect_filled(
+        Rect::from_x_y_ranges(cents_to_x(-IN_TUNE_CENTS)..=cents_to_x(IN_TUNE_CENTS), scale_y - 20.0..=scale_y + 20.0),
+        2.0,
+        zone_color,
+    );
+    painter.line_segment([pos2(cents_to_x(-50.0), scale_y), pos2(cents_to_x(50.0), scale_y)], stroke);
+    for cents in (-50..=50).step_by(10) {
+        let tick_height = if cents == 0 { 12.0 } else { 6.0 };
+        let x = cents_to_x(cents as f32);
+        painter.line_segment([pos2(x, scale_y - tick_height), pos2(x, scale_y + tick_height)], stroke);
+    }
+
+    let name_position = pos2(rect.center().x, rect.top() + rect.height() * 0.35);
+    let name_font = FontId::proportional((rect.height() * 0.3).clamp(20.0, 80.0));
+    let Some(note) = note else {
+        painter.text(name_position, Align2::CENTER_CENTER, "--", name_font, color);
+        return;
+    };
+
+    // Animate the needle so it glides between predictions instead of jumping.
+    let cents = ui.ctx().animate_value_with_time(ui.id().with("tuner_needle"), note.cents_offset, 0.1);
+    let needle_color = if is_active && note.cents_offset.abs() <= IN_TUNE_CENTS { Color32::LIGHT_GREEN } else { color };
+    let needle_x = cents_to_x(cents);
+    painter.line_segment([pos2(needle_x, scale_y - 25.0), pos2(needle_x, scale_y + 25.0)], Stroke::new(3.0, needle_color));
+
+    painter.text(name_position, Align2::CENTER_CENTER, format!("{}{}", note.name, note.octave), name_font, color);
+    painter.text(
+        pos2(rect.center().x, scale_y + 30.0),
+        Align2::CENTER_TOP,
+        format!("{:+.0} cents", note.cents_offset),
+        FontId::proportional(14.0),
+        color,
+    );
+}
+
+/// The number of cents on each side of the target note shown by the practice display.
+const PRACTICE_RANGE_CENTS: f32 = 100.0;
+
+/// The number of seconds the pitch has to stay within [`IN_TUNE_CENTS`] of the target note before
+/// the practice display confirms that it is in tune.
+const IN_TUNE_HOLD_SECONDS: f64 = 1.0;
+
+/// The notes that can be picked as the target in the practice display, from C2 to C6.
+const PRACTICE_MIDI_NOTES: std::ops::RangeInclusive<i32> = 36..=84;
+
+/// Maps the cents error to the target note to a color that goes from green when in tune over
+/// yellow to red at half a semitone or more.
+fn cents_error_color(cents_error: f32) -> Color32 {
+    confidence_color(1.0 - cents_error.abs() / 50.0, (0.0, 1.0))
+}
+
+/// The text and color comparing the current pitch with a stored reference frequency, `frequency`
+/// is `None` if there is no confident pitch.
+fn reference_comparison(frequency: Option<f32>, reference_hz: f32) -> (String, Color32) {
+    match frequency {
+        Some(frequency) => {
+            let cents = hz_to_cents(frequency, reference_hz);
+            (format!("Reference {:.1}Hz: {} cents", reference_hz, format_signed_cents(cents)), cents_error_color(cents))
         }
+        None => (format!("Reference {:.1}Hz: -- cents", reference_hz), Color32::GRAY),
     }
+}
 
-    fn current_device(&self) -> Option<&Device> {
-        if let Some(i) = self.current_device_index {
-            return Some(&self.available_input_devices[i]);
+/// The summary below the cents histogram, with the mean offset and whether it tends sharp or flat.
+fn tendency_summary(histogram: &CentsHistogram) -> String {
+    match (histogram.mean_cents(), histogram.tendency()) {
+        (Some(mean), Some(tendency)) => {
+            let tendency = match tendency {
+                Tendency::Flat => "tends flat",
+                Tendency::InTune => "in tune on average",
+                Tendency::Sharp => "tends sharp",
+            };
+            format!("Mean {} cents over {} frames, {}", format_signed_cents(mean), histogram.total(), tendency)
         }
+        _ => "No confident pitch detected yet.".to_owned(),
+    }
+}
 
-        None
+/// Tracks how long the pitch has stayed within [`IN_TUNE_CENTS`] of the target note, so that
+/// being in tune is only confirmed once the note is held and not when the pitch merely passes it.
+#[derive(Debug, Default, Clone, Copy)]
+struct InTuneTimer {
+    /// The time in seconds the pitch got in tune, `None` while it is not.
+    since: Option<f64>,
+}
+
+impl InTuneTimer {
+    /// Updates the timer with the cents error at `time` seconds, `None` if there is no confident
+    /// pitch, and returns whether the pitch has been in tune for [`IN_TUNE_HOLD_SECONDS`].
+    fn update(&mut self, cents_error: Option<f32>, time: f64) -> bool {
+        if !cents_error.is_some_and(|cents_error| cents_error.abs() <= IN_TUNE_CENTS) {
+            self.since = None;
+            return false;
+        }
+
+        let since = *self.since.get_or_insert(time);
+        time - since >= IN_TUNE_HOLD_SECONDS
     }
 }
 
-impl eframe::App for PitchOverlayApp {
-    fn update(&mut self, ctx: &Context, _frame: &mut Frame) {
-        if self.window_state.are_settings_open {
-            egui::Window::new("Settings")
-                .collapsible(false)
-                .open(&mut self.window_state.are_settings_open)
-                .show(ctx, |ui| {
-                    ui.add(egui::Slider::new(&mut self.settings.confidence_threshold, 0.0..=1.0).text("Pitch confidence threshold"));
-                    ui.add_space(20.0);
+/// Draws the target note with a marker for how many cents the current pitch is off from it,
+/// `cents_error` is `None` if there is no confident pitch.
+fn practice_display(ui: &mut Ui, target: Note, cents_error: Option<f32>, is_in_tune: bool, label_color: Color32) {
+    let (rect, _) = ui.allocate_exact_size(ui.available_size(), Sense::hover());
+    let painter = ui.painter_at(rect);
+    let color = if cents_error.is_some() { label_color } else { Color32::GRAY };
+    let stroke = Stroke::new(1.0, color);
 
-                    ui.horizontal(|ui| {
-                        // TODO: right-align these, looks weird
-                        egui::widgets::color_picker::color_edit_button_rgba(ui, &mut self.settings.target_color, Alpha::BlendOrAdditive);
-                        ui.add(Label::new("Target region color")).on_hover_ui(|ui| {
-                            ui.label("Color of the target region on the plot");
-                        });
-                    });
-                    ui.horizontal(|ui| {
-                        egui::widgets::color_picker::color_edit_button_rgba(ui, &mut self.settings.label_color, Alpha::BlendOrAdditive);
-                        ui.add(Label::new("Pitch label color")).on_hover_ui(|ui| {
-                            ui.label("Color in the center of the plot of the label that displays your current pitch");
-                        })
-                    });
-                    ui.add_space(20.0);
+    let scale_y = rect.top() + rect.height() * 0.65;
+    let half_scale_width = rect.width() * 0.45;
+    let cents_to_x = |cents: f32| {
+        rect.center().x + cents.clamp(-PRACTICE_RANGE_CENTS, PRACTICE_RANGE_CENTS) / PRACTICE_RANGE_CENTS * half_scale_width
+    };
 
-                    let min_display_response = ui.add(egui::Slider::new(&mut self.settings.display_range.0, 0..=499).text("Min display")).on_hover_ui(|ui| {
-                        ui.label("Minimum frequency to display on the graph.");
-                    });
-                    let max_display_response = ui.add(egui::Slider::new(&mut self.settings.display_range.1, 1..=500).text("Max display")).on_hover_ui(|ui| {
-                        ui.label("Maximum frequency to display on the graph");
-                    });
-                    let display_range_changed = min_display_response.changed()
-                        | max_display_response.changed();
-                    if display_range_changed {
-                        let (lower, upper) = &mut self.settings.display_range;
-                        if lower >= upper {
-                            *upper = *lower + 1;
-                        }
-                    }
+    painter.rect_filled(
+        Rect::from_x_y_ranges(cents_to_x(-IN_TUNE_CENTS)..=cents_to_x(IN_TUNE_CENTS), scale_y - 20.0..=scale_y + 20.0),
+        2.0,
+        if is_in_tune { Color32::GREEN } else { Color32::DARK_GREEN },
+    );
+    painter.line_segment([pos2(cents_to_x(-PRACTICE_RANGE_CENTS), scale_y), pos2(cents_to_x(PRACTICE_RANGE_CENTS), scale_y)], stroke);
+    for cents in (-100..=100).step_by(25) {
+        let tick_height = if cents == 0 { 12.0 } else { 6.0 };
+        let x = cents_to_x(cents as f32);
+        painter.line_segment([pos2(x, scale_y - tick_height), pos2(x, scale_y + tick_height)], stroke);
+    }
 
-                    // TODO: these only show tooltips when the slider itself is hovered, while the color setting shows its tooltip when the label is hovered, that's inconsistent.
-                    let min_target_response = ui.add(egui::Slider::new(&mut self.settings.target_range.0, 0..=499).text("Min target")).on_hover_ui(|ui| {
-                        ui.label("Minimum frequency you are aiming for");
-                    });
-                    let max_target_response = ui.add(egui::Slider::new(&mut self.settings.target_range.1, 1..=500).text("Max target")).on_hover_ui(|ui| {
-                        ui.label("Maximum frequency you are aiming for");
-                    });
-                    let target_range_changed = min_target_response.changed()
-                        | max_target_response.changed();
-                    if target_range_changed {
-                        let (lower, upper) = &mut self.settings.target_range;
-                        if lower >= upper {
-                            *upper = *lower + 1;
-                        }
-                    }
-                });
-        }
+    let name_position = pos2(rect.center().x, rect.top() + rect.height() * 0.3);
+    let name_font = FontId::proportional((rect.height() * 0.25).clamp(20.0, 80.0));
+    painter.text(name_position, Align2::CENTER_CENTER, format!("{}{}", target.name, target.octave), name_font, color);
 
-        if let Some(message) = self.window_state.error_message.clone() {
-            egui::Window::new("Error")
-                .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
-                .auto_sized()
-                .collapsible(false)
-                .show(&ctx, |ui| {
-                    ui.label(message);
-                    ui.vertical_centered(|ui| {
-                        if ui.button("Ok").clicked() {
-                            self.window_state.error_message = None;
-                        }
-                    });
-                });
-        }
+    let Some(cents_error) = cents_error else {
+        return;
+    };
+    let marker_cents = ui.ctx().animate_value_with_time(ui.id().with("practice_marker"), cents_error, 0.1);
+    let marker_x = cents_to_x(marker_cents);
+    painter.line_segment([pos2(marker_x, scale_y - 25.0), pos2(marker_x, scale_y + 25.0)], Stroke::new(3.0, cents_error_color(cents_error)));
 
-        let arc1 = Arc::clone(&self.audio_state);
-        egui::CentralPanel::default().show(ctx, |ui| {
-            let current_device_name = self.current_device().map(|device| device.name().unwrap_or("Unnamed device".to_owned())).unwrap_or("Audio disconnected".to_owned());
+    let text = if is_in_tune { "In tune!".to_owned() } else { format!("{:+.0} cents", cents_error) };
+    painter.text(
+        pos2(rect.center().x, scale_y + 30.0),
+        Align2::CENTER_TOP,
+        text,
+        FontId::proportional(if is_in_tune { 20.0 } else { 14.0 }),
+        if is_in_tune { Color32::GREEN } else { cents_error_color(cents_error) },
+    );
+}
 
-            ui.horizontal_wrapped(|ui| {
-                egui::ComboBox::from_id_salt("Audio Input device")
-                    .truncate()
-                    .selected_text(format!("{}", current_device_name))
-                    .show_ui(ui, |ui| {
-                        if ui.selectable_value(&mut self.current_device_index, None, "Disconnect audio").clicked() {
-                            println!("Disconnect clicked!");
-                            self.current_stream = None;
-                        }
-                        for (i, device) in self.available_input_devices.iter().enumerate() {
-                            let name = device.name().unwrap_or("Unknown device".to_owned());
-                            if ui.selectable_value(&mut self.current_device_index, Some(i), name).clicked() {
-                                println!("Connect to new device clicked!");
-
-                                let cloned_arc = Arc::clone(&self.audio_state);
-                                let cloned_ctx = ctx.clone();
-                                let model = Arc::clone(&self.crepe_model);
-
-                                let settings = self.settings;
-
-                                match self.available_input_devices[i].build_input_stream(
-                                    &CONFIG,
-                                    move |data: &[i16], info| {
-                                        let instant = info.timestamp().callback;
-
-                                        let mut audio_state = cloned_arc.write().unwrap();
-                                        if let None = audio_state.first_audio_instant {
-                                            audio_state.first_audio_instant = Some(instant);
-                                            println!("Updated first audio timestamp");
-                                        }
-
-                                        audio_state.recent_audio.extend_from_slice(data);
-
-                                        let sample_count = audio_state.recent_audio.len();
-                                        if sample_count < MIN_SAMPLES_PER_DISPLAY {
-                                            return;
-                                        }
-
-                                        let most_recent_audio: [i16; MIN_SAMPLES_PER_DISPLAY] = (&audio_state.recent_audio[sample_count - MIN_SAMPLES_PER_DISPLAY..sample_count]).try_into().unwrap();
-                                        let predictions = most_recent_audio.chunks_exact(crepe::SAMPLES_PER_STEP)
-                                            .map(|chunk| model.predict_single(chunk.try_into().unwrap()))
-                                            .filter(|prediction|
-                                                prediction.confidence >= settings.confidence_threshold
-                                                    && prediction.frequency >= settings.display_range.0 as f32
-                                                    && prediction.frequency <= settings.display_range.1 as f32)
-                                            .map(|prediction| prediction.frequency)
-                                            .collect::<Vec<f32>>();
-                                        let average_pitch = if predictions.is_empty() {
-                                            f32::NAN
-                                        } else {
-                                            predictions.iter().sum::<f32>() / predictions.len() as f32
-                                        };
-                                        audio_state.recent_audio.clear();
-
-                                        let since_start = instant.duration_since(&audio_state.first_audio_instant.unwrap()).unwrap_or(Duration::ZERO);
-                                        audio_state.pitch_points.push([since_start.as_secs_f64(), average_pitch as f64]);
-                                        if !average_pitch.is_nan() {
-                                            audio_state.last_valid_frequency = Some(average_pitch);
-                                        }
-
-                                        // Explicitly trigger repaint since this thread otherwise is so high-priority that it
-                                        // keeps on blocking the render thread through synchronization most of the time.
-                                        cloned_ctx.request_repaint();
-                                    },
-                                    move |err| {
-                                        println!("Error: {:?}", err);
-                                    },
-                                    None,
-                                ) {
-                                    Err(e) => {
-                                        self.current_stream = None;
-                                        self.current_device_index = None;
-
-                                        println!("Error creating input stream: {}", e);
-                                        self.window_state.error_message = Some(format!("Error creating input stream: {}", e));
-                                    }
-                                    Ok(stream) => {
-                                        match stream.play() {
-                                            Err(e) => {
-                                                self.current_stream = None;
-                                                self.current_device_index = None;
-
-                                                println!("Error starting input stream: {}", e);
-                                                self.window_state.error_message = Some(format!("Error starting input stream: {}", e));
-                                            }
-                                            Ok(_) => {
-                                                println!("Started audio stream.");
-                                                self.current_stream = Some(stream)
-                                            }
-                                        }
-                                    }
-                                };
-                            }
-                        }
-                    });
-                if ui.button("Reload devices").clicked() {
-                    self.available_input_devices = cpal::default_host().input_devices().expect("Failed to get input devices").collect();
-                }
+/// Draws whether the pitch is flat, in tune or sharp relative to the locked note in large letters
+/// with the note above them, `cents_error` is `None` if there is no confident pitch.
+fn lock_note_display(ui: &mut Ui, note: Option<Note>, cents_error: Option<f32>, tolerance_cents: f32, label_color: Color32) {
+    let (rect, _) = ui.allocate_exact_size(ui.available_size(), Sense::hover());
+    let painter = ui.painter_at(rect);
+    let Some(note) = note else {
+        painter.text(rect.center(), Align2::CENTER_CENTER, "Lock a note to tune to", FontId::proportional(14.0), Color32::GRAY);
+        return;
+    };
 
-                let checkbox_changed = ui.add_sized([80.0, 20.0], egui::Checkbox::new(&mut self.window_state.is_always_on_top, "Always on top")).changed();
-                let settings_button = ui.add_sized([100.0, 20.0], egui::Button::new("Settings"));
+    let (text, color) = match cents_error.map(|cents_error| Tendency::of(cents_error, tolerance_cents)) {
+        None => ("--", Color32::GRAY),
+        Some(Tendency::Flat) => ("FLAT", Color32::LIGHT_BLUE),
+        Some(Tendency::InTune) => ("IN TUNE", Color32::GREEN),
+        Some(Tendency::Sharp) => ("SHARP", Color32::from_rgb(255, 140, 0)),
+    };
+    // Sized so that the longest text fits the width.
+    let font_size = (rect.height() * 0.5).min(rect.width() / 5.0).max(12.0);
+    painter.text(rect.center(), Align2::CENTER_CENTER, text, FontId::proportional(font_size), color);
+    painter.text(
+        pos2(rect.center().x, rect.center().y - font_size * 0.6),
+        Align2::CENTER_BOTTOM,
+        format!("{}{}", note.name, note.octave),
+        FontId::proportional((font_size * 0.3).max(12.0)),
+        label_color,
+    );
+}
 
-                if checkbox_changed {
-                    let new_level = if self.window_state.is_always_on_top {
-                        WindowLevel::AlwaysOnTop
-                    } else {
-                        WindowLevel::Normal
-                    };
-                    ctx.send_viewport_cmd(ViewportCommand::WindowLevel(new_level))
-                }
-                if settings_button.clicked() {
-                    self.window_state.are_settings_open = true;
-                }
-            });
+/// The quietest level in dBFS shown by the level meter, quieter audio including silence is shown as this.
+const METER_MIN_DB: f32 = -80.0;
 
-            let current_device_index = self.current_device_index;
-            let label_color = self.settings.label_color;
-            let plot = Plot::new("My plot")
-                .allow_zoom(false)
-                .allow_scroll(false)
-                .allow_drag(false)
-                .allow_double_click_reset(false);
-            let cloned_arc = Arc::clone(&self.audio_state);
-            let response = plot.show(ui, move |plot_ui| {
-                let target_range_width = (self.settings.target_range.1 - self.settings.target_range.0) as f64;
-                let middle_y = self.settings.target_range.0 as f64 + target_range_width / 2.0;
-                plot_ui.hline(HLine::new(middle_y)
-                    .width(target_range_width as f32)
-                    .color(self.settings.target_color)
-                );
-                let audio_state = cloned_arc.read().unwrap();
-                let current_secs = if let Some(point) = audio_state.pitch_points.last() {
-                    point[0]
-                } else {
-                    10.0
-                };
-                plot_ui.set_plot_bounds(PlotBounds::from_min_max(
-                    [current_secs - 10.0, self.settings.display_range.0 as f64],
-                    [current_secs, self.settings.display_range.1 as f64],
-                ));
-                plot_ui.line(Line::new(PlotPoints::new(cloned_arc.read().unwrap().pitch_points.clone())));
-            });
-            // Place label over the created plot.
-            let rect = response.response.rect;
-            let display_frequency = match arc1.read().unwrap().last_valid_frequency {
-                None => match current_device_index {
-                    None => "No device selected.",
-                    Some(_) => "Waiting for audio data...",
-                }.to_owned(),
-                Some(frequency) => format!("{}Hz", frequency as u32),
-            };
-            let text = RichText::new(display_frequency).size(30.0).color(label_color);
-            let label = Label::new(text);
-            ui.put(rect, label);
-        });
+/// The number of seconds the peak marker of the level meter stays at a peak before it falls.
+const METER_PEAK_HOLD_SECONDS: f64 = 1.0;
+
+/// The speed at which the peak marker of the level meter falls after the hold time.
+const METER_PEAK_DECAY_DB_PER_SECOND: f32 = 20.0;
+
+/// The most recent value of a meter along with its recent peak, which is held for a moment and
+/// then falls at a constant speed so that short peaks can still be seen.
+#[derive(Debug, Clone, Copy)]
+struct PeakHold {
+    value: f32,
+    /// The time in seconds of the most recent value.
+    time: f64,
+    peak: f32,
+    /// The time in seconds the peak was reached at.
+    peak_time: f64,
+    /// The lowest value of the meter, which the value and the peak start at.
+    min: f32,
+    hold_seconds: f64,
+    decay_per_second: f32,
+}
+
+impl PeakHold {
+    fn new(min: f32, hold_seconds: f64, decay_per_second: f32) -> Self {
+        PeakHold {
+            value: min,
+            time: 0.0,
+            peak: min,
+            peak_time: 0.0,
+            min,
+            hold_seconds,
+            decay_per_second,
+        }
     }
 
-    fn save(&mut self, storage: &mut dyn Storage) {
-        println!("Saving settings...");
-        match serde_json::to_string(&self.settings) {
-            Ok(json) => {
-                storage.set_string(SETTINGS_STORAGE_KEY, json);
-                println!("Saved settings.");
-            }
-            Err(e) => println!("Error saving settings: {}", e),
+    /// Updates the meter with the value at `time` seconds.
+    fn update(&mut self, value: f32, time: f64) {
+        self.value = value;
+        self.time = time;
+        // The time starts over when another device is connected.
+        if value >= self.peak() || time < self.peak_time {
+            self.peak = value;
+            self.peak_time = time;
+        }
+    }
+
+    /// The value of the peak marker at the time of the most recent value.
+    fn peak(&self) -> f32 {
+        let falling_seconds = (self.time - self.peak_time - self.hold_seconds).max(0.0) as f32;
+
+        (self.peak - falling_seconds * self.decay_per_second).max(self.min)
+    }
+}
+
+/// The input level along with the recent peak, so that short sounds can still be seen.
+#[derive(Debug, Clone, Copy)]
+struct LevelMeter {
+    level: PeakHold,
+}
+
+impl Default for LevelMeter {
+    fn default() -> Self {
+        LevelMeter {
+            level: PeakHold::new(METER_MIN_DB, METER_PEAK_HOLD_SECONDS, METER_PEAK_DECAY_DB_PER_SECOND),
         }
     }
-}
\ No newline at end of file
+}
+
+impl LevelMeter {
+    /// Updates the meter with the level in dBFS of audio at `time` seconds.
+    fn update(&mut self, level_db: f32, time: f64) {
+        // Also replaces the negative infinity of silence.
+        self.level.update(level_db.max(METER_MIN_DB), time);
+    }
+
+    /// The most recent level in dBFS.
+    fn level_db(&self) -> f32 {
+        self.level.value
+    }
+
+    /// The level of the peak marker at the time of the most recent level.
+    fn peak_db(&self) -> f32 {
+        self.level.peak()
+    }
+}
+
+/// The position of a level in dBFS along the level meter, from 0 at [`METER_MIN_DB`] to 1 at 0 dBFS.
+fn meter_position(db: f32) -> f32 {
+    ((db - METER_MIN_DB) / -METER_MIN_DB).clamp(0.0, 1.0)
+}
+
+/// The level in dBFS at a position along the level meter, the inverse of [`meter_position`].
+fn meter_db(position: f32) -> f32 {
+    METER_MIN_DB - position.clamp(0.0, 1.0) * METER_MIN_DB
+}
+
+/// Draws the level meter into `rect`. The bar is gray while the level is below the noise floor and
+/// not analyzed.
+fn paint_level_meter(painter: &egui::Painter, rect: Rect, meter: &LevelMeter, noise_floor_db: f32, noise_floor_stroke: Stroke) {
+    let db_to_x = |db: f32| rect.left() + meter_position(db) * rect.width();
+
+    painter.rect_filled(rect, 2.0, Color32::from_gray(40));
+    let bar_color = if meter.level_db() >= noise_floor_db { Color32::GREEN } else { Color32::GRAY };
+    painter.rect_filled(Rect::from_x_y_ranges(rect.left()..=db_to_x(meter.level_db()), rect.y_range()), 2.0, bar_color);
+    painter.vline(db_to_x(meter.peak_db()), rect.y_range(), Stroke::new(2.0, Color32::WHITE));
+    painter.vline(db_to_x(noise_floor_db), rect.y_range(), noise_floor_stroke);
+}
+
+/// Draws a horizontal bar for the input level with a marker for the recent peak and a red line
+/// at the noise floor.
+fn level_meter(ui: &mut Ui, meter: &LevelMeter, noise_floor_db: f32) -> egui::Response {
+    let (rect, response) = ui.allocate_exact_size(vec2(100.0, 12.0), Sense::hover());
+    paint_level_meter(&ui.painter_at(rect), rect, meter, noise_floor_db, Stroke::new(1.0, Color32::RED));
+
+    response
+}
+
+/// Draws a wide level meter whose noise floor line can be dragged, or clicked anywhere to move it
+/// there, so that the noise floor can be set just above the background noise.
+fn noise_floor_editor(ui: &mut Ui, meter: &LevelMeter, noise_floor_db: &mut f32) -> egui::Response {
+    let (rect, mut response) = ui.allocate_exact_size(vec2(ui.available_width().min(300.0), 20.0), Sense::click_and_drag());
+    if let Some(pointer) = response.interact_pointer_pos() {
+        let db = meter_db((pointer.x - rect.left()) / rect.width()).round();
+        if db != *noise_floor_db {
+            *noise_floor_db = db;
+            response.mark_changed();
+        }
+    }
+
+    let stroke = if response.hovered() || response.dragged() { Stroke::new(3.0, Color32::RED) } else { Stroke::new(2.0, Color32::RED) };
+    paint_level_meter(&ui.painter_at(rect), rect, meter, *noise_floor_db, stroke);
+
+    response
+}
+
+/// The number of seconds the peak marker of the confidence meter stays at a peak before it falls.
+const CONFIDENCE_PEAK_HOLD_SECONDS: f64 = 0.5;
+
+/// The speed at which the peak marker of the confidence meter falls after the hold time.
+const CONFIDENCE_PEAK_DECAY_PER_SECOND: f32 = 0.5;
+
+/// The confidence of the most recent frame along with the recent peak, like [`LevelMeter`].
+#[derive(Debug, Clone, Copy)]
+struct ConfidenceMeter {
+    confidence: PeakHold,
+}
+
+impl Default for ConfidenceMeter {
+    fn default() -> Self {
+        ConfidenceMeter {
+            confidence: PeakHold::new(0.0, CONFIDENCE_PEAK_HOLD_SECONDS, CONFIDENCE_PEAK_DECAY_PER_SECOND),
+        }
+    }
+}
+
+impl ConfidenceMeter {
+    /// Updates the meter with the confidence of a frame at `time` seconds, 0 if it was not analyzed.
+    fn update(&mut self, confidence: f32, time: f64) {
+        self.confidence.update(clamp_confidence(confidence), time);
+    }
+
+    /// The confidence of the most recent frame.
+    fn confidence(&self) -> f32 {
+        self.confidence.value
+    }
+
+    /// The confidence of the peak marker at the time of the most recent confidence.
+    fn peak(&self) -> f32 {
+        self.confidence.peak()
+    }
+}
+
+/// Clamps a confidence to `0.0..=1.0`, treating NaN as no confidence.
+fn clamp_confidence(confidence: f32) -> f32 {
+    if confidence.is_nan() {
+        return 0.0;
+    }
+
+    confidence.clamp(0.0, 1.0)
+}
+
+/// Formats a confidence as a whole percentage.
+fn format_confidence(confidence: f32) -> String {
+    format!("{:.0}%", clamp_confidence(confidence) * 100.0)
+}
+
+/// Draws a bar filled up to the confidence in the confidence color, with a marker for the recent
+/// peak and the percentage next to it.
+fn confidence_meter(ui: &mut Ui, meter: &ConfidenceMeter, thresholds: (f32, f32)) -> egui::Response {
+    ui.horizontal(|ui| {
+        let (rect, _) = ui.allocate_exact_size(vec2(60.0, 12.0), Sense::hover());
+        let painter = ui.painter_at(rect);
+        let x = |confidence: f32| rect.left() + confidence * rect.width();
+        painter.rect_filled(rect, 2.0, Color32::from_gray(40));
+        painter.rect_filled(Rect::from_x_y_ranges(rect.left()..=x(meter.confidence()), rect.y_range()), 2.0, confidence_color(meter.confidence(), thresholds));
+        painter.vline(x(meter.peak()), rect.y_range(), Stroke::new(2.0, Color32::WHITE));
+        // The width fits 100% so that the layout does not move as the percentage changes.
+        ui.add_sized(vec2(32.0, 12.0), Label::new(format_confidence(meter.confidence())));
+    }).response
+}
+
+pub(crate) fn stream_config(sample_rate: u32, channels: u16) -> StreamConfig {
+    if sample_rate == crepe::SAMPLE_RATE {
+        return StreamConfig {
+            channels,
+            ..CONFIG
+        };
+    }
+
+    StreamConfig {
+        channels,
+        sample_rate: SampleRate(sample_rate),
+        buffer_size: BufferSize::Default,
+    }
+}
+
+#[derive(Default)]
+struct WindowState {
+    is_always_on_top: bool,
+    is_mouse_passthrough: bool,
+    are_settings_open: bool,
+    /// Whether the settings ask to confirm resetting all settings to their defaults.
+    is_reset_confirmation_open: bool,
+    /// Whether the result of the last pitch measurement is shown.
+    are_stats_open: bool,
+    /// The result of the last pitch measurement, `None` if there was no confident pitch.
+    pitch_stats: Option<PitchStats>,
+    /// Whether the list of captured readings is shown.
+    are_snapshots_open: bool,
+    snapshots: SnapshotList,
+    /// Whether the intonation report of the practiced scale is shown.
+    is_intonation_open: bool,
+    /// Whether the histogram of the offsets from the nearest notes is shown.
+    is_cents_histogram_open: bool,
+    /// Whether the metronome and the intonation on its beats are shown.
+    is_metronome_open: bool,
+    error_message: Option<String>,
+    /// The last confident note shown by the tuner, kept so it can be grayed out instead of disappearing.
+    tuner_note: Option<Note>,
+    in_tune_timer: InTuneTimer,
+    /// The frequency stored to compare the current pitch with, `None` if none is stored.
+    stored_reference_hz: Option<f32>,
+    heatmap_texture: Option<TextureHandle>,
+    spectrogram_texture: Option<TextureHandle>,
+}
+
+/// How the current pitch is shown in the main window.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub(crate) enum DisplayMode {
+    /// A plot of the recent pitch with the target range.
+    #[default]
+    Plot,
+    /// A tuner that shows the nearest note and how many cents it is off by.
+    Tuner,
+    /// A practice display that shows how many cents the pitch is off from a chosen target note.
+    Practice,
+    /// Only shows whether the pitch is flat, in tune or sharp relative to a locked note, e.g. for
+    /// tuning a drone in a click-through overlay.
+    LockNote,
+}
+
+impl DisplayMode {
+    /// The mode after this one, wrapping around after the last mode.
+    fn next(self) -> Self {
+        match self {
+            DisplayMode::Plot => DisplayMode::Tuner,
+            DisplayMode::Tuner => DisplayMode::Practice,
+            DisplayMode::Practice => DisplayMode::LockNote,
+            DisplayMode::LockNote => DisplayMode::Plot,
+        }
+    }
+}
+
+/// An action that can be triggered with a keyboard shortcut.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ShortcutAction {
+    ToggleRecording,
+    TogglePause,
+    CycleDisplayMode,
+    /// Clears the pitch that is held after the last confident pitch.
+    ClearHold,
+}
+
+/// The keyboard shortcuts along with a description for the help.
+const SHORTCUTS: [(egui::Key, ShortcutAction, &str); 4] = [
+    (egui::Key::R, ShortcutAction::ToggleRecording, "Start or stop recording"),
+    (egui::Key::Space, ShortcutAction::TogglePause, "Pause or resume"),
+    (egui::Key::T, ShortcutAction::CycleDisplayMode, "Switch between plot, tuner, practice and locked note"),
+    (egui::Key::Escape, ShortcutAction::ClearHold, "Clear the held pitch"),
+];
+
+/// Finds the actions of the shortcuts that were pressed in `events`.
+///
+/// Keys pressed together with Ctrl, Alt or Cmd are ignored so that they do not clash with the
+/// shortcuts of the platform.
+fn shortcut_actions(events: &[egui::Event]) -> Vec<ShortcutAction> {
+    events.iter()
+        .filter_map(|event| match event {
+            egui::Event::Key { key, pressed: true, repeat: false, modifiers, .. } if !modifiers.command && !modifiers.alt && !modifiers.ctrl => Some(*key),
+            _ => None,
+        })
+        .filter_map(|pressed_key| SHORTCUTS.iter().find(|(key, _, _)| *key == pressed_key))
+        .map(|(_, action, _)| *action)
+        .collect()
+}
+
+/// Which note the reference tone plays.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub(crate) enum ToneNote {
+    /// The note nearest to the pitch at the time the tone is started.
+    #[default]
+    Nearest,
+    /// The target note of the practice display, following it when it is changed.
+    Target,
+}
+
+fn scale_name(template: ScaleTemplate) -> &'static str {
+    match template {
+        ScaleTemplate::Major => "Major",
+        ScaleTemplate::NaturalMinor => "Natural minor",
+        ScaleTemplate::Chromatic => "Chromatic",
+    }
+}
+
+/// A sine at a note that is played on the default output device alongside the input stream, to
+/// match it by ear. Playing stops when it is dropped.
+struct ReferenceTone {
+    _stream: Stream,
+    control: Arc<ToneControl>,
+    note: NoteSpec,
+}
+
+impl ReferenceTone {
+    /// Starts playing `note` relative to `a4_hz` with an amplitude of `volume`.
+    fn start(note: NoteSpec, a4_hz: f32, volume: f32) -> Result<Self, String> {
+        let control = Arc::new(ToneControl::new(note.frequency(a4_hz), volume));
+        let stream_control = Arc::clone(&control);
+        let stream = start_output_stream(|sample_rate| {
+            let mut generator = SineGenerator::new(sample_rate as f32);
+            move |buffer: &mut [f32], channels, _: &OutputCallbackInfo| generator.fill(buffer, channels, stream_control.frequency(), stream_control.volume())
+        })?;
+
+        Ok(ReferenceTone {
+            _stream: stream,
+            control,
+            note,
+        })
+    }
+}
+
+/// The clicks of a metronome played on the default output device alongside the input stream.
+/// Playing stops when it is dropped.
+struct Metronome {
+    _stream: Stream,
+}
+
+impl Metronome {
+    /// Starts clicking at `bpm` beats per minute with an amplitude of `volume`, and starts the beat
+    /// capture of `audio_state` once it is known when the first click is heard.
+    ///
+    /// The first click is placed on the clock of the input stream by the time the output stream
+    /// plays it, since both streams of a host are timed by the same clock. This also leaves out the
+    /// output latency, so the sampled beats are the ones that are heard.
+    fn start(bpm: f32, volume: f32, audio_state: Arc<RwLock<AudioState>>) -> Result<Self, String> {
+        let stream = start_output_stream(|sample_rate| {
+            let mut generator = ClickGenerator::new(bpm, sample_rate);
+            let mut is_anchored = false;
+            move |buffer: &mut [f32], channels, info: &OutputCallbackInfo| {
+                // The audio thread must not wait for the lock, so this is tried again on the next
+                // buffer, whose playback time is just as good.
+                if !is_anchored {
+                    if let Ok(mut audio_state) = audio_state.try_write() {
+                        let since_start = audio_state.first_audio_instant
+                            .and_then(|first_audio_instant| info.timestamp().playback.duration_since(&first_audio_instant));
+                        if let Some(since_start) = since_start {
+                            let start = since_start.as_secs_f64() - generator.elapsed() - audio_state.paused_seconds;
+                            audio_state.beat_capture = Some(BeatCapture::new(BeatScheduler::new(bpm, start)));
+                            is_anchored = true;
+                        }
+                    }
+                }
+                generator.fill(buffer, channels, volume);
+            }
+        })?;
+
+        Ok(Metronome { _stream: stream })
+    }
+}
+
+/// Starts playing on the default output device. `create` is called with the sample rate of the
+/// device and returns the function that fills the buffers with interleaved frames of the given
+/// number of channels, which is also told when the buffer is played.
+fn start_output_stream<C, F>(create: C) -> Result<Stream, String>
+where
+    C: FnOnce(u32) -> F,
+    F: FnMut(&mut [f32], usize, &OutputCallbackInfo) + Send + 'static,
+{
+    let device = cpal::default_host().default_output_device()
+        .ok_or("No audio output device found".to_owned())?;
+    let config = device.default_output_config()
+        .map_err(|e| format!("Failed to get the output format: {}", e))?;
+    let fill = create(config.sample_rate().0);
+    let stream = match config.sample_format() {
+        SampleFormat::F32 => build_output_stream::<f32, F>(&device, &config.config(), fill),
+        SampleFormat::I16 => build_output_stream::<i16, F>(&device, &config.config(), fill),
+        SampleFormat::U16 => build_output_stream::<u16, F>(&device, &config.config(), fill),
+        format => return Err(format!("Unsupported output sample format {:?}", format)),
+    }.map_err(|e| format!("Failed to create output stream: {}", e))?;
+    stream.play().map_err(|e| format!("Failed to start output stream: {}", e))?;
+
+    Ok(stream)
+}
+
+/// Builds an output stream on `device` that plays what `fill` generates in samples of type `T`.
+fn build_output_stream<T, F>(device: &Device, config: &StreamConfig, mut fill: F) -> Result<Stream, cpal::BuildStreamError>
+where
+    T: SizedSample + FromSample<f32>,
+    F: FnMut(&mut [f32], usize, &OutputCallbackInfo) + Send + 'static,
+{
+    let channels = config.channels as usize;
+    let mut buffer = Vec::new();
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], info| {
+            buffer.resize(data.len(), 0.0);
+            fill(&mut buffer, channels, info);
+            for (sample, value) in data.iter_mut().zip(&buffer) {
+                *sample = T::from_sample(*value);
+            }
+        },
+        |err| println!("Error playing audio: {:?}", err),
+        None,
+    )
+}
+
+/// The number of most recent beats shown by the metronome.
+const BEATS_SHOWN: usize = 16;
+
+/// Draws the most recent beats as a row of boxes with the offset of their pitch from the nearest
+/// note in cents, colored from green when close to red when off, gray for beats without a pitch.
+fn beat_row(ui: &mut Ui, readings: &[BeatReading], a4_hz: f32) {
+    ui.horizontal(|ui| {
+        for reading in &readings[readings.len().saturating_sub(BEATS_SHOWN)..] {
+            let cents_error = reading.frequency.map(|frequency| NoteSpec::nearest(frequency, a4_hz).cents_error(frequency, a4_hz));
+            let (rect, response) = ui.allocate_exact_size(vec2(32.0, 32.0), Sense::hover());
+            ui.painter().rect_filled(rect, 4.0, cents_error.map_or(Color32::GRAY, cents_error_color));
+            let text = cents_error.map_or("-".to_owned(), |cents_error| format!("{:+.0}", cents_error));
+            ui.painter().text(rect.center(), Align2::CENTER_CENTER, text, FontId::proportional(12.0), Color32::BLACK);
+            response.on_hover_text(format!("Beat {}", reading.beat + 1));
+        }
+    });
+}
+
+/// The color theme of the window.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub(crate) enum Theme {
+    /// Follows the theme of the operating system, dark if it is not known.
+    #[default]
+    System,
+    Dark,
+    Light,
+}
+
+impl Theme {
+    /// The visuals of the theme, where `system_theme` is the theme of the operating system if known.
+    fn visuals(self, system_theme: Option<egui::Theme>) -> egui::Visuals {
+        match (self, system_theme) {
+            (Theme::Light, _) | (Theme::System, Some(egui::Theme::Light)) => egui::Visuals::light(),
+            _ => egui::Visuals::dark(),
+        }
+    }
+}
+
+/// Which algorithm detects the pitch of the audio.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub(crate) enum DetectorKind {
+    /// The CREPE model, which is accurate but needs the model file and more CPU time.
+    #[default]
+    Crepe,
+    /// The YIN autocorrelation algorithm, which is cheap but more prone to octave errors and noise.
+    Yin,
+}
+
+/// How the displayed pitch is smoothed over time.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub(crate) enum Smoothing {
+    /// Shows the raw pitch values.
+    None,
+    /// Shows the median of the given number of most recent pitch values.
+    Median(usize),
+    /// Shows an exponential moving average where each new pitch value has the given weight.
+    Ema(f32),
+    /// Shows an exponential moving average that gives new pitch values the weight `fast_alpha`
+    /// while the pitch changes by more than `slope_threshold_cents` per value, e.g. during a
+    /// glissando, and `slow_alpha` on held notes.
+    Adaptive {
+        slow_alpha: f32,
+        fast_alpha: f32,
+        slope_threshold_cents: f32,
+    },
+    /// Shows an exponential moving average where each new pitch value has the given weight times
+    /// its confidence, so that unsure values barely move the pitch.
+    ConfidenceWeighted(f32),
+}
+
+impl Smoothing {
+    fn smoother(self) -> Option<Box<dyn Smoother>> {
+        match self {
+            Smoothing::None => None,
+            Smoothing::Median(window) => Some(Box::new(MedianFilter::new(window))),
+            Smoothing::Ema(alpha) => Some(Box::new(EmaSmoother::new(alpha))),
+            Smoothing::Adaptive { slow_alpha, fast_alpha, slope_threshold_cents } => {
+                Some(Box::new(AdaptiveSmoother::new(slow_alpha, fast_alpha, slope_threshold_cents)))
+            }
+            Smoothing::ConfidenceWeighted(alpha) => Some(Box::new(ConfidenceWeightedEma::new(alpha))),
+        }
+    }
+}
+
+/// Settings of the application which are persisted between sessions.
+///
+/// Fields that are missing from the stored settings, e.g. because they were added in a later
+/// version, take their default values, so that the other stored settings are kept.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct Settings {
+    display_range: (u32, u32),
+    target_range: (u32, u32),
+    confidence_threshold: f32,
+    /// Whether the shown pitch is gated with hysteresis and attack and release times instead of
+    /// `confidence_threshold`, which keeps it from flickering at the ends of phrases.
+    gate_enabled: bool,
+    /// The confidence that opens the gate once it was held for `gate_attack_ms`.
+    gate_open_threshold: f32,
+    /// The confidence below which the gate closes once it stayed there for `gate_release_ms`.
+    gate_close_threshold: f32,
+    gate_attack_ms: f64,
+    gate_release_ms: f64,
+    /// Whether the starts of notes are marked on the pitch plot.
+    show_onsets: bool,
+    /// How small a rise in level and confidence marks the start of a note, from 0 to 1.
+    onset_sensitivity: f32,
+    target_color: Rgba,
+    label_color: Rgba,
+    log_to_csv: bool,
+    csv_log_path: String,
+    /// The path of the WAV file that the analyzed audio is recorded to.
+    recording_path: String,
+    /// The path of the CSV file that captured readings are exported to.
+    snapshot_csv_path: String,
+    /// The path of the CSV file that the intonation report of a practiced scale is exported to.
+    intonation_csv_path: String,
+    /// The path of the PNG file that the current activation or the heatmap is exported to.
+    activation_png_path: String,
+    /// The scale that is practiced upward from the target note.
+    scale_template: ScaleTemplate,
+    smoothing: Smoothing,
+    /// The number of display steps a new note has to be held for before it is shown, which hides
+    /// wrong pitches at note onsets but delays the display by as many steps. Zero disables it.
+    onset_confirm_frames: usize,
+    /// The level in dBFS below which audio is treated as silence and not analyzed.
+    noise_floor_db: f32,
+    /// The lowest detected frequency that is shown, lower ones are treated as no pitch.
+    freq_min_hz: f32,
+    /// The highest detected frequency that is shown, higher ones are treated as no pitch.
+    freq_max_hz: f32,
+    /// Whether CREPE only picks the pitch from the bins between `freq_min_hz` and `freq_max_hz`,
+    /// instead of ignoring pitches outside of them afterwards.
+    restrict_model_range: bool,
+    /// The gain in decibels that the audio is amplified by before it is gated and analyzed.
+    input_gain_db: f32,
+    /// Whether the audio is automatically scaled toward a constant level after the input gain.
+    agc_enabled: bool,
+    /// The reference frequency of A4 that note names are calculated from.
+    pub(crate) a4_hz: f32,
+    /// The number of cents that every detected frequency is shifted by to correct a known bias of the input.
+    calibration_cents: f32,
+    display_mode: DisplayMode,
+    /// The note that the practice display compares the pitch with, `None` until one is picked.
+    target_note: Option<NoteSpec>,
+    /// The note that the locked note display compares the pitch with, `None` until one is locked.
+    locked_note: Option<NoteSpec>,
+    /// The number of cents the pitch may be off from the locked note either way while still in tune.
+    lock_tolerance_cents: f32,
+    tone_note: ToneNote,
+    /// The amplitude of the reference tone and the metronome, from silent at 0 to full scale at 1.
+    tone_volume: f32,
+    /// The tempo of the metronome in beats per minute.
+    metronome_bpm: f32,
+    pitch_detector: DetectorKind,
+    /// Whether CREPE picks the pitch after reinforcing the fundamental with its harmonics, which
+    /// reduces octave errors.
+    harmonic_summation: bool,
+    /// The number of bins on each side of the most activated bin that CREPE averages to calculate
+    /// the pitch, wider windows steady the pitch of noisy sources. Changes take effect after a restart.
+    pub(crate) local_average_half_width: usize,
+    /// Whether the audio is pre-emphasized before it is analyzed, which boosts the harmonics of
+    /// bass-heavy sources.
+    pre_emphasis_enabled: bool,
+    /// The coefficient `k` of the pre-emphasis `y[n] = x[n] - k * x[n - 1]`.
+    pre_emphasis_coefficient: f32,
+    /// The number of seconds of pitch history shown on the plot.
+    history_seconds: f64,
+    /// The name of the input device that was connected when the application was closed.
+    last_device_name: Option<String>,
+    /// Part of the name of the device that is connected at startup if the last used device is not
+    /// available, e.g. "USB", ignoring case. Empty to use the default device.
+    pub(crate) preferred_device_substring: String,
+    /// Paths of further CREPE models, e.g. of another capacity, that run alongside the main model.
+    /// The prediction of the most confident model is shown. Changes take effect after a restart.
+    pub(crate) ensemble_model_paths: Vec<PathBuf>,
+    /// Which channel of multi-channel input devices is analyzed.
+    input_channel: ChannelMode,
+    /// Whether audio is captured from an input device or from what an output device plays back.
+    capture_source: CaptureSource,
+    /// Whether the window is transparent, borderless and always on top to be placed over other applications.
+    overlay_mode: bool,
+    /// Whether mouse clicks pass through the overlay window while it is not focused.
+    click_through: bool,
+    /// The color theme of the window.
+    theme: Theme,
+    show_heatmap: bool,
+    /// Whether the spectrum of the audio is shown as a spectrogram next to the heatmap.
+    show_spectrogram: bool,
+    /// The levels in dBFS that are shown as the darkest and brightest color of the spectrogram.
+    spectrogram_db_range: (f32, f32),
+    /// The colors of the heatmap, the spectrogram and exported activations.
+    colormap: ColormapKind,
+    /// Whether the plot shows a labeled line at every note.
+    show_note_grid: bool,
+    /// Whether the readout shows the frequency in Hz.
+    show_hz: bool,
+    /// The number of decimals the frequency in the readout is shown with, from 0 to 3.
+    freq_decimals: usize,
+    /// Whether the readout shows the name and octave of the nearest note.
+    show_note: bool,
+    /// Whether the readout shows the MIDI number of the nearest note.
+    show_midi: bool,
+    /// Whether the readout shows how many cents the pitch is off from the nearest note.
+    show_cents: bool,
+    /// Whether the readout shows the note that was detected most often in the last second.
+    show_note_mode: bool,
+    /// The number of most recent activations shown by the heatmap.
+    heatmap_columns: usize,
+    /// The confidences at which the indicator next to the label is red and green, it is yellow halfway between.
+    confidence_color_thresholds: (f32, f32),
+    /// Whether the label keeps showing the last confident pitch, dimmed, while there is no confident pitch.
+    hold_last_confident: bool,
+    /// The number of seconds after the last confident pitch that the label is cleared anyway.
+    hold_timeout_seconds: f64,
+    /// The number of seconds a pitch measurement collects predictions for.
+    stats_capture_seconds: f64,
+    /// Whether the numbers of captured, processed and dropped frames are shown along with how
+    /// close the model is to not keeping up with the audio.
+    show_dropped_frames: bool,
+    /// Whether the confidence of the model is shown as a percentage with a bar.
+    show_confidence_meter: bool,
+    /// The highest number of times per second the window is redrawn for new predictions, lower
+    /// values use less CPU.
+    max_ui_fps: u32,
+    /// The inner size of the window in points.
+    window_size: [f32; 2],
+    /// The position of the top left corner of the window in points, `None` lets the platform place it.
+    window_pos: Option<[f32; 2]>,
+}
+
+impl Settings {
+    /// The stored window size, or the default size if the stored one is not usable.
+    pub(crate) fn restored_window_size(&self) -> [f32; 2] {
+        if self.window_size.iter().all(|size| size.is_finite() && *size >= MIN_WINDOW_SIZE) {
+            self.window_size
+        } else {
+            DEFAULT_WINDOW_SIZE
+        }
+    }
+
+    /// The stored window position, or `None` if it is not usable.
+    pub(crate) fn restored_window_pos(&self) -> Option<[f32; 2]> {
+        self.window_pos.filter(|pos| pos.iter().all(|coordinate| coordinate.is_finite()))
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            display_range: (50, 500),
+            target_range: (185, 300),
+            confidence_threshold: 0.5,
+            gate_enabled: false,
+            gate_open_threshold: 0.6,
+            gate_close_threshold: 0.4,
+            gate_attack_ms: 50.0,
+            gate_release_ms: 200.0,
+            show_onsets: false,
+            onset_sensitivity: 0.5,
+            target_color: Rgba::from(Color32::LIGHT_GREEN),
+            label_color: Rgba::from(Color32::WHITE),
+            log_to_csv: false,
+            csv_log_path: "pitch-log.csv".to_owned(),
+            recording_path: "pitch-recording.wav".to_owned(),
+            snapshot_csv_path: "pitch-snapshots.csv".to_owned(),
+            intonation_csv_path: "pitch-intonation.csv".to_owned(),
+            activation_png_path: "pitch-activation.png".to_owned(),
+            scale_template: ScaleTemplate::default(),
+            smoothing: Smoothing::None,
+            onset_confirm_frames: 0,
+            noise_floor_db: -50.0,
+            freq_min_hz: 30.0,
+            freq_max_hz: 2000.0,
+            restrict_model_range: false,
+            input_gain_db: 0.0,
+            agc_enabled: false,
+            a4_hz: DEFAULT_A4_HZ,
+            calibration_cents: 0.0,
+            display_mode: DisplayMode::default(),
+            target_note: None,
+            locked_note: None,
+            lock_tolerance_cents: IN_TUNE_CENTS,
+            tone_note: ToneNote::default(),
+            tone_volume: 0.2,
+            metronome_bpm: DEFAULT_BPM,
+            pitch_detector: DetectorKind::default(),
+            harmonic_summation: false,
+            local_average_half_width: DEFAULT_LOCAL_AVERAGE_HALF_WIDTH,
+            pre_emphasis_enabled: false,
+            pre_emphasis_coefficient: DEFAULT_PRE_EMPHASIS,
+            history_seconds: 10.0,
+            last_device_name: None,
+            preferred_device_substring: String::new(),
+            ensemble_model_paths: Vec::new(),
+            input_channel: ChannelMode::default(),
+            capture_source: CaptureSource::default(),
+            overlay_mode: false,
+            theme: Theme::default(),
+            click_through: false,
+            show_heatmap: false,
+            show_spectrogram: false,
+            spectrogram_db_range: (-100.0, -20.0),
+            colormap: ColormapKind::default(),
+            show_note_grid: true,
+            show_hz: false,
+            freq_decimals: 1,
+            show_note: false,
+            show_midi: false,
+            show_cents: false,
+            show_note_mode: false,
+            heatmap_columns: 100,
+            confidence_color_thresholds: (0.3, 0.7),
+            hold_last_confident: true,
+            hold_timeout_seconds: 3.0,
+            stats_capture_seconds: 3.0,
+            show_dropped_frames: false,
+            show_confidence_meter: false,
+            max_ui_fps: DEFAULT_MAX_UI_FPS,
+            window_size: DEFAULT_WINDOW_SIZE,
+            window_pos: None,
+        }
+    }
+}
+
+/// The format that settings are stored in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SettingsFormat {
+    Json,
+    Toml,
+}
+
+impl SettingsFormat {
+    /// The format of a settings file by its extension, TOML for `.toml` files and JSON otherwise.
+    pub(crate) fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("toml") => SettingsFormat::Toml,
+            _ => SettingsFormat::Json,
+        }
+    }
+}
+
+impl Settings {
+    /// Parses settings, e.g. from the egui storage or a config file.
+    pub(crate) fn parse(text: &str, format: SettingsFormat) -> Result<Settings, String> {
+        match format {
+            SettingsFormat::Json => serde_json::from_str(text).map_err(|e| e.to_string()),
+            SettingsFormat::Toml => toml::from_str(text).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// The inverse of [`Settings::parse`].
+    pub(crate) fn serialize(&self, format: SettingsFormat) -> Result<String, String> {
+        match format {
+            SettingsFormat::Json => serde_json::to_string_pretty(self).map_err(|e| e.to_string()),
+            SettingsFormat::Toml => toml::to_string_pretty(self).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Reads settings from a config file in the format given by its extension.
+    pub(crate) fn load(path: &Path) -> Result<Settings, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read settings from \"{}\": {}", path.display(), e))?;
+
+        Settings::parse(&text, SettingsFormat::from_path(path))
+            .map_err(|e| format!("Invalid settings in \"{}\": {}", path.display(), e))
+    }
+
+    /// Writes settings to a config file in the format given by its extension.
+    pub(crate) fn save_to(&self, path: &Path) -> Result<(), String> {
+        let text = self.serialize(SettingsFormat::from_path(path))?;
+
+        std::fs::write(path, text).map_err(|e| format!("Failed to write settings to \"{}\": {}", path.display(), e))
+    }
+}
+
+/// A single displayed pitch value in the pitch history.
+#[derive(Debug, Clone, Copy)]
+struct PitchSample {
+    /// Seconds since the start of the audio stream.
+    time: f64,
+    /// The displayed frequency, or NaN if there was no valid pitch.
+    frequency: f32,
+    /// The average confidence of the predictions the frequency was calculated from.
+    confidence: f32,
+}
+
+/// A chunk of audio that is sent to the inference worker to be analyzed.
+struct AudioFrame {
+    /// Seconds since the start of the audio stream at the end of the display step the frame belongs to.
+    step_time: f64,
+    /// Seconds since the start of the audio stream at the end of the frame.
+    time: f64,
+    /// The audio, or `None` if it was below the noise floor and is not analyzed.
+    audio: Option<[i16; crepe::SAMPLES_PER_STEP]>,
+    /// The level of the audio in dBFS.
+    level_db: f32,
+    detector: DetectorKind,
+    /// Whether the pitch is picked after reinforcing the fundamental with its harmonics.
+    harmonic_summation: bool,
+    /// The lowest and highest frequency in Hz that the pitch is picked from, `None` to pick it from
+    /// every bin of the model.
+    pitch_range: Option<(f32, f32)>,
+    /// The most recent chunk of the left and right channel in stereo mode, `None` for a channel
+    /// that was below the noise floor.
+    stereo_audio: Option<[Option<[i16; crepe::SAMPLES_PER_STEP]>; 2]>,
+}
+
+/// The model output for an [`AudioFrame`].
+struct AnalyzedFrame {
+    step_time: f64,
+    time: f64,
+    level_db: f32,
+    /// The prediction and activation, or `None` if the frame was below the noise floor.
+    output: Option<(Prediction, Activation)>,
+    /// The predictions for the left and right channel if the frame had stereo audio.
+    stereo_output: Option<[Option<Prediction>; 2]>,
+}
+
+/// Runs `detector` on the audio of `frame`, or returns the error of the detector if it failed.
+fn analyze_frame(frame: &AudioFrame, detector: &dyn PitchDetector) -> Result<AnalyzedFrame, PredictError> {
+    let output = frame.audio
+        .map(|audio| detector.try_predict(audio, frame.harmonic_summation, frame.pitch_range))
+        .transpose()?
+        .map(|(prediction, activation)| (prediction, activation.unwrap_or([0.0; ACTIVATION_BINS])));
+    let stereo_output = match frame.stereo_audio {
+        Some(channels) => {
+            let [left, right] = channels.map(|audio| audio
+                .map(|audio| detector.try_predict(audio, false, None).map(|(prediction, _)| prediction))
+                .transpose());
+            Some([left?, right?])
+        }
+        None => None,
+    };
+
+    Ok(AnalyzedFrame {
+        step_time: frame.step_time,
+        time: frame.time,
+        level_db: frame.level_db,
+        output,
+        stereo_output,
+    })
+}
+
+#[derive(Default)]
+struct AudioState {
+    first_audio_instant: Option<StreamInstant>,
+    // Collects the captured audio into display steps at the model's sample rate, set up for the
+    // sample rate of the device when connecting.
+    // Some audio backends output less than a display step per callback, so we need to aggregate
+    // some values until we have enough.
+    audio_collector: ChunkCollector<MIN_SAMPLES_PER_DISPLAY>,
+    /// Like `audio_collector`, for the left and right channel in stereo mode.
+    stereo_collectors: [ChunkCollector<{ crepe::SAMPLES_PER_STEP }>; 2],
+    /// The most recent chunk of the left and right channel since the previous display step.
+    recent_stereo_chunks: [Option<[i16; crepe::SAMPLES_PER_STEP]>; 2],
+    /// The most recent predictions for the left and right channel in stereo mode, `None` for a
+    /// channel that was below the noise floor.
+    stereo_predictions: [Option<Prediction>; 2],
+    /// The pitch shown by the label, which may be held from an earlier step.
+    displayed_pitch: HeldPitch,
+    pitch_hold: PitchHold,
+    /// The prediction for the most recent chunk of audio, or `None` if it was below the noise floor
+    /// or not confident by the gate or the confidence threshold.
+    last_prediction: Option<Prediction>,
+    pitch_history: VecDeque<PitchSample>,
+    /// The activations of the most recent audio chunks, with empty activations for silent chunks.
+    activation_history: VecDeque<Activation>,
+    /// Marks the starts of notes, `None` if onsets are not shown.
+    onset_detector: Option<OnsetDetector>,
+    /// The times of the detected note starts within the history.
+    onset_history: VecDeque<f64>,
+    /// Calculates the spectrum of every chunk of audio, `None` if the spectrogram is hidden.
+    spectrum_analyzer: Option<SpectrumAnalyzer>,
+    /// The spectra of the most recent audio chunks, including silent ones.
+    spectrum_history: VecDeque<Spectrum>,
+    csv_logger: Option<CsvLogger>,
+    /// Streams every prediction to WebSocket clients if the app was started with `--serve`.
+    #[cfg(feature = "websocket")]
+    prediction_server: Option<PredictionServer>,
+    smoother: Option<Box<dyn Smoother>>,
+    /// Decides per frame whether its pitch is shown, `None` if frames are compared with the
+    /// confidence threshold instead.
+    gate: Option<Gate>,
+    /// Delays the displayed pitch to confirm note onsets, carrying the time and confidence of each step.
+    onset_confirmer: Option<OnsetConfirmer<(f64, f32)>>,
+    /// The factor that the audio is multiplied by before it is gated and analyzed.
+    input_gain: f32,
+    /// The level in dBFS below which audio is treated as silence and not analyzed.
+    noise_floor_db: f32,
+    agc: Option<Agc>,
+    /// Filters the analyzed audio, `None` if pre-emphasis is disabled.
+    pre_emphasis: Option<PreEmphasis>,
+    /// Whether the pitch is picked after reinforcing the fundamental with its harmonics.
+    harmonic_summation: bool,
+    /// The lowest and highest frequency in Hz that the model picks the pitch from, `None` to pick
+    /// it from every bin.
+    model_pitch_range: Option<(f32, f32)>,
+    level_meter: LevelMeter,
+    confidence_meter: ConfidenceMeter,
+    wav_recorder: WavRecorder,
+    /// Set by the stream's error callback when the input device is no longer available.
+    device_lost: bool,
+    /// The analyzed frames of the display step that is not complete yet.
+    pending_frames: Vec<AnalyzedFrame>,
+    /// Collects the predictions of a running pitch measurement.
+    stats_collector: Option<StatsCollector>,
+    /// Measures the vibrato of the note that is currently held.
+    vibrato_analyzer: VibratoAnalyzer,
+    /// The vibrato of the note that is currently held, or `None` if no note with vibrato is held.
+    vibrato: Option<Vibrato>,
+    note_mode_tracker: NoteModeTracker,
+    /// Counts the offsets of confident frames from their nearest note since it was last reset.
+    cents_histogram: CentsHistogram,
+    /// Samples the pitch on the beats of the metronome, `None` if it was not started yet.
+    beat_capture: Option<BeatCapture>,
+    /// The last prediction of the previous display step, which the octave correction compares the
+    /// first prediction of the next step with, `None` if it had no pitch.
+    octave_context: Option<Prediction>,
+    /// The note that was detected most often in the recent frames, `None` if none had a confident pitch.
+    note_mode: Option<NoteMode>,
+    /// Measures the intonation of every note of a practiced scale, `None` if no scale is practiced.
+    intonation_session: Option<IntonationSession>,
+    /// The time in seconds at which the most recently analyzed frame ended.
+    analyzed_until: f64,
+    /// Whether captured audio is discarded instead of analyzed, which freezes the display.
+    paused: bool,
+    /// The time in seconds since the first audio at which the current pause started.
+    pause_started: Option<f64>,
+    /// The total number of seconds spent paused, which is left out of the times of later frames
+    /// so that the history does not show a gap for the pause.
+    paused_seconds: f64,
+}
+
+impl AudioState {
+    /// Adds audio captured at `time` seconds since the first audio, returning the frames to analyze
+    /// for every display step that was completed by it.
+    ///
+    /// Audio is discarded while paused.
+    fn push_audio(&mut self, data: &[i16], time: f64, settings: &Settings) -> Vec<AudioFrame> {
+        if self.paused {
+            self.audio_collector.clear();
+            self.stereo_collectors.iter_mut().for_each(ChunkCollector::clear);
+            self.recent_stereo_chunks = [None; 2];
+            self.pause_started.get_or_insert(time);
+            return vec![];
+        }
+        if let Some(pause_started) = self.pause_started.take() {
+            self.paused_seconds += time - pause_started;
+        }
+
+        let display_steps = self.audio_collector.push(data);
+        if display_steps.is_empty() {
+            return vec![];
+        }
+
+        // Every display step is analyzed, so that no audio is skipped when a callback brings more
+        // than one display step.
+        let stereo_audio = self.take_stereo_chunks();
+        let mut frames = Vec::new();
+        for (i, audio) in display_steps.iter().enumerate() {
+            // The callback time marks the end of the audio, so earlier steps happened before it.
+            let steps_after = display_steps.len() - 1 - i;
+            let samples_after = steps_after * MIN_SAMPLES_PER_DISPLAY + self.audio_collector.buffered();
+            let step_time = time - self.paused_seconds - samples_after as f64 / crepe::SAMPLE_RATE as f64;
+            frames.extend(self.push_display_step(*audio, step_time, settings, stereo_audio.filter(|_| steps_after == 0)));
+        }
+
+        frames
+    }
+
+    /// Applies the gain to a display step of audio ending at `step_time`, records it and returns
+    /// its frames to analyze.
+    fn push_display_step(
+        &mut self,
+        mut audio: [i16; MIN_SAMPLES_PER_DISPLAY],
+        step_time: f64,
+        settings: &Settings,
+        stereo_audio: Option<[Option<[i16; crepe::SAMPLES_PER_STEP]>; 2]>,
+    ) -> Vec<AudioFrame> {
+        apply_gain(&mut audio, self.input_gain);
+        if let Some(agc) = self.agc.as_mut() {
+            for chunk in audio.chunks_exact_mut(crepe::SAMPLES_PER_STEP) {
+                agc.process(chunk);
+            }
+        }
+        if let Err(e) = self.wav_recorder.push(&audio) {
+            println!("Error writing WAV recording: {}", e);
+        }
+
+        let level_db = audio.chunks_exact(crepe::SAMPLES_PER_STEP)
+            .map(crepe::rms_dbfs)
+            .fold(f32::NEG_INFINITY, f32::max);
+        self.level_meter.update(level_db, step_time);
+        if let Some(analyzer) = self.spectrum_analyzer.as_mut() {
+            for chunk in audio.chunks_exact(crepe::SAMPLES_PER_STEP) {
+                self.spectrum_history.push_back(analyzer.spectrum(chunk.try_into().unwrap()));
+            }
+            while self.spectrum_history.len() > MAX_HEATMAP_COLUMNS {
+                self.spectrum_history.pop_front();
+            }
+        }
+        audio.chunks_exact(crepe::SAMPLES_PER_STEP)
+            .enumerate()
+            .map(|(i, chunk)| {
+                // The step time marks the end of the audio, so earlier chunks happened before it.
+                let chunks_after = (STEPS_PER_DISPLAY - 1 - i) as f64;
+                let level_db = crepe::rms_dbfs(chunk);
+                let mut audio: [i16; crepe::SAMPLES_PER_STEP] = chunk.try_into().unwrap();
+                // Silent chunks are filtered too, so that the filter continues across every chunk.
+                if let Some(pre_emphasis) = self.pre_emphasis.as_mut() {
+                    pre_emphasis.process(&mut audio);
+                }
+                AudioFrame {
+                    step_time,
+                    time: (step_time - chunks_after * STEP_SECONDS).max(0.0),
+                    level_db,
+                    audio: Some(audio).filter(|_| level_db >= self.noise_floor_db),
+                    detector: settings.pitch_detector,
+                    harmonic_summation: self.harmonic_summation,
+                    pitch_range: self.model_pitch_range,
+                    // Only the most recent chunk of each channel is analyzed to limit the extra work.
+                    stereo_audio: stereo_audio.filter(|_| i == STEPS_PER_DISPLAY - 1),
+                }
+            })
+            .collect()
+    }
+
+    /// Adds the left and right channel of audio in stereo mode, which are analyzed along with the
+    /// frames of the next display step that [`AudioState::push_audio`] returns.
+    fn push_stereo_audio(&mut self, left: &[i16], right: &[i16]) {
+        for (i, channel) in [left, right].into_iter().enumerate() {
+            if let Some(chunk) = self.stereo_collectors[i].push(channel).pop() {
+                self.recent_stereo_chunks[i] = Some(chunk);
+            }
+        }
+    }
+
+    /// Takes the most recent chunk of both stereo channels, scaled by the same gain as the mixed
+    /// audio, or `None` if not enough stereo audio was collected.
+    fn take_stereo_chunks(&mut self) -> Option<[Option<[i16; crepe::SAMPLES_PER_STEP]>; 2]> {
+        let [Some(left), Some(right)] = std::mem::take(&mut self.recent_stereo_chunks) else {
+            return None;
+        };
+
+        let gain = self.input_gain * self.agc.as_ref().map_or(1.0, Agc::gain);
+        Some([left, right].map(|mut chunk| {
+            apply_gain(&mut chunk, gain);
+            Some(chunk).filter(|chunk| crepe::rms_dbfs(chunk) >= self.noise_floor_db)
+        }))
+    }
+
+    /// Adds a frame analyzed by the inference worker, updating the displayed pitch once all frames
+    /// of its display step have been analyzed.
+    fn push_analyzed_frame(&mut self, frame: AnalyzedFrame, settings: &Settings) {
+        if let Some(stereo_output) = frame.stereo_output {
+            self.stereo_predictions = stereo_output.map(|prediction| prediction.map(|prediction| Prediction {
+                frequency: shift_by_cents(prediction.frequency, settings.calibration_cents),
+                ..prediction
+            }));
+        }
+        // Frames may have been dropped, so a step is also complete once a frame of a later step arrives.
+        if self.pending_frames.first().is_some_and(|pending| pending.step_time != frame.step_time) {
+            self.finish_step(settings);
+        }
+        self.pending_frames.push(frame);
+        if self.pending_frames.len() >= STEPS_PER_DISPLAY {
+            self.finish_step(settings);
+        }
+    }
+
+    /// Moves single predictions of a display step that are an octave off from both of their
+    /// neighbors into their octave, see [`correct_octaves`], before they are gated by frequency.
+    ///
+    /// The first prediction is compared with the last one of the previous step. The last prediction
+    /// cannot be corrected, since the next one is not known before it is shown.
+    fn correct_step_octaves(&mut self, predictions: &mut [Option<Prediction>]) {
+        // Missing predictions are passed as invalid frequencies, which are never corrected.
+        let no_pitch = Prediction { frequency: 0.0, confidence: 0.0 };
+        let mut context = std::iter::once(self.octave_context)
+            .chain(predictions.iter().copied())
+            .map(|prediction| prediction.unwrap_or(no_pitch))
+            .collect::<Vec<Prediction>>();
+        correct_octaves(&mut context);
+        for (prediction, corrected) in predictions.iter_mut().zip(&context[1..]) {
+            if let Some(prediction) = prediction.as_mut() {
+                *prediction = *corrected;
+            }
+        }
+        self.octave_context = predictions.last().copied().flatten();
+    }
+
+    /// Ends the intonation session after the most recently analyzed frame, so that the note held
+    /// at the end is part of the report.
+    fn finish_intonation_session(&mut self) {
+        let time = self.analyzed_until;
+        if let Some(session) = self.intonation_session.as_mut() {
+            session.finish(time);
+        }
+    }
+
+    /// Combines the pending frames into a single pitch value.
+    fn finish_step(&mut self, settings: &Settings) {
+        let frames = std::mem::take(&mut self.pending_frames);
+        let Some(time) = frames.first().map(|frame| frame.step_time) else {
+            return;
+        };
+
+        for frame in &frames {
+            let activation = frame.output.map(|(_, activation)| activation).unwrap_or([0.0; ACTIVATION_BINS]);
+            self.activation_history.push_back(activation);
+            self.confidence_meter.update(frame.output.map_or(0.0, |(prediction, _)| prediction.confidence), frame.time);
+        }
+        while self.activation_history.len() > MAX_HEATMAP_COLUMNS {
+            self.activation_history.pop_front();
+        }
+        let mut predictions = frames.iter()
+            .map(|frame| frame.output.map(|(prediction, _)| Prediction {
+                frequency: shift_by_cents(prediction.frequency, settings.calibration_cents),
+                ..prediction
+            }))
+            .collect::<Vec<Option<Prediction>>>();
+        self.correct_step_octaves(&mut predictions);
+        let predictions = predictions.into_iter()
+            .map(|prediction| prediction.and_then(|prediction| gate_frequency_range(prediction, settings.freq_min_hz, settings.freq_max_hz)))
+            .collect::<Vec<Option<Prediction>>>();
+        let is_shown = frames.iter()
+            .zip(&predictions)
+            .map(|(frame, prediction)| match self.gate.as_mut() {
+                Some(gate) => gate.update(prediction.map_or(0.0, |prediction| prediction.confidence), frame.time),
+                None => prediction.is_some_and(|prediction| prediction.confidence >= settings.confidence_threshold),
+            })
+            .collect::<Vec<bool>>();
+        // Everything that only takes confident pitches gets these, so that it follows the gate too.
+        let confident_predictions = predictions.iter()
+            .zip(is_shown)
+            .map(|(prediction, is_shown)| prediction.filter(|_| is_shown))
+            .collect::<Vec<Option<Prediction>>>();
+        let valid_predictions = confident_predictions.iter()
+            .flatten()
+            .filter(|prediction|
+                prediction.frequency >= settings.display_range.0 as f32
+                    && prediction.frequency <= settings.display_range.1 as f32)
+            .collect::<Vec<&Prediction>>();
+        let (average_pitch, average_confidence) = if valid_predictions.is_empty() {
+            (f32::NAN, 0.0)
+        } else {
+            let count = valid_predictions.len() as f32;
+            (
+                valid_predictions.iter().map(|prediction| prediction.frequency).sum::<f32>() / count,
+                valid_predictions.iter().map(|prediction| prediction.confidence).sum::<f32>() / count,
+            )
+        };
+        self.last_prediction = confident_predictions.last().copied().flatten();
+        if let Some(detector) = self.onset_detector.as_mut() {
+            for (frame, prediction) in frames.iter().zip(&predictions) {
+                if detector.update(frame.level_db, prediction.map_or(0.0, |prediction| prediction.confidence), frame.time) {
+                    self.onset_history.push_back(frame.time);
+                }
+            }
+            while self.onset_history.front().is_some_and(|onset| *onset < time - MAX_HISTORY_SECONDS) {
+                self.onset_history.pop_front();
+            }
+        }
+        if let Some(collector) = self.stats_collector.as_mut() {
+            for prediction in confident_predictions.iter().flatten() {
+                collector.push(prediction);
+            }
+        }
+        for prediction in &confident_predictions {
+            let cents = prediction
+                .map(|prediction| hz_to_cents(prediction.frequency, settings.a4_hz));
+            self.vibrato_analyzer.push(cents);
+        }
+        self.vibrato = self.vibrato_analyzer.analyze();
+        for prediction in &confident_predictions {
+            self.note_mode_tracker.push(prediction.as_ref(), 0.0, settings.a4_hz);
+            self.cents_histogram.push(prediction.as_ref(), 0.0, settings.a4_hz);
+        }
+        self.note_mode = self.note_mode_tracker.mode();
+        if let Some(frame) = frames.last() {
+            self.analyzed_until = frame.time + STEP_SECONDS;
+        }
+        if let Some(session) = self.intonation_session.as_mut() {
+            for (frame, prediction) in frames.iter().zip(&confident_predictions) {
+                session.push(&prediction.unwrap_or(Prediction { frequency: 0.0, confidence: 0.0 }), frame.time);
+            }
+        }
+
+        if let Some(logger) = self.csv_logger.as_mut() {
+            for (frame, prediction) in frames.iter().zip(&predictions) {
+                let Some(prediction) = prediction else {
+                    continue;
+                };
+                if let Err(e) = logger.record(frame.time, prediction) {
+                    println!("Error writing CSV log file: {}", e);
+                }
+            }
+        }
+        #[cfg(feature = "websocket")]
+        if let Some(server) = &self.prediction_server {
+            for (frame, prediction) in frames.iter().zip(&predictions) {
+                if let Some(prediction) = prediction {
+                    server.broadcast(frame.time, prediction, settings.a4_hz);
+                }
+            }
+        }
+        let samples = match self.onset_confirmer.as_mut() {
+            Some(confirmer) => confirmer.push(average_pitch, (time, average_confidence)).into_iter().collect(),
+            None => vec![(average_pitch, (time, average_confidence))],
+        };
+        for (pitch, (time, confidence)) in samples {
+            self.push_pitch(pitch, time, confidence);
+        }
+    }
+
+    /// Shows the pitch of a display step at `time`, NaN if it had no pitch.
+    fn push_pitch(&mut self, mut pitch: f32, time: f64, confidence: f32) {
+        if let Some(filter) = self.smoother.as_mut().filter(|_| !pitch.is_nan()) {
+            pitch = filter.push_with_confidence(pitch, confidence);
+        }
+        if let Some(capture) = self.beat_capture.as_mut() {
+            capture.push(time, Some(pitch).filter(|pitch| !pitch.is_nan()));
+        }
+        self.pitch_history.push_back(PitchSample {
+            time,
+            frequency: pitch,
+            confidence: if pitch.is_nan() { 0.0 } else { confidence },
+        });
+        while self.pitch_history.front().is_some_and(|sample| sample.time < time - MAX_HISTORY_SECONDS) {
+            self.pitch_history.pop_front();
+        }
+        self.displayed_pitch = self.pitch_hold.update(Some(pitch).filter(|pitch| !pitch.is_nan()), time);
+    }
+
+    /// Shows the steps that are still delayed for onset confirmation, e.g. when no more audio follows.
+    fn flush_onset_confirmer(&mut self) {
+        let samples = self.onset_confirmer.as_mut().map(OnsetConfirmer::flush).unwrap_or_default();
+        for (pitch, (time, confidence)) in samples {
+            self.push_pitch(pitch, time, confidence);
+        }
+    }
+}
+
+pub(crate) struct PitchOverlayApp {
+    current_stream: Option<Stream>,
+    current_device_index: Option<usize>,
+    available_input_devices: Vec<Device>,
+
+    audio_state: Arc<RwLock<AudioState>>,
+    /// Runs the model on its own thread so that slow predictions neither block the audio callback nor the UI.
+    inference_worker: InferenceWorker<AudioFrame, AnalyzedFrame>,
+    /// Whether the CREPE model was loaded, otherwise only the autocorrelation detector is available.
+    has_crepe_model: bool,
+    reconnector: Reconnector,
+    /// The configured maximum redraw rate, shared with the inference worker which requests the
+    /// redraws for new predictions.
+    max_ui_fps: Arc<AtomicU32>,
+    /// The time at which the running pitch measurement ends.
+    stats_capture_end: Option<Instant>,
+    reference_tone: Option<ReferenceTone>,
+    metronome: Option<Metronome>,
+    settings: Settings,
+    /// The config file that the settings were loaded from, which they are kept in instead of the
+    /// egui storage, if any.
+    config_path: Option<PathBuf>,
+    /// Whether the settings are written back to `config_path` on exit.
+    save_config: bool,
+
+    window_state: WindowState,
+}
+
+impl PitchOverlayApp {
+    /// Creates the app, where `crepe_detector` is the detector used when CREPE is chosen in the
+    /// settings, usually the loaded [`pitch_overlay::crepe::CrepeModel`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the detector does not take chunks of [`crepe::SAMPLES_PER_STEP`] samples at
+    /// [`crepe::SAMPLE_RATE`], which the audio is captured in.
+    pub(crate) fn new(ctx: &Context, input_devices: Vec<Device>, crepe_detector: Option<Box<dyn PitchDetector>>, settings: Settings) -> Self {
+        if let Some(detector) = &crepe_detector {
+            assert_eq!(detector.sample_rate(), crepe::SAMPLE_RATE, "detector must take audio at the model's sample rate");
+            assert_eq!(detector.samples_per_step(), crepe::SAMPLES_PER_STEP, "detector must take chunks of the model's size");
+        }
+        let has_crepe_model = crepe_detector.is_some();
+        let yin_detector: Box<dyn PitchDetector> = Box::new(YinDetector::new());
+        let cloned_ctx = ctx.clone();
+        let max_ui_fps = Arc::new(AtomicU32::new(settings.max_ui_fps));
+        let worker_max_ui_fps = Arc::clone(&max_ui_fps);
+        let inference_worker = InferenceWorker::spawn(
+            INFERENCE_QUEUE_FRAMES,
+            move |frame: AudioFrame| {
+                let detector = match (&crepe_detector, frame.detector) {
+                    (Some(crepe_detector), DetectorKind::Crepe) => crepe_detector,
+                    _ => &yin_detector,
+                };
+
+                analyze_frame(&frame, detector.as_ref()).unwrap_or_else(|e| {
+                    // The frame is shown as having no pitch, so that the display keeps running.
+                    println!("Error analyzing audio: {}", e);
+                    AnalyzedFrame {
+                        step_time: frame.step_time,
+                        time: frame.time,
+                        level_db: frame.level_db,
+                        output: None,
+                        stereo_output: None,
+                    }
+                })
+            },
+            move || cloned_ctx.request_repaint_after(repaint_interval(worker_max_ui_fps.load(Ordering::Relaxed))),
+        );
+        let mut app = Self {
+            current_stream: None,
+            current_device_index: None,
+            available_input_devices: input_devices,
+
+            audio_state: Arc::new(RwLock::new(AudioState::default())),
+            inference_worker,
+            has_crepe_model,
+            reconnector: Reconnector::new(),
+            config_path: None,
+            save_config: false,
+            max_ui_fps,
+            stats_capture_end: None,
+            reference_tone: None,
+            metronome: None,
+            settings,
+
+            window_state: WindowState::default(),
+        };
+        if app.settings.log_to_csv {
+            app.start_csv_logging();
+        }
+        if !has_crepe_model {
+            app.settings.pitch_detector = DetectorKind::Yin;
+        }
+        app.update_smoothing();
+        app.update_onset_confirmation();
+        app.update_gate();
+        app.update_spectrogram();
+        app.update_onset_detector();
+        app.update_pitch_hold();
+        app.update_gain();
+        app.update_noise_floor();
+        app.update_pre_emphasis();
+        app.update_harmonic_summation();
+        app.update_model_pitch_range();
+        if app.settings.capture_source != CaptureSource::default() {
+            app.apply_capture_source();
+        }
+        app.connect_startup_device(ctx);
+        app.apply_overlay_mode(ctx);
+        app.apply_theme(ctx);
+
+        app
+    }
+
+    fn apply_overlay_mode(&self, ctx: &Context) {
+        if self.settings.overlay_mode {
+            println!("Overlay mode enabled, the window stays opaque if the platform does not support transparent windows.");
+        }
+        ctx.send_viewport_cmd(ViewportCommand::Decorations(!self.settings.overlay_mode));
+        self.update_window_level(ctx);
+    }
+
+    fn apply_theme(&self, ctx: &Context) {
+        ctx.set_visuals(self.settings.theme.visuals(ctx.system_theme()));
+    }
+
+    fn restore_window_geometry(&self, ctx: &Context) {
+        let [width, height] = self.settings.restored_window_size();
+        ctx.send_viewport_cmd(ViewportCommand::InnerSize(vec2(width, height)));
+        if let Some([x, y]) = self.settings.restored_window_pos() {
+            ctx.send_viewport_cmd(ViewportCommand::OuterPosition(pos2(x, y)));
+        }
+    }
+
+    /// Remembers the current size and position of the window so that they are saved with the settings.
+    fn update_window_geometry(&mut self, ctx: &Context) {
+        ctx.input(|input| {
+            let viewport = input.viewport();
+            // A minimized window reports a meaningless size.
+            if viewport.minimized == Some(true) {
+                return;
+            }
+            if let Some(rect) = viewport.inner_rect {
+                self.settings.window_size = [rect.width(), rect.height()];
+            }
+            if let Some(rect) = viewport.outer_rect {
+                self.settings.window_pos = Some([rect.left(), rect.top()]);
+            }
+        });
+    }
+
+    /// Lets the mouse pass through the window in click-through mode while it is not focused.
+    ///
+    /// The window does not receive any mouse input while the mouse passes through it, so it has to
+    /// stay interactive while focused for the user to be able to move it or turn click-through off.
+    fn update_mouse_passthrough(&mut self, ctx: &Context) {
+        let is_focused = ctx.input(|input| input.viewport().focused.unwrap_or(true));
+        let is_mouse_passthrough = self.settings.overlay_mode && self.settings.click_through && !is_focused;
+        if is_mouse_passthrough != self.window_state.is_mouse_passthrough {
+            self.window_state.is_mouse_passthrough = is_mouse_passthrough;
+            ctx.send_viewport_cmd(ViewportCommand::MousePassthrough(is_mouse_passthrough));
+        }
+    }
+
+    fn update_window_level(&self, ctx: &Context) {
+        let new_level = if self.window_state.is_always_on_top || self.settings.overlay_mode {
+            WindowLevel::AlwaysOnTop
+        } else {
+            WindowLevel::Normal
+        };
+        ctx.send_viewport_cmd(ViewportCommand::WindowLevel(new_level))
+    }
+
+    /// Connects to the last used device, or else to the preferred device, the default device or
+    /// the first device, in this order.
+    fn connect_startup_device(&mut self, ctx: &Context) {
+        // The device is remembered, so that it is connected once it is plugged in and the devices are reloaded.
+        if self.available_input_devices.is_empty() {
+            println!("No input devices found, plug in a device and reload the devices.");
+            return;
+        }
+
+        let names = self.available_input_devices.iter()
+            .map(|device| device.name().unwrap_or_default())
+            .collect::<Vec<String>>();
+        let default_device_name = self.settings.capture_source.default_device()
+            .and_then(|device| device.name().ok());
+        let Some((index, choice)) = select_device(
+            &names,
+            self.settings.last_device_name.as_deref(),
+            Some(self.settings.preferred_device_substring.as_str()),
+            default_device_name.as_deref(),
+        ) else {
+            return;
+        };
+        if let Some(last_device_name) = self.settings.last_device_name.as_deref().filter(|name| *name != names[index]) {
+            println!("Last used device \"{}\" is not available anymore.", last_device_name);
+        }
+        println!("Using {} device \"{}\".", choice.description(), names[index]);
+        self.connect(index, ctx);
+    }
+
+    fn device_index(&self, name: &str) -> Option<usize> {
+        self.available_input_devices.iter()
+            .position(|device| device.name().is_ok_and(|device_name| device_name == name))
+    }
+
+    fn reload_devices(&mut self) {
+        let current_device_name = self.current_device().and_then(|device| device.name().ok());
+        match self.settings.capture_source.devices() {
+            Ok(devices) => self.available_input_devices = devices,
+            Err(e) => {
+                println!("Error getting devices to capture from: {}", e);
+                self.window_state.error_message = Some(format!("Error getting devices to capture from: {}", e));
+                return;
+            }
+        }
+
+        // Indices may have shifted, so find the connected device again by its name.
+        self.current_device_index = current_device_name.and_then(|name| self.device_index(&name));
+        if self.current_device_index.is_none() {
+            self.current_stream = None;
+        }
+    }
+
+    fn start_csv_logging(&mut self) {
+        match CsvLogger::new(Path::new(&self.settings.csv_log_path), self.settings.a4_hz) {
+            Ok(logger) => {
+                println!("Logging pitch to {}", self.settings.csv_log_path);
+                self.audio_state.write().unwrap().csv_logger = Some(logger);
+            }
+            Err(e) => {
+                self.settings.log_to_csv = false;
+
+                println!("Error creating CSV log file: {}", e);
+                self.window_state.error_message = Some(format!("Error creating CSV log file: {}", e));
+            }
+        }
+    }
+
+    fn update_smoothing(&mut self) {
+        self.audio_state.write().unwrap().smoother = self.settings.smoothing.smoother();
+    }
+
+    fn run_shortcut(&mut self, action: ShortcutAction) {
+        match action {
+            ShortcutAction::ToggleRecording => {
+                if self.audio_state.read().unwrap().wav_recorder.is_recording() {
+                    self.stop_recording();
+                } else {
+                    self.start_recording();
+                }
+            }
+            ShortcutAction::TogglePause => self.toggle_pause(),
+            ShortcutAction::CycleDisplayMode => self.settings.display_mode = self.settings.display_mode.next(),
+            ShortcutAction::ClearHold => {
+                self.update_pitch_hold();
+                let mut audio_state = self.audio_state.write().unwrap();
+                if matches!(audio_state.displayed_pitch, HeldPitch::Held(_)) {
+                    audio_state.displayed_pitch = HeldPitch::None;
+                }
+            }
+        }
+    }
+
+    /// Starts collecting predictions for the number of seconds set in the settings, after which
+    /// their statistics are shown.
+    fn start_stats_capture(&mut self, ctx: &Context) {
+        let duration = Duration::from_secs_f64(self.settings.stats_capture_seconds);
+        // Only confident predictions are pushed, so the collector needs no threshold of its own.
+        self.audio_state.write().unwrap().stats_collector = Some(StatsCollector::new(0.0, self.settings.a4_hz));
+        self.stats_capture_end = Some(Instant::now() + duration);
+        ctx.request_repaint_after(duration);
+    }
+
+    /// Streams every prediction to the clients of `server`, if any.
+    #[cfg(feature = "websocket")]
+    pub(crate) fn with_prediction_server(self, server: Option<PredictionServer>) -> Self {
+        self.audio_state.write().unwrap().prediction_server = server;
+        self
+    }
+
+    /// Keeps the settings in the config file at `path` instead of the egui storage, writing them
+    /// back to it when the app exits if `save_on_exit` is true.
+    pub(crate) fn with_config_path(mut self, path: PathBuf, save_on_exit: bool) -> Self {
+        self.config_path = Some(path);
+        self.save_config = save_on_exit;
+        self
+    }
+
+    /// Draws the locked note display for the most recent confident pitch.
+    fn show_lock_note_display(&self, ui: &mut Ui) {
+        let prediction = self.audio_state.read().unwrap().last_prediction;
+        let locked_note = self.settings.locked_note;
+        let cents_error = locked_note.zip(prediction)
+            .map(|(locked_note, prediction)| locked_note.cents_error(prediction.frequency, self.settings.a4_hz));
+        lock_note_display(ui, locked_note.map(NoteSpec::note), cents_error, self.settings.lock_tolerance_cents, Color32::from(self.settings.label_color));
+    }
+
+    /// Shows the statistics of the running pitch measurement once it has ended.
+    fn update_stats_capture(&mut self) {
+        if !self.stats_capture_end.is_some_and(|end| Instant::now() >= end) {
+            return;
+        }
+
+        self.stats_capture_end = None;
+        let collector = self.audio_state.write().unwrap().stats_collector.take();
+        self.window_state.pitch_stats = collector.and_then(|collector| collector.stats());
+        self.window_state.are_stats_open = true;
+    }
+
+    /// The current pitch with the time it was detected at, `None` if the label does not show a live
+    /// pitch. A live pitch is always confident by the gate or the confidence threshold.
+    fn current_reading(&self) -> Option<(f64, Prediction)> {
+        let audio_state = self.audio_state.read().unwrap();
+        if !matches!(audio_state.displayed_pitch, HeldPitch::Live(_)) {
+            return None;
+        }
+
+        audio_state.pitch_history.back()
+            .map(|sample| (sample.time, Prediction { frequency: sample.frequency, confidence: sample.confidence }))
+    }
+
+    /// The note that the reference tone plays when it is started now, `None` if there is none.
+    fn reference_tone_note(&self) -> Option<NoteSpec> {
+        match self.settings.tone_note {
+            ToneNote::Nearest => self.current_reading()
+                .map(|(_, prediction)| NoteSpec::nearest(prediction.frequency, self.settings.a4_hz)),
+            ToneNote::Target => self.settings.target_note,
+        }
+    }
+
+    fn toggle_reference_tone(&mut self) {
+        if self.reference_tone.take().is_some() {
+            return;
+        }
+        let Some(note) = self.reference_tone_note() else {
+            return;
+        };
+
+        match ReferenceTone::start(note, self.settings.a4_hz, self.settings.tone_volume) {
+            Ok(tone) => self.reference_tone = Some(tone),
+            Err(e) => {
+                println!("Error playing reference tone: {}", e);
+                self.window_state.error_message = Some(e);
+            }
+        }
+    }
+
+    /// Starts the metronome along with sampling the pitch on its beats, or stops both.
+    fn toggle_metronome(&mut self) {
+        if self.metronome.take().is_some() {
+            if let Some(capture) = self.audio_state.write().unwrap().beat_capture.as_mut() {
+                capture.stop();
+            }
+            return;
+        }
+
+        // The readings of the previous run are cleared until the first click of this one is played.
+        self.audio_state.write().unwrap().beat_capture = None;
+        match Metronome::start(self.settings.metronome_bpm, self.settings.tone_volume, Arc::clone(&self.audio_state)) {
+            Ok(metronome) => {
+                self.metronome = Some(metronome);
+            }
+            Err(e) => {
+                println!("Error playing metronome: {}", e);
+                self.window_state.error_message = Some(e);
+            }
+        }
+    }
+
+    /// Applies changes of the target note, the reference frequency and the volume to the playing tone.
+    fn update_reference_tone(&mut self) {
+        let Some(tone) = self.reference_tone.as_mut() else {
+            return;
+        };
+        if let Some(target) = self.settings.target_note.filter(|_| self.settings.tone_note == ToneNote::Target) {
+            tone.note = target;
+        }
+        tone.control.set_frequency(tone.note.frequency(self.settings.a4_hz));
+        tone.control.set_volume(self.settings.tone_volume);
+    }
+
+    /// Adds the current pitch to the list of captured readings if it is confident.
+    fn capture_snapshot(&mut self) {
+        let Some((time, prediction)) = self.current_reading() else {
+            return;
+        };
+        if self.window_state.snapshots.capture(time, &prediction, 0.0, self.settings.a4_hz) {
+            self.window_state.are_snapshots_open = true;
+        }
+    }
+
+    /// Stores the current pitch as the reference to compare with, or clears the stored reference.
+    fn toggle_stored_reference(&mut self) {
+        if self.window_state.stored_reference_hz.take().is_some() {
+            return;
+        }
+
+        self.window_state.stored_reference_hz = self.current_reading()
+            .map(|(_, prediction)| prediction.frequency);
+    }
+
+    fn export_snapshots(&mut self) {
+        match self.window_state.snapshots.export_csv(Path::new(&self.settings.snapshot_csv_path)) {
+            Ok(()) => println!("Exported captured readings to {}", self.settings.snapshot_csv_path),
+            Err(e) => {
+                println!("Error exporting captured readings: {}", e);
+                self.window_state.error_message = Some(format!("Error exporting captured readings: {}", e));
+            }
+        }
+    }
+
+    /// Writes the latest activation, or the activations shown by the heatmap if `whole_heatmap`
+    /// is set, to the PNG file of the settings.
+    fn export_activations(&mut self, whole_heatmap: bool) {
+        let activations = {
+            let audio_state = self.audio_state.read().unwrap();
+            let history = &audio_state.activation_history;
+            let count = if whole_heatmap { self.settings.heatmap_columns } else { 1 };
+            history.iter().skip(history.len().saturating_sub(count)).copied().collect::<Vec<Activation>>()
+        };
+        let column_width = if whole_heatmap { 1 } else { SINGLE_ACTIVATION_WIDTH };
+        let path = Path::new(&self.settings.activation_png_path);
+        match save_activations(path, &activations, column_width, self.settings.a4_hz, self.settings.colormap.colormap()) {
+            Ok(()) => println!("Exported activation to {}", self.settings.activation_png_path),
+            Err(e) => {
+                println!("Error exporting activation: {}", e);
+                self.window_state.error_message = Some(format!("Error exporting activation: {}", e));
+            }
+        }
+    }
+
+    fn export_intonation(&mut self) {
+        let result = {
+            let mut audio_state = self.audio_state.write().unwrap();
+            audio_state.finish_intonation_session();
+            match &audio_state.intonation_session {
+                Some(session) => session.export_csv(Path::new(&self.settings.intonation_csv_path)),
+                None => return,
+            }
+        };
+        match result {
+            Ok(()) => println!("Exported intonation report to {}", self.settings.intonation_csv_path),
+            Err(e) => {
+                println!("Error exporting intonation report: {}", e);
+                self.window_state.error_message = Some(format!("Error exporting intonation report: {}", e));
+            }
+        }
+    }
+
+    /// Stops or resumes analyzing audio, the stream keeps running so that resuming is instant.
+    fn toggle_pause(&mut self) {
+        let mut audio_state = self.audio_state.write().unwrap();
+        audio_state.paused = !audio_state.paused;
+        if audio_state.paused {
+            audio_state.flush_onset_confirmer();
+        }
+    }
+
+    fn update_onset_confirmation(&mut self) {
+        let mut audio_state = self.audio_state.write().unwrap();
+        audio_state.flush_onset_confirmer();
+        audio_state.onset_confirmer = (self.settings.onset_confirm_frames > 0)
+            .then(|| OnsetConfirmer::new(self.settings.onset_confirm_frames));
+    }
+
+    fn update_spectrogram(&mut self) {
+        let mut audio_state = self.audio_state.write().unwrap();
+        if !self.settings.show_spectrogram {
+            audio_state.spectrum_history.clear();
+        }
+        audio_state.spectrum_analyzer = self.settings.show_spectrogram.then(SpectrumAnalyzer::new);
+    }
+
+    fn update_onset_detector(&mut self) {
+        let mut audio_state = self.audio_state.write().unwrap();
+        if !self.settings.show_onsets {
+            audio_state.onset_history.clear();
+        }
+        audio_state.onset_detector = self.settings.show_onsets.then(|| OnsetDetector::new(self.settings.onset_sensitivity));
+    }
+
+    fn update_gate(&mut self) {
+        let settings = &self.settings;
+        self.audio_state.write().unwrap().gate = settings.gate_enabled.then(|| Gate::new(
+            settings.gate_open_threshold,
+            settings.gate_close_threshold.min(settings.gate_open_threshold),
+            settings.gate_attack_ms,
+            settings.gate_release_ms,
+        ));
+    }
+
+    fn update_gain(&mut self) {
+        let mut audio_state = self.audio_state.write().unwrap();
+        audio_state.input_gain = db_to_gain(self.settings.input_gain_db);
+        if !self.settings.agc_enabled {
+            audio_state.agc = None;
+        } else if audio_state.agc.is_none() {
+            audio_state.agc = Some(Agc::new(DEFAULT_TARGET_RMS, AGC_ATTACK, AGC_RELEASE));
+        }
+    }
+
+    fn update_noise_floor(&mut self) {
+        self.audio_state.write().unwrap().noise_floor_db = self.settings.noise_floor_db;
+    }
+
+    fn update_pre_emphasis(&mut self) {
+        self.audio_state.write().unwrap().pre_emphasis = self.settings.pre_emphasis_enabled
+            .then(|| PreEmphasis::new(self.settings.pre_emphasis_coefficient));
+    }
+
+    fn update_harmonic_summation(&mut self) {
+        self.audio_state.write().unwrap().harmonic_summation = self.settings.harmonic_summation;
+    }
+
+    fn update_model_pitch_range(&mut self) {
+        let settings = &self.settings;
+        self.audio_state.write().unwrap().model_pitch_range = Some((settings.freq_min_hz, settings.freq_max_hz))
+            .filter(|_| settings.restrict_model_range);
+    }
+
+    fn update_pitch_hold(&mut self) {
+        let timeout = if self.settings.hold_last_confident { self.settings.hold_timeout_seconds } else { 0.0 };
+        self.audio_state.write().unwrap().pitch_hold = PitchHold::new(timeout);
+    }
+
+    fn stop_csv_logging(&mut self) {
+        if let Some(logger) = self.audio_state.write().unwrap().csv_logger.take() {
+            if let Err(e) = logger.finish() {
+                println!("Error writing CSV log file: {}", e);
+            }
+        }
+    }
+
+    /// Connects to the input device at `index` and starts analyzing its audio.
+    fn connect(&mut self, index: usize, ctx: &Context) {
+        self.current_stream = None;
+        self.current_device_index = None;
+        let device_name = self.available_input_devices[index].name().unwrap_or("Unknown device".to_owned());
+
+        let cloned_arc = Arc::clone(&self.audio_state);
+        let error_arc = Arc::clone(&self.audio_state);
+        let cloned_ctx = ctx.clone();
+        let frame_sender = self.inference_worker.sender();
+        let settings = self.settings.clone();
+        let (sample_rate, channels) = self.settings.capture_source.capture_format(&self.available_input_devices[index]);
+        {
+            let mut audio_state = error_arc.write().unwrap();
+            audio_state.flush_onset_confirmer();
+            audio_state.device_lost = false;
+            audio_state.stereo_predictions = [None; 2];
+            audio_state.vibrato_analyzer = VibratoAnalyzer::default();
+            audio_state.vibrato = None;
+            audio_state.note_mode_tracker = NoteModeTracker::default();
+            audio_state.note_mode = None;
+            audio_state.octave_context = None;
+            audio_state.audio_collector = ChunkCollector::new(sample_rate);
+            audio_state.stereo_collectors = std::array::from_fn(|_| ChunkCollector::new(sample_rate));
+            audio_state.recent_stereo_chunks = [None; 2];
+        }
+
+        match self.available_input_devices[index].build_input_stream(
+            &stream_config(sample_rate, channels),
+            move |data: &[i16], info| {
+                let instant = info.timestamp().callback;
+
+                let mut audio_state = cloned_arc.write().unwrap();
+                if audio_state.first_audio_instant.is_none() {
+                    audio_state.first_audio_instant = Some(instant);
+                    println!("Updated first audio timestamp");
+                }
+
+                let since_start = instant.duration_since(&audio_state.first_audio_instant.unwrap()).unwrap_or(Duration::ZERO);
+                if settings.input_channel == ChannelMode::Stereo && !audio_state.paused {
+                    let left = downmix(data, channels as usize, ChannelMode::Left);
+                    let right = downmix(data, channels as usize, ChannelMode::Right);
+                    audio_state.push_stereo_audio(&left, &right);
+                }
+                let data = downmix(data, channels as usize, settings.input_channel);
+                for frame in audio_state.push_audio(&data, since_start.as_secs_f64(), &settings) {
+                    frame_sender.submit(frame);
+                }
+            },
+            move |err| {
+                println!("Error: {:?}", err);
+                if matches!(err, StreamError::DeviceNotAvailable) {
+                    error_arc.write().unwrap().device_lost = true;
+                    cloned_ctx.request_repaint();
+                }
+            },
+            None,
+        ) {
+            Err(e) => {
+                self.current_stream = None;
+                self.current_device_index = None;
+
+                println!("Error creating input stream: {}", e);
+                self.window_state.error_message = Some(format!("Error creating input stream: {}", e));
+            }
+            Ok(stream) => {
+                match stream.play() {
+                    Err(e) => {
+                        self.current_stream = None;
+                        self.current_device_index = None;
+
+                        println!("Error starting input stream: {}", e);
+                        self.window_state.error_message = Some(format!("Error starting input stream: {}", e));
+                    }
+                    Ok(_) => {
+                        println!("Started audio stream.");
+                        self.reconnector.connected();
+                        self.current_stream = Some(stream);
+                        self.current_device_index = Some(index);
+                        self.settings.last_device_name = Some(device_name);
+                    }
+                }
+            }
+        }
+    }
+
+    fn disconnect(&mut self) {
+        self.current_stream = None;
+        self.audio_state.write().unwrap().flush_onset_confirmer();
+        self.current_device_index = None;
+        self.settings.last_device_name = None;
+        self.reconnector.connected();
+    }
+
+    /// Replaces all settings with their defaults and applies them, which also connects to the
+    /// default input device.
+    fn reset_settings(&mut self, ctx: &Context) {
+        println!("Resetting settings to defaults.");
+        self.stop_csv_logging();
+        self.disconnect();
+        self.settings = Settings::default();
+        if !self.has_crepe_model {
+            self.settings.pitch_detector = DetectorKind::Yin;
+        }
+        self.update_smoothing();
+        self.update_onset_confirmation();
+        self.update_gate();
+        self.update_spectrogram();
+        self.update_onset_detector();
+        self.update_pitch_hold();
+        self.update_gain();
+        self.update_noise_floor();
+        self.update_pre_emphasis();
+        self.update_harmonic_summation();
+        self.update_model_pitch_range();
+        self.max_ui_fps.store(self.settings.max_ui_fps, Ordering::Relaxed);
+        self.apply_capture_source();
+        self.apply_overlay_mode(ctx);
+        self.apply_theme(ctx);
+        self.restore_window_geometry(ctx);
+        self.connect_startup_device(ctx);
+    }
+
+    /// Switches the device list to the devices of the current capture source, falling back to
+    /// input devices if the source is not supported on this platform.
+    fn apply_capture_source(&mut self) {
+        if !self.settings.capture_source.is_supported() {
+            self.settings.capture_source = CaptureSource::Input;
+
+            println!("Loopback capture is not supported on this platform, capturing input devices instead.");
+            self.window_state.error_message = Some("Loopback capture is not supported on this platform, capturing input devices instead.".to_owned());
+        }
+        self.reload_devices();
+    }
+
+    /// Drops the stream of a lost input device and tries to reconnect to it, or to the default
+    /// device, with increasing delays between attempts.
+    fn handle_device_loss(&mut self, ctx: &Context) {
+        let now = Instant::now();
+        if std::mem::take(&mut self.audio_state.write().unwrap().device_lost) {
+            println!("Input device lost, trying to reconnect.");
+            self.current_stream = None;
+            self.current_device_index = None;
+            self.reconnector.device_lost(now);
+        }
+        if self.reconnector.should_attempt(now) {
+            self.reload_devices();
+            let names = self.available_input_devices.iter()
+                .map(|device| device.name().unwrap_or_default())
+                .collect::<Vec<String>>();
+            let default_device_name = self.settings.capture_source.default_device()
+                .and_then(|device| device.name().ok());
+            let index = reconnect_target(&names, self.settings.last_device_name.as_deref(), default_device_name.as_deref())
+                .and_then(|name| self.device_index(name));
+            if let Some(index) = index {
+                self.connect(index, ctx);
+            }
+            if self.current_stream.is_none() {
+                self.reconnector.attempt_failed(now);
+            }
+        }
+        if let Some(delay) = self.reconnector.time_until_attempt(now) {
+            ctx.request_repaint_after(delay);
+        }
+    }
+
+    fn start_recording(&mut self) {
+        match self.audio_state.write().unwrap().wav_recorder.start(Path::new(&self.settings.recording_path)) {
+            Ok(()) => println!("Recording audio to {}", self.settings.recording_path),
+            Err(e) => {
+                println!("Error creating WAV recording: {}", e);
+                self.window_state.error_message = Some(format!("Error creating WAV recording: {}", e));
+            }
+        }
+    }
+
+    fn stop_recording(&mut self) {
+        if let Err(e) = self.audio_state.write().unwrap().wav_recorder.stop() {
+            println!("Error writing WAV recording: {}", e);
+        }
+    }
+
+    /// Takes the frames that the inference worker analyzed since the last update into the pitch history.
+    fn process_analyzed_frames(&mut self) {
+        let frames = self.inference_worker.poll();
+        if frames.is_empty() {
+            return;
+        }
+
+        let mut audio_state = self.audio_state.write().unwrap();
+        for frame in frames {
+            audio_state.push_analyzed_frame(frame, &self.settings);
+        }
+    }
+
+    fn current_device(&self) -> Option<&Device> {
+        if let Some(i) = self.current_device_index {
+            return Some(&self.available_input_devices[i]);
+        }
+
+        None
+    }
+}
+
+impl eframe::App for PitchOverlayApp {
+    fn update(&mut self, ctx: &Context, frame: &mut Frame) {
+        self.process_analyzed_frames();
+        self.handle_device_loss(ctx);
+        self.update_stats_capture();
+        // Text fields need the keys themselves while they are focused.
+        if !ctx.wants_keyboard_input() {
+            for action in ctx.input(|input| shortcut_actions(&input.events)) {
+                self.run_shortcut(action);
+            }
+        }
+
+        let mut csv_logging_changed = false;
+        let mut input_channel_changed = false;
+        let mut pitch_detector_changed = false;
+        let mut capture_source_changed = false;
+        let mut overlay_mode_changed = false;
+        let mut theme_changed = false;
+        let mut smoothing_changed = false;
+        let mut onset_confirmation_changed = false;
+        let mut gate_changed = false;
+        let mut spectrogram_changed = false;
+        let mut onsets_changed = false;
+        let mut pitch_hold_changed = false;
+        let mut gain_changed = false;
+        let mut noise_floor_changed = false;
+        let mut pre_emphasis_changed = false;
+        let mut harmonic_summation_changed = false;
+        let mut model_pitch_range_changed = false;
+        let mut reset_requested = false;
+        if self.window_state.are_settings_open {
+            egui::Window::new("Settings")
+                .collapsible(false)
+                .open(&mut self.window_state.are_settings_open)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut self.settings.display_mode, DisplayMode::Plot, "Pitch plot");
+                        ui.radio_value(&mut self.settings.display_mode, DisplayMode::Tuner, "Tuner");
+                        ui.radio_value(&mut self.settings.display_mode, DisplayMode::Practice, "Practice");
+                        ui.radio_value(&mut self.settings.display_mode, DisplayMode::LockNote, "Locked note").on_hover_ui(|ui| {
+                            ui.label("Only shows whether the pitch is flat, in tune or sharp relative to one note, the controls appear while hovering the window");
+                        });
+                    });
+                    ui.add_space(20.0);
+
+                    overlay_mode_changed = ui.checkbox(&mut self.settings.overlay_mode, "Overlay mode").on_hover_ui(|ui| {
+                        ui.label("Makes the window transparent, borderless and always on top, transparency is not supported on all platforms");
+                    }).changed();
+                    ui.add_enabled(self.settings.overlay_mode, egui::Checkbox::new(&mut self.settings.click_through, "Click-through")).on_hover_ui(|ui| {
+                        ui.label("Lets mouse clicks pass through the overlay to the window below while the overlay is not focused. Focus it from the taskbar or with Alt+Tab to use it again");
+                    });
+                    let previous_theme = self.settings.theme;
+                    ui.horizontal(|ui| {
+                        ui.label("Theme");
+                        ui.radio_value(&mut self.settings.theme, Theme::System, "System");
+                        ui.radio_value(&mut self.settings.theme, Theme::Dark, "Dark");
+                        ui.radio_value(&mut self.settings.theme, Theme::Light, "Light");
+                    });
+                    theme_changed = self.settings.theme != previous_theme;
+                    ui.add_space(20.0);
+
+                    let previous_pitch_detector = self.settings.pitch_detector;
+                    ui.horizontal(|ui| {
+                        ui.add_enabled_ui(self.has_crepe_model, |ui| {
+                            ui.radio_value(&mut self.settings.pitch_detector, DetectorKind::Crepe, "CREPE");
+                        }).response.on_hover_ui(|ui| {
+                            ui.label("Accurate neural network, needs the model file");
+                        });
+                        ui.radio_value(&mut self.settings.pitch_detector, DetectorKind::Yin, "Autocorrelation").on_hover_ui(|ui| {
+                            ui.label("Much cheaper to run, but more prone to octave errors and noise");
+                        });
+                    });
+                    pitch_detector_changed = self.settings.pitch_detector != previous_pitch_detector;
+                    let is_crepe = self.settings.pitch_detector == DetectorKind::Crepe;
+                    harmonic_summation_changed = ui.add_enabled(is_crepe, egui::Checkbox::new(&mut self.settings.harmonic_summation, "Reduce octave errors")).on_hover_ui(|ui| {
+                        ui.label("Reinforces the fundamental with its harmonics before picking the pitch, which helps when the detected pitch jumps an octave too high");
+                    }).changed();
+                    ui.add_enabled(is_crepe, egui::Slider::new(&mut self.settings.local_average_half_width, 0..=10).text("Averaged bins")).on_hover_ui(|ui| {
+                        ui.label("How many bins on each side of the peak are averaged, more steady the pitch of noisy sources, fewer are more accurate for clear tones. Takes effect after a restart");
+                    });
+                    ui.horizontal(|ui| {
+                        pre_emphasis_changed |= ui.checkbox(&mut self.settings.pre_emphasis_enabled, "Pre-emphasis").on_hover_ui(|ui| {
+                            ui.label("Boosts high frequencies before the pitch is detected, which can help with quiet or bass-heavy sources");
+                        }).changed();
+                        pre_emphasis_changed |= ui.add_enabled(
+                            self.settings.pre_emphasis_enabled,
+                            egui::Slider::new(&mut self.settings.pre_emphasis_coefficient, 0.0..=0.99).text("Coefficient"),
+                        ).changed();
+                    });
+                    ui.add_space(20.0);
+
+                    let previous_capture_source = self.settings.capture_source;
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut self.settings.capture_source, CaptureSource::Input, "Input device");
+                        ui.add_enabled_ui(CaptureSource::Loopback.is_supported(), |ui| {
+                            ui.radio_value(&mut self.settings.capture_source, CaptureSource::Loopback, "System audio");
+                        }).response.on_hover_ui(|ui| {
+                            ui.label("Analyzes what an output device plays back, only supported on Windows");
+                        });
+                    });
+                    capture_source_changed = self.settings.capture_source != previous_capture_source;
+                    let previous_input_channel = self.settings.input_channel;
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_id_salt("Input channel")
+                            .selected_text(match self.settings.input_channel {
+                                ChannelMode::Mono => "Mono".to_owned(),
+                                ChannelMode::Left => "Left".to_owned(),
+                                ChannelMode::Right => "Right".to_owned(),
+                                ChannelMode::Index(index) => format!("Channel {}", index + 1),
+                                ChannelMode::Stereo => "Compare left and right".to_owned(),
+                            })
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut self.settings.input_channel, ChannelMode::Mono, "Mono");
+                                ui.selectable_value(&mut self.settings.input_channel, ChannelMode::Left, "Left");
+                                ui.selectable_value(&mut self.settings.input_channel, ChannelMode::Right, "Right");
+                                ui.selectable_value(&mut self.settings.input_channel, ChannelMode::Stereo, "Compare left and right").on_hover_ui(|ui| {
+                                    ui.label("Also shows the pitch of the left and right channel separately and how fast they beat against each other, e.g. to tune two instruments to each other");
+                                });
+                                if ui.selectable_label(matches!(self.settings.input_channel, ChannelMode::Index(_)), "Channel number").clicked() {
+                                    self.settings.input_channel = ChannelMode::Index(0);
+                                }
+                            });
+                        if let ChannelMode::Index(index) = &mut self.settings.input_channel {
+                            // Channels are numbered from one for display.
+                            let mut number = *index + 1;
+                            ui.add(egui::DragValue::new(&mut number).range(1..=32));
+                            *index = number - 1;
+                        }
+                        ui.label("Input channel").on_hover_ui(|ui| {
+                            ui.label("Channel of multi-channel devices to analyze, mono averages all channels");
+                        });
+                    });
+                    input_channel_changed = self.settings.input_channel != previous_input_channel;
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut self.settings.preferred_device_substring);
+                        ui.label("Preferred device").on_hover_ui(|ui| {
+                            ui.label("Part of the device name, e.g. \"USB\", to connect at startup if the last used device is not available");
+                        });
+                    });
+                    ui.add_enabled(!self.settings.gate_enabled, egui::Slider::new(&mut self.settings.confidence_threshold, 0.0..=1.0).text("Pitch confidence threshold"));
+                    gate_changed |= ui.checkbox(&mut self.settings.gate_enabled, "Gate with hysteresis").on_hover_ui(|ui| {
+                        ui.label("Opens at a higher confidence than it closes at and waits before switching, which keeps the pitch from flickering at the ends of phrases");
+                    }).changed();
+                    ui.add_enabled_ui(self.settings.gate_enabled, |ui| {
+                        gate_changed |= ui.add(egui::Slider::new(&mut self.settings.gate_open_threshold, 0.0..=1.0).text("Open above confidence")).changed();
+                        gate_changed |= ui.add(egui::Slider::new(&mut self.settings.gate_close_threshold, 0.0..=self.settings.gate_open_threshold).text("Close below confidence")).changed();
+                        gate_changed |= ui.add(egui::Slider::new(&mut self.settings.gate_attack_ms, 0.0..=500.0).text("Attack (ms)")).changed();
+                        gate_changed |= ui.add(egui::Slider::new(&mut self.settings.gate_release_ms, 0.0..=2000.0).text("Release (ms)")).changed();
+                    });
+                    noise_floor_changed |= ui.add(egui::Slider::new(&mut self.settings.noise_floor_db, -100.0..=0.0).text("Noise floor (dBFS)")).on_hover_ui(|ui| {
+                        ui.label("Audio quieter than this is treated as silence and not analyzed");
+                    }).changed();
+                    let meter = self.audio_state.read().unwrap().level_meter;
+                    noise_floor_changed |= noise_floor_editor(ui, &meter, &mut self.settings.noise_floor_db).on_hover_ui(|ui| {
+                        ui.label(format!("Input level: {:.0} dBFS. Drag the red line just above the level of the background noise while nothing is played", meter.level_db()));
+                    }).changed();
+                    ui.horizontal(|ui| {
+                        let input_gain_response = ui.add(egui::Slider::new(&mut self.settings.input_gain_db, -20.0..=40.0).text("Input gain (dB)")).on_hover_ui(|ui| {
+                            ui.label("Amplifies quiet sources before the noise floor is applied and the audio is recorded");
+                        });
+                        let agc_response = ui.checkbox(&mut self.settings.agc_enabled, "Automatic").on_hover_ui(|ui| {
+                            ui.label("Automatically scales the audio toward a constant level after the input gain");
+                        });
+                        gain_changed = input_gain_response.changed() || agc_response.changed();
+                    });
+                    let low_confidence_response = ui.add(egui::Slider::new(&mut self.settings.confidence_color_thresholds.0, 0.0..=0.99).text("Red below confidence")).on_hover_ui(|ui| {
+                        ui.label("Confidence at which the indicator next to the pitch label is red");
+                    });
+                    let high_confidence_response = ui.add(egui::Slider::new(&mut self.settings.confidence_color_thresholds.1, 0.01..=1.0).text("Green above confidence")).on_hover_ui(|ui| {
+                        ui.label("Confidence at which the indicator next to the pitch label is green");
+                    });
+                    if low_confidence_response.changed() | high_confidence_response.changed() {
+                        let (lower, upper) = &mut self.settings.confidence_color_thresholds;
+                        if lower >= upper {
+                            *upper = (*lower + 0.01).min(1.0);
+                            *lower = *upper - 0.01;
+                        }
+                    }
+                    ui.add_space(20.0);
+
+                    ui.horizontal(|ui| {
+                        // TODO: right-align these, looks weird
+                        egui::widgets::color_picker::color_edit_button_rgba(ui, &mut self.settings.target_color, Alpha::BlendOrAdditive);
+                        ui.add(Label::new("Target region color")).on_hover_ui(|ui| {
+                            ui.label("Color of the target region on the plot");
+                        });
+                    });
+                    ui.horizontal(|ui| {
+                        egui::widgets::color_picker::color_edit_button_rgba(ui, &mut self.settings.label_color, Alpha::BlendOrAdditive);
+                        ui.add(Label::new("Pitch label color")).on_hover_ui(|ui| {
+                            ui.label("Color in the center of the plot of the label that displays your current pitch");
+                        })
+                    });
+                    ui.add_space(20.0);
+
+                    ui.add(egui::Slider::new(&mut self.settings.a4_hz, 400.0..=480.0).text("A4 reference (Hz)")).on_hover_ui(|ui| {
+                        ui.label("Frequency of the note A4 that note names are calculated from, usually 440 Hz");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Reference tone").on_hover_ui(|ui| {
+                            ui.label("The note that \"Play tone\" plays on the default output device");
+                        });
+                        ui.radio_value(&mut self.settings.tone_note, ToneNote::Nearest, "Nearest note");
+                        ui.radio_value(&mut self.settings.tone_note, ToneNote::Target, "Practice target");
+                        ui.add(egui::Slider::new(&mut self.settings.tone_volume, 0.0..=1.0).text("volume"));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(egui::Slider::new(&mut self.settings.calibration_cents, -100.0..=100.0).text("Calibration (cents)")).on_hover_ui(|ui| {
+                            ui.label("Shifts every detected pitch to correct a known bias of the microphone or audio interface");
+                        });
+                        let live_pitch = match self.audio_state.read().unwrap().displayed_pitch {
+                            HeldPitch::Live(frequency) => Some(frequency),
+                            _ => None,
+                        };
+                        let calibrate_response = ui.add_enabled(live_pitch.is_some(), egui::Button::new("Snap to nearest note")).on_hover_ui(|ui| {
+                            ui.label("Sets the calibration so that the current pitch is exactly on the closest note, play a reference note first");
+                        });
+                        if let Some(frequency) = live_pitch.filter(|_| calibrate_response.clicked()) {
+                            self.settings.calibration_cents -= frequency_to_note(frequency, self.settings.a4_hz).cents_offset;
+                        }
+                    });
+                    ui.add(egui::Slider::new(&mut self.settings.history_seconds, 2.0..=MAX_HISTORY_SECONDS).text("History length (s)")).on_hover_ui(|ui| {
+                        ui.label("Number of seconds of pitch history shown on the graph");
+                    });
+                    ui.add_space(20.0);
+
+                    let min_display_response = ui.add(egui::Slider::new(&mut self.settings.display_range.0, 0..=499).text("Min display")).on_hover_ui(|ui| {
+                        ui.label("Minimum frequency to display on the graph.");
+                    });
+                    let max_display_response = ui.add(egui::Slider::new(&mut self.settings.display_range.1, 1..=500).text("Max display")).on_hover_ui(|ui| {
+                        ui.label("Maximum frequency to display on the graph");
+                    });
+                    let display_range_changed = min_display_response.changed()
+                        | max_display_response.changed();
+                    if display_range_changed {
+                        let (lower, upper) = &mut self.settings.display_range;
+                        if lower >= upper {
+                            *upper = *lower + 1;
+                        }
+                    }
+
+                    let min_frequency_response = ui.add(egui::Slider::new(&mut self.settings.freq_min_hz, 30.0..=2000.0).logarithmic(true).text("Min pitch (Hz)")).on_hover_ui(|ui| {
+                        ui.label("Detected pitches below this are ignored, e.g. the lowest note of your instrument");
+                    });
+                    let max_frequency_response = ui.add(egui::Slider::new(&mut self.settings.freq_max_hz, 30.0..=2000.0).logarithmic(true).text("Max pitch (Hz)")).on_hover_ui(|ui| {
+                        ui.label("Detected pitches above this are ignored, which suppresses spurious high octaves");
+                    });
+                    if min_frequency_response.changed() || max_frequency_response.changed() {
+                        self.settings.freq_max_hz = self.settings.freq_max_hz.max(self.settings.freq_min_hz);
+                    }
+                    let restrict_response = ui.add_enabled(is_crepe, egui::Checkbox::new(&mut self.settings.restrict_model_range, "Restrict the model to this range")).on_hover_ui(|ui| {
+                        ui.label("Picks the pitch only from the part of the model output between the min and max pitch, so that a strong harmonic or hum outside of it cannot win");
+                    });
+                    model_pitch_range_changed = min_frequency_response.changed() || max_frequency_response.changed() || restrict_response.changed();
+
+                    // TODO: these only show tooltips when the slider itself is hovered, while the color setting shows its tooltip when the label is hovered, that's inconsistent.
+                    let min_target_response = ui.add(egui::Slider::new(&mut self.settings.target_range.0, 0..=499).text("Min target")).on_hover_ui(|ui| {
+                        ui.label("Minimum frequency you are aiming for");
+                    });
+                    let max_target_response = ui.add(egui::Slider::new(&mut self.settings.target_range.1, 1..=500).text("Max target")).on_hover_ui(|ui| {
+                        ui.label("Maximum frequency you are aiming for");
+                    });
+                    let target_range_changed = min_target_response.changed()
+                        | max_target_response.changed();
+                    if target_range_changed {
+                        let (lower, upper) = &mut self.settings.target_range;
+                        if lower >= upper {
+                            *upper = *lower + 1;
+                        }
+                    }
+                    ui.add_space(20.0);
+
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.settings.show_heatmap, "Show heatmap").on_hover_ui(|ui| {
+                            ui.label("Shows how strongly each pitch is detected over time, which helps to spot octave errors");
+                        });
+                        ui.add_enabled(self.settings.show_heatmap || self.settings.show_spectrogram, egui::Slider::new(&mut self.settings.heatmap_columns, 10..=MAX_HEATMAP_COLUMNS).text("columns"));
+                    });
+                    ui.horizontal(|ui| {
+                        let has_activation = !self.audio_state.read().unwrap().activation_history.is_empty();
+                        ui.text_edit_singleline(&mut self.settings.activation_png_path);
+                        if ui.add_enabled(has_activation, egui::Button::new("Export activation")).on_hover_ui(|ui| {
+                            ui.label("Saves the latest activation of the model as a PNG image, pause first to pick the moment");
+                        }).clicked() {
+                            self.export_activations(false);
+                        }
+                        if ui.add_enabled(has_activation, egui::Button::new("Export heatmap")).on_hover_ui(|ui| {
+                            ui.label("Saves the activations shown by the heatmap as a PNG image");
+                        }).clicked() {
+                            self.export_activations(true);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        spectrogram_changed = ui.checkbox(&mut self.settings.show_spectrogram, "Show spectrogram").on_hover_ui(|ui| {
+                            ui.label("Shows the spectrum of the audio over time, which helps to understand what the pitch detector reacts to");
+                        }).changed();
+                        let (lower, upper) = &mut self.settings.spectrogram_db_range;
+                        ui.add_enabled(self.settings.show_spectrogram, egui::DragValue::new(lower).range(-150.0..=-10.0).suffix(" dB"));
+                        ui.label("to");
+                        ui.add_enabled(self.settings.show_spectrogram, egui::DragValue::new(upper).range(-140.0..=0.0).suffix(" dB"));
+                        if *lower >= *upper {
+                            *upper = *lower + 10.0;
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_id_salt("Colormap")
+                            .selected_text(colormap_name(self.settings.colormap))
+                            .show_ui(ui, |ui| {
+                                for kind in ColormapKind::ALL {
+                                    ui.selectable_value(&mut self.settings.colormap, kind, colormap_name(kind));
+                                }
+                            });
+                        ui.label("Colormap").on_hover_ui(|ui| {
+                            ui.label("The colors of the heatmap, the spectrogram and exported activations, viridis and magma are readable with color blindness");
+                        });
+                    });
+                    ui.checkbox(&mut self.settings.show_note_grid, "Show note grid").on_hover_ui(|ui| {
+                        ui.label("Draws a labeled line at every note of the graph, natural notes more prominently than sharps");
+                    });
+                    ui.horizontal(|ui| {
+                        onsets_changed |= ui.checkbox(&mut self.settings.show_onsets, "Mark note starts").on_hover_ui(|ui| {
+                            ui.label("Draws a tick below the graph wherever the level and confidence rise suddenly, e.g. when a note is sung again");
+                        }).changed();
+                        onsets_changed |= ui.add_enabled(self.settings.show_onsets, egui::Slider::new(&mut self.settings.onset_sensitivity, 0.0..=1.0).text("Sensitivity")).changed();
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Readout").on_hover_ui(|ui| {
+                            ui.label("Shows the pitch in the chosen formats next to the display");
+                        });
+                        ui.checkbox(&mut self.settings.show_hz, "Hz");
+                        ui.add_enabled(self.settings.show_hz, egui::DragValue::new(&mut self.settings.freq_decimals).range(0..=MAX_FREQ_DECIMALS).suffix(" decimals"));
+                        ui.checkbox(&mut self.settings.show_note, "Note");
+                        ui.checkbox(&mut self.settings.show_midi, "MIDI");
+                        ui.checkbox(&mut self.settings.show_cents, "Cents");
+                        ui.checkbox(&mut self.settings.show_note_mode, "Most likely note").on_hover_ui(|ui| {
+                            ui.label("The note that was detected most often in the last second, with a bar for how often");
+                        });
+                    });
+                    ui.add_space(20.0);
+
+                    let previous_smoothing = self.settings.smoothing;
+                    ui.horizontal(|ui| {
+                        ui.label("Smoothing").on_hover_ui(|ui| {
+                            ui.label("Median removes short jitter, EMA glides smoothly between pitches, adaptive smooths held notes but follows slides and runs closely, confidence-weighted lets unsure values move the pitch less");
+                        });
+                        ui.radio_value(&mut self.settings.smoothing, Smoothing::None, "None");
+                        if ui.radio(matches!(self.settings.smoothing, Smoothing::Median(_)), "Median").clicked() {
+                            self.settings.smoothing = Smoothing::Median(5);
+                        }
+                        if ui.radio(matches!(self.settings.smoothing, Smoothing::Ema(_)), "EMA").clicked() {
+                            self.settings.smoothing = Smoothing::Ema(0.3);
+                        }
+                        if ui.radio(matches!(self.settings.smoothing, Smoothing::Adaptive { .. }), "Adaptive").clicked() {
+                            self.settings.smoothing = Smoothing::Adaptive {
+                                slow_alpha: 0.1,
+                                fast_alpha: 0.8,
+                                slope_threshold_cents: 30.0,
+                            };
+                        }
+                        if ui.radio(matches!(self.settings.smoothing, Smoothing::ConfidenceWeighted(_)), "Confidence-weighted").clicked() {
+                            self.settings.smoothing = Smoothing::ConfidenceWeighted(0.5);
+                        }
+                    });
+                    match &mut self.settings.smoothing {
+                        Smoothing::None => {}
+                        Smoothing::Median(window) => {
+                            ui.add(egui::Slider::new(window, 2..=15).text("values"));
+                        }
+                        Smoothing::Ema(alpha) => {
+                            ui.add(egui::Slider::new(alpha, 0.05..=1.0).text("weight of new values"));
+                        }
+                        Smoothing::Adaptive { slow_alpha, fast_alpha, slope_threshold_cents } => {
+                            ui.add(egui::Slider::new(slow_alpha, 0.05..=1.0).text("weight on held notes"));
+                            ui.add(egui::Slider::new(fast_alpha, 0.05..=1.0).text("weight during fast changes"));
+                            ui.add(egui::Slider::new(slope_threshold_cents, 5.0..=200.0).text("fast above cents per step"));
+                        }
+                        Smoothing::ConfidenceWeighted(alpha) => {
+                            ui.add(egui::Slider::new(alpha, 0.05..=1.0).text("weight of fully confident values"));
+                        }
+                    }
+                    smoothing_changed = self.settings.smoothing != previous_smoothing;
+                    onset_confirmation_changed = ui.add(egui::Slider::new(&mut self.settings.onset_confirm_frames, 0..=5).text("Onset confirmation steps")).on_hover_ui(|ui| {
+                        ui.label("Only shows a new note once it was held for this many steps, which hides wrong pitches at note onsets but delays the display");
+                    }).changed();
+                    ui.horizontal(|ui| {
+                        let hold_response = ui.checkbox(&mut self.settings.hold_last_confident, "Hold last pitch").on_hover_ui(|ui| {
+                            ui.label("Keeps showing the last confident pitch, dimmed, during pauses");
+                        });
+                        let timeout_response = ui.add_enabled(self.settings.hold_last_confident, egui::Slider::new(&mut self.settings.hold_timeout_seconds, 0.5..=10.0).text("seconds"));
+                        pitch_hold_changed = hold_response.changed() || timeout_response.changed();
+                    });
+                    ui.add(egui::Slider::new(&mut self.settings.stats_capture_seconds, 1.0..=10.0).text("Measurement length (s)")).on_hover_ui(|ui| {
+                        ui.label("Number of seconds the Measure button collects the pitch for");
+                    });
+                    ui.add_space(20.0);
+
+                    csv_logging_changed = ui.checkbox(&mut self.settings.log_to_csv, "Log pitch to CSV file").on_hover_ui(|ui| {
+                        ui.label("Writes every pitch prediction to a CSV file for later analysis");
+                    }).changed();
+                    ui.horizontal(|ui| {
+                        ui.add_enabled(!self.settings.log_to_csv, egui::TextEdit::singleline(&mut self.settings.csv_log_path));
+                        ui.label("CSV file path");
+                    });
+                    ui.checkbox(&mut self.settings.show_confidence_meter, "Show confidence meter").on_hover_ui(|ui| {
+                        ui.label("Shows how confident the pitch detector is as a percentage with a bar colored like the indicator next to the pitch");
+                    });
+                    ui.checkbox(&mut self.settings.show_dropped_frames, "Show frame statistics").on_hover_ui(|ui| {
+                        ui.label("Shows how many frames of audio were skipped because the model could not keep up, and the real-time factor of the model, which has to stay below 1");
+                    });
+                    if ui.add(egui::Slider::new(&mut self.settings.max_ui_fps, 5..=120).text("Max redraws per second")).on_hover_ui(|ui| {
+                        ui.label("Lower values use less CPU while the window is left running, higher values update the pitch more smoothly");
+                    }).changed() {
+                        self.max_ui_fps.store(self.settings.max_ui_fps, Ordering::Relaxed);
+                    }
+                    ui.add_space(20.0);
+
+                    ui.horizontal(|ui| {
+                        let is_recording = self.audio_state.read().unwrap().wav_recorder.is_recording();
+                        ui.add_enabled(!is_recording, egui::TextEdit::singleline(&mut self.settings.recording_path));
+                        ui.label("Recording file path").on_hover_ui(|ui| {
+                            ui.label("WAV file that the analyzed audio is recorded to, at the model's sample rate");
+                        });
+                    });
+                    ui.add_space(20.0);
+
+                    if self.window_state.is_reset_confirmation_open {
+                        ui.horizontal(|ui| {
+                            ui.label("Reset all settings?");
+                            if ui.button("Reset").clicked() {
+                                reset_requested = true;
+                                self.window_state.is_reset_confirmation_open = false;
+                            }
+                            if ui.button("Cancel").clicked() {
+                                self.window_state.is_reset_confirmation_open = false;
+                            }
+                        });
+                    } else if ui.button("Reset to defaults").clicked() {
+                        self.window_state.is_reset_confirmation_open = true;
+                    }
+                });
+        }
+        if smoothing_changed {
+            self.update_smoothing();
+        }
+        if onset_confirmation_changed {
+            self.update_onset_confirmation();
+        }
+        if gate_changed {
+            self.update_gate();
+        }
+        if spectrogram_changed {
+            self.update_spectrogram();
+        }
+        if onsets_changed {
+            self.update_onset_detector();
+        }
+        if pitch_hold_changed {
+            self.update_pitch_hold();
+        }
+        if gain_changed {
+            self.update_gain();
+        }
+        if noise_floor_changed {
+            self.update_noise_floor();
+        }
+        if pre_emphasis_changed {
+            self.update_pre_emphasis();
+        }
+        if harmonic_summation_changed {
+            self.update_harmonic_summation();
+        }
+        if model_pitch_range_changed {
+            self.update_model_pitch_range();
+        }
+        self.update_reference_tone();
+        if overlay_mode_changed {
+            self.apply_overlay_mode(ctx);
+        }
+        if theme_changed {
+            self.apply_theme(ctx);
+        }
+        self.update_mouse_passthrough(ctx);
+        self.update_window_geometry(ctx);
+        if capture_source_changed {
+            self.disconnect();
+            self.apply_capture_source();
+        }
+        if let Some(index) = self.current_device_index.filter(|_| input_channel_changed || pitch_detector_changed) {
+            // The channel and detector are chosen when the stream is created, so reconnect to apply them.
+            self.connect(index, ctx);
+        }
+        if csv_logging_changed {
+            if self.settings.log_to_csv {
+                self.start_csv_logging();
+            } else {
+                self.stop_csv_logging();
+            }
+        }
+        if reset_requested {
+            self.reset_settings(ctx);
+            if let Some(storage) = frame.storage_mut() {
+                self.save(storage);
+            }
+        }
+
+        if let Some(message) = self.window_state.error_message.clone() {
+            egui::Window::new("Error")
+                .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+                .auto_sized()
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    ui.label(message);
+                    ui.vertical_centered(|ui| {
+                        if ui.button("Ok").clicked() {
+                            self.window_state.error_message = None;
+                        }
+                    });
+                });
+        }
+
+        if self.window_state.are_stats_open {
+            egui::Window::new("Pitch statistics")
+                .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+                .auto_sized()
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    match self.window_state.pitch_stats {
+                        Some(stats) => {
+                            ui.label(format!("Average pitch: {:.1}Hz", stats.mean_hz));
+                            ui.label(format!(
+                                "Nearest note: {}{} {:+.0} cents",
+                                stats.nearest_note.name, stats.nearest_note.octave, stats.mean_cents_offset,
+                            ));
+                            ui.label(format!("Standard deviation: {:.1} cents", stats.cents_std));
+                            ui.label(format!("Predictions: {}", stats.frame_count));
+                        }
+                        None => {
+                            ui.label("No confident pitch was detected during the measurement.");
+                        }
+                    }
+                    ui.vertical_centered(|ui| {
+                        if ui.button("Ok").clicked() {
+                            self.window_state.are_stats_open = false;
+                        }
+                    });
+                });
+        }
+
+        if self.window_state.are_snapshots_open {
+            let mut export_requested = false;
+            egui::Window::new("Captured readings")
+                .collapsible(false)
+                .open(&mut self.window_state.are_snapshots_open)
+                .show(ctx, |ui| {
+                    let snapshots = &mut self.window_state.snapshots;
+                    egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                        if snapshots.is_empty() {
+                            ui.label("No readings captured yet.");
+                            return;
+                        }
+                        egui::Grid::new("Captured readings grid").striped(true).show(ui, |ui| {
+                            ui.strong("Time");
+                            ui.strong("Note");
+                            ui.strong("Frequency");
+                            ui.strong("Offset");
+                            ui.end_row();
+                            for snapshot in snapshots.snapshots() {
+                                ui.label(format!("{:.1}s", snapshot.time_s));
+                                ui.label(format!("{}{}", snapshot.note.name, snapshot.note.octave));
+                                ui.label(format!("{:.1}Hz", snapshot.frequency));
+                                ui.label(format!("{:+.0} cents", snapshot.note.cents_offset));
+                                ui.end_row();
+                            }
+                        });
+                    });
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut self.settings.snapshot_csv_path);
+                        ui.label("CSV file path");
+                    });
+                    ui.horizontal(|ui| {
+                        export_requested = ui.add_enabled(!snapshots.is_empty(), egui::Button::new("Export CSV")).clicked();
+                        if ui.button("Clear all").clicked() {
+                            snapshots.clear();
+                        }
+                    });
+                });
+            if export_requested {
+                self.export_snapshots();
+            }
+        }
+
+        if self.window_state.is_cents_histogram_open {
+            egui::Window::new("Cents histogram")
+                .collapsible(false)
+                .open(&mut self.window_state.is_cents_histogram_open)
+                .show(ctx, |ui| {
+                    let mut audio_state = self.audio_state.write().unwrap();
+                    let histogram = &audio_state.cents_histogram;
+                    let bars = (0..CENTS_BINS)
+                        .map(|bin| {
+                            let center = CentsHistogram::bin_center(bin);
+                            Bar::new(center as f64, histogram.counts()[bin] as f64)
+                                .width(CENTS_BIN_WIDTH as f64 * 0.9)
+                                .fill(cents_error_color(center))
+                        })
+                        .collect();
+                    Plot::new("Cents histogram plot")
+                        .height(150.0)
+                        .allow_drag(false)
+                        .allow_zoom(false)
+                        .allow_scroll(false)
+                        .include_x(-50.0)
+                        .include_x(50.0)
+                        .include_y(0.0)
+                        .x_axis_label("Cents")
+                        .show(ui, |plot_ui| plot_ui.bar_chart(BarChart::new(bars)));
+                    ui.label(tendency_summary(histogram));
+                    if ui.add_enabled(histogram.total() > 0, egui::Button::new("Reset")).clicked() {
+                        audio_state.cents_histogram.clear();
+                    }
+                });
+        }
+
+        if self.window_state.is_metronome_open {
+            let mut toggle_requested = false;
+            egui::Window::new("Metronome")
+                .collapsible(false)
+                .open(&mut self.window_state.is_metronome_open)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.add_enabled(self.metronome.is_none(), egui::DragValue::new(&mut self.settings.metronome_bpm).range(20.0..=300.0).suffix(" BPM"));
+                        toggle_requested = ui.button(if self.metronome.is_some() { "Stop" } else { "Start" }).clicked();
+                    });
+                    let audio_state = self.audio_state.read().unwrap();
+                    match audio_state.beat_capture.as_ref() {
+                        Some(capture) => beat_row(ui, capture.readings(), self.settings.a4_hz),
+                        None => {
+                            ui.label("Start the metronome to see how many cents the pitch is off on every beat");
+                        }
+                    }
+                });
+            if toggle_requested {
+                self.toggle_metronome();
+            }
+        }
+        // Closing the window stops the metronome.
+        if !self.window_state.is_metronome_open && self.metronome.is_some() {
+            self.toggle_metronome();
+        }
+
+        if self.window_state.is_intonation_open {
+            let mut export_requested = false;
+            let mut stop_requested = false;
+            egui::Window::new("Scale report")
+                .collapsible(false)
+                .open(&mut self.window_state.is_intonation_open)
+                .show(ctx, |ui| {
+                    let audio_state = self.audio_state.read().unwrap();
+                    let Some(session) = audio_state.intonation_session.as_ref() else {
+                        ui.label("Pick a target note and start a scale in the practice display.");
+                        return;
+                    };
+                    match session.next_expected().map(NoteSpec::note) {
+                        None => ui.label("Scale complete!"),
+                        Some(_) if session.is_finished() => ui.label("Scale stopped"),
+                        Some(note) => ui.label(format!("Play {}{} next", note.name, note.octave)),
+                    };
+                    egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                        egui::Grid::new("Scale report grid").striped(true).show(ui, |ui| {
+                            ui.strong("Expected");
+                            ui.strong("Played");
+                            ui.strong("Average");
+                            ui.strong("Stability");
+                            ui.strong("Duration");
+                            ui.end_row();
+                            for result in session.results() {
+                                let (expected, played) = (result.expected.note(), result.played.note());
+                                ui.label(format!("{}{}", expected.name, expected.octave));
+                                ui.label(format!("{}{}", played.name, played.octave));
+                                ui.label(format!("{:+.0} cents", result.mean_cents));
+                                ui.label(format!("±{:.0} cents", result.stability_cents));
+                                ui.label(format!("{:.1}s", result.duration_s));
+                                ui.end_row();
+                            }
+                        });
+                    });
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut self.settings.intonation_csv_path);
+                        ui.label("CSV file path");
+                    });
+                    ui.horizontal(|ui| {
+                        export_requested = ui.add_enabled(!session.results().is_empty(), egui::Button::new("Export CSV")).clicked();
+                        stop_requested = ui.add_enabled(!session.is_finished(), egui::Button::new("Stop")).clicked();
+                    });
+                });
+            if export_requested {
+                self.export_intonation();
+            }
+            if stop_requested {
+                self.audio_state.write().unwrap().finish_intonation_session();
+            }
+        }
+
+        if self.settings.show_heatmap || self.settings.show_spectrogram {
+            egui::TopBottomPanel::bottom("Heatmap")
+                .resizable(true)
+                .default_height(100.0)
+                .show(ctx, |ui| {
+                    let audio_state = self.audio_state.read().unwrap();
+                    let mut show_heatmap = |ui: &mut Ui| heatmap(
+                        ui,
+                        &mut self.window_state.heatmap_texture,
+                        &audio_state.activation_history,
+                        self.settings.heatmap_columns,
+                        self.settings.display_range,
+                        self.settings.a4_hz,
+                        self.settings.colormap.colormap(),
+                    );
+                    let mut show_spectrogram = |ui: &mut Ui| spectrogram(
+                        ui,
+                        &mut self.window_state.spectrogram_texture,
+                        &audio_state.spectrum_history,
+                        self.settings.heatmap_columns,
+                        self.settings.spectrogram_db_range,
+                        self.settings.colormap.colormap(),
+                    );
+                    match (self.settings.show_heatmap, self.settings.show_spectrogram) {
+                        (true, true) => ui.columns(2, |columns| {
+                            show_heatmap(&mut columns[0]);
+                            show_spectrogram(&mut columns[1]);
+                        }),
+                        (true, false) => show_heatmap(ui),
+                        _ => show_spectrogram(ui),
+                    }
+                });
+        }
+
+        let readout_frequency = match self.audio_state.read().unwrap().displayed_pitch {
+            HeldPitch::Live(frequency) | HeldPitch::Held(frequency) => Some(frequency),
+            HeldPitch::None => None,
+        };
+        let readout_lines = readout_lines(readout_frequency, &self.settings);
+        if !readout_lines.is_empty() || self.settings.show_note_mode {
+            let note_mode = self.audio_state.read().unwrap().note_mode;
+            egui::SidePanel::right("Readout")
+                .resizable(false)
+                .exact_width(READOUT_WIDTH)
+                .show(ctx, |ui| {
+                    if self.settings.show_note_mode {
+                        let name = note_mode.map_or("--".to_owned(), |mode| format_note(mode.note.note()));
+                        ui.label(RichText::new(name).size(28.0).color(Color32::from(self.settings.label_color)));
+                        ui.add(egui::ProgressBar::new(note_mode.map_or(0.0, |mode| mode.share)).desired_height(6.0))
+                            .on_hover_text("How often the note was detected in the last second");
+                    }
+                    for line in readout_lines {
+                        ui.label(RichText::new(line).monospace());
+                    }
+                });
+        }
+
+        let arc1 = Arc::clone(&self.audio_state);
+        let mut central_frame = egui::Frame::central_panel(&ctx.style());
+        if self.settings.overlay_mode {
+            central_frame = central_frame.fill(Color32::TRANSPARENT);
+        }
+        egui::CentralPanel::default().frame(central_frame).show(ctx, |ui| {
+            // The locked note display hides everything else unless the pointer is over the window.
+            if self.settings.display_mode == DisplayMode::LockNote && !ui.rect_contains_pointer(ui.max_rect()) {
+                self.show_lock_note_display(ui);
+                return;
+            }
+            let current_device_name = self.current_device().map(|device| device.name().unwrap_or("Unnamed device".to_owned())).unwrap_or("Audio disconnected".to_owned());
+
+            ui.horizontal_wrapped(|ui| {
+                if self.settings.overlay_mode {
+                    // The overlay window has no title bar, so this handle is used to move it instead.
+                    let handle = ui.add(Label::new(RichText::new("☰").size(16.0)).sense(Sense::drag()))
+                        .on_hover_text("Drag to move the window");
+                    if handle.drag_started() {
+                        ctx.send_viewport_cmd(ViewportCommand::StartDrag);
+                    }
+                }
+
+                let mut selected_device = None;
+                if self.available_input_devices.is_empty() {
+                    ui.label("No audio input devices found.").on_hover_ui(|ui| {
+                        ui.label("Plug in a device and press Reload devices");
+                    });
+                } else {
+                    egui::ComboBox::from_id_salt("Audio Input device")
+                        .truncate()
+                        .selected_text(current_device_name)
+                        .show_ui(ui, |ui| {
+                            if ui.selectable_label(self.current_device_index.is_none(), "Disconnect audio").clicked() {
+                                selected_device = Some(None);
+                            }
+                            for (i, device) in self.available_input_devices.iter().enumerate() {
+                                let name = device.name().unwrap_or("Unknown device".to_owned());
+                                if ui.selectable_label(self.current_device_index == Some(i), name).clicked() {
+                                    selected_device = Some(Some(i));
+                                }
+                            }
+                        });
+                }
+                match selected_device {
+                    Some(None) => {
+                        println!("Disconnect clicked!");
+                        self.disconnect();
+                    }
+                    Some(Some(i)) => {
+                        println!("Connect to new device clicked!");
+                        self.connect(i, ctx);
+                    }
+                    None => {}
+                }
+                if ui.button("Reload devices").clicked() {
+                    let had_devices = !self.available_input_devices.is_empty();
+                    self.reload_devices();
+                    if !had_devices {
+                        self.connect_startup_device(ctx);
+                    }
+                }
+                if self.current_device_index.is_some() {
+                    let meter = self.audio_state.read().unwrap().level_meter;
+                    level_meter(ui, &meter, self.settings.noise_floor_db).on_hover_ui(|ui| {
+                        ui.label(format!("Input level: {:.0} dBFS, audio below the red noise floor line is not analyzed", meter.level_db()));
+                    });
+                }
+                if self.current_device_index.is_some() && self.settings.show_confidence_meter {
+                    let meter = self.audio_state.read().unwrap().confidence_meter;
+                    confidence_meter(ui, &meter, self.settings.confidence_color_thresholds).on_hover_ui(|ui| {
+                        ui.label(format!("Confidence of the pitch detector, the white line is the recent peak of {}", format_confidence(meter.peak())));
+                    });
+                }
+                if self.reconnector.is_lost() {
+                    ui.colored_label(Color32::YELLOW, "Input device lost, reconnecting...");
+                }
+                if self.settings.show_dropped_frames {
+                    let metrics = self.inference_worker.metrics();
+                    let frame_duration = Duration::from_secs_f64(STEP_SECONDS);
+                    let color = if metrics.real_time_factor(frame_duration) > 1.0 { Color32::YELLOW } else { ui.visuals().text_color() };
+                    ui.colored_label(color, metrics.status_line(frame_duration)).on_hover_ui(|ui| {
+                        ui.label("Above a real-time factor of 1 the model cannot keep up, choose a smaller model capacity or the autocorrelation detector");
+                    });
+                }
+                match self.stats_capture_end {
+                    Some(end) => {
+                        ui.label(format!("Measuring... {:.0}s", end.saturating_duration_since(Instant::now()).as_secs_f32().ceil()));
+                        ctx.request_repaint_after(Duration::from_millis(200));
+                    }
+                    None => {
+                        let measure_response = ui.add_enabled(self.current_device_index.is_some(), egui::Button::new("Measure")).on_hover_ui(|ui| {
+                            ui.label("Collects the pitch of a held note for a few seconds and shows how far it is off on average");
+                        });
+                        if measure_response.clicked() {
+                            self.start_stats_capture(ctx);
+                        }
+                    }
+                }
+                let can_capture = self.current_reading().is_some();
+                let capture_response = ui.add_enabled(can_capture, egui::Button::new("Capture")).on_hover_ui(|ui| {
+                    ui.label("Adds the current note to a list of readings, e.g. to review every string after tuning");
+                });
+                if capture_response.clicked() {
+                    self.capture_snapshot();
+                }
+                let histogram_response = ui.button("Histogram").on_hover_ui(|ui| {
+                    ui.label("Shows how many cents the pitch was off from the nearest note over the session, to see whether it tends sharp or flat");
+                });
+                if histogram_response.clicked() {
+                    self.window_state.is_cents_histogram_open = !self.window_state.is_cents_histogram_open;
+                }
+                let metronome_response = ui.button("Metronome").on_hover_ui(|ui| {
+                    ui.label("Plays a click on every beat and shows how many cents the pitch was off on each of them");
+                });
+                if metronome_response.clicked() {
+                    self.window_state.is_metronome_open = !self.window_state.is_metronome_open;
+                }
+                let reference_button = egui::Button::new(if self.window_state.stored_reference_hz.is_some() { "Clear reference" } else { "Store reference" });
+                let reference_response = ui.add_enabled(can_capture || self.window_state.stored_reference_hz.is_some(), reference_button).on_hover_ui(|ui| {
+                    ui.label("Remembers the current pitch and shows how many cents the live pitch is away from it");
+                });
+                if reference_response.clicked() {
+                    self.toggle_stored_reference();
+                }
+                let tone_note = self.reference_tone.as_ref().map(|tone| tone.note).or_else(|| self.reference_tone_note());
+                let tone_button = egui::Button::new(if self.reference_tone.is_some() { "Stop tone" } else { "Play tone" });
+                let tone_response = ui.add_enabled(tone_note.is_some(), tone_button).on_hover_ui(|ui| {
+                    match tone_note.map(NoteSpec::note) {
+                        Some(note) => ui.label(format!("Plays a sine at {}{} to match by ear", note.name, note.octave)),
+                        None => ui.label("Plays a sine at the nearest note or the practice target to match by ear"),
+                    };
+                });
+                if tone_response.clicked() {
+                    self.toggle_reference_tone();
+                }
+                let is_paused = self.audio_state.read().unwrap().paused;
+                let pause_response = ui.button(if is_paused { "Resume" } else { "Pause" }).on_hover_ui(|ui| {
+                    ui.label("Freezes the display without closing the input device");
+                });
+                if pause_response.clicked() {
+                    self.toggle_pause();
+                }
+                let is_recording = self.audio_state.read().unwrap().wav_recorder.is_recording();
+                if ui.button(if is_recording { "Stop recording" } else { "Record" }).clicked() {
+                    if is_recording {
+                        self.stop_recording();
+                    } else {
+                        self.start_recording();
+                    }
+                }
+                if self.settings.display_mode == DisplayMode::Plot && ui.button("Clear history").clicked() {
+                    self.audio_state.write().unwrap().pitch_history.clear();
+                }
+
+                // Overlay mode is always on top, so the checkbox has no effect while it is enabled.
+                let checkbox_changed = ui.add_enabled_ui(!self.settings.overlay_mode, |ui| {
+                    ui.add_sized([80.0, 20.0], egui::Checkbox::new(&mut self.window_state.is_always_on_top, "Always on top"))
+                }).inner.changed();
+                let settings_button = ui.add_sized([100.0, 20.0], egui::Button::new("Settings")).on_hover_ui(|ui| {
+                    ui.label("Keyboard shortcuts:");
+                    for (key, _, description) in SHORTCUTS {
+                        ui.label(format!("{}: {}", key.name(), description));
+                    }
+                });
+
+                if checkbox_changed {
+                    self.update_window_level(ctx);
+                }
+                if settings_button.clicked() {
+                    self.window_state.are_settings_open = true;
+                }
+            });
+
+            if self.settings.input_channel == ChannelMode::Stereo {
+                // The channels have no gate of their own, with the gate enabled they use its open threshold.
+                let threshold = if self.settings.gate_enabled { self.settings.gate_open_threshold } else { self.settings.confidence_threshold };
+                let [left, right] = self.audio_state.read().unwrap().stereo_predictions
+                    .map(|prediction| prediction
+                        .filter(|prediction| prediction.confidence >= threshold)
+                        .map(|prediction| prediction.frequency));
+                let format_frequency = |frequency: Option<f32>| frequency.map_or("--".to_owned(), |frequency| format!("{:.1}Hz", frequency));
+                ui.horizontal(|ui| {
+                    ui.label(format!("L: {}", format_frequency(left)));
+                    ui.label(format!("R: {}", format_frequency(right)));
+                    if let (Some(left), Some(right)) = (left, right) {
+                        ui.label(format!("Beats: {:.1}/s ({:+.0} cents)", beat_frequency(left, right), hz_to_cents(right, left)))
+                            .on_hover_ui(|ui| {
+                                ui.label("How often per second the two sources beat against each other and how many cents the right one is above the left one");
+                            });
+                    }
+                });
+            }
+            if let Some(reference_hz) = self.window_state.stored_reference_hz {
+                let frequency = self.current_reading()
+                    .map(|(_, prediction)| prediction.frequency);
+                let (text, color) = reference_comparison(frequency, reference_hz);
+                ui.colored_label(color, text);
+            }
+            if let Some(vibrato) = self.audio_state.read().unwrap().vibrato {
+                ui.label(format!("Vibrato: {:.1}Hz, {:.0} cents", vibrato.rate_hz, vibrato.depth_cents))
+                    .on_hover_ui(|ui| {
+                        ui.label("How often per second the held note oscillates and how many cents lie between its highest and lowest pitch");
+                    });
+            }
+
+            let current_device_index = self.current_device_index;
+            let has_devices = !self.available_input_devices.is_empty();
+            let label_color = self.settings.label_color;
+            let a4_hz = self.settings.a4_hz;
+
+            if self.settings.display_mode == DisplayMode::Practice {
+                let prediction = arc1.read().unwrap().last_prediction;
+                ui.horizontal(|ui| {
+                    ui.label("Target");
+                    let selected_text = self.settings.target_note
+                        .map(|target| target.note())
+                        .map_or("None".to_owned(), |note| format!("{}{}", note.name, note.octave));
+                    egui::ComboBox::from_id_salt("Target note")
+                        .selected_text(selected_text)
+                        .show_ui(ui, |ui| {
+                            for midi in PRACTICE_MIDI_NOTES {
+                                let note = midi_to_note(midi);
+                                ui.selectable_value(&mut self.settings.target_note, Some(NoteSpec { midi }), format!("{}{}", note.name, note.octave));
+                            }
+                        });
+                    let use_pitch_button = ui.add_enabled(prediction.is_some(), egui::Button::new("Use current pitch")).on_hover_ui(|ui| {
+                        ui.label("Sets the target to the note nearest to what is played or sung right now, e.g. a reference tone");
+                    });
+                    if let Some(prediction) = prediction.filter(|_| use_pitch_button.clicked()) {
+                        self.settings.target_note = Some(NoteSpec::nearest(prediction.frequency, a4_hz));
+                    }
+                    ui.separator();
+                    egui::ComboBox::from_id_salt("Scale")
+                        .selected_text(scale_name(self.settings.scale_template))
+                        .show_ui(ui, |ui| {
+                            for template in [ScaleTemplate::Major, ScaleTemplate::NaturalMinor, ScaleTemplate::Chromatic] {
+                                ui.selectable_value(&mut self.settings.scale_template, template, scale_name(template));
+                            }
+                        });
+                    let scale_button = ui.add_enabled(self.settings.target_note.is_some(), egui::Button::new("Start scale")).on_hover_ui(|ui| {
+                        ui.label("Measures the intonation of every note of the scale played upward from the target and shows a report");
+                    });
+                    if let Some(root) = self.settings.target_note.filter(|_| scale_button.clicked()) {
+                        let notes = self.settings.scale_template.notes(root);
+                        // The session is only given confident predictions, see `finish_step`.
+                        arc1.write().unwrap().intonation_session = Some(IntonationSession::new(notes, 0.0, a4_hz));
+                        self.window_state.is_intonation_open = true;
+                    }
+                });
+
+                let Some(target) = self.settings.target_note else {
+                    ui.centered_and_justified(|ui| ui.label("Pick a target note to practice"));
+                    return;
+                };
+                let cents_error = prediction.map(|prediction| target.cents_error(prediction.frequency, a4_hz));
+                let is_in_tune = self.window_state.in_tune_timer.update(cents_error, ui.input(|input| input.time));
+                practice_display(ui, target.note(), cents_error, is_in_tune, Color32::from(label_color));
+                return;
+            }
+
+            if self.settings.display_mode == DisplayMode::LockNote {
+                let prediction = arc1.read().unwrap().last_prediction;
+                ui.horizontal(|ui| {
+                    ui.label("Locked note");
+                    let selected_text = self.settings.locked_note
+                        .map(|locked| locked.note())
+                        .map_or("None".to_owned(), |note| format!("{}{}", note.name, note.octave));
+                    egui::ComboBox::from_id_salt("Locked note")
+                        .selected_text(selected_text)
+                        .show_ui(ui, |ui| {
+                            for midi in PRACTICE_MIDI_NOTES {
+                                let note = midi_to_note(midi);
+                                ui.selectable_value(&mut self.settings.locked_note, Some(NoteSpec { midi }), format!("{}{}", note.name, note.octave));
+                            }
+                        });
+                    let lock_button = ui.add_enabled(prediction.is_some(), egui::Button::new("Lock current note")).on_hover_ui(|ui| {
+                        ui.label("Locks the note nearest to what is played or sung right now");
+                    });
+                    if let Some(prediction) = prediction.filter(|_| lock_button.clicked()) {
+                        self.settings.locked_note = Some(NoteSpec::nearest(prediction.frequency, a4_hz));
+                    }
+                    ui.add(egui::DragValue::new(&mut self.settings.lock_tolerance_cents).range(1.0..=50.0).suffix(" cents")).on_hover_ui(|ui| {
+                        ui.label("How many cents the pitch may be off either way while still in tune");
+                    });
+                });
+                self.show_lock_note_display(ui);
+                return;
+            }
+
+            if self.settings.display_mode == DisplayMode::Tuner {
+                let prediction = arc1.read().unwrap().last_prediction;
+                if let Some(prediction) = prediction {
+                    self.window_state.tuner_note = frequency_to_note_checked(prediction.frequency, a4_hz);
+                }
+                tuner(ui, self.window_state.tuner_note, prediction.is_some(), Color32::from(label_color));
+                return;
+            }
+
+            // Pitch is plotted as fractional MIDI note numbers so that the y-axis is logarithmic in frequency.
+            let to_plot_y = move |frequency: u32| frequency_to_midi(frequency.max(1) as f32, a4_hz) as f64;
+            let plot = Plot::new("My plot")
+                .show_background(!self.settings.overlay_mode)
+                .allow_zoom(false)
+                .allow_scroll(false)
+                .allow_drag(false)
+                .allow_double_click_reset(false)
+                // Labels every C, D#, F# and A when there is enough space.
+                .y_grid_spacer(uniform_grid_spacer(|_| [1.0, 3.0, 12.0]))
+                .y_axis_formatter(|mark, _| {
+                    let note = midi_to_note(mark.value.round() as i32);
+                    format!("{}{}", note.name, note.octave)
+                })
+                .label_formatter(move |_, point| {
+                    format!("{:.1}s\n{:.0}Hz", point.x, midi_to_frequency(point.y as f32, a4_hz))
+                });
+            let cloned_arc = Arc::clone(&self.audio_state);
+            let history_seconds = self.settings.history_seconds;
+            let target_range = self.settings.target_range;
+            let target_color = self.settings.target_color;
+            let display_range = self.settings.display_range;
+            let show_note_grid = self.settings.show_note_grid;
+            let response = plot.show(ui, move |plot_ui| {
+                let audio_state = cloned_arc.read().unwrap();
+                let current_secs = audio_state.pitch_history.back()
+                    .map(|sample| sample.time)
+                    .unwrap_or(history_seconds);
+                let history_start = current_secs - history_seconds;
+                let (plot_bottom, plot_top) = (to_plot_y(display_range.0), to_plot_y(display_range.1));
+                plot_ui.set_plot_bounds(PlotBounds::from_min_max(
+                    [history_start, plot_bottom],
+                    [current_secs, plot_top],
+                ));
+
+                let (target_low, target_high) = (to_plot_y(target_range.0), to_plot_y(target_range.1));
+                plot_ui.polygon(Polygon::new(PlotPoints::new(vec![
+                    [history_start, target_low],
+                    [current_secs, target_low],
+                    [current_secs, target_high],
+                    [history_start, target_high],
+                ]))
+                    .fill_color(target_color)
+                    .stroke(Stroke::NONE)
+                );
+
+                if show_note_grid {
+                    for (frequency, label) in note_gridlines(display_range.0.max(1) as f32, display_range.1 as f32, a4_hz) {
+                        // Natural notes are more prominent than accidentals.
+                        let (alpha, width) = if label.contains('#') { (25, 0.5) } else { (70, 1.0) };
+                        let y = frequency_to_midi(frequency, a4_hz) as f64;
+                        plot_ui.hline(HLine::new(y).color(Color32::from_white_alpha(alpha)).width(width));
+                        plot_ui.text(Text::new(PlotPoint::new(history_start, y), RichText::new(label).size(10.0))
+                            .anchor(Align2::LEFT_BOTTOM)
+                            .color(Color32::from_white_alpha(alpha * 2)));
+                    }
+                }
+
+                let tick_top = plot_bottom + (plot_top - plot_bottom) * ONSET_TICK_FRACTION;
+                for onset in audio_state.onset_history.iter().filter(|onset| **onset >= history_start) {
+                    plot_ui.line(Line::new(PlotPoints::new(vec![[*onset, plot_bottom], [*onset, tick_top]]))
+                        .color(PITCH_LINE_COLOR)
+                        .width(2.0)
+                    );
+                }
+
+                // Every segment is a separate line so that its opacity and width can reflect the confidence,
+                // segments next to a sample without pitch are skipped so that gaps break the line.
+                let visible_history = audio_state.pitch_history.iter()
+                    .skip_while(|sample| sample.time < history_start - STEP_SECONDS * STEPS_PER_DISPLAY as f64)
+                    .collect::<Vec<&PitchSample>>();
+                for segment in visible_history.windows(2) {
+                    let [start, end] = [segment[0], segment[1]];
+                    if start.frequency.is_nan() || end.frequency.is_nan() {
+                        continue;
+                    }
+
+                    let confidence = start.confidence.min(end.confidence);
+                    plot_ui.line(Line::new(PlotPoints::new(vec![
+                        [start.time, frequency_to_midi(start.frequency, a4_hz) as f64],
+                        [end.time, frequency_to_midi(end.frequency, a4_hz) as f64],
+                    ]))
+                        .color(PITCH_LINE_COLOR.gamma_multiply(confidence))
+                        .width(1.0 + 2.0 * confidence)
+                    );
+                }
+            });
+            // Place label over the created plot.
+            let rect = response.response.rect;
+            let (displayed_pitch, has_audio, confidence) = {
+                let audio_state = arc1.read().unwrap();
+                let confidence = audio_state.pitch_history.back().map(|sample| sample.confidence).unwrap_or(0.0);
+                (audio_state.displayed_pitch, audio_state.first_audio_instant.is_some(), confidence)
+            };
+            let display_frequency = match displayed_pitch {
+                HeldPitch::None => match current_device_index {
+                    None if !has_devices => "No input devices found.",
+                    None => "No device selected.",
+                    Some(_) if has_audio => "--",
+                    Some(_) => "Waiting for audio data...",
+                }.to_owned(),
+                HeldPitch::Live(frequency) | HeldPitch::Held(frequency) => {
+                    let note = frequency_to_note(frequency, a4_hz);
+                    format!("{}Hz ({}{})", frequency as u32, note.name, note.octave)
+                }
+            };
+            let color = match displayed_pitch {
+                HeldPitch::Held(_) => Color32::from(label_color).gamma_multiply(0.5),
+                _ => Color32::from(label_color),
+            };
+            let text = RichText::new(display_frequency).size(30.0).color(color);
+            let label = Label::new(text);
+            let label_rect = ui.put(rect, label).rect;
+            if displayed_pitch != HeldPitch::None {
+                let indicator_center = label_rect.left_center() - vec2(12.0, 0.0);
+                ui.painter().circle_filled(indicator_center, 6.0, confidence_color(confidence, self.settings.confidence_color_thresholds));
+            }
+        });
+    }
+
+    fn clear_color(&self, visuals: &egui::Visuals) -> [f32; 4] {
+        if self.settings.overlay_mode {
+            return Color32::TRANSPARENT.to_normalized_gamma_f32();
+        }
+
+        // The window is created transparent, so anything but an opaque color would show through.
+        visuals.panel_fill.to_normalized_gamma_f32()
+    }
+
+    fn on_exit(&mut self, _gl: Option<&glow::Context>) {
+        self.stop_csv_logging();
+        self.stop_recording();
+        if let Some(path) = self.config_path.as_ref().filter(|_| self.save_config) {
+            match self.settings.save_to(path) {
+                Ok(()) => println!("Saved settings to {}", path.display()),
+                Err(e) => println!("{}", e),
+            }
+        }
+    }
+
+    fn save(&mut self, storage: &mut dyn Storage) {
+        // Settings from a config file would otherwise replace the stored ones for the next start
+        // without it.
+        if self.config_path.is_some() {
+            return;
+        }
+
+        println!("Saving settings...");
+        match self.settings.serialize(SettingsFormat::Json) {
+            Ok(json) => {
+                storage.set_string(SETTINGS_STORAGE_KEY, json);
+                println!("Saved settings.");
+            }
+            Err(e) => println!("Error saving settings: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::*;
+
+    const FIXED_PREDICTION: Prediction = Prediction { frequency: 220.0, confidence: 0.9 };
+
+    /// Detects the same pitch in any audio.
+    struct FixedDetector;
+
+    impl PitchDetector for FixedDetector {
+        fn predict_single(&self, _audio: [i16; crepe::SAMPLES_PER_STEP]) -> Prediction {
+            FIXED_PREDICTION
+        }
+
+        fn sample_rate(&self) -> u32 {
+            crepe::SAMPLE_RATE
+        }
+
+        fn samples_per_step(&self) -> usize {
+            crepe::SAMPLES_PER_STEP
+        }
+    }
+
+    #[test]
+    fn test_app_shows_detector_predictions() {
+        let mut app = PitchOverlayApp::new(&Context::default(), vec![], Some(Box::new(FixedDetector)), Settings::default());
+        for _ in 0..STEPS_PER_DISPLAY {
+            app.inference_worker.submit(AudioFrame {
+                step_time: 1.0,
+                time: 1.0,
+                audio: Some([1000; crepe::SAMPLES_PER_STEP]),
+                level_db: crepe::rms_dbfs(&[1000; crepe::SAMPLES_PER_STEP]),
+                detector: DetectorKind::Crepe,
+                harmonic_summation: false,
+                pitch_range: None,
+                stereo_audio: None,
+            });
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while app.audio_state.read().unwrap().pitch_history.is_empty() && Instant::now() < deadline {
+            app.process_analyzed_frames();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let audio_state = app.audio_state.read().unwrap();
+        assert_eq!(audio_state.last_prediction, Some(FIXED_PREDICTION));
+        assert_eq!(audio_state.displayed_pitch, HeldPitch::Live(220.0));
+        assert_eq!(audio_state.pitch_history.len(), 1);
+    }
+
+    fn key_event(key: egui::Key, modifiers: egui::Modifiers) -> egui::Event {
+        egui::Event::Key {
+            key,
+            physical_key: None,
+            pressed: true,
+            repeat: false,
+            modifiers,
+        }
+    }
+
+    #[test]
+    fn test_shortcut_dispatch() {
+        let events = [
+            key_event(egui::Key::R, egui::Modifiers::NONE),
+            egui::Event::Text("r".to_owned()),
+            key_event(egui::Key::A, egui::Modifiers::NONE),
+            key_event(egui::Key::T, egui::Modifiers::SHIFT),
+            key_event(egui::Key::Space, egui::Modifiers::CTRL),
+            key_event(egui::Key::Escape, egui::Modifiers::NONE),
+        ];
+
+        assert_eq!(shortcut_actions(&events), vec![
+            ShortcutAction::ToggleRecording,
+            ShortcutAction::CycleDisplayMode,
+            ShortcutAction::ClearHold,
+        ]);
+        assert!(shortcut_actions(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_display_mode_cycles() {
+        assert_eq!(DisplayMode::Plot.next(), DisplayMode::Tuner);
+        assert_eq!(DisplayMode::Tuner.next(), DisplayMode::Practice);
+        assert_eq!(DisplayMode::Practice.next(), DisplayMode::LockNote);
+        assert_eq!(DisplayMode::LockNote.next(), DisplayMode::Plot);
+    }
+
+    #[test]
+    fn test_in_tune_is_confirmed_after_hold() {
+        let mut timer = InTuneTimer::default();
+
+        assert!(!timer.update(Some(3.0), 0.0));
+        assert!(!timer.update(Some(-4.0), 0.5));
+        assert!(timer.update(Some(1.0), 1.0));
+        // Drifting out of tune or losing the pitch starts the hold over.
+        assert!(!timer.update(Some(20.0), 1.1));
+        assert!(!timer.update(Some(0.0), 1.2));
+        assert!(!timer.update(None, 1.5));
+        assert!(!timer.update(Some(0.0), 2.0));
+        assert!(timer.update(Some(0.0), 3.0));
+    }
+
+    #[test]
+    fn test_readout_formats() {
+        assert_eq!(format_hz(440.0, 1), "440.0 Hz");
+        assert_eq!(format_hz(261.6256, 0), "262 Hz");
+        assert_eq!(format_hz(261.6256, 3), "261.626 Hz");
+        assert_eq!(format_hz(261.6256, 10), "261.626 Hz");
+        assert_eq!(format_note(frequency_to_note(440.0, DEFAULT_A4_HZ)), "A4");
+        assert_eq!(format_midi(69), "MIDI 69");
+        assert_eq!(format_cents(frequency_to_note(440.0, DEFAULT_A4_HZ).cents_offset), "+0.0 ¢");
+        assert_eq!(format_cents(-0.0), "+0.0 ¢");
+        assert_eq!(format_cents(-12.34), "-12.3 ¢");
+    }
+
+    #[test]
+    fn test_readout_lines() {
+        let settings = Settings {
+            show_hz: true,
+            show_midi: true,
+            show_cents: true,
+            ..Settings::default()
+        };
+
+        assert_eq!(readout_lines(Some(440.0), &settings), ["440.0 Hz", "MIDI 69", "+0.0 ¢"]);
+        assert_eq!(readout_lines(None, &settings), ["-- Hz", "MIDI --", "-- ¢"]);
+        assert!(readout_lines(Some(440.0), &Settings::default()).is_empty());
+    }
+
+    #[test]
+    fn test_readout_at_note_boundaries() {
+        let settings = Settings {
+            show_note: true,
+            show_midi: true,
+            show_cents: true,
+            ..Settings::default()
+        };
+        let lines = |cents: f32| readout_lines(Some(shift_by_cents(DEFAULT_A4_HZ, cents)), &settings);
+
+        assert_eq!(lines(49.9), ["A4", "MIDI 69", "+49.9 ¢"]);
+        // Rounds to +50.0 cents, but A4 is still the nearest note.
+        assert_eq!(lines(49.96), ["A4", "MIDI 69", "+50.0 ¢"]);
+        assert_eq!(lines(-49.96), ["A4", "MIDI 69", "-50.0 ¢"]);
+        assert_eq!(lines(-50.2), ["G#4", "MIDI 68", "+49.8 ¢"]);
+        assert_eq!(lines(-0.04), ["A4", "MIDI 69", "+0.0 ¢"]);
+    }
+
+    #[test]
+    fn test_cents_error_color() {
+        assert_eq!(cents_error_color(0.0), Color32::GREEN);
+        assert_eq!(cents_error_color(-25.0), Color32::YELLOW);
+        assert_eq!(cents_error_color(50.0), Color32::RED);
+        assert_eq!(cents_error_color(300.0), Color32::RED);
+    }
+
+    #[test]
+    fn test_reference_comparison() {
+        let (text, color) = reference_comparison(Some(440.0), 440.0);
+        assert_eq!(text, "Reference 440.0Hz: +0.0 cents");
+        assert_eq!(color, Color32::GREEN);
+
+        let (text, color) = reference_comparison(Some(shift_by_cents(440.0, -25.0)), 440.0);
+        assert_eq!(text, "Reference 440.0Hz: -25.0 cents");
+        assert_eq!(color, Color32::YELLOW);
+
+        assert_eq!(reference_comparison(Some(220.0), 440.0).0, "Reference 440.0Hz: -1200.0 cents");
+        assert_eq!(reference_comparison(None, 261.63), ("Reference 261.6Hz: -- cents".to_owned(), Color32::GRAY));
+    }
+
+    #[test]
+    fn test_app_without_devices() {
+        let ctx = Context::default();
+        let settings = Settings {
+            last_device_name: Some("Microphone".to_owned()),
+            ..Settings::default()
+        };
+
+        let app = PitchOverlayApp::new(&ctx, vec![], Some(Box::new(FixedDetector)), settings);
+        assert!(app.available_input_devices.is_empty());
+        assert_eq!(app.current_device_index, None);
+        assert!(app.current_stream.is_none());
+        assert!(app.current_device().is_none());
+        // The device is still remembered to connect to it once it is plugged in.
+        assert_eq!(app.settings.last_device_name.as_deref(), Some("Microphone"));
+    }
+
+    #[test]
+    fn test_reset_restores_default_settings() {
+        let ctx = Context::default();
+        let settings = Settings {
+            noise_floor_db: -20.0,
+            calibration_cents: 12.0,
+            smoothing: Smoothing::Ema(0.3),
+            agc_enabled: true,
+            max_ui_fps: 10,
+            ..Settings::default()
+        };
+        let mut app = PitchOverlayApp::new(&ctx, vec![], Some(Box::new(FixedDetector)), settings);
+        assert_ne!(app.settings, Settings::default());
+        assert_eq!(app.max_ui_fps.load(Ordering::Relaxed), 10);
+
+        app.reset_settings(&ctx);
+        assert_eq!(app.settings, Settings::default());
+        assert!(app.audio_state.read().unwrap().agc.is_none());
+        assert_eq!(app.max_ui_fps.load(Ordering::Relaxed), DEFAULT_MAX_UI_FPS);
+    }
+
+    #[test]
+    fn test_repaint_interval() {
+        assert_eq!(repaint_interval(30), Duration::from_secs_f64(1.0 / 30.0));
+        assert_eq!(repaint_interval(1), Duration::from_secs(1));
+        assert_eq!(repaint_interval(120).as_micros(), 8333);
+        // A rate of zero would never redraw, so it is treated as one redraw per second.
+        assert_eq!(repaint_interval(0), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_meter_position_round_trip() {
+        for db in [METER_MIN_DB, -50.0, -12.5, 0.0] {
+            assert_eq!(meter_db(meter_position(db)), db);
+        }
+        for position in [0.0, 0.25, 0.6, 1.0] {
+            assert!((meter_position(meter_db(position)) - position).abs() < 1e-6);
+        }
+        // Levels and positions outside of the meter are clamped to its ends.
+        assert_eq!(meter_position(-120.0), 0.0);
+        assert_eq!(meter_db(1.5), 0.0);
+        assert_eq!(meter_db(-0.5), METER_MIN_DB);
+    }
+
+    #[test]
+    fn test_paused_audio_is_not_analyzed() {
+        let settings = Settings::default();
+        let audio = [1000; MIN_SAMPLES_PER_DISPLAY];
+        let mut audio_state = AudioState {
+            input_gain: 1.0,
+            noise_floor_db: settings.noise_floor_db,
+            paused: true,
+            ..AudioState::default()
+        };
+
+        for i in 0..3 {
+            let frames = audio_state.push_audio(&audio, i as f64, &settings);
+            assert!(frames.is_empty());
+        }
+        assert_eq!(audio_state.audio_collector.buffered(), 0);
+
+        // The pause is left out of the time, so the history continues where it stopped.
+        audio_state.paused = false;
+        let frames = audio_state.push_audio(&audio, 10.0, &settings);
+        assert_eq!(frames.len(), STEPS_PER_DISPLAY);
+        assert!(frames.iter().all(|frame| frame.step_time == 0.0 && frame.audio.is_some()));
+        let frames = audio_state.push_audio(&audio, 11.0, &settings);
+        assert!(frames.iter().all(|frame| frame.step_time == 1.0));
+    }
+
+    /// Fails on any audio like a model whose output cannot be read.
+    struct FailingDetector;
+
+    impl PitchDetector for FailingDetector {
+        fn predict_single(&self, _audio: [i16; crepe::SAMPLES_PER_STEP]) -> Prediction {
+            panic!("the fallible prediction must be used");
+        }
+
+        fn sample_rate(&self) -> u32 {
+            crepe::SAMPLE_RATE
+        }
+
+        fn samples_per_step(&self) -> usize {
+            crepe::SAMPLES_PER_STEP
+        }
+
+        fn try_predict(&self, _audio: [i16; crepe::SAMPLES_PER_STEP], _harmonic_summation: bool, _pitch_range: Option<(f32, f32)>) -> Result<(Prediction, Option<Activation>), PredictError> {
+            Err(PredictError::ShapeMismatch { expected: ACTIVATION_BINS, actual: 100 })
+        }
+    }
+
+    #[test]
+    fn test_analyzing_frame_returns_detector_errors() {
+        let frame = AudioFrame {
+            step_time: 1.0,
+            time: 1.0,
+            audio: Some([1000; crepe::SAMPLES_PER_STEP]),
+            level_db: -30.0,
+            detector: DetectorKind::Crepe,
+            harmonic_summation: false,
+            pitch_range: None,
+            stereo_audio: None,
+        };
+
+        assert!(matches!(analyze_frame(&frame, &FailingDetector), Err(PredictError::ShapeMismatch { .. })));
+        let analyzed = analyze_frame(&frame, &FixedDetector).unwrap();
+        assert_eq!(analyzed.output.unwrap().0, FIXED_PREDICTION);
+        // Silent frames are not analyzed, so they cannot fail.
+        let silent = AudioFrame { audio: None, ..frame };
+        assert!(analyze_frame(&silent, &FailingDetector).unwrap().output.is_none());
+    }
+
+    #[test]
+    fn test_every_display_step_of_a_callback_is_analyzed() {
+        let settings = Settings::default();
+        let mut audio_state = AudioState {
+            input_gain: 1.0,
+            noise_floor_db: settings.noise_floor_db,
+            ..AudioState::default()
+        };
+
+        let frames = audio_state.push_audio(&[1000; 2 * MIN_SAMPLES_PER_DISPLAY + 100], 1.0, &settings);
+        assert_eq!(frames.len(), 2 * STEPS_PER_DISPLAY);
+        let samples_per_second = crepe::SAMPLE_RATE as f64;
+        assert!((frames[0].step_time - (1.0 - (MIN_SAMPLES_PER_DISPLAY + 100) as f64 / samples_per_second)).abs() < 1e-9);
+        assert!((frames[STEPS_PER_DISPLAY].step_time - (1.0 - 100.0 / samples_per_second)).abs() < 1e-9);
+        // The rest is kept for the next display step.
+        assert_eq!(audio_state.audio_collector.buffered(), 100);
+        let frames = audio_state.push_audio(&[1000; MIN_SAMPLES_PER_DISPLAY - 100], 1.2, &settings);
+        assert_eq!(frames.len(), STEPS_PER_DISPLAY);
+        assert_eq!(audio_state.audio_collector.buffered(), 0);
+    }
+
+    #[test]
+    fn test_stereo_chunks_are_attached_to_last_frame() {
+        let settings = Settings {
+            input_channel: ChannelMode::Stereo,
+            ..Settings::default()
+        };
+        let left = [1000; MIN_SAMPLES_PER_DISPLAY];
+        let mut audio_state = AudioState {
+            input_gain: 2.0,
+            noise_floor_db: settings.noise_floor_db,
+            ..AudioState::default()
+        };
+
+        audio_state.push_stereo_audio(&left, &[0; MIN_SAMPLES_PER_DISPLAY]);
+        let frames = audio_state.push_audio(&left, 0.0, &settings);
+        assert_eq!(frames.len(), STEPS_PER_DISPLAY);
+        assert!(frames[..STEPS_PER_DISPLAY - 1].iter().all(|frame| frame.stereo_audio.is_none()));
+        let [left_chunk, right_chunk] = frames[STEPS_PER_DISPLAY - 1].stereo_audio.unwrap();
+        assert!(left_chunk.unwrap().iter().all(|sample| *sample == 2000));
+        // The silent right channel is below the noise floor.
+        assert_eq!(right_chunk, None);
+        assert!(audio_state.recent_stereo_chunks.iter().all(Option::is_none));
+
+        // Without stereo audio, e.g. in other channel modes, no stereo chunks are analyzed.
+        let frames = audio_state.push_audio(&left, 1.0, &settings);
+        assert!(frames.iter().all(|frame| frame.stereo_audio.is_none()));
+    }
+
+    #[test]
+    fn test_onset_confirmation_delays_pitch_history() {
+        let settings = Settings {
+            onset_confirm_frames: 1,
+            ..Settings::default()
+        };
+        let mut audio_state = AudioState {
+            onset_confirmer: Some(OnsetConfirmer::new(1)),
+            ..AudioState::default()
+        };
+
+        for (step, frequency) in [470.0, 220.0, 220.0].into_iter().enumerate() {
+            for _ in 0..STEPS_PER_DISPLAY {
+                audio_state.push_analyzed_frame(AnalyzedFrame {
+                    step_time: step as f64,
+                    time: step as f64,
+                    level_db: -20.0,
+                    output: Some((Prediction { frequency, confidence: 0.9 }, [0.0; ACTIVATION_BINS])),
+                    stereo_output: None,
+                }, &settings);
+            }
+        }
+        assert_eq!(audio_state.pitch_history.len(), 2);
+        assert!(audio_state.pitch_history[0].frequency.is_nan());
+        assert_eq!(audio_state.pitch_history[1].frequency, 220.0);
+        assert_eq!(audio_state.displayed_pitch, HeldPitch::Live(220.0));
+
+        audio_state.flush_onset_confirmer();
+        assert_eq!(audio_state.pitch_history.len(), 3);
+        assert_eq!(audio_state.pitch_history[2].time, 2.0);
+    }
+
+    #[test]
+    fn test_octave_error_is_corrected_before_frequency_gate() {
+        let settings = Settings {
+            freq_max_hz: 300.0,
+            ..Settings::default()
+        };
+        let mut audio_state = AudioState::default();
+
+        // The first frame of the second step is an octave above both of its neighbors.
+        for (step, frequencies) in [[220.0, 220.0], [440.0, 221.0]].into_iter().enumerate() {
+            for (frame, frequency) in frequencies.into_iter().enumerate() {
+                audio_state.push_analyzed_frame(AnalyzedFrame {
+                    step_time: step as f64,
+                    time: step as f64 + frame as f64 * STEP_SECONDS,
+                    level_db: -20.0,
+                    output: Some((Prediction { frequency, confidence: 0.8 }, [0.0; ACTIVATION_BINS])),
+                    stereo_output: None,
+                }, &settings);
+            }
+        }
+        assert_eq!(audio_state.pitch_history.len(), 2);
+        assert!((audio_state.pitch_history[1].frequency - 220.5).abs() < 1e-3, "{}", audio_state.pitch_history[1].frequency);
+    }
+
+    #[test]
+    fn test_gate_holds_pitch_through_confidence_dips() {
+        // The threshold is ignored while the gate is enabled.
+        let settings = Settings {
+            gate_enabled: true,
+            confidence_threshold: 0.95,
+            ..Settings::default()
+        };
+        let mut audio_state = AudioState {
+            gate: Some(Gate::new(0.6, 0.4, 0.0, 200.0)),
+            ..AudioState::default()
+        };
+
+        for (step, confidence) in [0.9, 0.3, 0.9].into_iter().enumerate() {
+            for frame in 0..STEPS_PER_DISPLAY {
+                audio_state.push_analyzed_frame(AnalyzedFrame {
+                    step_time: step as f64 * 0.1,
+                    time: step as f64 * 0.1 + frame as f64 * 0.01,
+                    level_db: -20.0,
+                    output: Some((Prediction { frequency: 220.0, confidence }, [0.0; ACTIVATION_BINS])),
+                    stereo_output: None,
+                }, &settings);
+            }
+        }
+        // The dip is shorter than the release time, so the pitch is shown throughout.
+        assert_eq!(audio_state.pitch_history.len(), 3);
+        assert!(audio_state.pitch_history.iter().all(|sample| sample.frequency == 220.0));
+        // Everything else that takes confident pitches follows the gate as well.
+        assert_eq!(audio_state.last_prediction, Some(Prediction { frequency: 220.0, confidence: 0.9 }));
+        assert_eq!(audio_state.note_mode.map(|mode| mode.note), Some(NoteSpec::nearest(220.0, 440.0)));
+    }
+
+    #[test]
+    fn test_reattacked_note_is_marked_as_onset() {
+        let settings = Settings::default();
+        let mut audio_state = AudioState {
+            onset_detector: Some(OnsetDetector::new(0.5)),
+            ..AudioState::default()
+        };
+
+        // The same note is sung twice with a quiet breath in between.
+        for (step, (level_db, confidence)) in [(-20.0, 0.9), (-60.0, 0.2), (-20.0, 0.9), (-20.0, 0.9)].into_iter().enumerate() {
+            for frame in 0..STEPS_PER_DISPLAY {
+                audio_state.push_analyzed_frame(AnalyzedFrame {
+                    step_time: step as f64,
+                    time: step as f64 + frame as f64 * STEP_SECONDS,
+                    level_db,
+                    output: Some((Prediction { frequency: 220.0, confidence }, [0.0; ACTIVATION_BINS])),
+                    stereo_output: None,
+                }, &settings);
+            }
+        }
+        assert_eq!(audio_state.onset_history, [2.0]);
+    }
+
+    #[test]
+    fn test_level_meter_peak_holds_then_decays() {
+        let mut meter = LevelMeter::default();
+        meter.update(-20.0, 0.0);
+        meter.update(-60.0, 0.5);
+        assert_eq!(meter.level_db(), -60.0);
+        assert_eq!(meter.peak_db(), -20.0);
+
+        meter.update(-60.0, METER_PEAK_HOLD_SECONDS + 0.5);
+        assert_eq!(meter.peak_db(), -20.0 - 0.5 * METER_PEAK_DECAY_DB_PER_SECOND);
+
+        // A louder level replaces the falling peak.
+        meter.update(-25.0, METER_PEAK_HOLD_SECONDS + 0.6);
+        assert_eq!(meter.peak_db(), -25.0);
+
+        // The peak never falls below the bottom of the meter.
+        meter.update(f32::NEG_INFINITY, 100.0);
+        assert_eq!(meter.level_db(), METER_MIN_DB);
+        assert_eq!(meter.peak_db(), METER_MIN_DB);
+    }
+
+    #[test]
+    fn test_confidence_percentage() {
+        assert_eq!(format_confidence(0.0), "0%");
+        assert_eq!(format_confidence(0.874), "87%");
+        assert_eq!(format_confidence(1.0), "100%");
+        // Out-of-range values, e.g. from models that output logits, are clamped.
+        assert_eq!(format_confidence(1.7), "100%");
+        assert_eq!(format_confidence(-0.2), "0%");
+        assert_eq!(format_confidence(f32::NAN), "0%");
+
+        let mut meter = ConfidenceMeter::default();
+        meter.update(0.9, 0.0);
+        meter.update(2.0, 0.1);
+        assert_eq!(meter.confidence(), 1.0);
+        meter.update(0.2, 0.2);
+        assert_eq!(meter.peak(), 1.0);
+        meter.update(0.2, 0.1 + CONFIDENCE_PEAK_HOLD_SECONDS + 1.0);
+        assert_eq!(meter.peak(), 1.0 - CONFIDENCE_PEAK_DECAY_PER_SECOND);
+    }
+
+    #[test]
+    fn test_confidence_color_at_boundaries() {
+        let thresholds = (0.3, 0.7);
+
+        assert_eq!(confidence_color(0.0, thresholds), Color32::RED);
+        assert_eq!(confidence_color(0.3, thresholds), Color32::RED);
+        assert_eq!(confidence_color(0.5, thresholds), Color32::YELLOW);
+        assert_eq!(confidence_color(0.7, thresholds), Color32::GREEN);
+        assert_eq!(confidence_color(1.0, thresholds), Color32::GREEN);
+    }
+
+    #[test]
+    fn test_confidence_color_interpolates() {
+        let color = confidence_color(0.4, (0.3, 0.7));
+
+        assert_eq!((color.r(), color.b()), (255, 0));
+        assert!(color.g() > 100 && color.g() < 155);
+    }
+
+    #[test]
+    fn test_missing_window_geometry_uses_defaults() {
+        let mut json = serde_json::to_value(Settings::default()).unwrap();
+        let fields = json.as_object_mut().unwrap();
+        fields.remove("window_size");
+        fields.remove("window_pos");
+
+        let settings = serde_json::from_value::<Settings>(json).unwrap();
+        assert_eq!(settings.restored_window_size(), DEFAULT_WINDOW_SIZE);
+        assert_eq!(settings.restored_window_pos(), None);
+    }
+
+    #[test]
+    fn test_missing_fields_keep_stored_settings() {
+        let stored = Settings {
+            a4_hz: 432.0,
+            overlay_mode: true,
+            input_channel: ChannelMode::Right,
+            ..Settings::default()
+        };
+        let mut json = serde_json::to_value(&stored).unwrap();
+        let fields = json.as_object_mut().unwrap();
+        fields.remove("stats_capture_seconds");
+        fields.remove("target_note");
+
+        let settings = serde_json::from_value::<Settings>(json).unwrap();
+        assert_eq!(settings, stored);
+    }
+
+    #[test]
+    fn test_settings_round_trip_through_toml_file() {
+        let settings = Settings {
+            a4_hz: 442.0,
+            confidence_threshold: 0.35,
+            input_channel: ChannelMode::Index(2),
+            smoothing: Smoothing::Adaptive { slow_alpha: 0.1, fast_alpha: 0.7, slope_threshold_cents: 30.0 },
+            target_note: Some(NoteSpec { midi: 57 }),
+            preferred_device_substring: "Scarlett".to_owned(),
+            ensemble_model_paths: vec![PathBuf::from("crepe-tiny.onnx")],
+            window_pos: Some([10.0, 20.0]),
+            ..Settings::default()
+        };
+        // Test runs at the same time must not share the file.
+        let path = std::env::temp_dir().join(format!("pitch-overlay-test-settings-{}.toml", std::process::id()));
+        settings.save_to(&path).unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.contains("a4_hz = 442.0"), "{}", text);
+        assert_eq!(Settings::load(&path), Ok(settings.clone()));
+        // Fields that are missing from a hand-written file keep their defaults.
+        assert_eq!(Settings::parse("a4_hz = 415.0", SettingsFormat::Toml).unwrap().a4_hz, 415.0);
+        assert!(Settings::parse("a4_hz = \"high\"", SettingsFormat::Toml).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(Settings::load(&path).is_err());
+        assert_eq!(SettingsFormat::from_path(Path::new("settings.json")), SettingsFormat::Json);
+    }
+
+    #[test]
+    fn test_missing_theme_uses_system_theme() {
+        let mut json = serde_json::to_value(Settings::default()).unwrap();
+        json.as_object_mut().unwrap().remove("theme");
+
+        let settings = serde_json::from_value::<Settings>(json).unwrap();
+        assert_eq!(settings.theme, Theme::System);
+    }
+
+    #[test]
+    fn test_theme_visuals() {
+        assert!(Theme::Dark.visuals(Some(egui::Theme::Light)).dark_mode);
+        assert!(!Theme::Light.visuals(Some(egui::Theme::Dark)).dark_mode);
+        assert!(!Theme::System.visuals(Some(egui::Theme::Light)).dark_mode);
+        assert!(Theme::System.visuals(None).dark_mode);
+    }
+
+    #[test]
+    fn test_invalid_window_size_uses_default() {
+        let settings = Settings {
+            window_size: [-10.0, 0.0],
+            ..Settings::default()
+        };
+        assert_eq!(settings.restored_window_size(), DEFAULT_WINDOW_SIZE);
+
+        let settings = Settings {
+            window_size: [640.0, 480.0],
+            window_pos: Some([f32::NAN, 0.0]),
+            ..Settings::default()
+        };
+        assert_eq!(settings.restored_window_size(), [640.0, 480.0]);
+        assert_eq!(settings.restored_window_pos(), None);
+    }
+}
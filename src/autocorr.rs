@@ -0,0 +1,141 @@
+use crate::crepe::{PitchDetector, Prediction, SAMPLES_PER_STEP, SAMPLE_RATE};
+
+/// The highest frequency that is detected, which matches the highest bin of CREPE.
+const MAX_FREQUENCY: f32 = 2000.0;
+
+/// The number of samples that are compared with each shifted copy of the frame, which leaves room
+/// for shifts down to a frequency of about 31 Hz.
+const WINDOW: usize = SAMPLES_PER_STEP / 2;
+
+/// The default threshold of the normalized difference below which a period is accepted.
+pub const DEFAULT_YIN_THRESHOLD: f32 = 0.1;
+
+/// A lightweight pitch detector using the YIN algorithm, which does not need the model file and
+/// is much cheaper to run than CREPE, but is more prone to octave errors and noise.
+#[derive(Debug, Clone)]
+pub struct YinDetector {
+    threshold: f32,
+}
+
+impl Default for YinDetector {
+    fn default() -> Self {
+        YinDetector::new()
+    }
+}
+
+impl YinDetector {
+    pub fn new() -> Self {
+        YinDetector {
+            threshold: DEFAULT_YIN_THRESHOLD,
+        }
+    }
+
+    /// Sets the normalized difference below which a period is accepted, lower values find fewer
+    /// but more reliable periods.
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Detects the pitch of a chunk of audio at the model's sample rate.
+    ///
+    /// The confidence is one minus the normalized difference of the detected period, so that it is
+    /// close to one for periodic audio and close to zero for noise or silence.
+    pub fn predict(&self, audio: [i16; SAMPLES_PER_STEP]) -> Prediction {
+        let samples = audio.map(|sample| sample as f32);
+        let min_period = (SAMPLE_RATE as f32 / MAX_FREQUENCY) as usize;
+
+        // Cumulative mean normalized difference of the frame with itself shifted by each period.
+        let mut differences = vec![1.0; WINDOW];
+        let mut running_sum = 0.0;
+        for period in 1..WINDOW {
+            let difference = (0..WINDOW)
+                .map(|i| {
+                    let delta = samples[i] - samples[i + period];
+                    delta * delta
+                })
+                .sum::<f32>();
+            running_sum += difference;
+            differences[period] = if running_sum > 0.0 { difference * period as f32 / running_sum } else { 1.0 };
+        }
+
+        // Takes the first dip below the threshold, which avoids picking a multiple of the period.
+        let period = (min_period..WINDOW)
+            .find(|period| differences[*period] < self.threshold)
+            .map(|mut period| {
+                while period + 1 < WINDOW && differences[period + 1] < differences[period] {
+                    period += 1;
+                }
+                period
+            })
+            .unwrap_or_else(|| (min_period..WINDOW)
+                .min_by(|a, b| differences[*a].total_cmp(&differences[*b]))
+                .unwrap());
+
+        Prediction {
+            frequency: SAMPLE_RATE as f32 / refine_period(&differences, period),
+            confidence: (1.0 - differences[period]).clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// Fits a parabola through the differences around `period` to estimate the period more precisely
+/// than a whole number of samples.
+fn refine_period(differences: &[f32], period: usize) -> f32 {
+    if period == 0 || period + 1 >= differences.len() {
+        return period as f32;
+    }
+
+    let (left, center, right) = (differences[period - 1], differences[period], differences[period + 1]);
+    let curvature = left - 2.0 * center + right;
+    if curvature.abs() < f32::EPSILON {
+        return period as f32;
+    }
+
+    period as f32 + 0.5 * (left - right) / curvature
+}
+
+impl PitchDetector for YinDetector {
+    fn predict_single(&self, audio: [i16; SAMPLES_PER_STEP]) -> Prediction {
+        self.predict(audio)
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn samples_per_step(&self) -> usize {
+        SAMPLES_PER_STEP
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use crate::autocorr::*;
+    use crate::test_util::sine_chunk;
+
+    #[test]
+    fn test_detects_220_hz_sine() {
+        let prediction = YinDetector::new().predict(sine_chunk(220.0));
+
+        assert_relative_eq!(prediction.frequency, 220.0, epsilon = 3.0);
+        assert!(prediction.confidence > 0.9);
+    }
+
+    #[test]
+    fn test_detects_other_frequencies() {
+        for frequency in [80.0, 440.0, 1000.0] {
+            let prediction = YinDetector::new().predict(sine_chunk(frequency));
+
+            assert_relative_eq!(prediction.frequency, frequency, epsilon = frequency * 0.02);
+        }
+    }
+
+    #[test]
+    fn test_silence_is_not_confident() {
+        let prediction = YinDetector::new().predict([0; SAMPLES_PER_STEP]);
+
+        assert_eq!(prediction.confidence, 0.0);
+    }
+}
@@ -0,0 +1,88 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use ort::execution_providers::{CUDAExecutionProvider, DirectMLExecutionProvider, ExecutionProviderDispatch};
+use ort::session::builder::SessionBuilder;
+use ort::session::Session;
+
+/// The hardware that is used to run the model on.
+///
+/// GPU backends are only available if the application was built with the matching `cuda` or
+/// `directml` feature, otherwise creating a session falls back to the CPU.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionBackend {
+    #[default]
+    Cpu,
+    Cuda,
+    DirectMl,
+}
+
+impl ExecutionBackend {
+    fn execution_provider(&self) -> Option<ExecutionProviderDispatch> {
+        match self {
+            ExecutionBackend::Cpu => None,
+            ExecutionBackend::Cuda => Some(CUDAExecutionProvider::default().build()),
+            ExecutionBackend::DirectMl => Some(DirectMLExecutionProvider::default().build()),
+        }
+    }
+
+    /// Creates a session builder that runs the model on this backend.
+    ///
+    /// If the backend fails to initialize, a warning is logged and a builder for the CPU backend is
+    /// returned instead.
+    pub fn session_builder(&self) -> ort::Result<SessionBuilder> {
+        let Some(provider) = self.execution_provider() else {
+            return Session::builder();
+        };
+
+        match Session::builder()?.with_execution_providers([provider.error_on_failure()]) {
+            Ok(builder) => Ok(builder),
+            Err(e) => {
+                eprintln!("Warning: failed to initialize {} backend, falling back to CPU: {}", self, e);
+                Session::builder()
+            }
+        }
+    }
+}
+
+impl Display for ExecutionBackend {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ExecutionBackend::Cpu => "cpu",
+            ExecutionBackend::Cuda => "cuda",
+            ExecutionBackend::DirectMl => "directml",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for ExecutionBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "cpu" => Ok(ExecutionBackend::Cpu),
+            "cuda" => Ok(ExecutionBackend::Cuda),
+            "directml" => Ok(ExecutionBackend::DirectMl),
+            _ => Err(format!("Unknown backend \"{}\", expected one of cpu, cuda, directml", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::*;
+
+    #[test]
+    fn test_parse_backend_names() {
+        assert_eq!("cpu".parse(), Ok(ExecutionBackend::Cpu));
+        assert_eq!("CUDA".parse(), Ok(ExecutionBackend::Cuda));
+        assert_eq!("directml".parse(), Ok(ExecutionBackend::DirectMl));
+        assert!("vulkan".parse::<ExecutionBackend>().is_err());
+    }
+
+    #[test]
+    fn test_cpu_backend_always_succeeds() {
+        assert!(ExecutionBackend::Cpu.session_builder().is_ok());
+    }
+}
@@ -0,0 +1,162 @@
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+use crate::crepe::{CrepeModel, SAMPLES_PER_STEP};
+
+/// The number of frames that are passed to [`CrepeModel::predict_batch`] at once.
+const BATCH_SIZE: usize = 16;
+
+/// Seed of the noise generator, fixed so that every run benchmarks the same audio.
+const SEED: u64 = 0x5EED_CAFE_F00D_BEEF;
+
+/// Latency and throughput of a way of running the model.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimingStats {
+    pub mean: Duration,
+    /// The median latency.
+    pub p50: Duration,
+    pub p95: Duration,
+    pub frames_per_second: f64,
+}
+
+impl TimingStats {
+    /// Calculates the statistics from the per-frame latencies and the total time all frames took.
+    fn new(frame_times: &[Duration], total_time: Duration, frames: usize) -> Self {
+        let mut sorted = frame_times.to_vec();
+        sorted.sort();
+        let percentile = |p: f64| sorted[((sorted.len() - 1) as f64 * p).round() as usize];
+
+        TimingStats {
+            mean: sorted.iter().sum::<Duration>() / sorted.len() as u32,
+            p50: percentile(0.5),
+            p95: percentile(0.95),
+            frames_per_second: frames as f64 / total_time.as_secs_f64(),
+        }
+    }
+}
+
+impl Display for TimingStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "mean {:.2} ms, p50 {:.2} ms, p95 {:.2} ms, {:.1} frames/s",
+            self.mean.as_secs_f64() * 1000.0,
+            self.p50.as_secs_f64() * 1000.0,
+            self.p95.as_secs_f64() * 1000.0,
+            self.frames_per_second,
+        )
+    }
+}
+
+/// Per-frame timings of running the model on single frames and on batches of frames.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BenchmarkResult {
+    pub frames: usize,
+    /// Timings of [`CrepeModel::predict_single`].
+    pub single: TimingStats,
+    /// Timings of [`CrepeModel::predict_batch`], where the latency of a frame is the time of its
+    /// batch divided by the number of frames in it.
+    pub batch: TimingStats,
+}
+
+impl Display for BenchmarkResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Benchmark of {} frames of {} samples:", self.frames, SAMPLES_PER_STEP)?;
+        writeln!(f, "  single: {}", self.single)?;
+        write!(f, "  batch of {}: {}", BATCH_SIZE, self.batch)
+    }
+}
+
+/// Generates `count` frames of reproducible white noise.
+fn noise_frames(count: usize) -> Vec<[i16; SAMPLES_PER_STEP]> {
+    // Xorshift is plenty random for benchmark input and avoids depending on a random number crate.
+    let mut state = SEED;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+
+        (state >> 48) as i16
+    };
+
+    (0..count)
+        .map(|_| std::array::from_fn(|_| next() / 4))
+        .collect()
+}
+
+/// Runs `frames` frames of noise through the model, first one at a time and then in batches.
+///
+/// # Panics
+///
+/// Panics if `frames` is zero.
+pub fn run_benchmark(model: &CrepeModel, frames: usize) -> BenchmarkResult {
+    assert!(frames > 0, "benchmark needs at least one frame");
+
+    let input = noise_frames(frames);
+
+    let start = Instant::now();
+    let single_times = input.iter()
+        .map(|frame| {
+            let frame_start = Instant::now();
+            model.predict_single(*frame);
+
+            frame_start.elapsed()
+        })
+        .collect::<Vec<Duration>>();
+    let single = TimingStats::new(&single_times, start.elapsed(), frames);
+
+    let start = Instant::now();
+    let batch_times = input.chunks(BATCH_SIZE)
+        .flat_map(|batch| {
+            let batch_start = Instant::now();
+            model.predict_batch(batch);
+            let frame_time = batch_start.elapsed() / batch.len() as u32;
+
+            std::iter::repeat_n(frame_time, batch.len())
+        })
+        .collect::<Vec<Duration>>();
+    let batch = TimingStats::new(&batch_times, start.elapsed(), frames);
+
+    BenchmarkResult {
+        frames,
+        single,
+        batch,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ort::session::Session;
+    use crate::benchmark::*;
+
+    #[test]
+    fn test_timing_stats() {
+        let times = (1..=100).map(Duration::from_millis).collect::<Vec<Duration>>();
+
+        let stats = TimingStats::new(&times, Duration::from_secs(2), 100);
+        assert_eq!(stats.mean, Duration::from_micros(50_500));
+        assert_eq!(stats.p50, Duration::from_millis(51));
+        assert_eq!(stats.p95, Duration::from_millis(95));
+        assert_eq!(stats.frames_per_second, 50.0);
+    }
+
+    #[test]
+    fn test_noise_is_reproducible() {
+        assert_eq!(noise_frames(2), noise_frames(2));
+        assert_ne!(noise_frames(2)[0], noise_frames(2)[1]);
+    }
+
+    #[test]
+    #[ignore = "requires crepe-full.onnx in the working directory"]
+    fn test_benchmark_runs() {
+        let session = Session::builder().unwrap().commit_from_file("crepe-full.onnx").unwrap();
+        let model = CrepeModel::new(session).unwrap();
+
+        let result = run_benchmark(&model, 8);
+        assert_eq!(result.frames, 8);
+        for stats in [result.single, result.batch] {
+            assert!(stats.mean > Duration::ZERO);
+            assert!(stats.p50 <= stats.p95);
+            assert!(stats.frames_per_second > 0.0);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+
+/// Which channel of multi-channel input audio is analyzed.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum ChannelMode {
+    /// The average of all channels.
+    #[default]
+    Mono,
+    /// The first channel.
+    Left,
+    /// The second channel, or the first one for mono input.
+    Right,
+    /// The channel with the given zero-based index, or the last one if there are fewer channels.
+    Index(usize),
+    /// The average of all channels, while the first and second channel are also analyzed
+    /// separately to compare two sources, e.g. two instruments that are tuned to each other.
+    Stereo,
+}
+
+/// Converts interleaved audio with `channels` channels into a single channel according to `mode`.
+///
+/// # Panics
+///
+/// Panics if `channels` is zero.
+pub fn downmix(samples: &[i16], channels: usize, mode: ChannelMode) -> Vec<i16> {
+    assert!(channels > 0, "audio must have at least one channel");
+
+    if channels == 1 {
+        return samples.to_vec();
+    }
+
+    let frames = samples.chunks_exact(channels);
+    let channel = match mode {
+        ChannelMode::Mono | ChannelMode::Stereo => {
+            return frames
+                .map(|frame| (frame.iter().map(|&sample| sample as i32).sum::<i32>() / channels as i32) as i16)
+                .collect();
+        }
+        ChannelMode::Left => 0,
+        ChannelMode::Right => 1,
+        ChannelMode::Index(index) => index.min(channels - 1),
+    };
+
+    frames.map(|frame| frame[channel]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::channels::*;
+
+    #[test]
+    fn test_mono_averages_channels() {
+        assert_eq!(downmix(&[1, 3, 1, 3], 2, ChannelMode::Mono), [2, 2]);
+        // Summing must not overflow for loud input.
+        assert_eq!(downmix(&[i16::MAX, i16::MAX], 2, ChannelMode::Mono), [i16::MAX]);
+    }
+
+    #[test]
+    fn test_stereo_averages_channels() {
+        assert_eq!(downmix(&[1, 3, 10, 20], 2, ChannelMode::Stereo), [2, 15]);
+    }
+
+    #[test]
+    fn test_selects_channel() {
+        let samples = [1, 2, 3, 4, 5, 6];
+
+        assert_eq!(downmix(&samples, 3, ChannelMode::Left), [1, 4]);
+        assert_eq!(downmix(&samples, 3, ChannelMode::Right), [2, 5]);
+        assert_eq!(downmix(&samples, 3, ChannelMode::Index(2)), [3, 6]);
+        assert_eq!(downmix(&samples, 3, ChannelMode::Index(7)), [3, 6]);
+    }
+
+    #[test]
+    fn test_single_channel_is_unchanged() {
+        assert_eq!(downmix(&[1, 2, 3], 1, ChannelMode::Right), [1, 2, 3]);
+    }
+}
@@ -0,0 +1,210 @@
+use std::path::PathBuf;
+use pitch_overlay::backend::ExecutionBackend;
+use pitch_overlay::crepe::{ActivationKind, ModelCapacity};
+
+pub(crate) const USAGE: &str = "Usage: pitch-overlay [--model <path>] [--model-capacity tiny|small|medium|large|full] [--activation-kind probabilities|logits|auto] [--backend cpu|cuda|directml] [--benchmark <frames>] [--analyze <wav> [--json <path>]] [--headless] [--device <name>] [--serve <port>] [--config <path> [--save-config]] [--download-model --model-sha256 <checksum> [--model-url <url>]]";
+
+/// Options passed to the application on the command line.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Args {
+    /// Path of the ONNX model file, if it should not be loaded from the default location.
+    pub(crate) model_path: Option<PathBuf>,
+    /// The size of the model, which picks the default model file if no path is given.
+    pub(crate) model_capacity: ModelCapacity,
+    /// How the output of the model is scaled, for models that output logits instead of probabilities.
+    pub(crate) activation_kind: ActivationKind,
+    /// The hardware to run the model on.
+    pub(crate) backend: ExecutionBackend,
+    /// The number of frames to benchmark the model with instead of opening the window.
+    pub(crate) benchmark_frames: Option<usize>,
+    /// The WAV file to analyze instead of opening the window.
+    pub(crate) analyze_path: Option<PathBuf>,
+    /// The file that the analysis is written to as JSON, it is printed if no path is given.
+    pub(crate) json_path: Option<PathBuf>,
+    /// Whether to print the pitch of every chunk of the default input device, or of the analyzed
+    /// WAV file, instead of opening the window.
+    pub(crate) headless: bool,
+    /// Part of the name of the input device to capture in headless mode, e.g. "USB", ignoring case.
+    pub(crate) device: Option<String>,
+    /// The port to stream predictions to WebSocket clients on, alongside the window or the headless output.
+    pub(crate) serve_port: Option<u16>,
+    /// The TOML or JSON file that the settings are loaded from instead of the stored settings.
+    pub(crate) config_path: Option<PathBuf>,
+    /// Whether the settings are written back to the config file on exit.
+    pub(crate) save_config: bool,
+    /// Whether to download the model if it is missing.
+    pub(crate) download_model: bool,
+    /// URL to download the model from, if it should not be downloaded from the default location.
+    pub(crate) model_url: Option<String>,
+    /// The expected SHA-256 checksum of the downloaded model.
+    pub(crate) model_sha256: Option<String>,
+}
+
+impl Args {
+    /// Parses the given arguments, not including the name of the executable.
+    pub(crate) fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_owned(), Some(value.to_owned())),
+                None => (arg, None),
+            };
+            let mut value = || inline_value.clone()
+                .or_else(|| args.next())
+                .ok_or(format!("Missing value for {}", flag));
+
+            match flag.as_str() {
+                "--model" => parsed.model_path = Some(PathBuf::from(value()?)),
+                "--model-capacity" => parsed.model_capacity = value()?.parse()?,
+                "--activation-kind" => parsed.activation_kind = value()?.parse()?,
+                "--backend" => parsed.backend = value()?.parse()?,
+                "--benchmark" => {
+                    let frames = value()?;
+                    let frames = frames.parse::<usize>().ok()
+                        .filter(|frames| *frames > 0)
+                        .ok_or(format!("Invalid number of benchmark frames \"{}\"", frames))?;
+                    parsed.benchmark_frames = Some(frames);
+                }
+                "--analyze" => parsed.analyze_path = Some(PathBuf::from(value()?)),
+                "--json" => parsed.json_path = Some(PathBuf::from(value()?)),
+                "--headless" => parsed.headless = true,
+                "--device" => parsed.device = Some(value()?),
+                "--serve" => {
+                    let port = value()?;
+                    parsed.serve_port = Some(port.parse::<u16>().map_err(|_| format!("Invalid port \"{}\"", port))?);
+                }
+                "--config" => parsed.config_path = Some(PathBuf::from(value()?)),
+                "--save-config" => parsed.save_config = true,
+                "--download-model" => parsed.download_model = true,
+                "--model-url" => parsed.model_url = Some(value()?),
+                "--model-sha256" => parsed.model_sha256 = Some(value()?),
+                _ => return Err(format!("Unknown argument \"{}\"", flag)),
+            }
+        }
+        if parsed.json_path.is_some() && parsed.analyze_path.is_none() {
+            return Err("--json requires a WAV file to be analyzed with --analyze".to_owned());
+        }
+        if parsed.headless && parsed.json_path.is_some() {
+            return Err("--headless prints lines instead of JSON and cannot be combined with --json".to_owned());
+        }
+        if parsed.save_config && parsed.config_path.is_none() {
+            return Err("--save-config requires a config file to be given with --config".to_owned());
+        }
+        if parsed.download_model && parsed.model_sha256.is_none() {
+            return Err("--download-model requires the expected checksum to be passed with --model-sha256".to_owned());
+        }
+
+        Ok(parsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_no_arguments_uses_defaults() {
+        assert_eq!(parse(&[]), Ok(Args::default()));
+    }
+
+    #[test]
+    fn test_model_path() {
+        let expected = Some(PathBuf::from("/models/crepe.onnx"));
+
+        assert_eq!(parse(&["--model", "/models/crepe.onnx"]).unwrap().model_path, expected);
+        assert_eq!(parse(&["--model=/models/crepe.onnx"]).unwrap().model_path, expected);
+    }
+
+    #[test]
+    fn test_model_capacity() {
+        assert_eq!(parse(&["--model-capacity", "small"]).unwrap().model_capacity, ModelCapacity::Small);
+        assert!(parse(&["--model-capacity=huge"]).is_err());
+    }
+
+    #[test]
+    fn test_activation_kind() {
+        assert_eq!(parse(&[]).unwrap().activation_kind, ActivationKind::Auto);
+        assert_eq!(parse(&["--activation-kind", "logits"]).unwrap().activation_kind, ActivationKind::Logits);
+        assert!(parse(&["--activation-kind=softmax"]).is_err());
+    }
+
+    #[test]
+    fn test_backend() {
+        assert_eq!(parse(&["--backend", "cuda"]).unwrap().backend, ExecutionBackend::Cuda);
+    }
+
+    #[test]
+    fn test_benchmark() {
+        assert_eq!(parse(&["--benchmark", "100"]).unwrap().benchmark_frames, Some(100));
+        assert!(parse(&["--benchmark", "0"]).is_err());
+        assert!(parse(&["--benchmark", "many"]).is_err());
+    }
+
+    #[test]
+    fn test_analyze() {
+        let args = parse(&["--analyze", "voice.wav", "--json=voice.json"]).unwrap();
+
+        assert_eq!(args.analyze_path, Some(PathBuf::from("voice.wav")));
+        assert_eq!(args.json_path, Some(PathBuf::from("voice.json")));
+        assert_eq!(parse(&["--analyze", "voice.wav"]).unwrap().json_path, None);
+        assert!(parse(&["--json", "voice.json"]).is_err());
+    }
+
+    #[test]
+    fn test_headless() {
+        assert!(parse(&["--headless"]).unwrap().headless);
+        assert!(!parse(&[]).unwrap().headless);
+        let args = parse(&["--headless", "--analyze", "voice.wav"]).unwrap();
+        assert!(args.headless);
+        assert_eq!(args.analyze_path, Some(PathBuf::from("voice.wav")));
+        assert!(parse(&["--headless", "--analyze", "voice.wav", "--json", "voice.json"]).is_err());
+    }
+
+    #[test]
+    fn test_device() {
+        assert_eq!(parse(&["--headless", "--device", "Scarlett"]).unwrap().device.as_deref(), Some("Scarlett"));
+        assert_eq!(parse(&["--device=USB"]).unwrap().device.as_deref(), Some("USB"));
+        assert!(parse(&["--device"]).is_err());
+    }
+
+    #[test]
+    fn test_serve() {
+        assert_eq!(parse(&["--serve", "8080"]).unwrap().serve_port, Some(8080));
+        assert_eq!(parse(&["--headless", "--serve=9000"]).unwrap().serve_port, Some(9000));
+        assert!(parse(&["--serve", "70000"]).is_err());
+        assert!(parse(&["--serve"]).is_err());
+    }
+
+    #[test]
+    fn test_config() {
+        let args = parse(&["--config", "pitch-overlay.toml", "--save-config"]).unwrap();
+
+        assert_eq!(args.config_path, Some(PathBuf::from("pitch-overlay.toml")));
+        assert!(args.save_config);
+        assert!(!parse(&["--config=settings.json"]).unwrap().save_config);
+        assert!(parse(&["--save-config"]).is_err());
+    }
+
+    #[test]
+    fn test_download_model() {
+        let args = parse(&["--download-model", "--model-sha256", "abc123", "--model-url=http://localhost/model.onnx"]).unwrap();
+
+        assert!(args.download_model);
+        assert_eq!(args.model_sha256.as_deref(), Some("abc123"));
+        assert_eq!(args.model_url.as_deref(), Some("http://localhost/model.onnx"));
+        assert!(parse(&["--download-model"]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        assert!(parse(&["--model"]).is_err());
+        assert!(parse(&["--backend", "abacus"]).is_err());
+        assert!(parse(&["--unknown"]).is_err());
+    }
+}
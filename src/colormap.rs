@@ -0,0 +1,156 @@
+//! Colormaps that the heatmap, the spectrogram and exported activations are drawn with.
+use eframe::egui::Color32;
+use serde::{Deserialize, Serialize};
+
+/// Maps a value between 0 and 1 to a color.
+pub trait Colormap {
+    /// The color of `t`, values outside of `0.0..=1.0` are clamped.
+    fn map(&self, t: f32) -> Color32;
+}
+
+/// From dark blue over green to bright red, the original colors of the heatmap.
+#[derive(Debug, Clone, Copy)]
+pub struct BlueRed;
+
+impl Colormap for BlueRed {
+    fn map(&self, t: f32) -> Color32 {
+        let t = t.clamp(0.0, 1.0);
+        let hue = (1.0 - t) * 2.0 / 3.0;
+        let value = 0.2 + 0.8 * t;
+
+        // HSV with full saturation, interpreted as linear RGB like egui's Hsva.
+        let channel = |offset: f32| {
+            let k = (offset + hue * 6.0) % 6.0;
+            value - value * k.min(4.0 - k).clamp(0.0, 1.0)
+        };
+        let [r, g, b] = [channel(5.0), channel(3.0), channel(1.0)].map(|linear| {
+            let srgb = if linear <= 0.003_130_8 { 12.92 * linear } else { 1.055 * linear.powf(1.0 / 2.4) - 0.055 };
+            (srgb * 255.0).round() as u8
+        });
+
+        Color32::from_rgb(r, g, b)
+    }
+}
+
+/// From black to white.
+#[derive(Debug, Clone, Copy)]
+pub struct Grayscale;
+
+impl Colormap for Grayscale {
+    fn map(&self, t: f32) -> Color32 {
+        Color32::from_gray((t.clamp(0.0, 1.0) * 255.0).round() as u8)
+    }
+}
+
+/// A colormap that linearly interpolates between evenly spaced sRGB colors.
+#[derive(Debug, Clone, Copy)]
+pub struct Gradient(&'static [[u8; 3]]);
+
+impl Colormap for Gradient {
+    fn map(&self, t: f32) -> Color32 {
+        let position = t.clamp(0.0, 1.0) * (self.0.len() - 1) as f32;
+        let index = (position as usize).min(self.0.len() - 2);
+        let fraction = position - index as f32;
+        let [r, g, b] = [0, 1, 2].map(|channel| {
+            let (from, to) = (self.0[index][channel] as f32, self.0[index + 1][channel] as f32);
+            (from + (to - from) * fraction).round() as u8
+        });
+
+        Color32::from_rgb(r, g, b)
+    }
+}
+
+/// Viridis from matplotlib, from dark purple over teal to yellow. It is perceptually uniform and
+/// readable with the common forms of color blindness.
+pub const VIRIDIS: Gradient = Gradient(&[
+    [68, 1, 84],
+    [72, 40, 120],
+    [62, 73, 137],
+    [49, 104, 142],
+    [38, 130, 142],
+    [31, 158, 137],
+    [53, 183, 121],
+    [110, 206, 88],
+    [253, 231, 37],
+]);
+
+/// Magma from matplotlib, from black over purple to pale yellow. Like viridis it is perceptually
+/// uniform and readable with color blindness.
+pub const MAGMA: Gradient = Gradient(&[
+    [0, 0, 4],
+    [28, 16, 68],
+    [79, 18, 123],
+    [129, 37, 129],
+    [181, 54, 122],
+    [229, 80, 100],
+    [251, 135, 97],
+    [254, 194, 135],
+    [252, 253, 191],
+]);
+
+/// The colormaps that can be chosen in the settings.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum ColormapKind {
+    #[default]
+    BlueRed,
+    Viridis,
+    Magma,
+    Grayscale,
+}
+
+impl ColormapKind {
+    pub const ALL: [ColormapKind; 4] = [ColormapKind::BlueRed, ColormapKind::Viridis, ColormapKind::Magma, ColormapKind::Grayscale];
+
+    pub fn colormap(self) -> &'static dyn Colormap {
+        match self {
+            ColormapKind::BlueRed => &BlueRed,
+            ColormapKind::Viridis => &VIRIDIS,
+            ColormapKind::Magma => &MAGMA,
+            ColormapKind::Grayscale => &Grayscale,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::colormap::*;
+
+    /// The relative luminance of a color as defined by WCAG.
+    fn luminance(color: Color32) -> f32 {
+        let linear = |channel: u8| {
+            let srgb = channel as f32 / 255.0;
+            if srgb <= 0.04045 { srgb / 12.92 } else { ((srgb + 0.055) / 1.055).powf(2.4) }
+        };
+
+        0.2126 * linear(color.r()) + 0.7152 * linear(color.g()) + 0.0722 * linear(color.b())
+    }
+
+    #[test]
+    fn test_colors_at_ends_and_center() {
+        for kind in ColormapKind::ALL {
+            let colormap = kind.colormap();
+            for t in [0.0, 0.5, 1.0] {
+                assert_eq!(colormap.map(t).a(), 255, "{:?} at {}", kind, t);
+            }
+            assert_ne!(colormap.map(0.0), colormap.map(1.0), "{:?}", kind);
+            // Values out of range are clamped.
+            assert_eq!(colormap.map(-1.0), colormap.map(0.0), "{:?}", kind);
+            assert_eq!(colormap.map(2.0), colormap.map(1.0), "{:?}", kind);
+        }
+        assert_eq!(BlueRed.map(1.0), Color32::from_rgb(255, 0, 0));
+        assert_eq!(Grayscale.map(0.5), Color32::from_gray(128));
+        assert_eq!(VIRIDIS.map(0.0), Color32::from_rgb(68, 1, 84));
+        assert_eq!(MAGMA.map(1.0), Color32::from_rgb(252, 253, 191));
+    }
+
+    #[test]
+    fn test_luminance_is_monotonic() {
+        for kind in [ColormapKind::Viridis, ColormapKind::Magma, ColormapKind::Grayscale] {
+            let colormap = kind.colormap();
+            let luminances = (0..=100).map(|i| luminance(colormap.map(i as f32 / 100.0))).collect::<Vec<f32>>();
+            for pair in luminances.windows(2) {
+                assert!(pair[1] >= pair[0], "{:?} gets darker from {} to {}", kind, pair[0], pair[1]);
+            }
+        }
+    }
+}
@@ -1,26 +1,177 @@
 use std::convert::TryInto;
+use std::fmt::{Display, Formatter};
 use std::iter::Iterator;
-use lazy_static::lazy_static;
+use std::str::FromStr;
 use ndarray::{Array};
 use ort::inputs;
 use ort::session::{Session, SessionOutputs};
+use ort::value::ValueType;
+use crate::notes::frequency_to_note;
 
 // TODO: document that this code is adapted from the official CREPE Python package
 
 /// Outputs of the CREPE model for a single 1024-sample audio chunk.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Prediction {
     pub frequency: f32,
     pub confidence: f32,
 }
 
+/// A prediction along with the nearest note, as shown by a tuner.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NotePrediction {
+    pub prediction: Prediction,
+    pub note_name: String,
+    pub octave: i32,
+    /// Signed deviation of the predicted frequency from the note in cents, in the range `-50.0..=50.0`.
+    pub cents_offset: f32,
+}
+
+impl NotePrediction {
+    /// Finds the nearest note of `prediction`, using `a4` as the reference frequency.
+    ///
+    /// Like [`frequency_to_note`], the note is meaningless if the frequency is not positive.
+    pub fn from_prediction(prediction: Prediction, a4: f32) -> Self {
+        let note = frequency_to_note(prediction.frequency, a4);
+
+        NotePrediction {
+            prediction,
+            note_name: note.name.to_owned(),
+            octave: note.octave,
+            cents_offset: note.cents_offset,
+        }
+    }
+}
+
+/// A prediction along with the time in seconds of the window it was made from, relative to the
+/// start of the audio.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimedPrediction {
+    pub time_s: f64,
+    pub prediction: Prediction,
+}
+
+/// The size of the CREPE model, smaller models are faster but less accurate.
+///
+/// All capacities take the same input and output the same 360 bins, so everything in this module
+/// works the same way regardless of which model is loaded.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ModelCapacity {
+    Tiny,
+    Small,
+    Medium,
+    Large,
+    #[default]
+    Full,
+}
+
+impl ModelCapacity {
+    /// The file name the model of this capacity is loaded from if no path is given.
+    pub fn default_file_name(&self) -> String {
+        format!("crepe-{}.onnx", self)
+    }
+}
+
+impl Display for ModelCapacity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ModelCapacity::Tiny => "tiny",
+            ModelCapacity::Small => "small",
+            ModelCapacity::Medium => "medium",
+            ModelCapacity::Large => "large",
+            ModelCapacity::Full => "full",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for ModelCapacity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "tiny" => Ok(ModelCapacity::Tiny),
+            "small" => Ok(ModelCapacity::Small),
+            "medium" => Ok(ModelCapacity::Medium),
+            "large" => Ok(ModelCapacity::Large),
+            "full" => Ok(ModelCapacity::Full),
+            _ => Err(format!("Unknown model capacity \"{}\", expected one of tiny, small, medium, large, full", s)),
+        }
+    }
+}
+
 /// The default audio sample rate that is expected by the CREPE model.
 pub const SAMPLE_RATE: u32 = 16_000;
 
 /// The number of samples that is used to predict a single pitch output.
 pub const SAMPLES_PER_STEP: usize = 1024;
 
-type Activation = [f32; 360];
+/// The maximum number of chunks that are passed to the model in a single inference run, if the
+/// model supports batching.
+const MAX_BATCH_SIZE: usize = 256;
+
+/// The number of pitch bins the model outputs, which are 20 cents apart.
+pub const ACTIVATION_BINS: usize = 360;
+
+/// How strongly each pitch bin is present in an audio chunk, from the lowest to the highest pitch.
+pub type Activation = [f32; ACTIVATION_BINS];
+
+/// Something that detects the pitch of audio chunks, e.g. the CREPE model or a simpler algorithm.
+pub trait PitchDetector: Send {
+    /// Detects the pitch of a single audio chunk.
+    fn predict_single(&self, audio: [i16; SAMPLES_PER_STEP]) -> Prediction;
+
+    /// The sample rate in Hz that the audio has to be at.
+    fn sample_rate(&self) -> u32;
+
+    /// The number of samples of audio that a single prediction is made from.
+    fn samples_per_step(&self) -> usize;
+
+    /// Detects the pitch along with the activation of every pitch bin, if the detector has them.
+    fn predict_with_activation(&self, audio: [i16; SAMPLES_PER_STEP]) -> (Prediction, Option<Activation>) {
+        (self.predict_single(audio), None)
+    }
+
+    /// Like [`PitchDetector::predict_with_activation`], but picks the pitch after reinforcing the
+    /// fundamental with [`harmonic_sum`] if the detector has activations.
+    fn predict_with_harmonic_sum(&self, audio: [i16; SAMPLES_PER_STEP]) -> (Prediction, Option<Activation>) {
+        self.predict_with_activation(audio)
+    }
+
+    /// Like [`PitchDetector::predict_with_activation`], or [`PitchDetector::predict_with_harmonic_sum`]
+    /// if `harmonic_summation` is set, but only picks the pitch from the bins between the given
+    /// frequencies in Hz if the detector has activations, see [`mask_activation`].
+    ///
+    /// Detectors without activations ignore the range, their pitch has to be checked afterwards.
+    fn predict_in_range(&self, audio: [i16; SAMPLES_PER_STEP], harmonic_summation: bool, _min_hz: f32, _max_hz: f32) -> (Prediction, Option<Activation>) {
+        if harmonic_summation {
+            self.predict_with_harmonic_sum(audio)
+        } else {
+            self.predict_with_activation(audio)
+        }
+    }
+
+    /// Detects the pitch like [`PitchDetector::predict_in_range`] if `pitch_range` is given, or
+    /// otherwise like [`PitchDetector::predict_with_harmonic_sum`] or
+    /// [`PitchDetector::predict_with_activation`] depending on `harmonic_summation`.
+    ///
+    /// Returns an error instead of panicking if the detector fails, e.g. because a model cannot be
+    /// run. Detectors that cannot fail do not need to override this.
+    fn try_predict(&self, audio: [i16; SAMPLES_PER_STEP], harmonic_summation: bool, pitch_range: Option<(f32, f32)>) -> Result<(Prediction, Option<Activation>), PredictError> {
+        Ok(match pitch_range {
+            Some((min_hz, max_hz)) => self.predict_in_range(audio, harmonic_summation, min_hz, max_hz),
+            None if harmonic_summation => self.predict_with_harmonic_sum(audio),
+            None => self.predict_with_activation(audio),
+        })
+    }
+
+    /// Detects the pitch of a single audio chunk along with its nearest note, using `a4` as the
+    /// reference frequency.
+    fn predict_note(&self, audio: [i16; SAMPLES_PER_STEP], a4: f32) -> NotePrediction {
+        NotePrediction::from_prediction(self.predict_single(audio), a4)
+    }
+}
 
 fn argmax(values: &[f32]) -> Option<usize> {
     values.iter()
@@ -44,65 +195,847 @@ fn std(values: &[f32]) -> f32 {
     variance.sqrt()
 }
 
-pub struct CrepeModel {
-    model: Session,
+/// Calculates the root mean square of audio samples.
+pub fn rms(audio: &[i16]) -> f32 {
+    if audio.is_empty() {
+        return 0.0;
+    }
+    let sum_of_squares = audio.iter().map(|x| (*x as f32) * (*x as f32)).sum::<f32>();
+
+    (sum_of_squares / audio.len() as f32).sqrt()
+}
+
+/// Calculates the level of audio samples in decibels relative to full scale.
+///
+/// Silence results in negative infinity.
+pub fn rms_dbfs(audio: &[i16]) -> f32 {
+    20.0 * (rms(audio) / i16::MAX as f32).log10()
+}
+
+/// The number of bins on each side of the most activated bin that are averaged to calculate the
+/// pitch, which matches the reference implementation.
+pub const DEFAULT_LOCAL_AVERAGE_HALF_WIDTH: usize = 4;
+
+/// Calculates the weighted average cents of the bins up to `half_width` bins around the most
+/// activated bin, where `cents_mapping` holds the cents of every bin.
+///
+/// A half width of zero uses the cents of the most activated bin as they are.
+fn local_average_cents(activation: &Activation, cents_mapping: &[f32], half_width: usize) -> f32 {
+    local_average_cents_around(activation, argmax(activation).unwrap(), cents_mapping, half_width)
+}
+
+/// Calculates the weighted average cents of the bins up to `half_width` bins around the given
+/// center bin.
+fn local_average_cents_around(activation: &Activation, center: usize, cents_mapping: &[f32], half_width: usize) -> f32 {
+    let start = center.saturating_sub(half_width);
+    let end = center.saturating_add(half_width).saturating_add(1).min(activation.len());
+    let product_sum: f32 = (start..end).map(|i| activation[i] * cents_mapping[i]).sum();
+    let weight_sum: f32 = (start..end).map(|i| activation[i]).sum();
+
+    product_sum / weight_sum
+}
+
+/// The cents value of the lowest pitch bin of CREPE, about 32.7 Hz (C1), as used by the
+/// reference implementation.
+#[allow(clippy::excessive_precision)]
+pub const FIRST_BIN_CENTS: f32 = 1997.3794084376191;
+
+/// The distance in cents between two pitch bins of CREPE.
+pub const CENTS_PER_BIN: f32 = 20.0;
+
+/// Calculates the cents value of every pitch bin of a model whose lowest bin is at `start_cents`
+/// and whose bins are `step_cents` apart.
+///
+/// Cents are measured relative to 10 Hz, like the reference implementation does.
+pub fn build_cents_mapping(bins: usize, start_cents: f32, step_cents: f32) -> Vec<f32> {
+    (0..bins)
+        .map(|bin| bin as f32 * step_cents + start_cents)
+        .collect()
+}
+
+/// Normalizes an audio chunk to zero mean and unit variance, as the model expects.
+///
+/// Since the audio is normalized anyways, its scale does not matter: integer samples can be passed
+/// as they are without first converting them to the range `-1.0..=1.0`.
+fn normalize(audio: [f32; SAMPLES_PER_STEP]) -> [f32; SAMPLES_PER_STEP] {
+    let mean = mean(&audio);
+    let std = std(&audio);
+    let clipped_std = std.clamp(1e-8, f32::MAX);
+
+    audio.map(|x| (x - mean) / clipped_std)
+}
+
+fn cents_to_frequency(cents: f32) -> f32 {
+    10.0 * 2.0_f32.powf(cents / 1200.0)
+}
+
+fn frequency_to_cents(frequency: f32) -> f32 {
+    1200.0 * (frequency / 10.0).log2()
+}
+
+/// Calculates the fractional index of the CREPE activation bin that a frequency falls into.
+///
+/// This only applies to the default bin layout of CREPE starting at [`FIRST_BIN_CENTS`] in steps of
+/// [`CENTS_PER_BIN`], not to models with a custom mapping set by
+/// [`CrepeModel::with_cents_mapping`]. Use [`frequency_range_to_bins`] with the model's mapping
+/// for those.
+pub fn frequency_to_bin(frequency: f32) -> f32 {
+    (1200.0 * (frequency / 10.0).log2() - FIRST_BIN_CENTS) / CENTS_PER_BIN
+}
+
+fn confidence(activation: &Activation) -> f32 {
+    activation.iter().copied().reduce(f32::max).unwrap_or(0.0)
+}
+
+fn to_prediction(activation: &Activation, cents_mapping: &[f32], half_width: usize) -> Prediction {
+    Prediction {
+        frequency: cents_to_frequency(local_average_cents(activation, cents_mapping, half_width)),
+        confidence: confidence(activation),
+    }
+}
+
+/// The number of harmonics, including the fundamental, that [`harmonic_sum`] adds up.
+const HARMONIC_SUM_HARMONICS: usize = 4;
+
+/// How much each harmonic counts in [`harmonic_sum`] relative to the one below it.
+const HARMONIC_SUM_DECAY: f32 = 0.8;
+
+/// Reinforces the fundamental of an activation by adding the activation of its harmonics to it,
+/// which helps when the model puts more energy an octave above the true pitch than on it.
+///
+/// Every bin of the result is the weighted sum of the bin itself and the bins that are 2, 3 and 4
+/// times its frequency, so a bin whose octave is strongly activated outweighs that octave.
+pub fn harmonic_sum(activation: &Activation) -> Activation {
+    let bins_per_octave = 1200.0 / CENTS_PER_BIN;
+
+    std::array::from_fn(|bin| {
+        (1..=HARMONIC_SUM_HARMONICS)
+            .filter_map(|harmonic| {
+                let offset = (bins_per_octave * (harmonic as f32).log2()).round() as usize;
+                let weight = HARMONIC_SUM_DECAY.powi(harmonic as i32 - 1);
+                activation.get(bin + offset).map(|value| value * weight)
+            })
+            .sum()
+    })
+}
+
+/// Like [`to_prediction`], but picks the pitch from the [`harmonic_sum`] of the activation.
+///
+/// The confidence is still taken from the activation itself, since the sums are not probabilities.
+fn to_harmonic_prediction(activation: &Activation, cents_mapping: &[f32], half_width: usize) -> Prediction {
+    Prediction {
+        frequency: cents_to_frequency(local_average_cents(&harmonic_sum(activation), cents_mapping, half_width)),
+        confidence: confidence(activation),
+    }
+}
+
+/// Zeroes every bin of an activation below `min_bin` and above `max_bin`, so that a strong peak
+/// outside of that range, e.g. a harmonic, cannot be picked as the pitch.
+pub fn mask_activation(activation: &mut Activation, min_bin: usize, max_bin: usize) {
+    for (bin, value) in activation.iter_mut().enumerate() {
+        if bin < min_bin || bin > max_bin {
+            *value = 0.0;
+        }
+    }
 }
 
-lazy_static! {
-    static ref CENTS_MAPPING: [f32; 360] = (0..360)
-        .map(|x| x as f32 * 20.0 + 1997.3794084376191)
-        .collect::<Vec<f32>>()
-        .try_into()
+/// The bins of `cents_mapping` nearest to `min_hz` and `max_hz`, to pass to [`mask_activation`].
+pub fn frequency_range_to_bins(cents_mapping: &[f32], min_hz: f32, max_hz: f32) -> (usize, usize) {
+    let nearest_bin = |frequency: f32| {
+        let cents = frequency_to_cents(frequency);
+        argmax(&cents_mapping.iter().map(|bin_cents| -(bin_cents - cents).abs()).collect::<Vec<f32>>()).unwrap_or(0)
+    };
+
+    (nearest_bin(min_hz), nearest_bin(max_hz))
+}
+
+/// Picks the pitch from the bins of an activation between `min_bin` and `max_bin`, after
+/// reinforcing the fundamental with [`harmonic_sum`] if `harmonic_summation` is set.
+///
+/// The harmonics are summed before masking, so that bins at the top of the range still get the
+/// support of their harmonics above it. Like the pitch, the confidence is only taken from the
+/// bins in range.
+fn to_prediction_in_range(
+    activation: &Activation,
+    cents_mapping: &[f32],
+    half_width: usize,
+    harmonic_summation: bool,
+    (min_bin, max_bin): (usize, usize),
+) -> Prediction {
+    let mut masked = *activation;
+    mask_activation(&mut masked, min_bin, max_bin);
+    if !harmonic_summation {
+        return to_prediction(&masked, cents_mapping, half_width);
+    }
+    let mut summed = harmonic_sum(activation);
+    mask_activation(&mut summed, min_bin, max_bin);
+
+    Prediction {
+        frequency: cents_to_frequency(local_average_cents(&summed, cents_mapping, half_width)),
+        confidence: confidence(&masked),
+    }
+}
+
+/// Cost per squared bin of distance for moving between pitch bins from one frame to the next.
+///
+/// A jump of 12 bins (240 cents) costs about as much as a 4x less likely emission, an octave jump
+/// is practically impossible.
+const VITERBI_TRANSITION_WEIGHT: f32 = 0.01;
+
+/// Finds the most likely sequence of pitch bins through the given activations.
+///
+/// Activations of each frame are treated as emission probabilities, moving between bins in
+/// consecutive frames is penalized proportionally to the squared bin distance.
+fn viterbi_decode(activations: &[Activation]) -> Vec<usize> {
+    let Some(first) = activations.first() else {
+        return vec![];
+    };
+
+    let emission_costs = |activation: &Activation| -> Vec<f32> {
+        let sum = activation.iter().sum::<f32>().max(f32::MIN_POSITIVE);
+        activation.iter().map(|a| -(a / sum).max(1e-12).ln()).collect()
+    };
+
+    let bins = first.len();
+    let mut costs = emission_costs(first);
+    let mut backpointers: Vec<Vec<usize>> = Vec::with_capacity(activations.len() - 1);
+    for activation in &activations[1..] {
+        let emission = emission_costs(activation);
+        let mut pointers = vec![0; bins];
+        let new_costs = (0..bins).map(|to| {
+            let (best_from, best_cost) = (0..bins)
+                .map(|from| {
+                    let distance = from.abs_diff(to) as f32;
+                    (from, costs[from] + VITERBI_TRANSITION_WEIGHT * distance * distance)
+                })
+                .min_by(|(_, a), (_, b)| a.total_cmp(b))
+                .unwrap();
+            pointers[to] = best_from;
+
+            best_cost + emission[to]
+        }).collect();
+        costs = new_costs;
+        backpointers.push(pointers);
+    }
+
+    let last = costs.iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(i, _)| i)
         .unwrap();
+    let mut path = vec![last];
+    for pointers in backpointers.iter().rev() {
+        path.push(pointers[*path.last().unwrap()]);
+    }
+    path.reverse();
+
+    path
+}
+
+/// Splits audio into windows of [`SAMPLES_PER_STEP`] samples, starting every `hop_size` samples.
+///
+/// If `center` is set, the audio is padded with half a window of silence on either side so that
+/// the n-th window is centered on sample `n * hop_size`, like the reference implementation does.
+fn frames(samples: &[i16], hop_size: usize, center: bool) -> Vec<[i16; SAMPLES_PER_STEP]> {
+    assert!(
+        hop_size > 0 && hop_size <= SAMPLES_PER_STEP,
+        "hop size must be between 1 and {}, got {}", SAMPLES_PER_STEP, hop_size,
+    );
+
+    let padded_samples;
+    let samples = if center {
+        let padding = [0; SAMPLES_PER_STEP / 2];
+        padded_samples = [&padding, samples, &padding].concat();
+        padded_samples.as_slice()
+    } else {
+        samples
+    };
+
+    samples.windows(SAMPLES_PER_STEP)
+        .step_by(hop_size)
+        .map(|window| window.try_into().unwrap())
+        .collect()
+}
+
+/// Calculates the time in seconds of the window with index `frame` if windows start every
+/// `hop_size` samples.
+///
+/// For centered windows, see [`frames`], this is the time of the window's center instead.
+pub fn frame_time(frame: usize, hop_size: usize) -> f64 {
+    (frame * hop_size) as f64 / SAMPLE_RATE as f64
+}
+
+/// Picks the number of chunks to pass to a model in a single inference run from the dimensions of
+/// its input, where a negative dimension is dynamic.
+///
+/// Models whose batch dimension is fixed are run on one chunk at a time.
+fn batch_size_from_dimensions(dimensions: &[i64]) -> usize {
+    match dimensions.first() {
+        Some(batch_dimension) if *batch_dimension < 0 => MAX_BATCH_SIZE,
+        _ => 1,
+    }
+}
+
+/// The name of the model input that is used if the model has more than one input.
+const INPUT_NAME: &str = "input";
+
+/// The name of the model output that is used if the model has more than one output.
+const OUTPUT_NAME: &str = "output_0";
+
+/// The name of an input or output of a model and its dimensions, `None` if it is not a tensor.
+type TensorInfo = (String, Option<Vec<i64>>);
+
+/// An error for models whose inputs or outputs cannot be used to detect pitch.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModelError {
+    /// The model has no input or output with the expected name, and not exactly one to use instead.
+    TensorNotFound {
+        kind: &'static str,
+        expected: &'static str,
+        available: Vec<String>,
+    },
+    /// The last dimension of an input or output does not have the expected size.
+    IncompatibleShape {
+        name: String,
+        dimensions: Option<Vec<i64>>,
+        expected: usize,
+    },
+}
+
+impl Display for ModelError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModelError::TensorNotFound { kind, expected, available } => {
+                write!(f, "Model has no {} named \"{}\", available: [{}]", kind, expected, available.join(", "))
+            }
+            ModelError::IncompatibleShape { name, dimensions: Some(dimensions), expected } => {
+                write!(f, "Model tensor \"{}\" has shape {:?}, expected a last dimension of {}", name, dimensions, expected)
+            }
+            ModelError::IncompatibleShape { name, dimensions: None, .. } => {
+                write!(f, "Model tensor \"{}\" is not a tensor", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ModelError {}
+
+fn tensor_dimensions(value_type: &ValueType) -> Option<Vec<i64>> {
+    match value_type {
+        ValueType::Tensor { dimensions, .. } => Some(dimensions.clone()),
+        _ => None,
+    }
+}
 
+/// Picks the tensor named `expected`, or the only tensor if there is no such tensor, and checks
+/// that its last dimension is `size` or dynamic.
+fn select_tensor<'a>(
+    tensors: &'a [TensorInfo],
+    kind: &'static str,
+    expected: &'static str,
+    size: usize,
+) -> Result<&'a TensorInfo, ModelError> {
+    let tensor = match tensors.iter().find(|(name, _)| name == expected) {
+        Some(tensor) => tensor,
+        None if tensors.len() == 1 => &tensors[0],
+        None => return Err(ModelError::TensorNotFound {
+            kind,
+            expected,
+            available: tensors.iter().map(|(name, _)| name.clone()).collect(),
+        }),
+    };
+
+    let (name, dimensions) = tensor;
+    let is_compatible = match dimensions.as_deref().map(|dimensions| dimensions.last()) {
+        Some(Some(last)) => *last < 0 || *last as usize == size,
+        _ => false,
+    };
+    if !is_compatible {
+        return Err(ModelError::IncompatibleShape {
+            name: name.clone(),
+            dimensions: dimensions.clone(),
+            expected: size,
+        });
+    }
+
+    Ok(tensor)
+}
+
+/// Picks the input that takes audio chunks and the output that contains the pitch bins.
+fn select_tensors<'a>(
+    inputs: &'a [TensorInfo],
+    outputs: &'a [TensorInfo],
+) -> Result<(&'a TensorInfo, &'a TensorInfo), ModelError> {
+    Ok((
+        select_tensor(inputs, "input", INPUT_NAME, SAMPLES_PER_STEP)?,
+        select_tensor(outputs, "output", OUTPUT_NAME, ACTIVATION_BINS)?,
+    ))
+}
+
+/// An error that occurred while running the model on audio.
+#[derive(Debug)]
+pub enum PredictError {
+    /// The input or output of the model does not have the expected number of values.
+    ShapeMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    /// The model failed to run or its output could not be read.
+    Inference(ort::Error),
+    /// The model returned no values.
+    EmptyOutput,
+}
+
+impl Display for PredictError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PredictError::ShapeMismatch { expected, actual } => {
+                write!(f, "Model tensor has {} values, expected {}", actual, expected)
+            }
+            PredictError::Inference(e) => write!(f, "Error running model: {}", e),
+            PredictError::EmptyOutput => write!(f, "Model returned no values"),
+        }
+    }
+}
+
+impl std::error::Error for PredictError {}
+
+impl From<ort::Error> for PredictError {
+    fn from(e: ort::Error) -> Self {
+        PredictError::Inference(e)
+    }
+}
+
+/// How the values that the model outputs for each pitch bin are scaled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ActivationKind {
+    /// Probabilities from zero to one, like the official CREPE models output after their sigmoid.
+    Probabilities,
+    /// Logits before the sigmoid, e.g. from re-exported models that leave out the last layer.
+    Logits,
+    /// Treats the output of a chunk as logits if any of its values is outside of zero to one.
+    #[default]
+    Auto,
+}
+
+impl ActivationKind {
+    /// Scales the output of the model for a single chunk into probabilities, so that the highest
+    /// one can be used as the confidence.
+    pub fn normalize(self, activation: &mut Activation) {
+        let is_logits = match self {
+            ActivationKind::Probabilities => false,
+            ActivationKind::Logits => true,
+            ActivationKind::Auto => activation.iter().any(|value| !(0.0..=1.0).contains(value)),
+        };
+        if is_logits {
+            for value in activation.iter_mut() {
+                *value = 1.0 / (1.0 + (-*value).exp());
+            }
+        }
+    }
+}
+
+impl FromStr for ActivationKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "probabilities" => Ok(ActivationKind::Probabilities),
+            "logits" => Ok(ActivationKind::Logits),
+            "auto" => Ok(ActivationKind::Auto),
+            _ => Err(format!("Unknown activation kind \"{}\", expected one of probabilities, logits, auto", s)),
+        }
+    }
+}
+
+/// Splits the values of a model output for `chunks` audio chunks into one activation per chunk.
+fn activations_from_output(values: &[f32], chunks: usize) -> Result<Vec<Activation>, PredictError> {
+    if values.is_empty() {
+        return Err(PredictError::EmptyOutput);
+    }
+    if values.len() != chunks * ACTIVATION_BINS {
+        return Err(PredictError::ShapeMismatch { expected: chunks * ACTIVATION_BINS, actual: values.len() });
+    }
+
+    Ok(values.chunks_exact(ACTIVATION_BINS)
+        .map(|bins| {
+            let mut activation = [0.0; ACTIVATION_BINS];
+            activation.copy_from_slice(bins);
+            activation
+        })
+        .collect())
+}
+
+pub struct CrepeModel {
+    model: Session,
+    /// The name of the model input that audio chunks are passed to.
+    input_name: String,
+    /// The name of the model output that contains the pitch bins.
+    output_name: String,
+    /// The maximum number of chunks passed to the model in a single inference run.
+    max_batch_size: usize,
+    /// The cents value of every pitch bin that the model outputs.
+    cents_mapping: Vec<f32>,
+    /// The number of bins on each side of the most activated bin that are averaged.
+    local_average_half_width: usize,
+    /// How the output of the model is scaled.
+    activation_kind: ActivationKind,
 }
 
 impl CrepeModel {
-    pub fn new(model: Session) -> Self {
-        CrepeModel {
-            model
+    /// Wraps a loaded model, taking the names of its input and output from the model.
+    ///
+    /// Returns an error if the model does not take chunks of [`SAMPLES_PER_STEP`] samples or does
+    /// not output [`ACTIVATION_BINS`] bins.
+    pub fn new(model: Session) -> Result<Self, ModelError> {
+        let inputs = model.inputs.iter()
+            .map(|input| (input.name.clone(), tensor_dimensions(&input.input_type)))
+            .collect::<Vec<_>>();
+        let outputs = model.outputs.iter()
+            .map(|output| (output.name.clone(), tensor_dimensions(&output.output_type)))
+            .collect::<Vec<_>>();
+        let ((input_name, input_dimensions), (output_name, _)) = select_tensors(&inputs, &outputs)?;
+        let max_batch_size = input_dimensions.as_deref().map_or(1, batch_size_from_dimensions);
+
+        Ok(CrepeModel {
+            input_name: input_name.clone(),
+            output_name: output_name.clone(),
+            model,
+            max_batch_size,
+            cents_mapping: build_cents_mapping(ACTIVATION_BINS, FIRST_BIN_CENTS, CENTS_PER_BIN),
+            local_average_half_width: DEFAULT_LOCAL_AVERAGE_HALF_WIDTH,
+            activation_kind: ActivationKind::default(),
+        })
+    }
+
+    /// Sets the cents values of the pitch bins for models that were trained with a different bin
+    /// layout than CREPE, e.g. one starting at another frequency. The mapping can be built with
+    /// [`build_cents_mapping`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the mapping does not have a value for each of the [`ACTIVATION_BINS`] bins.
+    pub fn with_cents_mapping(mut self, cents_mapping: Vec<f32>) -> Self {
+        assert_eq!(cents_mapping.len(), ACTIVATION_BINS, "cents mapping must have a value for every bin");
+        self.cents_mapping = cents_mapping;
+
+        self
+    }
+
+    /// Sets the number of bins on each side of the most activated bin that are averaged to
+    /// calculate the pitch. Wider windows smooth the pitch of noisy activations, narrower ones are
+    /// more accurate for clearly peaked activations.
+    pub fn with_local_average_half_width(mut self, half_width: usize) -> Self {
+        self.local_average_half_width = half_width;
+
+        self
+    }
+
+    /// Calculates the weighted average cents of the bins up to `half_width` bins around the most
+    /// activated bin of `activation`, e.g. to compare window widths on the same activation.
+    pub fn to_local_average_cents_windowed(&self, activation: &Activation, half_width: usize) -> f32 {
+        local_average_cents(activation, &self.cents_mapping, half_width)
+    }
+
+    /// Sets how the output of the model is scaled, for models that output logits instead of
+    /// probabilities. By default, this is detected from the output.
+    pub fn with_activation_kind(mut self, activation_kind: ActivationKind) -> Self {
+        self.activation_kind = activation_kind;
+
+        self
+    }
+
+    /// Limits the number of chunks passed to the model in a single inference run, e.g. to reduce
+    /// memory use. A limit of one runs the model on every chunk separately.
+    ///
+    /// The limit cannot be raised above what the model supports.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_batch_size` is zero.
+    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+        assert!(max_batch_size > 0, "batch size must be at least one");
+        self.max_batch_size = self.max_batch_size.min(max_batch_size);
+
+        self
+    }
+
+    /// Whether the model accepts more than one chunk per inference run, otherwise batches of chunks
+    /// are run one chunk at a time.
+    pub fn supports_batching(&self) -> bool {
+        self.max_batch_size > 1
+    }
+
+    /// Runs the model on `chunks` normalized audio chunks that are laid out one after another in
+    /// `input`.
+    fn run_model(&self, input: Vec<f32>, chunks: usize) -> Result<Vec<Activation>, PredictError> {
+        let input_len = input.len();
+        let input = Array::from_vec(input)
+            .into_shape_with_order((chunks, SAMPLES_PER_STEP))
+            .map_err(|_| PredictError::ShapeMismatch { expected: chunks * SAMPLES_PER_STEP, actual: input_len })?;
+        let outputs: SessionOutputs = self.model.run(inputs![self.input_name.as_str() => input.view()]?)?;
+        let output = outputs[self.output_name.as_str()].try_extract_tensor::<f32>()?;
+
+        let mut activations = activations_from_output(&output.iter().copied().collect::<Vec<f32>>(), chunks)?;
+        for activation in &mut activations {
+            self.activation_kind.normalize(activation);
+        }
+
+        Ok(activations)
+    }
+
+    fn get_activation(&self, audio: [f32; SAMPLES_PER_STEP]) -> Result<Activation, PredictError> {
+        Ok(self.run_model(normalize(audio).to_vec(), 1)?[0])
+    }
+
+    fn get_activations(&self, chunks: &[[i16; SAMPLES_PER_STEP]]) -> Result<Vec<Activation>, PredictError> {
+        let mut activations = Vec::with_capacity(chunks.len());
+        for batch in chunks.chunks(self.max_batch_size) {
+            activations.extend(self.get_batch_activations(batch)?);
         }
+
+        Ok(activations)
     }
 
-    fn get_activation(&self, audio: [i16; SAMPLES_PER_STEP]) -> Activation {
-        let audio = audio.map(|x| x as f32);
-        // Pad audio with 512 zeros from either side.
-        // TODO: check whether this is actually needed.
-        //let mut centered_audio = [0.0; 512 + 1024 + 512];
-        //centered_audio[512..(512 + 1024)].copy_from_slice(audio.as_slice());
-        let mean = mean(&audio);
-        let std = std(&audio);
-        let clipped_std = std.clamp(1e-8, f32::MAX);
-        let normalized_audio = audio.map(|x| (x - mean) / clipped_std);
+    fn get_batch_activations(&self, chunks: &[[i16; SAMPLES_PER_STEP]]) -> Result<Vec<Activation>, PredictError> {
+        if chunks.is_empty() {
+            return Ok(vec![]);
+        }
 
-        let input= Array::from_iter(normalized_audio).into_shape_with_order((1, 1024)).unwrap();
-        let outputs: SessionOutputs = self.model.run(inputs!["input" => input.view()].unwrap()).unwrap();
-        let output = outputs["output_0"].try_extract_tensor::<f32>().unwrap();
+        let input = chunks.iter()
+            .flat_map(|chunk| normalize(chunk.map(|x| x as f32)))
+            .collect();
 
-        output.as_slice().unwrap().try_into().unwrap()
+        self.run_model(input, chunks.len())
     }
 
-    fn to_local_average_cents(&self, activation: Activation) -> f32 {
-        let center = argmax(&activation).unwrap();
-        let start = center.saturating_sub(4);
-        let end = (center + 5).min(activation.len());
-        let product_sum: f32 = (start..end).map(|i| activation[i] * CENTS_MAPPING[i]).sum();
-        let weight_sum: f32 = activation.iter().sum();
+    /// Calculates the raw model output for a single audio chunk, which shows e.g. harmonics or
+    /// ambiguity between octaves that a single [`Prediction`] does not.
+    ///
+    /// # Panics
+    ///
+    /// Panics if running the model fails, see [`CrepeModel::try_activation`].
+    pub fn activation(&self, audio: [i16; SAMPLES_PER_STEP]) -> Activation {
+        self.try_activation(audio).unwrap()
+    }
 
-        product_sum / weight_sum
+    /// Like [`CrepeModel::activation`], but returns an error instead of panicking if running the
+    /// model fails or its output cannot be read.
+    pub fn try_activation(&self, audio: [i16; SAMPLES_PER_STEP]) -> Result<Activation, PredictError> {
+        self.get_activation(audio.map(|x| x as f32))
+    }
+
+    /// Picks the pitch from `activation` like [`PitchDetector::try_predict`] does.
+    fn prediction_from_activation(&self, activation: &Activation, harmonic_summation: bool, pitch_range: Option<(f32, f32)>) -> Prediction {
+        match pitch_range {
+            Some((min_hz, max_hz)) => {
+                let bins = frequency_range_to_bins(&self.cents_mapping, min_hz, max_hz);
+                to_prediction_in_range(activation, &self.cents_mapping, self.local_average_half_width, harmonic_summation, bins)
+            }
+            None if harmonic_summation => to_harmonic_prediction(activation, &self.cents_mapping, self.local_average_half_width),
+            None => to_prediction(activation, &self.cents_mapping, self.local_average_half_width),
+        }
+    }
+
+    /// Calculates both the prediction and the raw model output for a single audio chunk, without
+    /// running the model twice.
+    pub fn predict_with_activation(&self, audio: [i16; SAMPLES_PER_STEP]) -> (Prediction, Activation) {
+        let activation = self.activation(audio);
+
+        (self.prediction_from_activation(&activation, false, None), activation)
+    }
+
+    /// Like [`CrepeModel::predict_with_activation`], but picks the pitch from the [`harmonic_sum`]
+    /// of the activation to reduce octave errors. The returned activation is not modified.
+    pub fn predict_with_harmonic_sum(&self, audio: [i16; SAMPLES_PER_STEP]) -> (Prediction, Activation) {
+        let activation = self.activation(audio);
+
+        (self.prediction_from_activation(&activation, true, None), activation)
+    }
+
+    /// Like [`CrepeModel::predict_with_activation`], but only picks the pitch from the bins between
+    /// `min_hz` and `max_hz`, see [`mask_activation`]. The returned activation is not modified.
+    pub fn predict_in_range(&self, audio: [i16; SAMPLES_PER_STEP], harmonic_summation: bool, min_hz: f32, max_hz: f32) -> (Prediction, Activation) {
+        let activation = self.activation(audio);
+
+        (self.prediction_from_activation(&activation, harmonic_summation, Some((min_hz, max_hz))), activation)
     }
 
     /// Calculates the model output for a single audio chunk.
+    ///
+    /// # Panics
+    ///
+    /// Panics if running the model fails, see [`CrepeModel::try_predict_single`].
     pub fn predict_single(&self, audio: [i16; SAMPLES_PER_STEP]) -> Prediction {
-        let activation = self.get_activation(audio);
-        let confidence = activation.into_iter().reduce(f32::max).unwrap_or(0.0);
-        let cents = self.to_local_average_cents(activation);
-        let frequency = 10.0 * 2.0_f32.powf(cents / 1200.0);
+        self.try_predict_single(audio).unwrap()
+    }
 
-        Prediction {
-            frequency,
-            confidence,
-        }
+    /// Like [`CrepeModel::predict_single`], but returns an error instead of panicking if running
+    /// the model fails or its output cannot be read.
+    pub fn try_predict_single(&self, audio: [i16; SAMPLES_PER_STEP]) -> Result<Prediction, PredictError> {
+        let activation = self.get_activation(audio.map(|x| x as f32))?;
+
+        Ok(to_prediction(&activation, &self.cents_mapping, self.local_average_half_width))
+    }
+
+    /// Calculates the model output for a single audio chunk of floating point samples.
+    ///
+    /// The samples can be in any range, e.g. `-1.0..=1.0` as most audio backends provide them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if running the model fails, see [`CrepeModel::try_predict_single_f32`].
+    pub fn predict_single_f32(&self, audio: [f32; SAMPLES_PER_STEP]) -> Prediction {
+        self.try_predict_single_f32(audio).unwrap()
+    }
+
+    /// Like [`CrepeModel::predict_single_f32`], but returns an error instead of panicking if
+    /// running the model fails or its output cannot be read.
+    pub fn try_predict_single_f32(&self, audio: [f32; SAMPLES_PER_STEP]) -> Result<Prediction, PredictError> {
+        Ok(to_prediction(&self.get_activation(audio)?, &self.cents_mapping, self.local_average_half_width))
+    }
+
+    /// Like [`CrepeModel::predict_single`], but returns `None` if the confidence of the prediction
+    /// is below `min_confidence`, e.g. because the audio is silent or noise.
+    pub fn predict_single_gated(&self, audio: [i16; SAMPLES_PER_STEP], min_confidence: f32) -> Option<Prediction> {
+        Some(self.predict_single(audio))
+            .filter(|prediction| prediction.confidence >= min_confidence)
+    }
+
+    /// Calculates the model outputs for many audio chunks using as few inference runs as possible.
+    ///
+    /// Each chunk is normalized independently, so the results are the same as calling
+    /// [`CrepeModel::predict_single`] on every chunk. If the model does not support batching, see
+    /// [`CrepeModel::supports_batching`], it is run on every chunk separately.
+    ///
+    /// # Panics
+    ///
+    /// Panics if running the model fails, see [`CrepeModel::try_predict_batch`].
+    pub fn predict_batch(&self, chunks: &[[i16; SAMPLES_PER_STEP]]) -> Vec<Prediction> {
+        self.try_predict_batch(chunks).unwrap()
+    }
+
+    /// Like [`CrepeModel::predict_batch`], but returns an error instead of panicking if running
+    /// the model fails or its output cannot be read.
+    pub fn try_predict_batch(&self, chunks: &[[i16; SAMPLES_PER_STEP]]) -> Result<Vec<Prediction>, PredictError> {
+        Ok(self.get_activations(chunks)?
+            .iter()
+            .map(|activation| to_prediction(activation, &self.cents_mapping, self.local_average_half_width))
+            .collect())
+    }
+
+    /// Calculates the model outputs for a window of [`SAMPLES_PER_STEP`] samples that slides over
+    /// the audio by `hop_size` samples at a time.
+    ///
+    /// Smaller hop sizes result in overlapping windows and a smoother pitch contour, the reference
+    /// implementation uses a hop of 10 milliseconds, which is 160 samples.
+    ///
+    /// If `center` is set, the audio is padded with silence so that each window is centered on its
+    /// starting sample instead of starting at it, which is what the reference implementation does.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `hop_size` is zero or larger than [`SAMPLES_PER_STEP`].
+    pub fn predict_stream(&self, samples: &[i16], hop_size: usize, center: bool) -> Vec<TimedPrediction> {
+        self.predict_batch(&frames(samples, hop_size, center))
+            .into_iter()
+            .enumerate()
+            .map(|(frame, prediction)| TimedPrediction {
+                time_s: frame_time(frame, hop_size),
+                prediction,
+            })
+            .collect()
+    }
+
+    /// Like [`CrepeModel::predict_stream`], but skips inference for windows whose level is below
+    /// `noise_floor_db` dBFS and returns `None` for them instead.
+    pub fn predict_stream_gated(&self, samples: &[i16], hop_size: usize, center: bool, noise_floor_db: f32) -> Vec<Option<TimedPrediction>> {
+        let frames = frames(samples, hop_size, center);
+        let is_audible = frames.iter()
+            .map(|frame| rms_dbfs(frame) >= noise_floor_db)
+            .collect::<Vec<bool>>();
+        let audible_frames = frames.iter()
+            .zip(&is_audible)
+            .filter(|(_, is_audible)| **is_audible)
+            .map(|(frame, _)| *frame)
+            .collect::<Vec<[i16; SAMPLES_PER_STEP]>>();
+        let mut predictions = self.predict_batch(&audible_frames).into_iter();
+
+        is_audible.into_iter()
+            .enumerate()
+            .map(|(frame, is_audible)| {
+                let prediction = if is_audible { predictions.next() } else { None };
+                prediction.map(|prediction| TimedPrediction {
+                    time_s: frame_time(frame, hop_size),
+                    prediction,
+                })
+            })
+            .collect()
+    }
+
+    /// Calculates the model outputs for a sequence of consecutive audio chunks, smoothing the
+    /// pitch over time with Viterbi decoding like the reference implementation's `viterbi=True`.
+    ///
+    /// This avoids the octave jumps and jitter of picking the most activated bin of each chunk.
+    ///
+    /// # Panics
+    ///
+    /// Panics if running the model fails, see [`CrepeModel::try_predict_sequence_viterbi`].
+    pub fn predict_sequence_viterbi(&self, chunks: &[[i16; SAMPLES_PER_STEP]]) -> Vec<Prediction> {
+        self.try_predict_sequence_viterbi(chunks).unwrap()
+    }
+
+    /// Like [`CrepeModel::predict_sequence_viterbi`], but returns an error instead of panicking if
+    /// running the model fails or its output cannot be read.
+    pub fn try_predict_sequence_viterbi(&self, chunks: &[[i16; SAMPLES_PER_STEP]]) -> Result<Vec<Prediction>, PredictError> {
+        let activations = self.get_activations(chunks)?;
+
+        Ok(viterbi_decode(&activations)
+            .into_iter()
+            .zip(activations.iter())
+            .map(|(bin, activation)| Prediction {
+                frequency: cents_to_frequency(local_average_cents_around(activation, bin, &self.cents_mapping, self.local_average_half_width)),
+                confidence: confidence(activation),
+            })
+            .collect())
+    }
+}
+
+impl PitchDetector for CrepeModel {
+    fn predict_single(&self, audio: [i16; SAMPLES_PER_STEP]) -> Prediction {
+        CrepeModel::predict_single(self, audio)
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn samples_per_step(&self) -> usize {
+        SAMPLES_PER_STEP
+    }
+
+    fn predict_with_activation(&self, audio: [i16; SAMPLES_PER_STEP]) -> (Prediction, Option<Activation>) {
+        let (prediction, activation) = CrepeModel::predict_with_activation(self, audio);
+
+        (prediction, Some(activation))
+    }
+
+    fn predict_with_harmonic_sum(&self, audio: [i16; SAMPLES_PER_STEP]) -> (Prediction, Option<Activation>) {
+        let (prediction, activation) = CrepeModel::predict_with_harmonic_sum(self, audio);
+
+        (prediction, Some(activation))
+    }
+
+    fn predict_in_range(&self, audio: [i16; SAMPLES_PER_STEP], harmonic_summation: bool, min_hz: f32, max_hz: f32) -> (Prediction, Option<Activation>) {
+        let (prediction, activation) = CrepeModel::predict_in_range(self, audio, harmonic_summation, min_hz, max_hz);
+
+        (prediction, Some(activation))
+    }
+
+    fn try_predict(&self, audio: [i16; SAMPLES_PER_STEP], harmonic_summation: bool, pitch_range: Option<(f32, f32)>) -> Result<(Prediction, Option<Activation>), PredictError> {
+        let activation = self.try_activation(audio)?;
+
+        Ok((self.prediction_from_activation(&activation, harmonic_summation, pitch_range), Some(activation)))
     }
 }
 
@@ -110,15 +1043,571 @@ impl CrepeModel {
 mod tests {
     use approx::assert_relative_eq;
     use crate::crepe::*;
+    use crate::test_util::sine_chunk;
+
+    fn crepe_cents_mapping() -> Vec<f32> {
+        build_cents_mapping(ACTIVATION_BINS, FIRST_BIN_CENTS, CENTS_PER_BIN)
+    }
     
     #[test]
+    #[allow(clippy::excessive_precision)]
     fn test_cents_mapping() {
+        let cents_mapping = crepe_cents_mapping();
+
         // Values taken as calculated by Python code.
-        assert_relative_eq!(CENTS_MAPPING[0], 1997.37940844);
-        assert_relative_eq!(CENTS_MAPPING[1], 2017.37940844);
-        assert_relative_eq!(CENTS_MAPPING[358], 9157.37940844);
-        assert_relative_eq!(CENTS_MAPPING[359], 9177.37940844);
+        assert_eq!(cents_mapping.len(), 360);
+        assert_relative_eq!(cents_mapping[0], 1997.37940844);
+        assert_relative_eq!(cents_mapping[1], 2017.37940844);
+        assert_relative_eq!(cents_mapping[358], 9157.37940844);
+        assert_relative_eq!(cents_mapping[359], 9177.37940844);
+    }
+
+    #[test]
+    fn test_custom_cents_mapping() {
+        let cents_mapping = build_cents_mapping(4, 1000.0, 50.0);
+
+        assert_eq!(cents_mapping, vec![1000.0, 1050.0, 1100.0, 1150.0]);
+        assert!(build_cents_mapping(0, 1000.0, 50.0).is_empty());
     }
     
-    // TODO: add tests for comparing calculated output of some example audio with Python output.
-}
\ No newline at end of file
+    #[test]
+    fn test_model_capacity_file_names() {
+        assert_eq!(ModelCapacity::Tiny.default_file_name(), "crepe-tiny.onnx");
+        assert_eq!(ModelCapacity::Small.default_file_name(), "crepe-small.onnx");
+        assert_eq!(ModelCapacity::Medium.default_file_name(), "crepe-medium.onnx");
+        assert_eq!(ModelCapacity::Large.default_file_name(), "crepe-large.onnx");
+        assert_eq!(ModelCapacity::Full.default_file_name(), "crepe-full.onnx");
+        assert_eq!("Small".parse(), Ok(ModelCapacity::Small));
+        assert!("huge".parse::<ModelCapacity>().is_err());
+    }
+
+    #[test]
+    fn test_frequency_to_bin() {
+        let cents_mapping = crepe_cents_mapping();
+
+        assert_relative_eq!(frequency_to_bin(cents_to_frequency(cents_mapping[0])), 0.0, epsilon = 1e-3);
+        assert_relative_eq!(frequency_to_bin(cents_to_frequency(cents_mapping[100])), 100.0, epsilon = 1e-3);
+        // An octave is 1200 cents, which is 60 bins.
+        assert_relative_eq!(frequency_to_bin(440.0) - frequency_to_bin(220.0), 60.0, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn test_local_average_cents_single_peak() {
+        let mut activation = [0.0; 360];
+        activation[100] = 0.9;
+        let cents_mapping = crepe_cents_mapping();
+
+        assert_relative_eq!(local_average_cents(&activation, &cents_mapping, DEFAULT_LOCAL_AVERAGE_HALF_WIDTH), cents_mapping[100]);
+    }
+
+    #[test]
+    fn test_local_average_cents_ignores_activation_outside_window() {
+        let mut activation = [0.0; 360];
+        activation[100] = 0.9;
+        activation[300] = 0.5;
+        let cents_mapping = crepe_cents_mapping();
+
+        assert_relative_eq!(local_average_cents(&activation, &cents_mapping, DEFAULT_LOCAL_AVERAGE_HALF_WIDTH), cents_mapping[100]);
+    }
+
+    #[test]
+    fn test_local_average_cents_two_bin_plateau() {
+        let mut activation = [0.0; 360];
+        activation[200] = 0.8;
+        activation[201] = 0.8;
+        let cents_mapping = crepe_cents_mapping();
+
+        let expected = (cents_mapping[200] + cents_mapping[201]) / 2.0;
+        assert_relative_eq!(local_average_cents(&activation, &cents_mapping, DEFAULT_LOCAL_AVERAGE_HALF_WIDTH), expected);
+    }
+
+    #[test]
+    fn test_local_average_window_width() {
+        let mut activation = [0.0; 360];
+        activation[100] = 0.9;
+        activation[102] = 0.6;
+        activation[105] = 0.3;
+        let cents_mapping = crepe_cents_mapping();
+
+        assert_relative_eq!(local_average_cents(&activation, &cents_mapping, 0), cents_mapping[100]);
+        let expected = (0.9 * cents_mapping[100] + 0.6 * cents_mapping[102]) / 1.5;
+        assert_relative_eq!(local_average_cents(&activation, &cents_mapping, 4), expected);
+        let expected = (0.9 * cents_mapping[100] + 0.6 * cents_mapping[102] + 0.3 * cents_mapping[105]) / 1.8;
+        assert_relative_eq!(local_average_cents(&activation, &cents_mapping, 5), expected, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn test_local_average_window_is_clamped_to_bins() {
+        let mut activation = [0.0; 360];
+        activation[0] = 0.9;
+        activation[359] = 0.95;
+        let cents_mapping = crepe_cents_mapping();
+
+        assert_relative_eq!(local_average_cents(&activation, &cents_mapping, 4), cents_mapping[359]);
+        assert_relative_eq!(local_average_cents(&activation, &cents_mapping, usize::MAX), (0.9 * cents_mapping[0] + 0.95 * cents_mapping[359]) / 1.85, epsilon = 1e-2);
+    }
+
+    #[test]
+    fn test_viterbi_avoids_octave_jump() {
+        let mut first = [0.0; 360];
+        first[100] = 0.9;
+        let mut second = [0.0; 360];
+        second[100] = 0.5;
+        // One octave up, which greedy argmax would pick.
+        second[160] = 0.6;
+
+        assert_eq!(argmax(&second), Some(160));
+        assert_eq!(viterbi_decode(&[first, second]), vec![100, 100]);
+    }
+
+    #[test]
+    fn test_viterbi_follows_small_steps() {
+        let mut first = [0.0; 360];
+        first[100] = 0.9;
+        let mut second = [0.0; 360];
+        second[101] = 0.9;
+
+        assert_eq!(viterbi_decode(&[first, second]), vec![100, 101]);
+    }
+
+    #[test]
+    fn test_silence_is_below_noise_floor() {
+        assert_eq!(rms(&[0; SAMPLES_PER_STEP]), 0.0);
+        assert!(rms_dbfs(&[0; SAMPLES_PER_STEP]) < -50.0);
+    }
+
+    #[test]
+    fn test_full_scale_sine_is_above_noise_floor() {
+        let chunk: [i16; SAMPLES_PER_STEP] = std::array::from_fn(|i| {
+            ((2.0 * std::f32::consts::PI * i as f32 / 64.0).sin() * i16::MAX as f32) as i16
+        });
+
+        // A full scale sine has an RMS of 1/sqrt(2), which is about -3 dB.
+        assert_relative_eq!(rms_dbfs(&chunk), -3.01, epsilon = 0.01);
+        assert!(rms_dbfs(&chunk) > -50.0);
+    }
+
+    #[test]
+    fn test_frame_count_for_hop_sizes() {
+        let samples = vec![0; 16_000];
+
+        for hop_size in [1, 160, 512, SAMPLES_PER_STEP] {
+            let expected = (samples.len() - SAMPLES_PER_STEP) / hop_size + 1;
+            assert_eq!(frames(&samples, hop_size, false).len(), expected);
+        }
+    }
+
+    #[test]
+    fn test_frames_overlap_by_hop_size() {
+        let samples = (0..2048).map(|i| i as i16).collect::<Vec<i16>>();
+
+        let frames = frames(&samples, 160, false);
+        assert_eq!(frames[0][0], 0);
+        assert_eq!(frames[1][0], 160);
+        assert_eq!(frames[1][SAMPLES_PER_STEP - 1], 160 + SAMPLES_PER_STEP as i16 - 1);
+    }
+
+    #[test]
+    fn test_frames_shorter_than_window() {
+        assert!(frames(&[0; 100], 160, false).is_empty());
+    }
+
+    #[test]
+    fn test_centered_frames() {
+        let samples = (1..=4096).map(|i| i as i16).collect::<Vec<i16>>();
+
+        let uncentered = frames(&samples, 160, false);
+        let centered = frames(&samples, 160, true);
+        assert_eq!(centered.len(), samples.len() / 160 + 1);
+        assert!(centered.len() > uncentered.len());
+        // The first centered frame is centered on the first sample instead of starting at it.
+        assert_eq!(uncentered[0][0], 1);
+        assert!(centered[0][..SAMPLES_PER_STEP / 2].iter().all(|x| *x == 0));
+        assert_eq!(centered[0][SAMPLES_PER_STEP / 2], 1);
+        assert_eq!(centered[1][SAMPLES_PER_STEP / 2], 161);
+    }
+
+    #[test]
+    fn test_frame_time() {
+        assert_eq!(frame_time(0, 160), 0.0);
+        assert_relative_eq!(frame_time(2, 160), 2.0 * 160.0 / 16_000.0);
+        assert_relative_eq!(frame_time(100, 160), 1.0);
+        assert_relative_eq!(frame_time(1, SAMPLES_PER_STEP), 0.064);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_frames_rejects_zero_hop_size() {
+        frames(&[0; SAMPLES_PER_STEP], 0, false);
+    }
+
+    #[test]
+    fn test_batch_size_from_dimensions() {
+        assert_eq!(batch_size_from_dimensions(&[-1, 1024]), MAX_BATCH_SIZE);
+        assert_eq!(batch_size_from_dimensions(&[1, 1024]), 1);
+        assert_eq!(batch_size_from_dimensions(&[]), 1);
+    }
+
+    fn tensor(name: &str, dimensions: &[i64]) -> TensorInfo {
+        (name.to_string(), Some(dimensions.to_vec()))
+    }
+
+    #[test]
+    fn test_select_tensors_prefers_crepe_names() {
+        let inputs = [tensor("other", &[1, 1024]), tensor("input", &[-1, 1024])];
+        let outputs = [tensor("output_0", &[-1, 360]), tensor("embedding", &[-1, 2048])];
+
+        let (input, output) = select_tensors(&inputs, &outputs).unwrap();
+        assert_eq!(input.0, "input");
+        assert_eq!(output.0, "output_0");
+    }
+
+    #[test]
+    fn test_select_tensors_falls_back_to_single_tensor() {
+        let inputs = [tensor("audio", &[1, 1024])];
+        let outputs = [tensor("bins", &[1, 360])];
+
+        let (input, output) = select_tensors(&inputs, &outputs).unwrap();
+        assert_eq!(input.0, "audio");
+        assert_eq!(output.0, "bins");
+
+        let outputs = [tensor("bins", &[1, 360]), tensor("embedding", &[1, 2048])];
+        assert_eq!(select_tensors(&inputs, &outputs), Err(ModelError::TensorNotFound {
+            kind: "output",
+            expected: "output_0",
+            available: vec!["bins".to_string(), "embedding".to_string()],
+        }));
+    }
+
+    #[test]
+    fn test_select_tensors_rejects_output_that_is_not_360_wide() {
+        let inputs = [tensor("input", &[-1, 1024])];
+        let outputs = [tensor("output_0", &[-1, 100])];
+
+        assert_eq!(select_tensors(&inputs, &outputs), Err(ModelError::IncompatibleShape {
+            name: "output_0".to_string(),
+            dimensions: Some(vec![-1, 100]),
+            expected: ACTIVATION_BINS,
+        }));
+        assert!(select_tensors(&[tensor("input", &[1, 512])], &[tensor("output_0", &[1, 360])]).is_err());
+    }
+
+    #[test]
+    fn test_activations_from_output() {
+        let values = (0..2 * ACTIVATION_BINS).map(|i| i as f32).collect::<Vec<f32>>();
+
+        let activations = activations_from_output(&values, 2).unwrap();
+        assert_eq!(activations.len(), 2);
+        assert_eq!(activations[0][0], 0.0);
+        assert_eq!(activations[1][0], ACTIVATION_BINS as f32);
+    }
+
+    #[test]
+    fn test_logits_are_normalized() {
+        // A peak at bin 100 in logit scale, which is far outside of zero to one.
+        let mut activation: Activation = std::array::from_fn(|i| -8.0 + 12.0 * (-((i as f32 - 100.0) / 3.0).powi(2)).exp());
+
+        let mut auto = activation;
+        ActivationKind::Auto.normalize(&mut auto);
+        ActivationKind::Logits.normalize(&mut activation);
+        assert_eq!(auto, activation);
+        assert!(activation.iter().all(|value| (0.0..=1.0).contains(value)));
+
+        let prediction = to_prediction(&activation, &crepe_cents_mapping(), DEFAULT_LOCAL_AVERAGE_HALF_WIDTH);
+        assert!((0.0..=1.0).contains(&prediction.confidence));
+        assert_relative_eq!(prediction.confidence, 1.0 / (1.0 + (-4.0f32).exp()), epsilon = 1e-5);
+        assert_relative_eq!(frequency_to_bin(prediction.frequency), 100.0, epsilon = 0.1);
+    }
+
+    #[test]
+    fn test_probabilities_are_kept() {
+        let mut activation = [0.0; ACTIVATION_BINS];
+        activation[100] = 0.9;
+        activation[101] = 0.4;
+
+        let original = activation;
+        ActivationKind::Auto.normalize(&mut activation);
+        assert_eq!(activation, original);
+        ActivationKind::Probabilities.normalize(&mut activation);
+        assert_eq!(activation, original);
+        assert_eq!("Logits".parse(), Ok(ActivationKind::Logits));
+        assert!("softmax".parse::<ActivationKind>().is_err());
+    }
+
+    #[test]
+    fn test_mismatched_output_is_an_error() {
+        // E.g. a session whose output has 100 bins instead of 360.
+        let values = [0.5; 100];
+
+        assert!(matches!(
+            activations_from_output(&values, 1),
+            Err(PredictError::ShapeMismatch { expected: ACTIVATION_BINS, actual: 100 })
+        ));
+        assert!(matches!(
+            activations_from_output(&[0.5; ACTIVATION_BINS], 2),
+            Err(PredictError::ShapeMismatch { .. })
+        ));
+        assert!(matches!(activations_from_output(&[], 1), Err(PredictError::EmptyOutput)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_frames_rejects_hop_size_larger_than_window() {
+        frames(&[0; SAMPLES_PER_STEP], SAMPLES_PER_STEP + 1, false);
+    }
+
+    fn load_model() -> CrepeModel {
+        let session = Session::builder()
+            .unwrap()
+            .commit_from_file("crepe-full.onnx")
+            .unwrap();
+
+        CrepeModel::new(session).unwrap()
+    }
+
+    #[test]
+    fn test_predict_note_of_detector() {
+        let detector: &dyn PitchDetector = &crate::autocorr::YinDetector::new();
+
+        let note = detector.predict_note(sine_chunk(440.0), 440.0);
+        assert_eq!(note.note_name, "A");
+        assert_eq!(note.octave, 4);
+        assert!(note.cents_offset.abs() < 5.0);
+        assert_relative_eq!(note.prediction.frequency, 440.0, epsilon = 1.0);
+
+        // The same pitch is sharp of A4 when A4 is tuned lower.
+        let note = detector.predict_note(sine_chunk(440.0), 430.0);
+        assert_eq!(note.note_name, "A");
+        assert!(note.cents_offset > 30.0);
+    }
+
+    #[test]
+    #[ignore = "requires crepe-full.onnx in the working directory"]
+    fn test_predict_note() {
+        let model = load_model();
+
+        let note = model.predict_note(sine_chunk(440.0), 440.0);
+        assert_eq!(note.note_name, "A");
+        assert_eq!(note.octave, 4);
+        assert!(note.cents_offset.abs() < 10.0);
+    }
+
+    #[test]
+    #[ignore = "requires crepe-full.onnx in the working directory"]
+    fn test_predict_batch_matches_predict_single() {
+        let model = load_model();
+        let chunk = sine_chunk(220.0);
+
+        let single = model.predict_single(chunk);
+        let batch = model.predict_batch(&[chunk, chunk]);
+
+        assert_eq!(batch.len(), 2);
+        for prediction in batch {
+            assert_relative_eq!(prediction.frequency, single.frequency, epsilon = 1e-3);
+            assert_relative_eq!(prediction.confidence, single.confidence, epsilon = 1e-5);
+        }
+    }
+
+    #[test]
+    #[ignore = "requires crepe-full.onnx in the working directory"]
+    fn test_batched_results_match_looped_results() {
+        let batched_model = load_model();
+        let looped_model = load_model().with_max_batch_size(1);
+        assert!(!looped_model.supports_batching());
+        let chunks = [110.0, 220.0, 330.0, 440.0].map(sine_chunk);
+
+        let batched = batched_model.predict_batch(&chunks);
+        let looped = looped_model.predict_batch(&chunks);
+
+        assert_eq!(batched.len(), looped.len());
+        for (batched, looped) in batched.iter().zip(&looped) {
+            assert_relative_eq!(batched.frequency, looped.frequency, epsilon = 1e-3);
+            assert_relative_eq!(batched.confidence, looped.confidence, epsilon = 1e-5);
+        }
+    }
+
+    #[test]
+    #[ignore = "requires crepe-full.onnx in the working directory"]
+    fn test_activation_matches_prediction() {
+        let model = load_model();
+
+        let (prediction, activation) = model.predict_with_activation(sine_chunk(220.0));
+        assert_eq!(activation.len(), 360);
+        assert_eq!(to_prediction(&activation, &crepe_cents_mapping(), DEFAULT_LOCAL_AVERAGE_HALF_WIDTH), prediction);
+        let implied_bin = frequency_to_bin(prediction.frequency).round() as usize;
+        assert!(argmax(&activation).unwrap().abs_diff(implied_bin) <= 1);
+    }
+
+    #[test]
+    #[ignore = "requires crepe-full.onnx in the working directory"]
+    fn test_try_predict_single_matches_predict_single() {
+        let model = load_model();
+        let chunk = sine_chunk(220.0);
+
+        assert_eq!(model.try_predict_single(chunk).unwrap(), model.predict_single(chunk));
+    }
+
+    #[test]
+    #[ignore = "requires crepe-full.onnx in the working directory"]
+    fn test_local_average_half_width_of_model() {
+        let model = load_model().with_local_average_half_width(0);
+
+        let (prediction, activation) = model.predict_with_activation(sine_chunk(220.0));
+        let argmax_cents = model.to_local_average_cents_windowed(&activation, 0);
+        assert_eq!(argmax_cents, crepe_cents_mapping()[argmax(&activation).unwrap()]);
+        assert_relative_eq!(prediction.frequency, cents_to_frequency(argmax_cents));
+    }
+
+    #[test]
+    fn test_harmonic_sum_resolves_octave_error() {
+        let mut activation = [0.0; ACTIVATION_BINS];
+        // The fundamental and the much stronger octave above it, 60 bins of 20 cents higher.
+        activation[99] = 0.2;
+        activation[100] = 0.5;
+        activation[101] = 0.2;
+        activation[159] = 0.3;
+        activation[160] = 0.9;
+        activation[161] = 0.3;
+
+        assert_eq!(argmax(&activation), Some(160));
+        assert_eq!(argmax(&harmonic_sum(&activation)), Some(100));
+        let prediction = to_harmonic_prediction(&activation, &crepe_cents_mapping(), DEFAULT_LOCAL_AVERAGE_HALF_WIDTH);
+        assert_relative_eq!(frequency_to_bin(prediction.frequency), 100.0, epsilon = 0.1);
+        assert_relative_eq!(prediction.confidence, 0.9);
+    }
+
+    #[test]
+    fn test_mask_removes_out_of_range_peak() {
+        let cents_mapping = crepe_cents_mapping();
+        let mut activation = [0.0; ACTIVATION_BINS];
+        // A weaker peak at 110 Hz and a dominant one below the range, e.g. from mains hum.
+        let in_range = frequency_to_bin(110.0).round() as usize;
+        activation[in_range] = 0.6;
+        activation[20] = 0.9;
+
+        let (min_bin, max_bin) = frequency_range_to_bins(&cents_mapping, 80.0, 400.0);
+        assert_eq!((min_bin, max_bin), (frequency_to_bin(80.0).round() as usize, frequency_to_bin(400.0).round() as usize));
+        let mut masked = activation;
+        mask_activation(&mut masked, min_bin, max_bin);
+        assert_eq!(masked[20], 0.0);
+        assert_eq!(argmax(&masked), Some(in_range));
+
+        let prediction = to_prediction_in_range(&activation, &cents_mapping, DEFAULT_LOCAL_AVERAGE_HALF_WIDTH, false, (min_bin, max_bin));
+        assert_relative_eq!(frequency_to_bin(prediction.frequency), in_range as f32, epsilon = 0.01);
+        assert_relative_eq!(prediction.confidence, 0.6);
+        let prediction = to_prediction_in_range(&activation, &cents_mapping, DEFAULT_LOCAL_AVERAGE_HALF_WIDTH, true, (min_bin, max_bin));
+        assert_relative_eq!(frequency_to_bin(prediction.frequency), in_range as f32, epsilon = 0.01);
+    }
+
+    #[test]
+    fn test_harmonic_sum_keeps_single_peak() {
+        let mut activation = [0.0; ACTIVATION_BINS];
+        activation[200] = 0.8;
+
+        assert_eq!(argmax(&harmonic_sum(&activation)), Some(200));
+    }
+
+    #[test]
+    fn test_normalize_ignores_scale() {
+        let chunk = sine_chunk(220.0);
+
+        let integer = normalize(chunk.map(|x| x as f32));
+        let float = normalize(chunk.map(|x| x as f32 / i16::MAX as f32));
+        for (a, b) in integer.iter().zip(float.iter()) {
+            assert_relative_eq!(a, b, epsilon = 1e-4);
+        }
+    }
+
+    #[test]
+    #[ignore = "requires crepe-full.onnx in the working directory"]
+    fn test_f32_prediction_matches_quantized_prediction() {
+        let model = load_model();
+        let float: [f32; SAMPLES_PER_STEP] = std::array::from_fn(|i| {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            (2.0 * std::f32::consts::PI * 220.0 * t).sin() * 0.5
+        });
+        let quantized = float.map(|x| (x * i16::MAX as f32) as i16);
+
+        let float_prediction = model.predict_single_f32(float);
+        let quantized_prediction = model.predict_single(quantized);
+        assert_relative_eq!(float_prediction.frequency, quantized_prediction.frequency, epsilon = 0.5);
+    }
+
+    #[test]
+    #[ignore = "requires crepe-full.onnx in the working directory"]
+    fn test_gated_prediction_of_silence() {
+        let model = load_model();
+
+        assert!(model.predict_single_gated([0; SAMPLES_PER_STEP], 0.5).is_none());
+    }
+
+    #[test]
+    #[ignore = "requires crepe-full.onnx in the working directory"]
+    fn test_gated_prediction_of_sine() {
+        let model = load_model();
+
+        assert!(model.predict_single_gated(sine_chunk(220.0), 0.5).is_some());
+    }
+
+    /// A harmonic tone at 220 Hz as raw little-endian samples at the model's sample rate.
+    const REFERENCE_CLIP: &[u8] = include_bytes!("../testdata/harmonic-220hz.i16");
+
+    fn reference_clip() -> Vec<i16> {
+        REFERENCE_CLIP.chunks_exact(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn test_normalization_ignores_sample_scale() {
+        // The Python package normalizes samples in the range -1.0..=1.0, this the raw samples.
+        let chunk: [i16; SAMPLES_PER_STEP] = reference_clip()[..SAMPLES_PER_STEP].try_into().unwrap();
+
+        let raw = normalize(chunk.map(|sample| sample as f32));
+        let scaled = normalize(chunk.map(|sample| sample as f32 / 32768.0));
+        for (raw, scaled) in raw.iter().zip(scaled) {
+            assert_relative_eq!(*raw, scaled, epsilon = 1e-4);
+        }
+        assert_relative_eq!(mean(&raw), 0.0, epsilon = 1e-5);
+        assert_relative_eq!(std(&raw), 1.0, epsilon = 1e-4);
+    }
+
+    #[test]
+    fn test_reference_clip_is_framed_like_python() {
+        // crepe.core.get_activation pads 512 samples on either side and makes
+        // 1 + (len(audio) - 1024) // hop_length frames of the padded audio.
+        let clip = reference_clip();
+        let frames = frames(&clip, 160, true);
+
+        assert_eq!(frames.len(), 1 + clip.len() / 160);
+        assert_eq!(frames[0][..SAMPLES_PER_STEP / 2], [0; SAMPLES_PER_STEP / 2]);
+        assert_eq!(frames[4][..], clip[4 * 160 - SAMPLES_PER_STEP / 2..4 * 160 + SAMPLES_PER_STEP / 2]);
+    }
+
+    #[test]
+    fn test_normalization_uses_population_std() {
+        // Like numpy's std, the variance is divided by the number of samples instead of one less.
+        let audio = std::array::from_fn(|i| if i % 2 == 0 { 1.0 } else { 3.0 });
+
+        let normalized = normalize(audio);
+        assert_eq!(normalized[..4], [-1.0, 1.0, -1.0, 1.0]);
+        // Silence is not divided by zero.
+        assert_eq!(normalize([5.0; SAMPLES_PER_STEP]), [0.0; SAMPLES_PER_STEP]);
+    }
+
+    // TODO: compare with the output of create-onnx-model/reference_predictions.py once it is
+    // checked in, the test below only compares with the known pitch of the clip.
+    #[test]
+    #[ignore = "requires crepe-full.onnx in the working directory"]
+    fn test_predictions_of_reference_clip() {
+        let predictions = load_model().predict_stream(&reference_clip(), 160, true);
+
+        assert_eq!(predictions.len(), 1 + reference_clip().len() / 160);
+        for timed in predictions.iter().filter(|timed| timed.prediction.confidence >= 0.5) {
+            assert!(
+                (timed.prediction.frequency - 220.0).abs() < 1.0,
+                "predicted {}Hz at {}s for the 220Hz clip", timed.prediction.frequency, timed.time_s,
+            );
+        }
+    }
+}
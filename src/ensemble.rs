@@ -0,0 +1,176 @@
+//! Combining several pitch detectors, e.g. CREPE models of different capacities, to make fewer
+//! errors on hard material than any one of them.
+use crate::crepe::{Activation, PitchDetector, PredictError, Prediction, SAMPLES_PER_STEP};
+
+/// A pitch detector that runs every detector it holds on each chunk and uses the prediction that
+/// is the most confident.
+///
+/// The activations are not averaged, since the detectors may use different bin layouts. Instead,
+/// the activation of the detector whose prediction is used is returned along with it.
+pub struct EnsembleDetector {
+    detectors: Vec<Box<dyn PitchDetector>>,
+}
+
+impl EnsembleDetector {
+    /// # Panics
+    ///
+    /// Panics if there are no detectors or if they do not all take audio at the same sample rate
+    /// and in chunks of the same size.
+    pub fn new(detectors: Vec<Box<dyn PitchDetector>>) -> Self {
+        assert!(!detectors.is_empty(), "an ensemble needs at least one detector");
+        let (sample_rate, samples_per_step) = (detectors[0].sample_rate(), detectors[0].samples_per_step());
+        assert!(
+            detectors.iter().all(|detector| detector.sample_rate() == sample_rate && detector.samples_per_step() == samples_per_step),
+            "all detectors of an ensemble must take the same audio",
+        );
+
+        EnsembleDetector { detectors }
+    }
+
+    /// The number of detectors in the ensemble.
+    pub fn len(&self) -> usize {
+        self.detectors.len()
+    }
+
+    /// Always false, an ensemble has at least one detector.
+    pub fn is_empty(&self) -> bool {
+        self.detectors.is_empty()
+    }
+
+    /// Runs `predict` with every detector and returns the most confident result, the earliest
+    /// detector wins ties.
+    fn most_confident<F>(&self, predict: F) -> (Prediction, Option<Activation>)
+    where
+        F: Fn(&dyn PitchDetector) -> (Prediction, Option<Activation>),
+    {
+        most_confident(self.detectors.iter().map(|detector| predict(detector.as_ref())))
+    }
+}
+
+/// Returns the most confident of the results of the detectors of an ensemble, the earliest one
+/// wins ties.
+fn most_confident<I>(results: I) -> (Prediction, Option<Activation>)
+where
+    I: IntoIterator<Item = (Prediction, Option<Activation>)>,
+{
+    results.into_iter()
+        .reduce(|best, result| if result.0.confidence > best.0.confidence { result } else { best })
+        .expect("an ensemble has at least one detector")
+}
+
+impl PitchDetector for EnsembleDetector {
+    fn predict_single(&self, audio: [i16; SAMPLES_PER_STEP]) -> Prediction {
+        self.most_confident(|detector| (detector.predict_single(audio), None)).0
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.detectors[0].sample_rate()
+    }
+
+    fn samples_per_step(&self) -> usize {
+        self.detectors[0].samples_per_step()
+    }
+
+    fn predict_with_activation(&self, audio: [i16; SAMPLES_PER_STEP]) -> (Prediction, Option<Activation>) {
+        self.most_confident(|detector| detector.predict_with_activation(audio))
+    }
+
+    fn predict_with_harmonic_sum(&self, audio: [i16; SAMPLES_PER_STEP]) -> (Prediction, Option<Activation>) {
+        self.most_confident(|detector| detector.predict_with_harmonic_sum(audio))
+    }
+
+    fn predict_in_range(&self, audio: [i16; SAMPLES_PER_STEP], harmonic_summation: bool, min_hz: f32, max_hz: f32) -> (Prediction, Option<Activation>) {
+        self.most_confident(|detector| detector.predict_in_range(audio, harmonic_summation, min_hz, max_hz))
+    }
+
+    /// Fails with the error of the first detector that fails.
+    fn try_predict(&self, audio: [i16; SAMPLES_PER_STEP], harmonic_summation: bool, pitch_range: Option<(f32, f32)>) -> Result<(Prediction, Option<Activation>), PredictError> {
+        let results = self.detectors.iter()
+            .map(|detector| detector.try_predict(audio, harmonic_summation, pitch_range))
+            .collect::<Result<Vec<(Prediction, Option<Activation>)>, PredictError>>()?;
+
+        Ok(most_confident(results))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crepe::{ACTIVATION_BINS, SAMPLE_RATE};
+    use crate::ensemble::*;
+
+    /// Detects the same pitch in any audio, with an activation that marks which detector it is.
+    struct FixedDetector(Prediction, f32);
+
+    impl PitchDetector for FixedDetector {
+        fn predict_single(&self, _audio: [i16; SAMPLES_PER_STEP]) -> Prediction {
+            self.0
+        }
+
+        fn sample_rate(&self) -> u32 {
+            SAMPLE_RATE
+        }
+
+        fn samples_per_step(&self) -> usize {
+            SAMPLES_PER_STEP
+        }
+
+        fn predict_with_activation(&self, audio: [i16; SAMPLES_PER_STEP]) -> (Prediction, Option<Activation>) {
+            (self.predict_single(audio), Some([self.1; ACTIVATION_BINS]))
+        }
+    }
+
+    #[test]
+    fn test_most_confident_prediction_is_used() {
+        let unsure = Prediction { frequency: 440.0, confidence: 0.4 };
+        let confident = Prediction { frequency: 220.0, confidence: 0.9 };
+        let audio = [0; SAMPLES_PER_STEP];
+
+        for detectors in [[unsure, confident], [confident, unsure]] {
+            let ensemble = EnsembleDetector::new(detectors.iter()
+                .map(|prediction| Box::new(FixedDetector(*prediction, prediction.confidence)) as Box<dyn PitchDetector>)
+                .collect());
+            assert_eq!(ensemble.predict_single(audio), confident);
+            // The activation belongs to the detector whose prediction is used.
+            let (prediction, activation) = ensemble.predict_with_activation(audio);
+            assert_eq!(prediction, confident);
+            assert_eq!(activation.unwrap()[0], confident.confidence);
+        }
+    }
+
+    /// Fails on any audio like a model whose output cannot be read.
+    struct FailingDetector;
+
+    impl PitchDetector for FailingDetector {
+        fn predict_single(&self, _audio: [i16; SAMPLES_PER_STEP]) -> Prediction {
+            panic!("the fallible prediction must be used");
+        }
+
+        fn sample_rate(&self) -> u32 {
+            SAMPLE_RATE
+        }
+
+        fn samples_per_step(&self) -> usize {
+            SAMPLES_PER_STEP
+        }
+
+        fn try_predict(&self, _audio: [i16; SAMPLES_PER_STEP], _harmonic_summation: bool, _pitch_range: Option<(f32, f32)>) -> Result<(Prediction, Option<Activation>), PredictError> {
+            Err(PredictError::EmptyOutput)
+        }
+    }
+
+    #[test]
+    fn test_error_of_a_detector_is_returned() {
+        let confident = Prediction { frequency: 220.0, confidence: 0.9 };
+        let ensemble = EnsembleDetector::new(vec![Box::new(FixedDetector(confident, 1.0)), Box::new(FailingDetector)]);
+
+        assert!(matches!(ensemble.try_predict([0; SAMPLES_PER_STEP], false, None), Err(PredictError::EmptyOutput)));
+        let ensemble = EnsembleDetector::new(vec![Box::new(FixedDetector(confident, 1.0))]);
+        assert_eq!(ensemble.try_predict([0; SAMPLES_PER_STEP], true, Some((100.0, 400.0))).unwrap().0, confident);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_empty_ensemble_panics() {
+        EnsembleDetector::new(Vec::new());
+    }
+}
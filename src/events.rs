@@ -0,0 +1,371 @@
+use crate::crepe::Prediction;
+use crate::notes::{frequency_to_midi, DEFAULT_A4_HZ};
+
+/// How far in semitones past the halfway point to the next note the pitch has to move before
+/// the current note ends, so that a pitch wobbling around a note boundary does not end the note.
+const HYSTERESIS_SEMITONES: f32 = 0.2;
+
+/// The default number of seconds a note has to be held before it starts.
+pub const DEFAULT_MIN_NOTE_SECONDS: f64 = 0.1;
+
+/// The default confidence below which predictions are treated as silence.
+pub const DEFAULT_MIN_CONFIDENCE: f32 = 0.5;
+
+/// The start or end of a note, with the time in seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoteEvent {
+    /// A note started, `time` is the time of its first prediction.
+    On { midi: u8, time: f64 },
+    /// A note ended, `time` is the time of the first prediction that was not part of it anymore.
+    Off { midi: u8, time: f64 },
+}
+
+/// A note that ended, with the times of its start and end in seconds and statistics of the
+/// predictions that were part of it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompletedNote {
+    pub midi: u8,
+    pub start_s: f64,
+    pub end_s: f64,
+    /// The mean number of cents the predictions were above the note, negative if below.
+    pub mean_cents_offset: f32,
+    pub mean_confidence: f32,
+}
+
+impl CompletedNote {
+    pub fn duration_s(&self) -> f64 {
+        self.end_s - self.start_s
+    }
+}
+
+/// Sums of the predictions of a note, which its means are calculated from once it ends.
+#[derive(Debug, Default, Clone, Copy)]
+struct NoteStats {
+    cents_offset_sum: f64,
+    confidence_sum: f64,
+    count: usize,
+}
+
+impl NoteStats {
+    fn add(&mut self, cents_offset: f32, confidence: f32) {
+        self.cents_offset_sum += cents_offset as f64;
+        self.confidence_sum += confidence as f64;
+        self.count += 1;
+    }
+
+    fn mean(sum: f64, count: usize) -> f32 {
+        if count == 0 { 0.0 } else { (sum / count as f64) as f32 }
+    }
+}
+
+/// Turns a stream of pitch predictions into discrete note events.
+///
+/// A note starts once its pitch was held for a minimum duration and ends when the pitch moves to
+/// another note or is no longer confident.
+pub struct NoteTracker {
+    min_duration: f64,
+    min_confidence: f32,
+    a4_hz: f32,
+    /// The note that is currently held.
+    active_note: Option<u8>,
+    /// The time the held note started at.
+    active_start: f64,
+    active_stats: NoteStats,
+    /// A note that was predicted recently but not held long enough yet, along with the time of its
+    /// first prediction.
+    candidate: Option<(u8, f64)>,
+    candidate_stats: NoteStats,
+    /// Notes that ended since they were last taken.
+    completed: Vec<CompletedNote>,
+}
+
+impl Default for NoteTracker {
+    fn default() -> Self {
+        NoteTracker::new()
+    }
+}
+
+impl NoteTracker {
+    pub fn new() -> Self {
+        NoteTracker {
+            min_duration: DEFAULT_MIN_NOTE_SECONDS,
+            min_confidence: DEFAULT_MIN_CONFIDENCE,
+            a4_hz: DEFAULT_A4_HZ,
+            active_note: None,
+            active_start: 0.0,
+            active_stats: NoteStats::default(),
+            candidate: None,
+            candidate_stats: NoteStats::default(),
+            completed: Vec::new(),
+        }
+    }
+
+    /// Sets the number of seconds a note has to be held before it starts.
+    pub fn with_min_duration(mut self, seconds: f64) -> Self {
+        self.min_duration = seconds;
+        self
+    }
+
+    /// Sets the confidence below which predictions are treated as silence.
+    pub fn with_min_confidence(mut self, min_confidence: f32) -> Self {
+        self.min_confidence = min_confidence;
+        self
+    }
+
+    /// Sets the reference frequency of A4 that notes are calculated from.
+    pub fn with_a4(mut self, a4_hz: f32) -> Self {
+        self.a4_hz = a4_hz;
+        self
+    }
+
+    /// The note that is currently held, if any.
+    pub fn active_note(&self) -> Option<u8> {
+        self.active_note
+    }
+
+    /// Updates the held note from a prediction made at `time` seconds, returning the notes that
+    /// ended or started because of it.
+    pub fn update(&mut self, prediction: &Prediction, time: f64) -> Vec<NoteEvent> {
+        let mut events = Vec::new();
+        let is_valid = prediction.confidence >= self.min_confidence && prediction.frequency > 0.0;
+        if !is_valid {
+            self.candidate = None;
+            events.extend(self.finish(time));
+            return events;
+        }
+
+        let midi = frequency_to_midi(prediction.frequency, self.a4_hz);
+        if let Some(active) = self.active_note {
+            if (midi - active as f32).abs() <= 0.5 + HYSTERESIS_SEMITONES {
+                self.candidate = None;
+                self.active_stats.add((midi - active as f32) * 100.0, prediction.confidence);
+                return events;
+            }
+            events.extend(self.finish(time));
+        }
+
+        let note = midi.round().clamp(0.0, 127.0) as u8;
+        let start = match self.candidate {
+            Some((candidate, start)) if candidate == note => start,
+            _ => {
+                self.candidate_stats = NoteStats::default();
+                time
+            }
+        };
+        self.candidate_stats.add((midi - note as f32) * 100.0, prediction.confidence);
+        if time - start >= self.min_duration {
+            self.candidate = None;
+            self.active_note = Some(note);
+            self.active_start = start;
+            self.active_stats = self.candidate_stats;
+            events.push(NoteEvent::On { midi: note, time: start });
+        } else {
+            self.candidate = Some((note, start));
+        }
+
+        events
+    }
+
+    /// Ends the currently held note at `time`, if any.
+    pub fn finish(&mut self, time: f64) -> Option<NoteEvent> {
+        let midi = self.active_note.take()?;
+        let stats = self.active_stats;
+        self.completed.push(CompletedNote {
+            midi,
+            start_s: self.active_start,
+            end_s: time,
+            mean_cents_offset: NoteStats::mean(stats.cents_offset_sum, stats.count),
+            mean_confidence: NoteStats::mean(stats.confidence_sum, stats.count),
+        });
+
+        Some(NoteEvent::Off { midi, time })
+    }
+
+    /// Returns the notes that ended since the last call, in the order they ended.
+    pub fn take_completed(&mut self) -> Vec<CompletedNote> {
+        std::mem::take(&mut self.completed)
+    }
+}
+
+/// The default number of seconds after an onset during which no further onset is detected.
+pub const DEFAULT_ONSET_REFRACTORY_SECONDS: f64 = 0.15;
+
+/// The level in dBFS that quieter audio is raised to, so that a rise out of digital silence is finite.
+const ONSET_FLOOR_DB: f32 = -90.0;
+
+/// The rise in level in dB that counts as much toward an onset as a rise in confidence by
+/// [`CONFIDENCE_RISE_SCALE`].
+const LEVEL_RISE_SCALE_DB: f32 = 12.0;
+
+const CONFIDENCE_RISE_SCALE: f32 = 0.5;
+
+/// Detects the starts of notes, including notes that are re-articulated at the same pitch, from
+/// sudden rises in the level of the audio and the confidence of the pitch.
+pub struct OnsetDetector {
+    /// The combined rise of level and confidence that marks an onset.
+    threshold: f32,
+    refractory_seconds: f64,
+    /// The level and confidence of the previous frame.
+    previous: Option<(f32, f32)>,
+    last_onset: Option<f64>,
+}
+
+impl OnsetDetector {
+    /// Creates a detector with a `sensitivity` from 0 to 1, higher values detect onsets from
+    /// smaller rises.
+    pub fn new(sensitivity: f32) -> Self {
+        OnsetDetector {
+            threshold: 0.25 + 1.5 * (1.0 - sensitivity.clamp(0.0, 1.0)),
+            refractory_seconds: DEFAULT_ONSET_REFRACTORY_SECONDS,
+            previous: None,
+            last_onset: None,
+        }
+    }
+
+    /// Sets the number of seconds after an onset during which no further onset is detected, so
+    /// that a single attack does not trigger twice.
+    pub fn with_refractory_period(mut self, seconds: f64) -> Self {
+        self.refractory_seconds = seconds;
+        self
+    }
+
+    /// Updates the detector with the level in dBFS and the pitch confidence of a frame at `time`
+    /// seconds, returning whether a note started at it.
+    ///
+    /// Frames without a pitch should be passed with a confidence of 0.
+    pub fn update(&mut self, level_db: f32, confidence: f32, time: f64) -> bool {
+        let level_db = level_db.max(ONSET_FLOOR_DB);
+        let Some((previous_level_db, previous_confidence)) = self.previous.replace((level_db, confidence)) else {
+            return false;
+        };
+
+        let rise = ((level_db - previous_level_db) / LEVEL_RISE_SCALE_DB).max(0.0)
+            + ((confidence - previous_confidence) / CONFIDENCE_RISE_SCALE).max(0.0);
+        if rise < self.threshold || self.last_onset.is_some_and(|last| time - last < self.refractory_seconds) {
+            return false;
+        }
+        self.last_onset = Some(time);
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use crate::events::*;
+    use crate::notes::cents_to_hz;
+    use crate::test_util::{prediction, STEP};
+
+    fn track(tracker: &mut NoteTracker, predictions: &[Prediction]) -> Vec<NoteEvent> {
+        predictions.iter()
+            .enumerate()
+            .flat_map(|(i, prediction)| tracker.update(prediction, i as f64 * STEP))
+            .collect()
+    }
+
+    #[test]
+    fn test_held_note_followed_by_silence() {
+        let mut predictions = vec![prediction(440.0, 0.9); 10];
+        predictions.extend([prediction(0.0, 0.1); 3]);
+
+        let events = track(&mut NoteTracker::new(), &predictions);
+        assert_eq!(events, vec![
+            NoteEvent::On { midi: 69, time: 0.0 },
+            NoteEvent::Off { midi: 69, time: 10.0 * STEP },
+        ]);
+    }
+
+    #[test]
+    fn test_short_note_is_ignored() {
+        let predictions = [
+            prediction(440.0, 0.9),
+            prediction(523.0, 0.9),
+            prediction(440.0, 0.1),
+        ];
+
+        assert!(track(&mut NoteTracker::new(), &predictions).is_empty());
+    }
+
+    #[test]
+    fn test_note_change() {
+        let mut predictions = vec![prediction(440.0, 0.9); 3];
+        predictions.extend([prediction(494.0, 0.9); 3]);
+
+        let events = track(&mut NoteTracker::new(), &predictions);
+        assert_eq!(events, vec![
+            NoteEvent::On { midi: 69, time: 0.0 },
+            NoteEvent::Off { midi: 69, time: 3.0 * STEP },
+            NoteEvent::On { midi: 71, time: 3.0 * STEP },
+        ]);
+    }
+
+    #[test]
+    fn test_custom_min_duration_and_confidence() {
+        let predictions = [prediction(440.0, 0.4), prediction(440.0, 0.4)];
+
+        assert!(track(&mut NoteTracker::new(), &predictions).is_empty());
+        let mut tracker = NoteTracker::new()
+            .with_min_duration(0.0)
+            .with_min_confidence(0.3);
+        assert_eq!(track(&mut tracker, &predictions), vec![NoteEvent::On { midi: 69, time: 0.0 }]);
+        assert_eq!(tracker.finish(1.0), Some(NoteEvent::Off { midi: 69, time: 1.0 }));
+    }
+
+    #[test]
+    fn test_completed_note_statistics() {
+        // A held A4 that alternates between 10 and 20 cents sharp, followed by silence.
+        let mut predictions = (0..10)
+            .map(|i| if i % 2 == 0 { prediction(cents_to_hz(10.0, 440.0), 0.8) } else { prediction(cents_to_hz(20.0, 440.0), 1.0) })
+            .collect::<Vec<Prediction>>();
+        predictions.extend([prediction(0.0, 0.1); 2]);
+
+        let mut tracker = NoteTracker::new();
+        track(&mut tracker, &predictions);
+        let completed = tracker.take_completed();
+        assert_eq!(completed.len(), 1);
+        let note = completed[0];
+        assert_eq!(note.midi, 69);
+        assert_relative_eq!(note.start_s, 0.0);
+        assert_relative_eq!(note.duration_s(), 10.0 * STEP);
+        assert_relative_eq!(note.mean_cents_offset, 15.0, epsilon = 0.01);
+        assert_relative_eq!(note.mean_confidence, 0.9, epsilon = 1e-6);
+        assert!(tracker.take_completed().is_empty());
+    }
+
+    /// The onset times of frames that are silent except for bursts of a pitched sound at `bursts`.
+    fn onsets(bursts: &[usize]) -> Vec<f64> {
+        let mut detector = OnsetDetector::new(0.5);
+        (0..12)
+            .filter(|i| {
+                let (level_db, confidence) = if bursts.contains(i) { (-12.0, 0.9) } else { (f32::NEG_INFINITY, 0.0) };
+                detector.update(level_db, confidence, *i as f64 * STEP)
+            })
+            .map(|i| i as f64 * STEP)
+            .collect()
+    }
+
+    #[test]
+    fn test_onsets_outside_refractory_period() {
+        assert_eq!(onsets(&[2, 3, 7, 8]), vec![2.0 * STEP, 7.0 * STEP]);
+    }
+
+    #[test]
+    fn test_onsets_within_refractory_period_trigger_once() {
+        assert_eq!(onsets(&[2, 4]), vec![2.0 * STEP]);
+        assert!(onsets(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_sensitivity() {
+        // A rise of 6 dB without a change in confidence is only an onset for a sensitive detector.
+        let rise = |sensitivity: f32| {
+            let mut detector = OnsetDetector::new(sensitivity);
+            detector.update(-30.0, 0.8, 0.0);
+            detector.update(-24.0, 0.8, STEP)
+        };
+
+        assert!(rise(1.0));
+        assert!(!rise(0.5));
+    }
+}
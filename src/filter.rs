@@ -0,0 +1,650 @@
+use std::collections::VecDeque;
+use crate::crepe::Prediction;
+use crate::notes::{cents_to_hz, hz_to_cents, DEFAULT_A4_HZ};
+
+/// A filter that smooths a sequence of pitch values.
+pub trait Smoother: Send + Sync {
+    /// Adds a value and returns the smoothed value.
+    fn push(&mut self, value: f32) -> f32;
+
+    /// Adds a value that was detected with `confidence` and returns the smoothed value. Smoothers
+    /// that do not take the confidence into account ignore it.
+    fn push_with_confidence(&mut self, value: f32, _confidence: f32) -> f32 {
+        self.push(value)
+    }
+}
+
+/// Running median over the most recent frequencies, which removes short outliers without lagging
+/// behind as much as an average.
+pub struct MedianFilter {
+    window: usize,
+    values: VecDeque<f32>,
+}
+
+impl MedianFilter {
+    /// Creates a filter over the last `window` values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` is zero.
+    pub fn new(window: usize) -> Self {
+        assert!(window > 0, "median filter window must not be empty");
+
+        MedianFilter {
+            window,
+            values: VecDeque::with_capacity(window),
+        }
+    }
+
+    /// Adds a frequency and returns the median of the frequencies in the window.
+    ///
+    /// For even numbers of frequencies, the median is the mean of the two middle frequencies in
+    /// cents, so that the median of two octaves is the note halfway between them.
+    pub fn push(&mut self, value: f32) -> f32 {
+        if self.values.len() == self.window {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+
+        // Cents increase with the frequency, so sorting frequencies sorts their cents as well.
+        let mut sorted = self.values.iter().copied().collect::<Vec<f32>>();
+        sorted.sort_by(f32::total_cmp);
+        let middle = sorted.len() / 2;
+        if sorted.len() % 2 == 0 {
+            let mean_cents = (hz_to_cents(sorted[middle - 1], DEFAULT_A4_HZ) + hz_to_cents(sorted[middle], DEFAULT_A4_HZ)) / 2.0;
+            cents_to_hz(mean_cents, DEFAULT_A4_HZ)
+        } else {
+            sorted[middle]
+        }
+    }
+}
+
+impl Smoother for MedianFilter {
+    fn push(&mut self, value: f32) -> f32 {
+        MedianFilter::push(self, value)
+    }
+}
+
+/// Exponential moving average of frequencies, which glides smoothly between pitches.
+///
+/// The average is taken in cents, so that a step up is smoothed the same way as an equally large
+/// step down.
+pub struct EmaSmoother {
+    alpha: f32,
+    cents_average: Option<f32>,
+}
+
+impl EmaSmoother {
+    /// Creates a filter where each new frequency has a weight of `alpha`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `alpha` is not greater than zero and at most one.
+    pub fn new(alpha: f32) -> Self {
+        assert!(alpha > 0.0 && alpha <= 1.0, "EMA alpha must be in (0, 1], got {}", alpha);
+
+        EmaSmoother {
+            alpha,
+            cents_average: None,
+        }
+    }
+
+    /// Adds a frequency and returns the updated average frequency.
+    pub fn push(&mut self, frequency: f32) -> f32 {
+        let cents = hz_to_cents(frequency, DEFAULT_A4_HZ);
+        let cents_average = match self.cents_average {
+            None => cents,
+            Some(previous) => self.alpha * cents + (1.0 - self.alpha) * previous,
+        };
+        self.cents_average = Some(cents_average);
+
+        cents_to_hz(cents_average, DEFAULT_A4_HZ)
+    }
+}
+
+impl Smoother for EmaSmoother {
+    fn push(&mut self, value: f32) -> f32 {
+        EmaSmoother::push(self, value)
+    }
+}
+
+/// Exponential moving average that smooths held notes strongly but follows fast pitch changes,
+/// e.g. glissandi and runs, without lagging behind.
+///
+/// New frequencies have a weight of `fast_alpha` while the pitch changes by more than
+/// `slope_threshold_cents` from one value to the next, and `slow_alpha` otherwise.
+pub struct AdaptiveSmoother {
+    slow_alpha: f32,
+    fast_alpha: f32,
+    slope_threshold_cents: f32,
+    cents_average: Option<f32>,
+    /// The previous unsmoothed value in cents, which the slope is measured from.
+    previous_cents: Option<f32>,
+}
+
+impl AdaptiveSmoother {
+    /// # Panics
+    ///
+    /// Panics if either weight is not greater than zero and at most one.
+    pub fn new(slow_alpha: f32, fast_alpha: f32, slope_threshold_cents: f32) -> Self {
+        for alpha in [slow_alpha, fast_alpha] {
+            assert!(alpha > 0.0 && alpha <= 1.0, "EMA alpha must be in (0, 1], got {}", alpha);
+        }
+
+        AdaptiveSmoother {
+            slow_alpha,
+            fast_alpha,
+            slope_threshold_cents,
+            cents_average: None,
+            previous_cents: None,
+        }
+    }
+
+    /// Adds a frequency and returns the updated average frequency.
+    pub fn push(&mut self, frequency: f32) -> f32 {
+        let cents = hz_to_cents(frequency, DEFAULT_A4_HZ);
+        let is_fast = self.previous_cents.is_some_and(|previous| (cents - previous).abs() > self.slope_threshold_cents);
+        let alpha = if is_fast { self.fast_alpha } else { self.slow_alpha };
+        let cents_average = match self.cents_average {
+            None => cents,
+            Some(previous) => alpha * cents + (1.0 - alpha) * previous,
+        };
+        self.cents_average = Some(cents_average);
+        self.previous_cents = Some(cents);
+
+        cents_to_hz(cents_average, DEFAULT_A4_HZ)
+    }
+}
+
+impl Smoother for AdaptiveSmoother {
+    fn push(&mut self, value: f32) -> f32 {
+        AdaptiveSmoother::push(self, value)
+    }
+}
+
+/// Exponential moving average in cents where the weight of each new value scales with its
+/// confidence, so that unsure frames barely move the average without being dropped by a hard gate.
+pub struct ConfidenceWeightedEma {
+    alpha: f32,
+    cents_average: Option<f32>,
+}
+
+impl ConfidenceWeightedEma {
+    /// Creates a filter where a new value with full confidence has a weight of `alpha`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `alpha` is not greater than zero and at most one.
+    pub fn new(alpha: f32) -> Self {
+        assert!(alpha > 0.0 && alpha <= 1.0, "EMA alpha must be in (0, 1], got {}", alpha);
+
+        ConfidenceWeightedEma {
+            alpha,
+            cents_average: None,
+        }
+    }
+
+    /// Adds a value in cents with a weight of `alpha` times its confidence and returns the updated
+    /// average in cents. The first value is taken as is, whatever its confidence.
+    pub fn push(&mut self, freq_cents: f32, confidence: f32) -> f32 {
+        let alpha = self.alpha * confidence.clamp(0.0, 1.0);
+        let cents_average = match self.cents_average {
+            None => freq_cents,
+            Some(previous) => alpha * freq_cents + (1.0 - alpha) * previous,
+        };
+        self.cents_average = Some(cents_average);
+
+        cents_average
+    }
+}
+
+impl Smoother for ConfidenceWeightedEma {
+    fn push(&mut self, value: f32) -> f32 {
+        self.push_with_confidence(value, 1.0)
+    }
+
+    fn push_with_confidence(&mut self, value: f32, confidence: f32) -> f32 {
+        cents_to_hz(ConfidenceWeightedEma::push(self, hz_to_cents(value, DEFAULT_A4_HZ), confidence), DEFAULT_A4_HZ)
+    }
+}
+
+/// How far in cents the following frames may be from a frame for [`OnsetConfirmer`] to confirm it.
+pub const DEFAULT_ONSET_TOLERANCE_CENTS: f32 = 50.0;
+
+/// Delays frequencies by a fixed number of frames to suppress the burst of wrong frequencies the
+/// model predicts at note onsets before it settles.
+///
+/// A frame is let through if it continues the previous frame that was let through, or if all of
+/// the following frames in the confirmation window agree with it. Frames that are not confirmed
+/// become NaN, like frames without pitch. Every frame carries a payload, e.g. its time, that is
+/// passed through unchanged.
+pub struct OnsetConfirmer<T> {
+    confirm_frames: usize,
+    tolerance_cents: f32,
+    pending: VecDeque<(f32, T)>,
+    /// The frequency of the last frame that was returned, NaN if it had no pitch or was suppressed.
+    last_frequency: f32,
+}
+
+impl<T> OnsetConfirmer<T> {
+    /// Creates a confirmer that delays frames by `confirm_frames` frames, where zero lets every
+    /// frame through right away.
+    pub fn new(confirm_frames: usize) -> Self {
+        OnsetConfirmer {
+            confirm_frames,
+            tolerance_cents: DEFAULT_ONSET_TOLERANCE_CENTS,
+            pending: VecDeque::with_capacity(confirm_frames + 1),
+            last_frequency: f32::NAN,
+        }
+    }
+
+    /// Sets how far in cents frames may be apart to agree with each other.
+    pub fn with_tolerance_cents(mut self, tolerance_cents: f32) -> Self {
+        self.tolerance_cents = tolerance_cents;
+        self
+    }
+
+    /// Adds the frequency of a frame, NaN if it has no pitch, and returns the frame from
+    /// `confirm_frames` frames ago once there is one.
+    pub fn push(&mut self, frequency: f32, payload: T) -> Option<(f32, T)> {
+        self.pending.push_back((frequency, payload));
+        if self.pending.len() <= self.confirm_frames {
+            return None;
+        }
+
+        self.next_frame()
+    }
+
+    /// Returns all frames that are still delayed, e.g. at the end of a stream. They are confirmed
+    /// by the frames that followed them so far, so the very last frame is always let through.
+    pub fn flush(&mut self) -> Vec<(f32, T)> {
+        std::iter::from_fn(|| self.next_frame()).collect()
+    }
+
+    fn next_frame(&mut self) -> Option<(f32, T)> {
+        let (frequency, payload) = self.pending.pop_front()?;
+        let agrees = |other: f32| other.is_finite() && cents_between(frequency, other).abs() <= self.tolerance_cents;
+        let is_confirmed = frequency.is_finite() && frequency > 0.0
+            && (agrees(self.last_frequency) || self.pending.iter().all(|(other, _)| agrees(*other)));
+
+        self.last_frequency = if is_confirmed { frequency } else { f32::NAN };
+        Some((self.last_frequency, payload))
+    }
+}
+
+/// A confidence gate with hysteresis and attack and release times, which keeps the pitch from
+/// flickering on and off while the confidence hovers around a single threshold, e.g. at the end of
+/// a phrase.
+#[derive(Debug, Clone)]
+pub struct Gate {
+    open_threshold: f32,
+    close_threshold: f32,
+    attack_ms: f64,
+    release_ms: f64,
+    is_open: bool,
+    /// The time in seconds since which the confidence has been past the threshold for changing
+    /// the state, `None` if it is not.
+    pending_since: Option<f64>,
+}
+
+impl Gate {
+    /// Creates a closed gate that opens once the confidence has been at least `open_threshold`
+    /// for `attack_ms` milliseconds, and closes again once it has been below `close_threshold` for
+    /// `release_ms` milliseconds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `close_threshold` is greater than `open_threshold`.
+    pub fn new(open_threshold: f32, close_threshold: f32, attack_ms: f64, release_ms: f64) -> Self {
+        assert!(
+            close_threshold <= open_threshold,
+            "gate close threshold {} must not be above the open threshold {}", close_threshold, open_threshold,
+        );
+
+        Gate {
+            open_threshold,
+            close_threshold,
+            attack_ms,
+            release_ms,
+            is_open: false,
+            pending_since: None,
+        }
+    }
+
+    /// Updates the gate with the confidence of a frame at `time_s` seconds and returns whether the
+    /// gate is open.
+    pub fn update(&mut self, confidence: f32, time_s: f64) -> bool {
+        let wants_change = if self.is_open {
+            confidence < self.close_threshold
+        } else {
+            confidence >= self.open_threshold
+        };
+        if !wants_change {
+            self.pending_since = None;
+            return self.is_open;
+        }
+
+        let since = *self.pending_since.get_or_insert(time_s);
+        let hold_ms = if self.is_open { self.release_ms } else { self.attack_ms };
+        if (time_s - since) * 1000.0 >= hold_ms {
+            self.is_open = !self.is_open;
+            self.pending_since = None;
+        }
+
+        self.is_open
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.is_open
+    }
+}
+
+/// How far in cents a frequency may be from an exact octave of its neighbors to still be
+/// corrected by [`correct_octaves`].
+pub const DEFAULT_OCTAVE_TOLERANCE_CENTS: f32 = 100.0;
+
+fn cents_between(from: f32, to: f32) -> f32 {
+    hz_to_cents(to, from)
+}
+
+/// Moves single predictions that are an octave above or below both of their neighbors into the
+/// octave of the neighbors, keeping their offset within the octave.
+///
+/// This fixes the model briefly locking onto a harmonic. See [`correct_octaves_with_tolerance`]
+/// for when a prediction is corrected. Returns the number of corrected predictions.
+pub fn correct_octaves(predictions: &mut [Prediction]) -> usize {
+    correct_octaves_with_tolerance(predictions, DEFAULT_OCTAVE_TOLERANCE_CENTS)
+}
+
+/// Like [`correct_octaves`], with a custom tolerance in cents.
+///
+/// A prediction is corrected if its neighbors are within `tolerance_cents` of each other, it is
+/// within `tolerance_cents` of an octave above or below their average, and the neighbors are on
+/// average at least as confident as the prediction itself.
+/// The first and last predictions are never corrected since they only have one neighbor.
+pub fn correct_octaves_with_tolerance(predictions: &mut [Prediction], tolerance_cents: f32) -> usize {
+    let is_valid = |prediction: &Prediction| prediction.frequency > 0.0 && prediction.frequency.is_finite();
+
+    // Decisions are made on the original predictions so that corrections do not cascade.
+    let original = predictions.to_vec();
+    let mut corrected = 0;
+    for (i, window) in original.windows(3).enumerate() {
+        let [previous, current, next] = [window[0], window[1], window[2]];
+        if !is_valid(&previous) || !is_valid(&current) || !is_valid(&next) {
+            continue;
+        }
+        if cents_between(previous.frequency, next.frequency).abs() > tolerance_cents {
+            continue;
+        }
+        if (previous.confidence + next.confidence) / 2.0 < current.confidence {
+            continue;
+        }
+
+        let context_frequency = (previous.frequency * next.frequency).sqrt();
+        let deviation = cents_between(context_frequency, current.frequency);
+        let octaves = (deviation / 1200.0).round();
+        if octaves.abs() != 1.0 || (deviation - octaves * 1200.0).abs() > tolerance_cents {
+            continue;
+        }
+
+        predictions[i + 1].frequency = current.frequency / octaves.exp2();
+        corrected += 1;
+    }
+
+    corrected
+}
+
+/// Treats a prediction outside of `min_hz..=max_hz` as no pitch, e.g. to suppress octave errors
+/// above the highest note an instrument can play.
+///
+/// Octave correction should run before this, so that corrected predictions are kept.
+pub fn gate_frequency_range(prediction: Prediction, min_hz: f32, max_hz: f32) -> Option<Prediction> {
+    Some(prediction).filter(|prediction| prediction.frequency >= min_hz && prediction.frequency <= max_hz)
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use crate::filter::*;
+    use crate::test_util::prediction;
+
+    #[test]
+    fn test_spike_is_removed() {
+        let mut filter = MedianFilter::new(3);
+
+        let output = [220.0, 221.0, 440.0, 222.0, 221.0].map(|value| filter.push(value));
+        assert_eq!(output[2..], [221.0, 222.0, 222.0]);
+    }
+
+    #[test]
+    fn test_monotonic_ramp() {
+        let mut filter = MedianFilter::new(3);
+
+        let output = [100.0, 110.0, 120.0, 130.0, 140.0].map(|value| filter.push(value));
+        // The first two outputs average over the values seen so far, in cents.
+        assert_relative_eq!(output[1], (100.0f32 * 110.0).sqrt(), epsilon = 1e-3);
+        assert_eq!(output[2..], [110.0, 120.0, 130.0]);
+    }
+
+    #[test]
+    fn test_octaves_are_averaged_in_cents() {
+        let mut filter = MedianFilter::new(2);
+        filter.push(440.0);
+        // The geometric mean, not the 660 Hz of averaging in Hz.
+        assert_relative_eq!(filter.push(880.0), 622.25, epsilon = 0.01);
+
+        let mut smoother = EmaSmoother::new(0.5);
+        smoother.push(440.0);
+        assert_relative_eq!(smoother.push(880.0), 622.25, epsilon = 0.01);
+    }
+
+    #[test]
+    fn test_ema_with_full_weight_is_identity() {
+        let mut smoother = EmaSmoother::new(1.0);
+
+        for value in [220.0, 440.0, 110.0, 330.0] {
+            assert_relative_eq!(smoother.push(value), value, epsilon = 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_ema_with_small_weight_damps_step() {
+        let mut smoother = EmaSmoother::new(0.01);
+
+        smoother.push(220.0);
+        let output = [440.0; 10].map(|value| smoother.push(value));
+        assert!(output.iter().all(|value| *value > 220.0 && *value < 240.0));
+        assert!(output.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_adaptive_smoothing_tracks_ramp_and_steadies_held_note() {
+        let mut smoother = AdaptiveSmoother::new(0.05, 0.8, 20.0);
+
+        // A held note that jitters by 5 cents around 220 Hz barely moves the output.
+        smoother.push(220.0);
+        for i in 0..50 {
+            let jitter = if i % 2 == 0 { 5.0 } else { -5.0 };
+            let output = smoother.push(cents_to_hz(jitter, 220.0));
+            assert!(hz_to_cents(output, 220.0).abs() < 0.5);
+        }
+
+        // A glide of 50 cents per value is followed closely.
+        let mut output = 0.0;
+        for i in 1..=20 {
+            output = smoother.push(cents_to_hz(i as f32 * 50.0, 220.0));
+        }
+        assert!((1000.0 - hz_to_cents(output, 220.0)).abs() < 15.0);
+
+        // A plain EMA with the slow weight lags far behind.
+        let mut ema = EmaSmoother::new(0.05);
+        ema.push(220.0);
+        for i in 1..=20 {
+            output = ema.push(cents_to_hz(i as f32 * 50.0, 220.0));
+        }
+        assert!((1000.0 - hz_to_cents(output, 220.0)).abs() > 500.0);
+    }
+
+    #[test]
+    fn test_confident_values_dominate_unsure_outliers() {
+        let mut smoother = ConfidenceWeightedEma::new(0.5);
+        let mut ema = EmaSmoother::new(0.5);
+
+        // A held note at 0 cents with an unsure octave error after every frame.
+        smoother.push(0.0, 0.95);
+        ema.push(cents_to_hz(0.0, DEFAULT_A4_HZ));
+        let mut outputs = Vec::new();
+        let mut ema_outputs = Vec::new();
+        for _ in 0..20 {
+            outputs.push(smoother.push(1200.0, 0.05));
+            outputs.push(smoother.push(0.0, 0.95));
+            ema_outputs.push(hz_to_cents(ema.push(cents_to_hz(1200.0, DEFAULT_A4_HZ)), DEFAULT_A4_HZ));
+            ema_outputs.push(hz_to_cents(ema.push(cents_to_hz(0.0, DEFAULT_A4_HZ)), DEFAULT_A4_HZ));
+        }
+        assert!(outputs.iter().all(|cents| (0.0..100.0).contains(cents)), "{:?}", outputs);
+        assert!(ema_outputs.iter().skip(2).all(|cents| *cents > 300.0), "{:?}", ema_outputs);
+
+        // A confident value moves the average as much as in a plain EMA.
+        let mut smoother = ConfidenceWeightedEma::new(0.5);
+        smoother.push(0.0, 1.0);
+        assert_relative_eq!(smoother.push(100.0, 1.0), 50.0);
+        assert_relative_eq!(smoother.push(100.0, 0.0), 50.0);
+    }
+
+    #[test]
+    fn test_octave_high_frame_is_corrected() {
+        let mut predictions = [prediction(220.0, 0.9), prediction(445.0, 0.7), prediction(222.0, 0.9)];
+
+        assert_eq!(correct_octaves(&mut predictions), 1);
+        assert_relative_eq!(predictions[1].frequency, 222.5);
+        assert_relative_eq!(predictions[1].confidence, 0.7);
+        assert_eq!(predictions[0].frequency, 220.0);
+        assert_eq!(predictions[2].frequency, 222.0);
+    }
+
+    #[test]
+    fn test_octave_low_frame_is_corrected() {
+        let mut predictions = [prediction(440.0, 0.9), prediction(220.0, 0.8), prediction(440.0, 0.9)];
+
+        assert_eq!(correct_octaves(&mut predictions), 1);
+        assert_relative_eq!(predictions[1].frequency, 440.0);
+    }
+
+    #[test]
+    fn test_octaves_are_kept_without_consistent_context() {
+        // The neighbors disagree with each other.
+        let mut predictions = [prediction(220.0, 0.9), prediction(440.0, 0.7), prediction(330.0, 0.9)];
+        assert_eq!(correct_octaves(&mut predictions), 0);
+
+        // The neighbors are less confident than the frame.
+        let mut predictions = [prediction(220.0, 0.3), prediction(440.0, 0.9), prediction(220.0, 0.3)];
+        assert_eq!(correct_octaves(&mut predictions), 0);
+
+        // The jump is a fifth, not an octave.
+        let mut predictions = [prediction(220.0, 0.9), prediction(330.0, 0.7), prediction(220.0, 0.9)];
+        assert_eq!(correct_octaves(&mut predictions), 0);
+    }
+
+    #[test]
+    fn test_octave_tolerance() {
+        let mut predictions = [prediction(220.0, 0.9), prediction(470.0, 0.7), prediction(220.0, 0.9)];
+
+        let mut strict_predictions = predictions;
+        assert_eq!(correct_octaves_with_tolerance(&mut strict_predictions, 50.0), 0);
+        assert_eq!(correct_octaves_with_tolerance(&mut predictions, 150.0), 1);
+        assert_relative_eq!(predictions[1].frequency, 235.0);
+    }
+
+    #[test]
+    fn test_spurious_onset_is_suppressed() {
+        let mut confirmer = OnsetConfirmer::new(2);
+        let frequencies = [f32::NAN, 780.0, 220.0, 221.0, 220.0, 222.0];
+
+        let mut output = frequencies.iter()
+            .enumerate()
+            .filter_map(|(i, frequency)| confirmer.push(*frequency, i))
+            .collect::<Vec<(f32, usize)>>();
+        assert_eq!(output.iter().map(|(_, i)| *i).collect::<Vec<usize>>(), [0, 1, 2, 3]);
+        output.extend(confirmer.flush());
+
+        assert!(output[0].0.is_nan());
+        assert!(output[1].0.is_nan());
+        assert_eq!(output[2..].iter().map(|(frequency, _)| *frequency).collect::<Vec<f32>>(), [220.0, 221.0, 220.0, 222.0]);
+        assert_eq!(output.iter().map(|(_, i)| *i).collect::<Vec<usize>>(), [0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_confirmed_note_continues_until_it_changes() {
+        let mut confirmer = OnsetConfirmer::new(2);
+        // The glide continues the held note, the jump to 330 Hz is confirmed by the frames after it.
+        let frequencies = [220.0, 220.0, 220.0, 225.0, 230.0, 330.0, 330.0, 331.0];
+
+        let mut output = frequencies.iter()
+            .filter_map(|frequency| confirmer.push(*frequency, ()))
+            .map(|(frequency, _)| frequency)
+            .collect::<Vec<f32>>();
+        output.extend(confirmer.flush().into_iter().map(|(frequency, _)| frequency));
+        assert_eq!(output, frequencies);
+    }
+
+    #[test]
+    fn test_flush_at_end_of_stream() {
+        let mut confirmer = OnsetConfirmer::new(3);
+
+        assert_eq!(confirmer.push(440.0, 0), None);
+        assert_eq!(confirmer.push(441.0, 1), None);
+        assert_eq!(confirmer.flush(), vec![(440.0, 0), (441.0, 1)]);
+        assert!(confirmer.flush().is_empty());
+
+        let mut confirmer = OnsetConfirmer::new(0);
+        assert_eq!(confirmer.push(440.0, 0), Some((440.0, 0)));
+        assert!(confirmer.flush().is_empty());
+    }
+
+    #[test]
+    fn test_hysteresis_prevents_chatter() {
+        // The confidence dithers around 0.5 every 10 milliseconds.
+        let confidences = (0..100).map(|i| if i % 2 == 0 { 0.52 } else { 0.48 }).collect::<Vec<f32>>();
+        let toggles = |gate: &mut Gate| confidences.iter()
+            .enumerate()
+            .map(|(i, confidence)| gate.update(*confidence, i as f64 * 0.01))
+            .collect::<Vec<bool>>()
+            .windows(2)
+            .filter(|pair| pair[0] != pair[1])
+            .count();
+
+        // A single threshold follows every change of the confidence.
+        assert_eq!(toggles(&mut Gate::new(0.5, 0.5, 0.0, 0.0)), 99);
+
+        let mut gate = Gate::new(0.5, 0.3, 0.0, 0.0);
+        assert_eq!(toggles(&mut gate), 0);
+        assert!(gate.is_open());
+    }
+
+    #[test]
+    fn test_gate_attack_and_release() {
+        let mut gate = Gate::new(0.6, 0.4, 50.0, 100.0);
+
+        // A short burst of confidence does not open the gate.
+        assert!(!gate.update(0.9, 0.0));
+        assert!(!gate.update(0.9, 0.02));
+        assert!(!gate.update(0.5, 0.04));
+        assert!(!gate.update(0.9, 0.06));
+        assert!(gate.update(0.9, 0.11));
+
+        // A short dip does not close it.
+        assert!(gate.update(0.1, 0.2));
+        assert!(gate.update(0.5, 0.25));
+        assert!(gate.update(0.1, 0.3));
+        assert!(gate.update(0.1, 0.39));
+        assert!(!gate.update(0.1, 0.4));
+    }
+
+    #[test]
+    fn test_frequency_range_gate() {
+        assert_eq!(gate_frequency_range(prediction(1200.0, 0.9), 50.0, 700.0), None);
+        assert_eq!(gate_frequency_range(prediction(30.0, 0.9), 50.0, 700.0), None);
+        assert_eq!(gate_frequency_range(prediction(220.0, 0.9), 50.0, 700.0), Some(prediction(220.0, 0.9)));
+        assert_eq!(gate_frequency_range(prediction(700.0, 0.9), 50.0, 700.0), Some(prediction(700.0, 0.9)));
+    }
+}
@@ -0,0 +1,69 @@
+use std::io;
+use std::io::Write;
+use crate::crepe::{frame_time, PitchDetector, Prediction, SAMPLES_PER_STEP};
+
+/// Runs `detector` on every chunk of `chunks` and writes a `time_s freq_hz confidence` line for
+/// each of them to `out`, returning the number of lines written.
+///
+/// Every line is flushed right away so that other tools can read the pitch while the audio is
+/// still being captured.
+pub fn run_headless<I, W>(detector: &dyn PitchDetector, chunks: I, out: &mut W) -> io::Result<usize>
+where
+    I: IntoIterator<Item = [i16; SAMPLES_PER_STEP]>,
+    W: Write,
+{
+    run_headless_with(detector, chunks, out, |_, _| {})
+}
+
+/// Like [`run_headless`], but also passes the time and prediction of every chunk to
+/// `on_prediction` after its line was written, e.g. to send it elsewhere.
+pub fn run_headless_with<I, W, F>(detector: &dyn PitchDetector, chunks: I, out: &mut W, mut on_prediction: F) -> io::Result<usize>
+where
+    I: IntoIterator<Item = [i16; SAMPLES_PER_STEP]>,
+    W: Write,
+    F: FnMut(f64, &Prediction),
+{
+    let mut lines = 0;
+    for (frame, chunk) in chunks.into_iter().enumerate() {
+        let prediction = detector.predict_single(chunk);
+        let time = frame_time(frame, SAMPLES_PER_STEP);
+        writeln!(out, "{:.3} {:.2} {:.3}", time, prediction.frequency, prediction.confidence)?;
+        out.flush()?;
+        on_prediction(time, &prediction);
+        lines += 1;
+    }
+
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::autocorr::YinDetector;
+    use crate::headless::*;
+    use crate::test_util::sine_chunk;
+
+    #[test]
+    fn test_writes_one_line_per_chunk() {
+        let chunks = vec![sine_chunk(220.0); 5];
+        let mut out = Vec::new();
+
+        let lines = run_headless(&YinDetector::new(), chunks, &mut out).unwrap();
+        assert_eq!(lines, 5);
+        let output = String::from_utf8(out).unwrap();
+        assert_eq!(output.lines().count(), 5);
+
+        let fields = output.lines().nth(1).unwrap().split(' ').collect::<Vec<&str>>();
+        assert_eq!(fields[0], "0.064");
+        let frequency = fields[1].parse::<f32>().unwrap();
+        assert!((frequency - 220.0).abs() < 3.0);
+        assert!(fields[2].parse::<f32>().unwrap() > 0.9);
+    }
+
+    #[test]
+    fn test_no_chunks_writes_nothing() {
+        let mut out = Vec::new();
+
+        assert_eq!(run_headless(&YinDetector::new(), Vec::new(), &mut out).unwrap(), 0);
+        assert!(out.is_empty());
+    }
+}
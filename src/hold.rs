@@ -0,0 +1,85 @@
+/// The pitch that is shown after taking held values into account.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum HeldPitch {
+    /// There is no pitch to show.
+    #[default]
+    None,
+    /// The pitch of the most recent frame.
+    Live(f32),
+    /// The pitch of an earlier frame, kept because the recent frames had no confident pitch.
+    Held(f32),
+}
+
+/// Keeps showing the last confident pitch for a while when the following frames have none, so
+/// that a value that only flickered by can still be read.
+#[derive(Debug, Default, Clone)]
+pub struct PitchHold {
+    timeout: f64,
+    /// The last confident pitch and the time in seconds it was seen at.
+    last_confident: Option<(f32, f64)>,
+}
+
+impl PitchHold {
+    /// Creates a hold that clears `timeout` seconds after the last confident pitch.
+    ///
+    /// A timeout of zero clears the pitch as soon as a frame without a confident pitch arrives.
+    pub fn new(timeout: f64) -> Self {
+        PitchHold {
+            timeout,
+            last_confident: None,
+        }
+    }
+
+    /// Updates the hold with the pitch of a frame at `time` seconds, `None` if the frame had no
+    /// confident pitch, and returns the pitch to show.
+    pub fn update(&mut self, frequency: Option<f32>, time: f64) -> HeldPitch {
+        if let Some(frequency) = frequency {
+            self.last_confident = Some((frequency, time));
+            return HeldPitch::Live(frequency);
+        }
+
+        match self.last_confident {
+            Some((frequency, confident_time)) if time - confident_time < self.timeout => HeldPitch::Held(frequency),
+            _ => {
+                self.last_confident = None;
+                HeldPitch::None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hold::*;
+
+    #[test]
+    fn test_held_until_timeout() {
+        let mut hold = PitchHold::new(1.0);
+
+        assert_eq!(hold.update(Some(220.0), 0.0), HeldPitch::Live(220.0));
+        for i in 1..=10 {
+            assert_eq!(hold.update(None, i as f64 * 0.064), HeldPitch::Held(220.0));
+        }
+        assert_eq!(hold.update(None, 1.1), HeldPitch::None);
+        // A cleared pitch stays cleared.
+        assert_eq!(hold.update(None, 1.2), HeldPitch::None);
+    }
+
+    #[test]
+    fn test_new_pitch_replaces_held_pitch() {
+        let mut hold = PitchHold::new(1.0);
+
+        hold.update(Some(220.0), 0.0);
+        hold.update(None, 0.5);
+        assert_eq!(hold.update(Some(330.0), 0.9), HeldPitch::Live(330.0));
+        assert_eq!(hold.update(None, 1.5), HeldPitch::Held(330.0));
+    }
+
+    #[test]
+    fn test_zero_timeout_clears_immediately() {
+        let mut hold = PitchHold::new(0.0);
+
+        hold.update(Some(220.0), 0.0);
+        assert_eq!(hold.update(None, 0.064), HeldPitch::None);
+    }
+}
@@ -0,0 +1,306 @@
+use std::sync::mpsc::{channel, sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Counts the frames that pass through a processing pipeline and the time spent processing them,
+/// to tell whether processing keeps up with the audio.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PipelineMetrics {
+    /// The number of frames that were captured, including dropped ones.
+    pub captured: usize,
+    pub processed: usize,
+    /// The number of frames that were dropped because processing was too far behind.
+    pub dropped: usize,
+    /// The total time spent processing the processed frames.
+    pub processing_time: Duration,
+}
+
+impl PipelineMetrics {
+    /// Records a captured frame, which was dropped instead of queued if `is_dropped` is true.
+    pub fn record_captured(&mut self, is_dropped: bool) {
+        self.captured += 1;
+        if is_dropped {
+            self.dropped += 1;
+        }
+    }
+
+    /// Records a frame that took `duration` to process.
+    pub fn record_processed(&mut self, duration: Duration) {
+        self.processed += 1;
+        self.processing_time += duration;
+    }
+
+    /// The average time spent processing a frame relative to the `frame_duration` of audio in it,
+    /// or 0 if no frame was processed yet.
+    ///
+    /// Above 1, frames are processed slower than they are captured and some of them are dropped.
+    pub fn real_time_factor(&self, frame_duration: Duration) -> f64 {
+        if self.processed == 0 {
+            return 0.0;
+        }
+
+        self.processing_time.as_secs_f64() / self.processed as f64 / frame_duration.as_secs_f64()
+    }
+
+    /// A short summary of the metrics for frames of `frame_duration`, e.g. for a status line.
+    pub fn status_line(&self, frame_duration: Duration) -> String {
+        format!(
+            "Frames: {} captured, {} processed, {} dropped, real-time factor {:.2}",
+            self.captured, self.processed, self.dropped, self.real_time_factor(frame_duration),
+        )
+    }
+}
+
+/// [`PipelineMetrics`] that can be recorded from several threads at once without locking, e.g.
+/// from an audio callback that must not wait for a thread reading them.
+#[derive(Debug, Default)]
+pub struct SharedPipelineMetrics {
+    captured: AtomicUsize,
+    processed: AtomicUsize,
+    dropped: AtomicUsize,
+    processing_nanos: AtomicU64,
+}
+
+impl SharedPipelineMetrics {
+    /// Records a captured frame, which was dropped instead of queued if `is_dropped` is true.
+    pub fn record_captured(&self, is_dropped: bool) {
+        self.captured.fetch_add(1, Ordering::Relaxed);
+        if is_dropped {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records a frame that took `duration` to process.
+    pub fn record_processed(&self, duration: Duration) {
+        self.processed.fetch_add(1, Ordering::Relaxed);
+        self.processing_nanos.fetch_add(duration.as_nanos().try_into().unwrap_or(u64::MAX), Ordering::Relaxed);
+    }
+
+    /// The metrics recorded so far.
+    ///
+    /// The counters are read one after another, so frames that are recorded meanwhile may only be
+    /// counted in some of them.
+    pub fn snapshot(&self) -> PipelineMetrics {
+        PipelineMetrics {
+            captured: self.captured.load(Ordering::Relaxed),
+            processed: self.processed.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            processing_time: Duration::from_nanos(self.processing_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Queues frames for an [`InferenceWorker`], dropping them if the worker is too far behind.
+///
+/// Submitting never blocks, so frames can be submitted from an audio callback.
+pub struct FrameSender<I> {
+    queue: SyncSender<I>,
+    metrics: Arc<SharedPipelineMetrics>,
+}
+
+impl<I> Clone for FrameSender<I> {
+    fn clone(&self) -> Self {
+        FrameSender {
+            queue: self.queue.clone(),
+            metrics: Arc::clone(&self.metrics),
+        }
+    }
+}
+
+impl<I> FrameSender<I> {
+    /// Queues a frame to be processed, returns whether it was queued or had to be dropped.
+    pub fn submit(&self, frame: I) -> bool {
+        match self.queue.try_send(frame) {
+            Ok(()) => {
+                self.metrics.record_captured(false);
+                true
+            }
+            Err(TrySendError::Full(_)) => {
+                self.metrics.record_captured(true);
+                false
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                println!("Inference worker thread has stopped.");
+                false
+            }
+        }
+    }
+}
+
+/// Processes frames on a background thread, e.g. to run the model without blocking the audio
+/// callback or the UI.
+///
+/// Frames are processed in the order they were submitted, up to `capacity` frames can wait to be
+/// processed before new ones are dropped.
+pub struct InferenceWorker<I, O> {
+    sender: FrameSender<I>,
+    results: Receiver<O>,
+}
+
+impl<I: Send + 'static, O: Send + 'static> InferenceWorker<I, O> {
+    /// Starts a thread that passes every submitted frame to `process` and sends back the result,
+    /// calling `on_result` after each result is ready to be polled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn spawn<F, N>(capacity: usize, mut process: F, on_result: N) -> Self
+    where
+        F: FnMut(I) -> O + Send + 'static,
+        N: Fn() + Send + 'static,
+    {
+        assert!(capacity > 0, "worker needs room for at least one frame");
+
+        let (queue, frames) = sync_channel::<I>(capacity);
+        let (result_sender, results) = channel::<O>();
+        let metrics = Arc::new(SharedPipelineMetrics::default());
+        let processed_metrics = Arc::clone(&metrics);
+        // The thread stops once all senders are dropped and the queue is closed, or once the
+        // worker is dropped and nobody receives the results anymore.
+        thread::spawn(move || {
+            for frame in frames {
+                let start = Instant::now();
+                let result = process(frame);
+                processed_metrics.record_processed(start.elapsed());
+                if result_sender.send(result).is_err() {
+                    break;
+                }
+                on_result();
+            }
+        });
+
+        InferenceWorker {
+            sender: FrameSender {
+                queue,
+                metrics,
+            },
+            results,
+        }
+    }
+}
+
+impl<I, O> InferenceWorker<I, O> {
+    /// Returns a handle that frames can be submitted through, e.g. from another thread.
+    pub fn sender(&self) -> FrameSender<I> {
+        self.sender.clone()
+    }
+
+    /// Queues a frame to be processed, returns whether it was queued or had to be dropped.
+    pub fn submit(&self, frame: I) -> bool {
+        self.sender.submit(frame)
+    }
+
+    /// Returns the results that are ready without waiting, oldest first.
+    pub fn poll(&self) -> Vec<O> {
+        self.results.try_iter().collect()
+    }
+
+    /// The number of frames that were dropped because the worker was too far behind.
+    pub fn dropped_frames(&self) -> usize {
+        self.metrics().dropped
+    }
+
+    /// The frames that were submitted, processed and dropped so far.
+    pub fn metrics(&self) -> PipelineMetrics {
+        self.sender.metrics.snapshot()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::inference::*;
+
+    fn poll_until(worker: &InferenceWorker<usize, usize>, count: usize) -> Vec<usize> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut results = Vec::new();
+        while results.len() < count && Instant::now() < deadline {
+            results.extend(worker.poll());
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        results
+    }
+
+    #[test]
+    fn test_results_keep_order() {
+        let worker = InferenceWorker::spawn(16, |frame: usize| frame * 2, || {});
+        for frame in 0..10 {
+            assert!(worker.submit(frame));
+        }
+
+        assert_eq!(poll_until(&worker, 10), (0..10).map(|frame| frame * 2).collect::<Vec<usize>>());
+        assert_eq!(worker.dropped_frames(), 0);
+    }
+
+    #[test]
+    fn test_frames_faster_than_processed_are_dropped_and_drain() {
+        let worker = InferenceWorker::spawn(4, |frame: usize| {
+            thread::sleep(Duration::from_millis(5));
+            frame
+        }, || {});
+
+        let sender = worker.sender();
+        let submitted = (0..100)
+            .filter(|frame| sender.submit(*frame))
+            .collect::<Vec<usize>>();
+        assert!(worker.dropped_frames() > 0);
+        assert_eq!(submitted.len() + worker.dropped_frames(), 100);
+
+        assert_eq!(poll_until(&worker, submitted.len()), submitted);
+        assert!(worker.poll().is_empty());
+        let metrics = worker.metrics();
+        assert_eq!(metrics.captured, 100);
+        assert_eq!(metrics.processed, submitted.len());
+        assert!(metrics.processing_time >= Duration::from_millis(5) * submitted.len() as u32);
+    }
+
+    #[test]
+    fn test_metrics_of_simulated_run() {
+        let frame_duration = Duration::from_millis(64);
+        let mut metrics = PipelineMetrics::default();
+        assert_eq!(metrics.real_time_factor(frame_duration), 0.0);
+
+        // Every fourth frame is dropped and every processed frame takes 80 ms.
+        for frame in 0..100 {
+            let is_dropped = frame % 4 == 3;
+            metrics.record_captured(is_dropped);
+            if !is_dropped {
+                metrics.record_processed(Duration::from_millis(80));
+            }
+        }
+        assert_eq!(metrics.captured, 100);
+        assert_eq!(metrics.processed, 75);
+        assert_eq!(metrics.dropped, 25);
+        assert_eq!(metrics.processing_time, Duration::from_millis(6000));
+        assert!((metrics.real_time_factor(frame_duration) - 1.25).abs() < 1e-9);
+        assert_eq!(
+            metrics.status_line(frame_duration),
+            "Frames: 100 captured, 75 processed, 25 dropped, real-time factor 1.25",
+        );
+    }
+
+    #[test]
+    fn test_shared_metrics_count_from_several_threads() {
+        let metrics = Arc::new(SharedPipelineMetrics::default());
+
+        let threads = (0..4).map(|_| {
+            let metrics = Arc::clone(&metrics);
+            thread::spawn(move || {
+                for frame in 0..100 {
+                    metrics.record_captured(frame >= 90);
+                    metrics.record_processed(Duration::from_micros(10));
+                }
+            })
+        }).collect::<Vec<_>>();
+        threads.into_iter().for_each(|thread| thread.join().unwrap());
+
+        assert_eq!(metrics.snapshot(), PipelineMetrics {
+            captured: 400,
+            processed: 400,
+            dropped: 40,
+            processing_time: Duration::from_millis(4),
+        });
+    }
+}
@@ -0,0 +1,297 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::crepe::Prediction;
+use crate::events::{NoteEvent, NoteTracker};
+use crate::notes::NoteSpec;
+
+const HEADER: &str = "expected,played,mean_cents,stability_cents,duration_s";
+
+/// A sequence of notes that is practiced from a root note upward.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum ScaleTemplate {
+    #[default]
+    Major,
+    NaturalMinor,
+    Chromatic,
+}
+
+impl ScaleTemplate {
+    /// The semitones of one octave of the scale above the root, including the root and the octave.
+    fn intervals(self) -> &'static [i32] {
+        match self {
+            ScaleTemplate::Major => &[0, 2, 4, 5, 7, 9, 11, 12],
+            ScaleTemplate::NaturalMinor => &[0, 2, 3, 5, 7, 8, 10, 12],
+            ScaleTemplate::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12],
+        }
+    }
+
+    /// The notes of one octave of the scale upward from `root`.
+    pub fn notes(self, root: NoteSpec) -> Vec<NoteSpec> {
+        self.intervals()
+            .iter()
+            .map(|interval| NoteSpec { midi: root.midi + interval })
+            .collect()
+    }
+}
+
+/// How well a single note of a practiced scale was played.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoteIntonation {
+    pub expected: NoteSpec,
+    /// The note that was actually played, which differs from the expected note if it was missed.
+    pub played: NoteSpec,
+    /// The average number of cents the pitch was above the expected note, negative if below.
+    pub mean_cents: f32,
+    /// The standard deviation of the pitch in cents, lower values mean a steadier note.
+    pub stability_cents: f32,
+    pub duration_s: f64,
+}
+
+/// Splits the confident pitch of a practiced scale into its notes and measures the intonation of
+/// each of them against the expected notes.
+///
+/// Notes are segmented by a [`NoteTracker`], so notes that are held too briefly are not counted.
+pub struct IntonationSession {
+    expected: Vec<NoteSpec>,
+    tracker: NoteTracker,
+    min_confidence: f32,
+    a4_hz: f32,
+    /// The note that is currently held and the time it started at.
+    active_note: Option<(NoteSpec, f64)>,
+    /// The times and frequencies of the confident predictions that are not part of a finished note yet.
+    samples: Vec<(f64, f32)>,
+    results: Vec<NoteIntonation>,
+    /// Whether the session was ended by [`IntonationSession::finish`].
+    is_finished: bool,
+}
+
+impl IntonationSession {
+    /// Starts a session that expects `expected` to be played in order, ignoring predictions less
+    /// confident than `min_confidence` and calculating notes relative to `a4_hz`.
+    pub fn new(expected: Vec<NoteSpec>, min_confidence: f32, a4_hz: f32) -> Self {
+        IntonationSession {
+            expected,
+            tracker: NoteTracker::new().with_min_confidence(min_confidence).with_a4(a4_hz),
+            min_confidence,
+            a4_hz,
+            active_note: None,
+            samples: Vec::new(),
+            results: Vec::new(),
+            is_finished: false,
+        }
+    }
+
+    /// Adds a prediction made at `time` seconds, which finishes the held note if the pitch moved
+    /// to another note or is no longer confident.
+    pub fn push(&mut self, prediction: &Prediction, time: f64) {
+        if self.is_complete() || self.is_finished {
+            return;
+        }
+
+        for event in self.tracker.update(prediction, time) {
+            self.handle_event(event);
+        }
+        if prediction.confidence >= self.min_confidence && prediction.frequency > 0.0 {
+            self.samples.push((time, prediction.frequency));
+        }
+    }
+
+    /// Finishes the held note at `time` and ends the session, e.g. when it is stopped, so that
+    /// later predictions are ignored.
+    pub fn finish(&mut self, time: f64) {
+        if self.is_finished {
+            return;
+        }
+
+        if let Some(event) = self.tracker.finish(time) {
+            self.handle_event(event);
+        }
+        self.is_finished = true;
+    }
+
+    fn handle_event(&mut self, event: NoteEvent) {
+        match event {
+            NoteEvent::On { midi, time } => {
+                self.samples.retain(|(sample_time, _)| *sample_time >= time);
+                self.active_note = Some((NoteSpec { midi: midi as i32 }, time));
+            }
+            NoteEvent::Off { time, .. } => {
+                let Some((played, start)) = self.active_note.take() else {
+                    return;
+                };
+                let Some(expected) = self.next_expected() else {
+                    return;
+                };
+                let cents = self.samples.iter()
+                    .filter(|(sample_time, _)| *sample_time < time)
+                    .map(|(_, frequency)| expected.cents_error(*frequency, self.a4_hz))
+                    .collect::<Vec<f32>>();
+                self.samples.retain(|(sample_time, _)| *sample_time >= time);
+                if cents.is_empty() {
+                    return;
+                }
+
+                let count = cents.len() as f32;
+                let mean_cents = cents.iter().sum::<f32>() / count;
+                let variance = cents.iter().map(|cents| (cents - mean_cents).powi(2)).sum::<f32>() / count;
+                self.results.push(NoteIntonation {
+                    expected,
+                    played,
+                    mean_cents,
+                    stability_cents: variance.sqrt(),
+                    duration_s: time - start,
+                });
+            }
+        }
+    }
+
+    /// The note that is expected to be played next, `None` once every note was played.
+    pub fn next_expected(&self) -> Option<NoteSpec> {
+        self.expected.get(self.results.len()).copied()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.next_expected().is_none()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.is_finished
+    }
+
+    /// The notes that were played so far, in the order of the expected notes.
+    pub fn results(&self) -> &[NoteIntonation] {
+        &self.results
+    }
+
+    /// Writes the intonation of every played note as CSV to `out`, one row per note.
+    pub fn write_csv<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, "{}", HEADER)?;
+        for result in &self.results {
+            let (expected, played) = (result.expected.note(), result.played.note());
+            writeln!(
+                out,
+                "{}{},{}{},{:.1},{:.1},{:.3}",
+                expected.name, expected.octave, played.name, played.octave, result.mean_cents, result.stability_cents, result.duration_s,
+            )?;
+        }
+
+        out.flush()
+    }
+
+    /// Writes the intonation of every played note to a CSV file at `path`, overwriting any existing file.
+    pub fn export_csv(&self, path: &Path) -> io::Result<()> {
+        self.write_csv(BufWriter::new(File::create(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use crate::intonation::*;
+    use crate::notes::{cents_to_hz, DEFAULT_A4_HZ};
+    use crate::test_util::STEP;
+
+    /// Predictions of `notes` held for ten steps each, followed by a short silence. The `offsets`
+    /// in cents are added in turn, plus one more cent for every note.
+    fn played(notes: &[NoteSpec], offsets: &[f32], mistake: Option<(usize, i32)>) -> Vec<Prediction> {
+        notes.iter()
+            .enumerate()
+            .flat_map(|(i, note)| {
+                let midi = match mistake {
+                    Some((index, midi)) if index == i => midi,
+                    _ => note.midi,
+                };
+                let frequency = NoteSpec { midi }.frequency(DEFAULT_A4_HZ);
+                let mut predictions = (0..10)
+                    .map(|step| Prediction { frequency: cents_to_hz(offsets[step % offsets.len()] + i as f32, frequency), confidence: 0.9 })
+                    .collect::<Vec<Prediction>>();
+                predictions.extend([Prediction { frequency: 0.0, confidence: 0.1 }; 2]);
+                predictions
+            })
+            .collect()
+    }
+
+    fn session_for(notes: &[NoteSpec], predictions: &[Prediction]) -> IntonationSession {
+        let mut session = IntonationSession::new(notes.to_vec(), 0.5, DEFAULT_A4_HZ);
+        for (i, prediction) in predictions.iter().enumerate() {
+            session.push(prediction, i as f64 * STEP);
+        }
+        session.finish(predictions.len() as f64 * STEP);
+        session
+    }
+
+    #[test]
+    fn test_c_major_scale() {
+        let notes = ScaleTemplate::Major.notes(NoteSpec { midi: 60 });
+        // Every note alternates 5 cents around its offset, which rises by a cent per note.
+        let session = session_for(&notes, &played(&notes, &[-5.0, 5.0], None));
+
+        assert!(session.is_complete());
+        assert_eq!(session.results().len(), 8);
+        for (i, result) in session.results().iter().enumerate() {
+            assert_eq!(result.expected, notes[i]);
+            assert_eq!(result.played, notes[i]);
+            assert_relative_eq!(result.mean_cents, i as f32, epsilon = 0.05);
+            assert_relative_eq!(result.stability_cents, 5.0, epsilon = 0.05);
+            assert_relative_eq!(result.duration_s, 10.0 * STEP, epsilon = 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_missed_note_is_measured_against_expected_note() {
+        let notes = ScaleTemplate::Major.notes(NoteSpec { midi: 60 });
+        // E4 is played a semitone too low.
+        let session = session_for(&notes[..3], &played(&notes[..3], &[0.0], Some((2, 63))));
+
+        let result = session.results()[2];
+        assert_eq!(result.played, NoteSpec { midi: 63 });
+        assert_relative_eq!(result.mean_cents, -98.0, epsilon = 0.05);
+        assert_relative_eq!(result.stability_cents, 0.0, epsilon = 0.05);
+    }
+
+    #[test]
+    fn test_incomplete_scale_and_csv() {
+        let notes = ScaleTemplate::Chromatic.notes(NoteSpec { midi: 69 });
+        assert_eq!(notes.len(), 13);
+        let session = session_for(&notes, &played(&notes[..2], &[0.0], None));
+
+        assert!(!session.is_complete());
+        assert_eq!(session.next_expected(), Some(NoteSpec { midi: 71 }));
+
+        let mut out = Vec::new();
+        session.write_csv(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().lines().collect::<Vec<&str>>(), vec![
+            HEADER,
+            "A4,A4,0.0,0.0,0.640",
+            "A#4,A#4,1.0,0.0,0.640",
+        ]);
+    }
+
+    #[test]
+    fn test_held_note_is_measured_when_finished() {
+        let notes = ScaleTemplate::Major.notes(NoteSpec { midi: 60 });
+        let mut predictions = played(&notes[..2], &[0.0], None);
+        // The second note is still held when the session is finished.
+        predictions.truncate(predictions.len() - 2);
+        let mut session = IntonationSession::new(notes.clone(), 0.5, DEFAULT_A4_HZ);
+        for (i, prediction) in predictions.iter().enumerate() {
+            session.push(prediction, i as f64 * STEP);
+        }
+        assert_eq!(session.results().len(), 1);
+
+        session.finish(predictions.len() as f64 * STEP);
+        assert!(session.is_finished());
+        assert_eq!(session.results().len(), 2);
+        assert_relative_eq!(session.results()[1].duration_s, 10.0 * STEP, epsilon = 1e-9);
+
+        // Notes played after the session was finished are not measured.
+        for (i, prediction) in played(&notes[2..3], &[0.0], None).iter().enumerate() {
+            session.push(prediction, (predictions.len() + i) as f64 * STEP);
+        }
+        session.finish(100.0);
+        assert_eq!(session.results().len(), 2);
+    }
+}
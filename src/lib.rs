@@ -0,0 +1,35 @@
+pub mod activation_image;
+pub mod agc;
+pub mod autocorr;
+pub mod backend;
+pub mod benchmark;
+pub mod channels;
+pub mod colormap;
+pub mod crepe;
+pub mod ensemble;
+pub mod events;
+pub mod filter;
+pub mod headless;
+pub mod hold;
+pub mod inference;
+pub mod intonation;
+pub mod logging;
+pub mod metronome;
+pub mod midi;
+pub mod model_download;
+pub mod notes;
+pub mod osc;
+pub mod preemphasis;
+pub mod reconnect;
+pub mod resample;
+pub mod smf;
+pub mod snapshots;
+pub mod spectrum;
+pub mod stats;
+#[cfg(test)]
+mod test_util;
+pub mod tone;
+pub mod vibrato;
+pub mod wav;
+#[cfg(feature = "websocket")]
+pub mod websocket;
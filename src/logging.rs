@@ -0,0 +1,136 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use serde_json::{json, Value};
+use crate::crepe::{Prediction, TimedPrediction};
+use crate::notes::{frequency_to_midi, frequency_to_note_checked};
+
+const HEADER: &str = "time_s,frequency_hz,confidence,midi_note";
+
+/// The number of rows after which the written data is flushed to the file.
+const FLUSH_INTERVAL: usize = 50;
+
+/// Writes predictions to a CSV file, one row per prediction.
+pub struct CsvLogger {
+    writer: BufWriter<File>,
+    a4_hz: f32,
+    unflushed_rows: usize,
+}
+
+impl CsvLogger {
+    /// Creates the file at `path`, overwriting any existing file, and writes the CSV header.
+    ///
+    /// MIDI notes are calculated relative to `a4_hz`.
+    pub fn new(path: &Path, a4_hz: f32) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", HEADER)?;
+
+        Ok(CsvLogger {
+            writer,
+            a4_hz,
+            unflushed_rows: 0,
+        })
+    }
+
+    /// Writes a row for a prediction made at `timestamp_s` seconds.
+    ///
+    /// The MIDI note is left empty if the frequency is not a valid pitch.
+    pub fn record(&mut self, timestamp_s: f64, prediction: &Prediction) -> io::Result<()> {
+        let midi_note = if prediction.frequency.is_finite() && prediction.frequency > 0.0 {
+            (frequency_to_midi(prediction.frequency, self.a4_hz).round() as i32).to_string()
+        } else {
+            String::new()
+        };
+        writeln!(
+            self.writer,
+            "{:.3},{:.2},{:.4},{}",
+            timestamp_s, prediction.frequency, prediction.confidence, midi_note,
+        )?;
+
+        self.unflushed_rows += 1;
+        if self.unflushed_rows >= FLUSH_INTERVAL {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.unflushed_rows = 0;
+        self.writer.flush()
+    }
+
+    /// Flushes all remaining rows and closes the file.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+/// Formats analyzed predictions as a JSON array with one object per prediction, containing the
+/// time, frequency, confidence and the nearest note relative to `a4`.
+///
+/// The note fields are `null` if the frequency is not a valid pitch.
+pub fn export_session_json(preds: &[TimedPrediction], a4: f32) -> String {
+    let records = preds.iter()
+        .map(|timed| {
+            let prediction = timed.prediction;
+            let note = frequency_to_note_checked(prediction.frequency, a4);
+            json!({
+                "time_s": timed.time_s,
+                "frequency_hz": prediction.frequency,
+                "confidence": prediction.confidence,
+                "midi_note": note.map(|_| frequency_to_midi(prediction.frequency, a4).round() as i32),
+                "note_name": note.map(|note| format!("{}{}", note.name, note.octave)),
+                "cents_offset": note.map(|note| note.cents_offset),
+            })
+        })
+        .collect::<Vec<Value>>();
+
+    Value::Array(records).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logging::*;
+
+    #[test]
+    fn test_logged_rows() {
+        let path = std::env::temp_dir().join("pitch-overlay-test-log.csv");
+        let mut logger = CsvLogger::new(&path, 440.0).unwrap();
+
+        logger.record(0.0, &Prediction { frequency: 440.0, confidence: 0.91234 }).unwrap();
+        logger.record(0.128, &Prediction { frequency: 220.456, confidence: 0.5 }).unwrap();
+        logger.record(0.256, &Prediction { frequency: f32::NAN, confidence: 0.1 }).unwrap();
+        logger.finish().unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines = contents.lines().collect::<Vec<&str>>();
+        assert_eq!(lines, vec![
+            HEADER,
+            "0.000,440.00,0.9123,69",
+            "0.128,220.46,0.5000,57",
+            "0.256,NaN,0.1000,",
+        ]);
+    }
+
+    #[test]
+    fn test_session_json_round_trip() {
+        let predictions = [
+            TimedPrediction { time_s: 0.0, prediction: Prediction { frequency: 440.0, confidence: 0.9 } },
+            TimedPrediction { time_s: 0.064, prediction: Prediction { frequency: 0.0, confidence: 0.1 } },
+        ];
+
+        let json: Value = serde_json::from_str(&export_session_json(&predictions, 440.0)).unwrap();
+        let records = json.as_array().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["time_s"], 0.0);
+        assert_eq!(records[0]["frequency_hz"], 440.0);
+        assert_eq!(records[0]["confidence"].as_f64().unwrap() as f32, 0.9);
+        assert_eq!(records[0]["midi_note"], 69);
+        assert_eq!(records[0]["note_name"], "A4");
+        assert_eq!(records[0]["cents_offset"], 0.0);
+        assert!(records[1]["note_name"].is_null());
+        assert!(records[1]["midi_note"].is_null());
+    }
+}
@@ -1,55 +1,371 @@
-mod crepe;
 mod app;
+mod cli;
 
-use crate::app::{PitchOverlayApp, Settings, SETTINGS_STORAGE_KEY};
-use crate::crepe::CrepeModel;
-use cpal::traits::HostTrait;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+use crate::app::{capture_channels, capture_sample_rate, stream_config, PitchOverlayApp, Settings, SettingsFormat, SETTINGS_STORAGE_KEY};
+use crate::cli::{Args, USAGE};
+use pitch_overlay::autocorr::YinDetector;
+use pitch_overlay::backend::ExecutionBackend;
+use pitch_overlay::benchmark::run_benchmark;
+use pitch_overlay::channels::{downmix, ChannelMode};
+use pitch_overlay::crepe::{ActivationKind, CrepeModel, PitchDetector, Prediction, DEFAULT_LOCAL_AVERAGE_HALF_WIDTH, SAMPLES_PER_STEP, SAMPLE_RATE};
+use pitch_overlay::ensemble::EnsembleDetector;
+use pitch_overlay::headless::run_headless_with;
+use pitch_overlay::inference::SharedPipelineMetrics;
+use pitch_overlay::logging::export_session_json;
+use pitch_overlay::notes::DEFAULT_A4_HZ;
+use pitch_overlay::reconnect::{select_device, DeviceChoice};
+use pitch_overlay::resample::ChunkCollector;
+use pitch_overlay::wav::{analyze_wav, read_wav_chunks};
+use pitch_overlay::model_download::{ensure_model, ModelSource, DEFAULT_MODEL_BASE_URL};
+#[cfg(feature = "websocket")]
+use pitch_overlay::websocket::PredictionServer;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::Device;
-use eframe::{egui, CreationContext};
-use ort::session::Session;
+use eframe::egui;
 
-const ONNX_MODEL_PATH: &str = "crepe-full.onnx";
+/// The title of the window, which eframe also names the directory of its storage after.
+const APP_NAME: &str = "Pitch Overlay";
 
+/// Reads the settings from the storage of eframe before the window is created, so that the window
+/// can be created with the stored size and position.
+fn read_stored_settings() -> Option<Settings> {
+    let path = eframe::storage_dir(APP_NAME)?.join("app.ron");
+    let values = ron::from_str::<HashMap<String, String>>(&std::fs::read_to_string(path).ok()?).ok()?;
+    Settings::parse(values.get(SETTINGS_STORAGE_KEY)?, SettingsFormat::Json).ok()
+}
+
+fn load_model(path: &Path, backend: ExecutionBackend) -> Result<CrepeModel, String> {
+    if !path.is_file() {
+        return Err(format!("Failed to find model file at \"{}\"", path.display()));
+    }
+
+    let session = backend.session_builder()
+        .map_err(|e| format!("Failed to create ONNX session: {}", e))?
+        .commit_from_file(path)
+        .map_err(|e| format!("Failed to load model file at \"{}\": {}", path.display(), e))?;
+
+    CrepeModel::new(session)
+        .map_err(|e| format!("Model file at \"{}\" is not supported: {}", path.display(), e))
+}
+
+/// Combines `model` with the models at `paths` into an [`EnsembleDetector`], or returns it alone if
+/// there are none. Models that fail to load are left out of the ensemble. Every model averages
+/// `local_average_half_width` bins on each side of its peak.
+fn with_ensemble(
+    model: CrepeModel,
+    paths: &[PathBuf],
+    backend: ExecutionBackend,
+    activation_kind: ActivationKind,
+    local_average_half_width: usize,
+) -> Box<dyn PitchDetector> {
+    let model = model.with_local_average_half_width(local_average_half_width);
+    if paths.is_empty() {
+        return Box::new(model);
+    }
+
+    let mut detectors: Vec<Box<dyn PitchDetector>> = vec![Box::new(model)];
+    for path in paths {
+        match load_model(path, backend) {
+            Ok(extra_model) => detectors.push(Box::new(extra_model
+                .with_activation_kind(activation_kind)
+                .with_local_average_half_width(local_average_half_width))),
+            Err(e) => eprintln!("{}\nLeaving it out of the ensemble.", e),
+        }
+    }
+
+    Box::new(EnsembleDetector::new(detectors))
+}
+
+/// Starts streaming predictions to WebSocket clients that connect to `port` on this machine.
+#[cfg(feature = "websocket")]
+fn start_prediction_server(port: u16) -> Result<PredictionServer, String> {
+    let server = PredictionServer::bind(("127.0.0.1", port))
+        .map_err(|e| format!("Failed to start the WebSocket server on port {}: {}", port, e))?;
+    println!("Streaming predictions to WebSocket clients at ws://{}", server.local_addr());
+
+    Ok(server)
+}
+
+/// Prints the pitch of every chunk to stdout, stopping without an error once the output is closed,
+/// e.g. because it was piped into a command that exited.
+///
+/// Every printed prediction is also passed to `on_prediction` with its time.
+fn print_pitches<I, F>(detector: &dyn PitchDetector, chunks: I, on_prediction: F) -> Result<(), String>
+where
+    I: IntoIterator<Item = [i16; SAMPLES_PER_STEP]>,
+    F: FnMut(f64, &Prediction),
+{
+    match run_headless_with(detector, chunks, &mut io::stdout().lock(), on_prediction) {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(format!("Failed to write to stdout: {}", e)),
+        _ => Ok(()),
+    }
+}
+
+/// The number of captured chunks that can wait to be analyzed in headless mode before new ones are dropped.
+const HEADLESS_QUEUE_CHUNKS: usize = 32;
+
+/// The number of analyzed chunks after which the frame statistics are printed in headless mode,
+/// about every 10 seconds.
+const HEADLESS_STATUS_INTERVAL: usize = 156;
+
+/// Picks the first input device whose name contains `preferred_device`, or else the default or
+/// the first input device.
+fn select_input_device(preferred_device: Option<&str>) -> Result<Device, String> {
+    let host = cpal::default_host();
+    let mut devices = host.input_devices()
+        .map_err(|e| format!("Failed to enumerate input devices: {}", e))?
+        .collect::<Vec<Device>>();
+    let names = devices.iter()
+        .map(|device| device.name().unwrap_or_default())
+        .collect::<Vec<String>>();
+    let default_name = host.default_input_device().and_then(|device| device.name().ok());
+
+    let (index, choice) = select_device(&names, None, preferred_device, default_name.as_deref())
+        .ok_or("Failed to find an input device".to_owned())?;
+    if let Some(preferred_device) = preferred_device.filter(|_| choice != DeviceChoice::Preferred) {
+        eprintln!("No input device matches \"{}\".", preferred_device);
+    }
+    eprintln!("Using {} input device \"{}\".", choice.description(), names[index]);
 
-fn read_stored_settings(cc: &CreationContext) -> Option<Settings> {
-    cc.storage?.get_string(SETTINGS_STORAGE_KEY)
-        .map(|value| serde_json::from_str(value.as_str()))?
-        .map_or(None, |settings| Some(settings))
+    Ok(devices.swap_remove(index))
 }
 
-fn main() -> eframe::Result {
+/// Prints the pitch of the audio from the preferred or default input device until the output is closed.
+///
+/// The frame statistics are printed to stderr every few seconds and once more at the end, so that
+/// they do not mix with the pitch lines.
+fn print_captured_pitches<F: FnMut(f64, &Prediction)>(detector: &dyn PitchDetector, preferred_device: Option<&str>, mut on_prediction: F) -> Result<(), String> {
+    let device = select_input_device(preferred_device)?;
+    let channels = capture_channels(&device);
+    let sample_rate = capture_sample_rate(&device, channels);
+    let mut collector = ChunkCollector::<SAMPLES_PER_STEP>::new(sample_rate);
+    let (sender, receiver) = mpsc::sync_channel(HEADLESS_QUEUE_CHUNKS);
+    let metrics = Arc::new(SharedPipelineMetrics::default());
+
+    let captured_metrics = Arc::clone(&metrics);
+    let stream = device.build_input_stream(
+        &stream_config(sample_rate, channels),
+        move |data: &[i16], _| {
+            for chunk in collector.push(&downmix(data, channels as usize, ChannelMode::Mono)) {
+                // Sending also fails once printing stopped and the stream is about to be dropped.
+                let is_dropped = sender.try_send(chunk).is_err();
+                captured_metrics.record_captured(is_dropped);
+            }
+        },
+        |err| eprintln!("Error: {:?}", err),
+        None,
+    ).map_err(|e| format!("Error creating input stream: {}", e))?;
+    stream.play().map_err(|e| format!("Error starting input stream: {}", e))?;
+
+    // A chunk is processed from when it is taken until the next one is requested.
+    let processed_metrics = Arc::clone(&metrics);
+    let mut taken_at: Option<Instant> = None;
+    let chunks = std::iter::from_fn(move || {
+        if let Some(taken_at) = taken_at.take() {
+            processed_metrics.record_processed(taken_at.elapsed());
+        }
+        let chunk = receiver.recv().ok()?;
+        taken_at = Some(Instant::now());
+        Some(chunk)
+    });
+    let frame_duration = Duration::from_secs_f64(SAMPLES_PER_STEP as f64 / SAMPLE_RATE as f64);
+    let mut predictions_since_status = 0;
+    let result = print_pitches(detector, chunks, |time, prediction| {
+        on_prediction(time, prediction);
+        predictions_since_status += 1;
+        if predictions_since_status == HEADLESS_STATUS_INTERVAL {
+            predictions_since_status = 0;
+            eprintln!("{}", metrics.snapshot().status_line(frame_duration));
+        }
+    });
+    eprintln!("{}", metrics.snapshot().status_line(frame_duration));
+
+    result
+}
+
+fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let config_settings = match &args.config_path {
+        // A config file that does not exist yet is created on exit.
+        Some(path) if args.save_config && !path.exists() => None,
+        Some(path) => match Settings::load(path) {
+            Ok(settings) => Some(settings),
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+
     ort::init()
         .commit()
         .expect("Failed to init ort.");
-    let session = Session::builder()
-        .expect("Failed to create ONNX session.")
-        .commit_from_file(ONNX_MODEL_PATH)
-        .expect(format!("Failed to find model file at \"{}\"", ONNX_MODEL_PATH).as_str());
-    let crepe_model = CrepeModel::new(session);
+    let model_file_name = args.model_capacity.default_file_name();
+    // Only a missing default model falls back to the autocorrelation detector, a model that was
+    // asked for explicitly has to load.
+    let is_model_path_explicit = args.model_path.is_some();
+    let model_path = args.model_path.unwrap_or(model_file_name.as_str().into());
+    if let Some(sha256) = args.model_sha256.filter(|_| args.download_model) {
+        let source = ModelSource {
+            url: args.model_url.unwrap_or(format!("{}{}", DEFAULT_MODEL_BASE_URL, model_file_name)),
+            sha256,
+        };
+        if let Err(e) = ensure_model(&model_path, &source) {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    }
+    let crepe_model = match load_model(&model_path, args.backend).map(|model| model.with_activation_kind(args.activation_kind)) {
+        Ok(model) => Some(model),
+        Err(e) if is_model_path_explicit || args.benchmark_frames.is_some() || args.analyze_path.is_some() => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+        Err(e) => {
+            eprintln!("{}\nFalling back to the autocorrelation pitch detector.", e);
+            None
+        }
+    };
+    #[cfg(not(feature = "websocket"))]
+    if args.serve_port.is_some() {
+        eprintln!("--serve requires building with the websocket feature");
+        return ExitCode::from(2);
+    }
+    #[cfg(feature = "websocket")]
+    let prediction_server = match args.serve_port.map(start_prediction_server).transpose() {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Some((frames, model)) = args.benchmark_frames.zip(crepe_model.as_ref()) {
+        println!("{}", run_benchmark(model, frames));
+        return ExitCode::SUCCESS;
+    }
+    if args.headless {
+        let ensemble_paths = config_settings.as_ref().map_or(&[][..], |settings| settings.ensemble_model_paths.as_slice());
+        let local_average_half_width = config_settings.as_ref()
+            .map_or(DEFAULT_LOCAL_AVERAGE_HALF_WIDTH, |settings| settings.local_average_half_width);
+        let detector: Box<dyn PitchDetector> = match crepe_model {
+            Some(model) => with_ensemble(model, ensemble_paths, args.backend, args.activation_kind, local_average_half_width),
+            None => Box::new(YinDetector::new()),
+        };
+        #[cfg(feature = "websocket")]
+        let a4_hz = config_settings.as_ref().map_or(DEFAULT_A4_HZ, |settings| settings.a4_hz);
+        #[cfg(feature = "websocket")]
+        let broadcast = |time: f64, prediction: &Prediction| {
+            if let Some(server) = &prediction_server {
+                server.broadcast(time, prediction, a4_hz);
+            }
+        };
+        #[cfg(not(feature = "websocket"))]
+        let broadcast = |_: f64, _: &Prediction| {};
+        let result = match &args.analyze_path {
+            Some(path) => read_wav_chunks(path)
+                .map_err(|e| e.to_string())
+                .and_then(|chunks| print_pitches(detector.as_ref(), chunks, broadcast)),
+            None => {
+                let config_device = config_settings.as_ref()
+                    .map(|settings| settings.preferred_device_substring.as_str())
+                    .filter(|device| !device.is_empty());
+                print_captured_pitches(detector.as_ref(), args.device.as_deref().or(config_device), broadcast)
+            }
+        };
+        return match result {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("{}", e);
+                ExitCode::FAILURE
+            }
+        };
+    }
+    if let Some((path, model)) = args.analyze_path.zip(crepe_model.as_ref()) {
+        let predictions = match analyze_wav(&path, model) {
+            Ok(predictions) => predictions,
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        };
+        let json = export_session_json(&predictions, config_settings.as_ref().map_or(DEFAULT_A4_HZ, |settings| settings.a4_hz));
+        let Some(json_path) = args.json_path else {
+            println!("{}", json);
+            return ExitCode::SUCCESS;
+        };
+        if let Err(e) = std::fs::write(&json_path, json) {
+            eprintln!("Failed to write \"{}\": {}", json_path.display(), e);
+            return ExitCode::FAILURE;
+        }
+        return ExitCode::SUCCESS;
+    }
 
     let host = cpal::default_host();
-    let all_devices = host.input_devices()
-        .expect("Failed to get input devices")
-        .map(|device| device.clone())
-        .collect::<Vec<Device>>();
+    let all_devices = match host.input_devices() {
+        Ok(devices) => devices.collect::<Vec<Device>>(),
+        Err(e) => {
+            eprintln!("Failed to get input devices: {}", e);
+            Vec::new()
+        }
+    };
 
+    let settings = config_settings.unwrap_or_else(|| read_stored_settings().unwrap_or_default());
+    // Transparency can only be requested when the window is created, it is only visible when
+    // overlay mode is enabled since the window is opaque otherwise.
+    let mut viewport = egui::ViewportBuilder::default()
+        .with_inner_size(settings.restored_window_size())
+        .with_transparent(true);
+    if let Some(position) = settings.restored_window_pos() {
+        viewport = viewport.with_position(position);
+    }
     let options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default().with_inner_size([320.0, 240.0]),
+        viewport,
+        // The size and position are kept with the settings, which may come from a config file.
+        persist_window: false,
         ..Default::default()
     };
-    eframe::run_native(
-        "Pitch Overlay",
+    let result = eframe::run_native(
+        APP_NAME,
         options,
         Box::new(|cc| {
             egui_extras::install_image_loaders(&cc.egui_ctx);
 
-            let settings = read_stored_settings(cc).unwrap_or(Settings::default());
-
-            Ok(Box::<PitchOverlayApp>::new(PitchOverlayApp::new(
+            let app = PitchOverlayApp::new(
+                &cc.egui_ctx,
                 all_devices,
-                crepe_model,
+                crepe_model.map(|model| with_ensemble(model, &settings.ensemble_model_paths, args.backend, args.activation_kind, settings.local_average_half_width)),
                 settings,
-            )))
+            );
+            #[cfg(feature = "websocket")]
+            let app = app.with_prediction_server(prediction_server);
+            let app = match args.config_path {
+                Some(path) => app.with_config_path(path, args.save_config),
+                None => app,
+            };
+
+            Ok(Box::<PitchOverlayApp>::new(app))
         }),
-    )
+    );
+
+    match result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error running application: {}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
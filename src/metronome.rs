@@ -0,0 +1,240 @@
+//! A metronome for rhythmic practice, and sampling of the pitch on its beats.
+//!
+//! Beats are scheduled on the clocks of the audio streams, the click on the number of samples
+//! played and the pitch on the timestamps of the captured audio, so that they do not drift apart
+//! the way timers on the wall clock would. The [`BeatScheduler`] of the pitch starts at the time
+//! the first click is played, which ties both clocks together.
+
+/// The default tempo of the metronome in beats per minute.
+pub const DEFAULT_BPM: f32 = 90.0;
+
+/// The frequency in Hz of the tone that a click is made of.
+const CLICK_FREQUENCY: f64 = 1000.0;
+
+/// The length of a click in seconds, during which it decays linearly to silence.
+const CLICK_SECONDS: f64 = 0.03;
+
+/// Computes the times of the beats at a fixed tempo from a start time.
+///
+/// Every beat time is calculated from its index instead of by adding up beat lengths, so rounding
+/// errors do not accumulate over long sessions.
+#[derive(Debug, Clone)]
+pub struct BeatScheduler {
+    start: f64,
+    seconds_per_beat: f64,
+    /// The index of the first beat that was not returned by [`BeatScheduler::due_beats`] yet.
+    next_beat: u64,
+}
+
+impl BeatScheduler {
+    /// Creates a scheduler whose first beat is at `start` seconds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bpm` is not greater than zero.
+    pub fn new(bpm: f32, start: f64) -> Self {
+        assert!(bpm > 0.0, "tempo must be greater than zero, got {} BPM", bpm);
+
+        BeatScheduler {
+            start,
+            seconds_per_beat: 60.0 / bpm as f64,
+            next_beat: 0,
+        }
+    }
+
+    pub fn seconds_per_beat(&self) -> f64 {
+        self.seconds_per_beat
+    }
+
+    /// The time in seconds of the beat with the given index, the first beat has index 0.
+    pub fn beat_time(&self, beat: u64) -> f64 {
+        self.start + beat as f64 * self.seconds_per_beat
+    }
+
+    /// Returns the indices and times of the beats up to and including `time` that were not
+    /// returned before.
+    pub fn due_beats(&mut self, time: f64) -> Vec<(u64, f64)> {
+        let mut beats = Vec::new();
+        while self.beat_time(self.next_beat) <= time {
+            beats.push((self.next_beat, self.beat_time(self.next_beat)));
+            self.next_beat += 1;
+        }
+
+        beats
+    }
+}
+
+/// Generates the clicks of a metronome sample by sample, with a click starting on every beat.
+#[derive(Debug, Clone)]
+pub struct ClickGenerator {
+    sample_rate: f64,
+    samples_per_beat: f64,
+    /// The number of samples generated so far, which beats are counted in.
+    position: u64,
+}
+
+impl ClickGenerator {
+    /// # Panics
+    ///
+    /// Panics if `bpm` is not greater than zero.
+    pub fn new(bpm: f32, sample_rate: u32) -> Self {
+        assert!(bpm > 0.0, "tempo must be greater than zero, got {} BPM", bpm);
+
+        ClickGenerator {
+            sample_rate: sample_rate as f64,
+            samples_per_beat: sample_rate as f64 * 60.0 / bpm as f64,
+            position: 0,
+        }
+    }
+
+    /// The time in seconds from the start of the first click to the next sample.
+    pub fn elapsed(&self) -> f64 {
+        self.position as f64 / self.sample_rate
+    }
+
+    /// Returns the next sample with an amplitude of `volume`, silence between the clicks.
+    pub fn next_sample(&mut self, volume: f32) -> f32 {
+        // The number of samples since the most recent beat, which can fall between two samples.
+        let offset = self.position as f64 % self.samples_per_beat;
+        self.position += 1;
+        let elapsed = offset / self.sample_rate;
+        if elapsed >= CLICK_SECONDS {
+            return 0.0;
+        }
+
+        let envelope = 1.0 - elapsed / CLICK_SECONDS;
+        ((2.0 * std::f64::consts::PI * CLICK_FREQUENCY * elapsed).cos() * envelope) as f32 * volume
+    }
+
+    /// Fills `out` with interleaved frames of `channels` samples, playing the same click on every
+    /// channel.
+    pub fn fill(&mut self, out: &mut [f32], channels: usize, volume: f32) {
+        for frame in out.chunks_exact_mut(channels.max(1)) {
+            frame.fill(self.next_sample(volume));
+        }
+    }
+}
+
+/// The pitch sampled on a beat.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BeatReading {
+    /// The index of the beat, the first beat has index 0.
+    pub beat: u64,
+    /// The time of the beat in seconds.
+    pub time: f64,
+    /// The frequency in Hz of the pitch closest in time to the beat, `None` if there was no pitch.
+    pub frequency: Option<f32>,
+}
+
+/// Samples a stream of pitches on the beats of a [`BeatScheduler`].
+#[derive(Debug, Clone)]
+pub struct BeatCapture {
+    /// The beats that are sampled, `None` once the capture was stopped.
+    scheduler: Option<BeatScheduler>,
+    /// The time and frequency of the previous pitch, which may be closer to a beat than the next one.
+    previous: Option<(f64, Option<f32>)>,
+    readings: Vec<BeatReading>,
+}
+
+impl BeatCapture {
+    pub fn new(scheduler: BeatScheduler) -> Self {
+        BeatCapture {
+            scheduler: Some(scheduler),
+            previous: None,
+            readings: Vec::new(),
+        }
+    }
+
+    /// Adds the pitch at `time`, `None` if there was none, and records the beats that passed since
+    /// the previous pitch with the pitch closest to them.
+    pub fn push(&mut self, time: f64, frequency: Option<f32>) {
+        let Some(scheduler) = self.scheduler.as_mut() else {
+            return;
+        };
+
+        for (beat, beat_time) in scheduler.due_beats(time) {
+            let frequency = match self.previous {
+                Some((previous_time, previous_frequency)) if beat_time - previous_time < time - beat_time => previous_frequency,
+                _ => frequency,
+            };
+            self.readings.push(BeatReading { beat, time: beat_time, frequency });
+        }
+        self.previous = Some((time, frequency));
+    }
+
+    /// Stops recording beats, keeping the readings so far.
+    pub fn stop(&mut self) {
+        self.scheduler = None;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.scheduler.is_some()
+    }
+
+    /// The pitches on the beats so far, in the order of the beats.
+    pub fn readings(&self) -> &[BeatReading] {
+        &self.readings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use crate::metronome::*;
+
+    #[test]
+    fn test_beats_are_evenly_spaced() {
+        let mut scheduler = BeatScheduler::new(120.0, 1.5);
+
+        // Polling at irregular times returns every beat once.
+        let mut beats = Vec::new();
+        for time in [1.0, 1.6, 1.7, 3.2, 3.25, 4.0] {
+            beats.extend(scheduler.due_beats(time));
+        }
+        assert_eq!(beats.iter().map(|(beat, _)| *beat).collect::<Vec<u64>>(), vec![0, 1, 2, 3, 4, 5]);
+        for (beat, time) in beats {
+            assert_relative_eq!(time, 1.5 + beat as f64 * 0.5);
+        }
+
+        // Beats do not drift after an hour at a tempo whose beat length is not exact in binary.
+        let scheduler = BeatScheduler::new(97.0, 0.0);
+        let beat = 97 * 60;
+        assert_relative_eq!(scheduler.beat_time(beat), 3600.0, epsilon = 1e-9);
+        assert_relative_eq!(scheduler.beat_time(beat + 1) - scheduler.beat_time(beat), 60.0 / 97.0, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn test_clicks_start_on_beats() {
+        let sample_rate = 44_100;
+        let mut generator = ClickGenerator::new(97.0, sample_rate);
+        let samples = (0..sample_rate * 60).map(|_| generator.next_sample(0.5)).collect::<Vec<f32>>();
+
+        let onsets = (0..samples.len())
+            .filter(|i| samples[*i] != 0.0 && (*i == 0 || samples[i - 1] == 0.0))
+            .collect::<Vec<usize>>();
+        assert_eq!(onsets.len(), 97);
+        let samples_per_beat = sample_rate as f64 * 60.0 / 97.0;
+        for (beat, onset) in onsets.iter().enumerate() {
+            assert_eq!(*onset, (beat as f64 * samples_per_beat).ceil() as usize);
+        }
+        assert_relative_eq!(samples[0], 0.5);
+        assert_relative_eq!(generator.elapsed(), 60.0);
+    }
+
+    #[test]
+    fn test_pitch_closest_to_beat_is_captured() {
+        let mut capture = BeatCapture::new(BeatScheduler::new(60.0, 1.0));
+        capture.push(0.9, Some(200.0));
+        capture.push(1.05, Some(220.0));
+        capture.push(1.9, None);
+        capture.push(2.3, Some(240.0));
+        capture.stop();
+        capture.push(3.5, Some(260.0));
+
+        assert_eq!(capture.readings(), [
+            BeatReading { beat: 0, time: 1.0, frequency: Some(220.0) },
+            BeatReading { beat: 1, time: 2.0, frequency: None },
+        ]);
+        assert!(!capture.is_running());
+    }
+}
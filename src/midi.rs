@@ -0,0 +1,204 @@
+use midir::{MidiOutput, MidiOutputConnection};
+use crate::crepe::Prediction;
+use crate::notes::{frequency_to_midi, DEFAULT_A4_HZ};
+
+/// How far in semitones past the halfway point to the next note the pitch has to move before
+/// the current note is released.
+///
+/// Without this, a pitch wobbling around a note boundary would send a note-on and note-off for
+/// every wobble.
+const HYSTERESIS_SEMITONES: f32 = 0.2;
+
+/// The number of consecutive predictions of the same note that are needed before it is sent.
+const STABLE_PREDICTIONS: usize = 2;
+
+const NOTE_ON: u8 = 0x90;
+const NOTE_OFF: u8 = 0x80;
+const VELOCITY: u8 = 100;
+
+/// Receiver of raw MIDI messages.
+pub trait MidiSink {
+    fn send(&mut self, message: &[u8]);
+}
+
+impl MidiSink for MidiOutputConnection {
+    fn send(&mut self, message: &[u8]) {
+        if let Err(e) = MidiOutputConnection::send(self, message) {
+            println!("Error sending MIDI message: {}", e);
+        }
+    }
+}
+
+/// Turns a stream of pitch predictions into MIDI note-on and note-off messages.
+pub struct MidiEmitter<S: MidiSink = MidiOutputConnection> {
+    sink: S,
+    channel: u8,
+    a4_hz: f32,
+    /// The note that is currently playing.
+    active_note: Option<u8>,
+    /// A note that was predicted recently but is not yet stable enough to be sent, along with
+    /// the number of consecutive predictions of it.
+    candidate: Option<(u8, usize)>,
+}
+
+impl MidiEmitter<MidiOutputConnection> {
+    /// Connects to the first MIDI output port whose name contains `port_name`, or the first
+    /// available port if no name is given.
+    pub fn connect(port_name: Option<&str>) -> Result<Self, String> {
+        let output = MidiOutput::new("Pitch Overlay")
+            .map_err(|e| format!("Failed to initialize MIDI output: {}", e))?;
+        let port = output.ports()
+            .into_iter()
+            .find(|port| match port_name {
+                None => true,
+                Some(name) => output.port_name(port).is_ok_and(|port_name| port_name.contains(name)),
+            })
+            .ok_or("No matching MIDI output port found".to_owned())?;
+        let connection = output.connect(&port, "pitch-overlay")
+            .map_err(|e| format!("Failed to connect to MIDI output port: {}", e))?;
+
+        Ok(MidiEmitter::new(connection))
+    }
+}
+
+impl<S: MidiSink> MidiEmitter<S> {
+    pub fn new(sink: S) -> Self {
+        MidiEmitter {
+            sink,
+            channel: 0,
+            a4_hz: DEFAULT_A4_HZ,
+            active_note: None,
+            candidate: None,
+        }
+    }
+
+    /// Sets the reference frequency of A4 that notes are calculated from.
+    pub fn with_a4(mut self, a4_hz: f32) -> Self {
+        self.a4_hz = a4_hz;
+        self
+    }
+
+    /// Updates the playing note from a new prediction.
+    pub fn update(&mut self, prediction: &Prediction, min_confidence: f32) {
+        let is_valid = prediction.confidence >= min_confidence && prediction.frequency > 0.0;
+        if !is_valid {
+            self.candidate = None;
+            self.release();
+            return;
+        }
+
+        let midi = frequency_to_midi(prediction.frequency, self.a4_hz);
+        if let Some(active) = self.active_note {
+            if (midi - active as f32).abs() <= 0.5 + HYSTERESIS_SEMITONES {
+                self.candidate = None;
+                return;
+            }
+            self.release();
+        }
+
+        let note = midi.round().clamp(0.0, 127.0) as u8;
+        let count = match self.candidate {
+            Some((candidate, count)) if candidate == note => count + 1,
+            _ => 1,
+        };
+        if count >= STABLE_PREDICTIONS {
+            self.candidate = None;
+            self.active_note = Some(note);
+            self.sink.send(&[NOTE_ON | self.channel, note, VELOCITY]);
+        } else {
+            self.candidate = Some((note, count));
+        }
+    }
+
+    /// Stops the currently playing note, if any.
+    pub fn release(&mut self) {
+        if let Some(note) = self.active_note.take() {
+            self.sink.send(&[NOTE_OFF | self.channel, note, 0]);
+        }
+    }
+}
+
+impl<S: MidiSink> Drop for MidiEmitter<S> {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::midi::*;
+    use crate::test_util::prediction;
+
+    /// Records notes of sent messages as positive numbers for note-on and negative for note-off.
+    #[derive(Default)]
+    struct RecordingSink(Vec<i32>);
+
+    impl MidiSink for &mut RecordingSink {
+        fn send(&mut self, message: &[u8]) {
+            let note = message[1] as i32;
+            self.0.push(if message[0] & 0xF0 == NOTE_ON { note } else { -note });
+        }
+    }
+
+    fn emit(predictions: &[Prediction]) -> Vec<i32> {
+        let mut sink = RecordingSink::default();
+        let mut emitter = MidiEmitter::new(&mut sink);
+        for prediction in predictions {
+            emitter.update(prediction, 0.5);
+        }
+        drop(emitter);
+
+        sink.0
+    }
+
+    #[test]
+    fn test_note_sequence() {
+        let events = emit(&[
+            prediction(440.0, 0.9),
+            prediction(440.0, 0.9),
+            prediction(442.0, 0.9),
+            prediction(494.0, 0.9),
+            prediction(494.0, 0.9),
+            prediction(494.0, 0.1),
+        ]);
+
+        assert_eq!(events, vec![69, -69, 71, -71]);
+    }
+
+    #[test]
+    fn test_wobble_near_boundary_is_ignored() {
+        // 453 Hz is about 50 cents above A4, right at the boundary to A#4.
+        let events = emit(&[
+            prediction(440.0, 0.9),
+            prediction(440.0, 0.9),
+            prediction(453.0, 0.9),
+            prediction(451.0, 0.9),
+            prediction(453.5, 0.9),
+        ]);
+
+        assert_eq!(events, vec![69, -69]);
+    }
+
+    #[test]
+    fn test_custom_reference_frequency() {
+        let mut sink = RecordingSink::default();
+        let mut emitter = MidiEmitter::new(&mut sink).with_a4(415.0);
+        for _ in 0..2 {
+            emitter.update(&prediction(415.0, 0.9), 0.5);
+        }
+        drop(emitter);
+
+        assert_eq!(sink.0, vec![69, -69]);
+    }
+
+    #[test]
+    fn test_single_prediction_is_not_stable() {
+        let events = emit(&[
+            prediction(440.0, 0.9),
+            prediction(523.0, 0.9),
+            prediction(440.0, 0.1),
+        ]);
+
+        assert!(events.is_empty());
+    }
+}
@@ -0,0 +1,193 @@
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use sha2::{Digest, Sha256};
+
+/// Where models are downloaded from if they are missing, by default the project's latest release.
+///
+/// The file name of the model is appended to this URL.
+pub const DEFAULT_MODEL_BASE_URL: &str = "https://github.com/whatthehecker/Pitch-Overlay/releases/latest/download/";
+
+/// Errors that can occur while downloading the model.
+#[derive(Debug)]
+pub enum DownloadError {
+    /// The request failed or the server responded with an error.
+    Http(String),
+    Io(io::Error),
+    /// The downloaded file does not have the expected SHA-256 checksum.
+    ChecksumMismatch {
+        expected: String,
+        actual: String,
+    },
+}
+
+impl Display for DownloadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DownloadError::Http(message) => write!(f, "Error downloading model: {}", message),
+            DownloadError::Io(e) => write!(f, "Error writing model file: {}", e),
+            DownloadError::ChecksumMismatch { expected, actual } => {
+                write!(f, "Downloaded model has SHA-256 checksum {}, expected {}", actual, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DownloadError {}
+
+impl From<io::Error> for DownloadError {
+    fn from(e: io::Error) -> Self {
+        DownloadError::Io(e)
+    }
+}
+
+/// The location and checksum of a model file to download.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelSource {
+    pub url: String,
+    /// The expected SHA-256 checksum of the file as a hex string.
+    pub sha256: String,
+}
+
+/// Downloads the model from `source` to `path` if there is no file at `path` yet.
+///
+/// The model is downloaded to a temporary file next to `path` first and only moved to `path`
+/// once its checksum is verified, so no partial or corrupted model is left behind.
+pub fn ensure_model(path: &Path, source: &ModelSource) -> Result<(), DownloadError> {
+    if path.is_file() {
+        return Ok(());
+    }
+
+    let mut partial_path = path.as_os_str().to_owned();
+    partial_path.push(".part");
+    let partial_path = PathBuf::from(partial_path);
+
+    let result = download(&source.url, &partial_path).and_then(|actual| {
+        if actual.eq_ignore_ascii_case(source.sha256.trim()) {
+            Ok(())
+        } else {
+            Err(DownloadError::ChecksumMismatch {
+                expected: source.sha256.trim().to_lowercase(),
+                actual,
+            })
+        }
+    });
+    match result {
+        Ok(()) => std::fs::rename(&partial_path, path)?,
+        Err(e) => {
+            let _ = std::fs::remove_file(&partial_path);
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Downloads `url` to `path` while printing the progress, returning the file's SHA-256 checksum.
+fn download(url: &str, path: &Path) -> Result<String, DownloadError> {
+    eprintln!("Downloading model from {}", url);
+    let response = ureq::get(url).call().map_err(|e| DownloadError::Http(e.to_string()))?;
+    let total_bytes = response.header("Content-Length").and_then(|length| length.parse::<u64>().ok());
+
+    let mut reader = response.into_reader();
+    let mut file = File::create(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 64 * 1024];
+    let mut downloaded_bytes = 0;
+    loop {
+        let count = reader.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        file.write_all(&buffer[..count])?;
+        hasher.update(&buffer[..count]);
+
+        downloaded_bytes += count as u64;
+        match total_bytes {
+            Some(total_bytes) => eprint!("\rDownloaded {:.1} of {:.1} MB", to_megabytes(downloaded_bytes), to_megabytes(total_bytes)),
+            None => eprint!("\rDownloaded {:.1} MB", to_megabytes(downloaded_bytes)),
+        }
+    }
+    eprintln!();
+    file.flush()?;
+
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+fn to_megabytes(bytes: u64) -> f64 {
+    bytes as f64 / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use crate::model_download::*;
+
+    /// Serves `body` to a single HTTP request on a local port and returns the URL to request.
+    fn serve_once(body: &'static [u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len()).unwrap();
+            stream.write_all(body).unwrap();
+        });
+
+        format!("http://{}/crepe-full.onnx", address)
+    }
+
+    fn temp_model_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_file(&path);
+
+        path
+    }
+
+    #[test]
+    fn test_download_with_matching_checksum() {
+        let path = temp_model_path("pitch-overlay-test-download.onnx");
+        let source = ModelSource {
+            url: serve_once(b"model"),
+            // SHA-256 of "model".
+            sha256: "9372c470eeadd5ecd9c3c74c2b3cb633f8e2f2fad799250a0f70d652b6b825e4".to_owned(),
+        };
+
+        ensure_model(&path, &source).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"model");
+    }
+
+    #[test]
+    fn test_checksum_mismatch_leaves_no_file() {
+        let path = temp_model_path("pitch-overlay-test-mismatch.onnx");
+        let source = ModelSource {
+            url: serve_once(b"corrupted model"),
+            sha256: "9372c470eeadd5ecd9c3c74c2b3cb633f8e2f2fad799250a0f70d652b6b825e4".to_owned(),
+        };
+
+        let result = ensure_model(&path, &source);
+        assert!(matches!(result, Err(DownloadError::ChecksumMismatch { .. })));
+        assert!(!path.exists());
+        assert!(!path.with_extension("onnx.part").exists());
+    }
+
+    #[test]
+    fn test_existing_model_is_not_downloaded() {
+        let path = temp_model_path("pitch-overlay-test-existing.onnx");
+        std::fs::write(&path, b"existing").unwrap();
+        let source = ModelSource {
+            url: "http://127.0.0.1:1/unreachable".to_owned(),
+            sha256: String::new(),
+        };
+
+        ensure_model(&path, &source).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"existing");
+    }
+}
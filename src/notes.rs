@@ -0,0 +1,263 @@
+use serde::{Deserialize, Serialize};
+
+/// Names of the twelve equal-tempered notes, starting at C.
+pub const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+/// The standard reference frequency of A4 in Hz.
+pub const DEFAULT_A4_HZ: f32 = 440.0;
+
+/// The MIDI note number of A4.
+const A4_MIDI: i32 = 69;
+
+/// The nearest equal-tempered note for a frequency.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Note {
+    pub name: &'static str,
+    pub octave: i32,
+    /// Signed deviation from the note in cents, in the range `-50.0..=50.0`.
+    pub cents_offset: f32,
+}
+
+/// Converts a frequency into a fractional MIDI note number, using `a4` as the reference frequency.
+pub fn frequency_to_midi(freq: f32, a4: f32) -> f32 {
+    12.0 * (freq / a4).log2() + A4_MIDI as f32
+}
+
+/// Converts a fractional MIDI note number into a frequency, using `a4` as the reference frequency.
+pub fn midi_to_frequency(midi: f32, a4: f32) -> f32 {
+    a4 * 2f32.powf((midi - A4_MIDI as f32) / 12.0)
+}
+
+/// Converts a frequency into cents above `ref_hz`, which is negative for lower frequencies.
+///
+/// Pitch is perceived logarithmically, so averages and distances are musically meaningful in cents
+/// but not in Hz.
+pub fn hz_to_cents(hz: f32, ref_hz: f32) -> f32 {
+    1200.0 * (hz / ref_hz).log2()
+}
+
+/// Converts cents above `ref_hz` back into a frequency.
+pub fn cents_to_hz(cents: f32, ref_hz: f32) -> f32 {
+    ref_hz * 2f32.powf(cents / 1200.0)
+}
+
+/// Calculates the number of beats per second that are heard when two tones of close frequencies
+/// sound together, which vanish as the tones get in tune with each other.
+pub fn beat_frequency(a_hz: f32, b_hz: f32) -> f32 {
+    (a_hz - b_hz).abs()
+}
+
+/// Shifts a frequency by `cents` hundredths of a semitone, e.g. to correct a known bias of the input.
+pub fn shift_by_cents(freq: f32, cents: f32) -> f32 {
+    freq * 2f32.powf(cents / 1200.0)
+}
+
+/// Returns the note with the given MIDI note number.
+pub fn midi_to_note(midi: i32) -> Note {
+    Note {
+        name: NOTE_NAMES[midi.rem_euclid(12) as usize],
+        octave: midi.div_euclid(12) - 1,
+        cents_offset: 0.0,
+    }
+}
+
+/// Lists the frequency and name with octave, e.g. `C#4`, of every equal-tempered note from
+/// `min_hz` to `max_hz`, using `a4` as the reference frequency.
+pub fn note_gridlines(min_hz: f32, max_hz: f32, a4: f32) -> Vec<(f32, String)> {
+    if !(min_hz > 0.0 && max_hz >= min_hz) {
+        return vec![];
+    }
+
+    let min_midi = frequency_to_midi(min_hz, a4).ceil() as i32;
+    let max_midi = frequency_to_midi(max_hz, a4).floor() as i32;
+    (min_midi..=max_midi)
+        .map(|midi| {
+            let note = midi_to_note(midi);
+            (midi_to_frequency(midi as f32, a4), format!("{}{}", note.name, note.octave))
+        })
+        .collect()
+}
+
+/// Calculates the nearest note for a frequency, using `a4` as the reference frequency.
+///
+/// The result is meaningless for frequencies that are not positive, use
+/// [`frequency_to_note_checked`] if the frequency might be invalid.
+pub fn frequency_to_note(freq: f32, a4: f32) -> Note {
+    let midi = frequency_to_midi(freq, a4);
+    let nearest = midi.round() as i32;
+
+    Note {
+        cents_offset: (midi - nearest as f32) * 100.0,
+        ..midi_to_note(nearest)
+    }
+}
+
+/// Like [`frequency_to_note`], but returns `None` for frequencies that are not positive.
+pub fn frequency_to_note_checked(freq: f32, a4: f32) -> Option<Note> {
+    if !freq.is_finite() || freq <= 0.0 {
+        return None;
+    }
+
+    Some(frequency_to_note(freq, a4))
+}
+
+/// A note chosen by the user, e.g. as a target to sing, identified by its MIDI note number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteSpec {
+    pub midi: i32,
+}
+
+impl NoteSpec {
+    /// The note nearest to a frequency, e.g. of a reference tone that was played.
+    pub fn nearest(freq: f32, a4: f32) -> Self {
+        NoteSpec {
+            midi: frequency_to_midi(freq, a4).round() as i32,
+        }
+    }
+
+    /// The frequency of the note, using `a4` as the reference frequency.
+    pub fn frequency(self, a4: f32) -> f32 {
+        midi_to_frequency(self.midi as f32, a4)
+    }
+
+    pub fn note(self) -> Note {
+        midi_to_note(self.midi)
+    }
+
+    /// Calculates how many cents `freq` is above the note, which is negative for lower frequencies.
+    ///
+    /// Unlike [`Note::cents_offset`], the error is not limited to half a semitone, since the
+    /// frequency may be closer to another note than to this one.
+    pub fn cents_error(self, freq: f32, a4: f32) -> f32 {
+        hz_to_cents(freq, self.frequency(a4))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use crate::notes::*;
+
+    #[test]
+    fn test_a4_has_no_offset() {
+        let note = frequency_to_note(440.0, 440.0);
+
+        assert_eq!(note.name, "A");
+        assert_eq!(note.octave, 4);
+        assert_relative_eq!(note.cents_offset, 0.0, epsilon = 1e-4);
+    }
+
+    #[test]
+    fn test_a_sharp_4() {
+        let note = frequency_to_note(466.16, 440.0);
+
+        assert_eq!(note.name, "A#");
+        assert_eq!(note.octave, 4);
+        assert_relative_eq!(note.cents_offset, 0.0, epsilon = 0.1);
+    }
+
+    #[test]
+    fn test_octave_changes_at_c() {
+        let note = frequency_to_note(261.63, 440.0);
+
+        assert_eq!(note.name, "C");
+        assert_eq!(note.octave, 4);
+    }
+
+    #[test]
+    fn test_custom_reference_frequency() {
+        let note = frequency_to_note(432.0, 432.0);
+
+        assert_eq!(note.name, "A");
+        assert_eq!(note.octave, 4);
+        assert_relative_eq!(note.cents_offset, 0.0, epsilon = 1e-4);
+        assert_relative_eq!(frequency_to_note(440.0, 432.0).cents_offset, 31.77, epsilon = 0.01);
+    }
+
+    #[test]
+    fn test_midi_round_trip() {
+        assert_relative_eq!(midi_to_frequency(69.0, 440.0), 440.0, epsilon = 1e-3);
+        assert_relative_eq!(midi_to_frequency(57.0, 440.0), 220.0, epsilon = 1e-3);
+        assert_relative_eq!(frequency_to_midi(midi_to_frequency(61.3, 432.0), 432.0), 61.3, epsilon = 1e-4);
+        assert_eq!(midi_to_note(60), Note { name: "C", octave: 4, cents_offset: 0.0 });
+    }
+
+    #[test]
+    fn test_cents_conversion() {
+        assert_relative_eq!(hz_to_cents(880.0, 440.0), 1200.0, epsilon = 1e-3);
+        assert_relative_eq!(hz_to_cents(220.0, 440.0), -1200.0, epsilon = 1e-3);
+        assert_relative_eq!(hz_to_cents(466.16, 440.0), 100.0, epsilon = 0.05);
+        assert_relative_eq!(cents_to_hz(1200.0, 440.0), 880.0, epsilon = 1e-3);
+        assert_relative_eq!(cents_to_hz(hz_to_cents(123.4, 440.0), 440.0), 123.4, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn test_shift_by_cents() {
+        let shifted = shift_by_cents(440.0, 50.0);
+
+        assert_relative_eq!(shifted, 452.893, epsilon = 1e-3);
+        assert_relative_eq!(frequency_to_note(shifted, 440.0).cents_offset.abs(), 50.0, epsilon = 1e-2);
+        assert_relative_eq!(frequency_to_midi(shifted, 440.0), 69.5, epsilon = 1e-4);
+        assert_relative_eq!(shift_by_cents(440.0, -1200.0), 220.0, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn test_note_gridlines_for_one_octave() {
+        let gridlines = note_gridlines(261.0, 524.0, 440.0);
+        let labels = gridlines.iter().map(|(_, label)| label.as_str()).collect::<Vec<&str>>();
+
+        // Twelve semitones from C4 up to the C5 that closes the octave.
+        assert_eq!(labels, vec!["C4", "C#4", "D4", "D#4", "E4", "F4", "F#4", "G4", "G#4", "A4", "A#4", "B4", "C5"]);
+        assert_relative_eq!(gridlines[0].0, 261.63, epsilon = 0.01);
+        assert_relative_eq!(gridlines[9].0, 440.0, epsilon = 1e-3);
+        assert_relative_eq!(gridlines[12].0, 523.25, epsilon = 0.01);
+    }
+
+    #[test]
+    fn test_note_gridlines_follow_reference_frequency() {
+        let gridlines = note_gridlines(430.0, 440.0, 432.0);
+
+        assert_eq!(gridlines.len(), 1);
+        assert_relative_eq!(gridlines[0].0, 432.0, epsilon = 1e-3);
+        assert_eq!(gridlines[0].1, "A4");
+        assert!(note_gridlines(0.0, 100.0, 440.0).iter().all(|(frequency, _)| *frequency > 0.0));
+        assert!(note_gridlines(500.0, 400.0, 440.0).is_empty());
+    }
+
+    #[test]
+    fn test_beat_frequency() {
+        assert_relative_eq!(beat_frequency(440.0, 441.5), 1.5, epsilon = 1e-4);
+        assert_relative_eq!(beat_frequency(441.5, 440.0), 1.5, epsilon = 1e-4);
+        assert_relative_eq!(beat_frequency(196.0, 196.0), 0.0);
+        // Two cents apart at A4 beat about once every two seconds.
+        assert_relative_eq!(beat_frequency(440.0, shift_by_cents(440.0, 2.0)), 0.508, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn test_cents_error_to_fixed_target() {
+        let a4 = NoteSpec { midi: 69 };
+
+        assert_relative_eq!(a4.cents_error(440.0, 440.0), 0.0);
+        assert_relative_eq!(a4.cents_error(shift_by_cents(440.0, 12.0), 440.0), 12.0, epsilon = 1e-2);
+        assert_relative_eq!(a4.cents_error(shift_by_cents(440.0, -30.0), 440.0), -30.0, epsilon = 1e-2);
+        // Errors are not wrapped around to the nearest note.
+        assert_relative_eq!(a4.cents_error(493.88, 440.0), 200.0, epsilon = 0.05);
+        assert_relative_eq!(a4.cents_error(220.0, 440.0), -1200.0, epsilon = 1e-2);
+        assert_relative_eq!(a4.cents_error(432.0, 432.0), 0.0);
+    }
+
+    #[test]
+    fn test_nearest_note_spec() {
+        assert_eq!(NoteSpec::nearest(shift_by_cents(261.63, 40.0), 440.0), NoteSpec { midi: 60 });
+        assert_eq!(NoteSpec::nearest(shift_by_cents(261.63, 60.0), 440.0), NoteSpec { midi: 61 });
+        assert_eq!(NoteSpec { midi: 61 }.note().name, "C#");
+        assert_relative_eq!(NoteSpec { midi: 57 }.frequency(440.0), 220.0, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn test_checked_rejects_non_positive_frequencies() {
+        assert_eq!(frequency_to_note_checked(0.0, 440.0), None);
+        assert_eq!(frequency_to_note_checked(-10.0, 440.0), None);
+        assert!(frequency_to_note_checked(440.0, 442.0).is_some());
+    }
+}
@@ -0,0 +1,123 @@
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::thread;
+use rosc::{OscError, OscMessage, OscPacket, OscType};
+use crate::crepe::Prediction;
+use crate::notes::{frequency_to_midi, frequency_to_note_checked, DEFAULT_A4_HZ};
+
+/// The OSC address that predictions are sent to by default.
+pub const DEFAULT_OSC_ADDRESS: &str = "/pitch";
+
+/// The number of predictions that can wait to be sent before new ones are dropped.
+const QUEUE_SIZE: usize = 64;
+
+/// Encodes a prediction as an OSC message with the arguments frequency (float), confidence
+/// (float), nearest MIDI note (int) and cents offset from that note (float).
+///
+/// Notes are calculated relative to `a4_hz`, the MIDI note is -1 if the frequency is not positive.
+pub fn encode_prediction(osc_address: &str, prediction: &Prediction, a4_hz: f32) -> Result<Vec<u8>, OscError> {
+    let (midi_note, cents_offset) = match frequency_to_note_checked(prediction.frequency, a4_hz) {
+        Some(note) => (frequency_to_midi(prediction.frequency, a4_hz).round() as i32, note.cents_offset),
+        None => (-1, 0.0),
+    };
+    let message = OscMessage {
+        addr: osc_address.to_owned(),
+        args: vec![
+            OscType::Float(prediction.frequency),
+            OscType::Float(prediction.confidence),
+            OscType::Int(midi_note),
+            OscType::Float(cents_offset),
+        ],
+    };
+
+    rosc::encoder::encode(&OscPacket::Message(message))
+}
+
+/// Sends predictions as OSC messages over UDP.
+///
+/// Messages are sent from a background thread so that sending never blocks the caller.
+pub struct OscSender {
+    queue: SyncSender<Prediction>,
+}
+
+impl OscSender {
+    /// Creates a sender that sends predictions to [`DEFAULT_OSC_ADDRESS`] on the given target,
+    /// using standard tuning for notes.
+    pub fn new(target: SocketAddr) -> io::Result<Self> {
+        Self::with_osc_address(target, DEFAULT_OSC_ADDRESS, DEFAULT_A4_HZ)
+    }
+
+    /// Creates a sender that sends predictions to the given OSC address on the given target,
+    /// calculating notes relative to `a4_hz`.
+    pub fn with_osc_address(target: SocketAddr, osc_address: &str, a4_hz: f32) -> io::Result<Self> {
+        let local_address = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let socket = UdpSocket::bind(local_address)?;
+        socket.connect(target)?;
+
+        let (queue, receiver) = sync_channel::<Prediction>(QUEUE_SIZE);
+        let osc_address = osc_address.to_owned();
+        // The thread stops once the sender is dropped and the queue is closed.
+        thread::spawn(move || {
+            for prediction in receiver {
+                let result = encode_prediction(&osc_address, &prediction, a4_hz)
+                    .map_err(|e| e.to_string())
+                    .and_then(|packet| socket.send(&packet).map_err(|e| e.to_string()));
+                if let Err(e) = result {
+                    println!("Error sending OSC message: {}", e);
+                }
+            }
+        });
+
+        Ok(OscSender { queue })
+    }
+
+    /// Queues a prediction to be sent, dropping it if too many predictions are already waiting.
+    pub fn send(&self, prediction: &Prediction) {
+        if let Err(TrySendError::Disconnected(_)) = self.queue.try_send(*prediction) {
+            println!("OSC sender thread has stopped.");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use approx::assert_relative_eq;
+    use crate::osc::*;
+
+    fn decode(packet: &[u8]) -> OscMessage {
+        match rosc::decoder::decode_udp(packet).unwrap().1 {
+            OscPacket::Message(message) => message,
+            packet => panic!("Expected a message, got {:?}", packet),
+        }
+    }
+
+    #[test]
+    fn test_encoded_prediction_round_trips() {
+        let prediction = Prediction { frequency: 440.0, confidence: 0.8 };
+
+        let message = decode(&encode_prediction("/pitch", &prediction, DEFAULT_A4_HZ).unwrap());
+        assert_eq!(message.addr, "/pitch");
+        let [OscType::Float(frequency), OscType::Float(confidence), OscType::Int(midi_note), OscType::Float(cents_offset)] = message.args[..] else {
+            panic!("Unexpected arguments {:?}", message.args);
+        };
+        assert_relative_eq!(frequency, 440.0);
+        assert_relative_eq!(confidence, 0.8);
+        assert_eq!(midi_note, 69);
+        assert_relative_eq!(cents_offset, 0.0, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn test_sender_delivers_message() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let sender = OscSender::with_osc_address(receiver.local_addr().unwrap(), "/voice", DEFAULT_A4_HZ).unwrap();
+
+        sender.send(&Prediction { frequency: 220.0, confidence: 0.9 });
+
+        let mut buffer = [0; rosc::decoder::MTU];
+        let size = receiver.recv(&mut buffer).unwrap();
+        assert_eq!(decode(&buffer[..size]).addr, "/voice");
+    }
+}
@@ -0,0 +1,77 @@
+/// The default coefficient of [`PreEmphasis`], which is common for speech.
+pub const DEFAULT_PRE_EMPHASIS: f32 = 0.97;
+
+/// A first-order high-pass filter, `y[n] = x[n] - k * x[n - 1]`, that boosts the harmonics of
+/// bass-heavy sources relative to their fundamental.
+///
+/// The pitch detectors normalize the amplitude of every frame anyway, so the filter mainly tilts
+/// the spectrum toward higher frequencies.
+#[derive(Debug, Clone)]
+pub struct PreEmphasis {
+    coefficient: f32,
+    /// The last input sample of the previous frame, so that frames are filtered as one signal.
+    previous: f32,
+}
+
+impl PreEmphasis {
+    /// Creates a filter with the coefficient `k`, 0 leaves the audio unchanged and values close
+    /// to 1 remove more of the low frequencies.
+    pub fn new(coefficient: f32) -> Self {
+        PreEmphasis {
+            coefficient,
+            previous: 0.0,
+        }
+    }
+
+    /// Filters a frame in place, continuing from the end of the previously filtered frame.
+    ///
+    /// Samples are clipped to the range of `i16` instead of overflowing.
+    pub fn process(&mut self, frame: &mut [i16]) {
+        for sample in frame {
+            let input = *sample as f32;
+            *sample = (input - self.coefficient * self.previous).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+            self.previous = input;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::preemphasis::*;
+
+    #[test]
+    fn test_impulse_response() {
+        let mut frame = [0; 6];
+        frame[1] = 10_000;
+        PreEmphasis::new(0.5).process(&mut frame);
+
+        assert_eq!(frame, [0, 10_000, -5_000, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_continuity_across_frames() {
+        let audio = (0..64).map(|i| ((i * 1237) % 4000 - 2000) as i16).collect::<Vec<i16>>();
+        let mut whole = audio.clone();
+        PreEmphasis::new(DEFAULT_PRE_EMPHASIS).process(&mut whole);
+
+        let mut split = audio.clone();
+        let mut filter = PreEmphasis::new(DEFAULT_PRE_EMPHASIS);
+        for frame in split.chunks_mut(10) {
+            filter.process(frame);
+        }
+        assert_eq!(split, whole);
+
+        // The first sample of a later frame depends on the last sample of the previous frame.
+        let mut second_frame = audio[10..20].to_vec();
+        PreEmphasis::new(DEFAULT_PRE_EMPHASIS).process(&mut second_frame);
+        assert_ne!(second_frame[0], whole[10]);
+    }
+
+    #[test]
+    fn test_output_is_clipped() {
+        let mut frame = [i16::MIN, i16::MAX];
+        PreEmphasis::new(1.0).process(&mut frame);
+
+        assert_eq!(frame, [i16::MIN, i16::MAX]);
+    }
+}
@@ -0,0 +1,188 @@
+use std::time::{Duration, Instant};
+
+/// The time to wait after the device was lost before the first reconnection attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// The longest time to wait between two reconnection attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(8);
+
+/// Decides when to try reconnecting to an input device after it was lost, waiting twice as long
+/// after every failed attempt so that a missing device is not retried in a tight loop.
+#[derive(Debug, Default)]
+pub struct Reconnector {
+    /// The time of the next reconnection attempt, `None` while the device is connected.
+    next_attempt: Option<Instant>,
+    backoff: Duration,
+}
+
+impl Reconnector {
+    pub fn new() -> Self {
+        Reconnector::default()
+    }
+
+    /// Whether the device was lost and has not been reconnected yet.
+    pub fn is_lost(&self) -> bool {
+        self.next_attempt.is_some()
+    }
+
+    /// Marks the device as lost at `now`, the first attempt is made after a short delay.
+    ///
+    /// Does nothing if the device is already lost, so that repeated errors do not reset the backoff.
+    pub fn device_lost(&mut self, now: Instant) {
+        if self.is_lost() {
+            return;
+        }
+
+        self.backoff = INITIAL_BACKOFF;
+        self.next_attempt = Some(now + self.backoff);
+    }
+
+    /// Whether a reconnection attempt should be made at `now`.
+    pub fn should_attempt(&self, now: Instant) -> bool {
+        self.next_attempt.is_some_and(|next_attempt| now >= next_attempt)
+    }
+
+    /// The time until the next reconnection attempt, or `None` if the device is connected.
+    pub fn time_until_attempt(&self, now: Instant) -> Option<Duration> {
+        self.next_attempt.map(|next_attempt| next_attempt.saturating_duration_since(now))
+    }
+
+    /// Schedules the next attempt after an attempt at `now` failed, e.g. because no device was found.
+    pub fn attempt_failed(&mut self, now: Instant) {
+        if !self.is_lost() {
+            return;
+        }
+
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+        self.next_attempt = Some(now + self.backoff);
+    }
+
+    /// Marks the device as connected, e.g. after a successful attempt or after the user picked a device.
+    pub fn connected(&mut self) {
+        self.next_attempt = None;
+    }
+}
+
+/// Picks the device to reconnect to from the names of the available devices, preferring the
+/// previously chosen device over the default device.
+pub fn reconnect_target<'a>(available: &'a [String], previous: Option<&str>, default: Option<&str>) -> Option<&'a str> {
+    [previous, default].into_iter()
+        .flatten()
+        .find_map(|name| available.iter().find(|available| *available == name))
+        .map(String::as_str)
+}
+
+/// Why [`select_device`] chose a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceChoice {
+    /// The device that was used last time.
+    Stored,
+    /// The first device whose name contains the preferred name.
+    Preferred,
+    /// The default device of the audio host.
+    Default,
+    /// The first device, since no other device could be chosen.
+    First,
+}
+
+impl DeviceChoice {
+    /// Describes the choice for the log, e.g. "last used".
+    pub fn description(self) -> &'static str {
+        match self {
+            DeviceChoice::Stored => "last used",
+            DeviceChoice::Preferred => "preferred",
+            DeviceChoice::Default => "default",
+            DeviceChoice::First => "first available",
+        }
+    }
+}
+
+/// Picks the device to connect to at startup from the names of the available devices and returns
+/// its index.
+///
+/// The `stored` device is preferred, then the first device whose name contains `preferred_substring`
+/// ignoring case, e.g. "usb" for any USB microphone, then the `default` device and finally the
+/// first device. Returns `None` only if no devices are available.
+pub fn select_device(available: &[String], stored: Option<&str>, preferred_substring: Option<&str>, default: Option<&str>) -> Option<(usize, DeviceChoice)> {
+    let position = |name: &str| available.iter().position(|available| available == name);
+    let preferred = preferred_substring
+        .map(str::to_lowercase)
+        .filter(|substring| !substring.is_empty())
+        .and_then(|substring| available.iter().position(|available| available.to_lowercase().contains(&substring)));
+
+    stored.and_then(position).map(|index| (index, DeviceChoice::Stored))
+        .or(preferred.map(|index| (index, DeviceChoice::Preferred)))
+        .or_else(|| default.and_then(position).map(|index| (index, DeviceChoice::Default)))
+        .or_else(|| (!available.is_empty()).then_some((0, DeviceChoice::First)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reconnect::*;
+
+    #[test]
+    fn test_lost_and_found_sequence() {
+        let start = Instant::now();
+        let mut reconnector = Reconnector::new();
+        assert!(!reconnector.is_lost());
+        assert!(!reconnector.should_attempt(start));
+
+        reconnector.device_lost(start);
+        assert!(reconnector.is_lost());
+        assert!(!reconnector.should_attempt(start));
+        assert!(reconnector.should_attempt(start + INITIAL_BACKOFF));
+
+        // The device is still missing, so the wait doubles.
+        let first_attempt = start + INITIAL_BACKOFF;
+        reconnector.attempt_failed(first_attempt);
+        assert!(!reconnector.should_attempt(first_attempt + INITIAL_BACKOFF));
+        assert!(reconnector.should_attempt(first_attempt + INITIAL_BACKOFF * 2));
+        assert_eq!(reconnector.time_until_attempt(first_attempt), Some(INITIAL_BACKOFF * 2));
+
+        // Errors while already lost do not reset the backoff.
+        reconnector.device_lost(first_attempt);
+        assert_eq!(reconnector.time_until_attempt(first_attempt), Some(INITIAL_BACKOFF * 2));
+
+        reconnector.connected();
+        assert!(!reconnector.is_lost());
+        assert!(!reconnector.should_attempt(first_attempt + MAX_BACKOFF));
+        assert_eq!(reconnector.time_until_attempt(first_attempt), None);
+    }
+
+    #[test]
+    fn test_backoff_is_capped() {
+        let now = Instant::now();
+        let mut reconnector = Reconnector::new();
+        reconnector.device_lost(now);
+        for _ in 0..10 {
+            reconnector.attempt_failed(now);
+        }
+
+        assert_eq!(reconnector.time_until_attempt(now), Some(MAX_BACKOFF));
+    }
+
+    #[test]
+    fn test_reconnect_target_prefers_previous_device() {
+        let available = ["Speakers".to_owned(), "Headset".to_owned()];
+
+        assert_eq!(reconnect_target(&available, Some("Headset"), Some("Speakers")), Some("Headset"));
+        assert_eq!(reconnect_target(&available, Some("USB mic"), Some("Speakers")), Some("Speakers"));
+        assert_eq!(reconnect_target(&available, Some("USB mic"), None), None);
+        assert_eq!(reconnect_target(&[], Some("Headset"), Some("Speakers")), None);
+    }
+
+    #[test]
+    fn test_select_device() {
+        let available = ["Built-in Microphone".to_owned(), "Scarlett 2i2 USB".to_owned(), "Headset".to_owned()];
+
+        assert_eq!(select_device(&available, Some("Headset"), Some("scarlett"), Some("Built-in Microphone")), Some((2, DeviceChoice::Stored)));
+        // A stored device that was unplugged falls back to the preferred device.
+        assert_eq!(select_device(&available, Some("USB mic"), Some("scarlett"), Some("Built-in Microphone")), Some((1, DeviceChoice::Preferred)));
+        assert_eq!(select_device(&available, None, Some("USB"), None), Some((1, DeviceChoice::Preferred)));
+        assert_eq!(select_device(&available, None, Some("Yeti"), Some("Headset")), Some((2, DeviceChoice::Default)));
+        // An empty hint matches nothing instead of every device.
+        assert_eq!(select_device(&available, None, Some(""), Some("Headset")), Some((2, DeviceChoice::Default)));
+        assert_eq!(select_device(&available, None, None, Some("Speakers")), Some((0, DeviceChoice::First)));
+        assert_eq!(select_device(&[], Some("Headset"), Some("USB"), Some("Headset")), None);
+    }
+}
@@ -0,0 +1,424 @@
+//! Conversion of captured audio to the sample rate expected by the model.
+//!
+//! Resampling uses windowed sinc interpolation, which low-pass filters the audio at the Nyquist
+//! frequency of the lower of both sample rates so that high frequencies do not alias into the
+//! pitch range.
+//! Each output sample looks at [`KERNEL_ZERO_CROSSINGS`] input samples on either side (scaled by the
+//! downsampling ratio), which means a block of resampled audio is only accurate once that many
+//! samples after it are known. At 48 kHz this adds less than a millisecond of latency, which is
+//! negligible compared to the 64 milliseconds of audio the model needs for a single prediction.
+//! Plain linear interpolation would be cheaper, but audibly aliases when downsampling.
+//!
+//! Sample rates that are a small integer multiple of the output rate, like the common 48 kHz, are
+//! decimated instead: every output sample lines up with an input sample, so a short low-pass
+//! filter is calculated once and applied to every kept sample.
+//!
+//! Audio that is captured in pieces is resampled with a [`StreamingResampler`], which carries the
+//! filter across the edges of the pieces.
+use std::f64::consts::PI;
+use crate::crepe::SAMPLE_RATE;
+
+/// The number of zero crossings of the sinc kernel on either side of its center.
+///
+/// Larger values result in a sharper low-pass filter at the cost of more computation per sample.
+pub const KERNEL_ZERO_CROSSINGS: usize = 16;
+
+/// The number of zero crossings of the low-pass filter on either side of its center when
+/// decimating, which is enough since its weights do not have to be interpolated.
+pub const DECIMATION_ZERO_CROSSINGS: usize = 6;
+
+/// The largest ratio between the input and output rate that is decimated instead of interpolated.
+pub const MAX_DECIMATION_FACTOR: u32 = 6;
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Blackman window over `-1.0..=1.0`.
+fn window(x: f64) -> f64 {
+    if x.abs() >= 1.0 {
+        return 0.0;
+    }
+    let phase = PI * (x + 1.0);
+
+    0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos()
+}
+
+/// The low-pass filter that output samples are calculated with.
+#[derive(Debug, Clone)]
+enum Filter {
+    /// Taps at whole input samples, for keeping every `factor`th sample.
+    Decimation { factor: usize, taps: Vec<f64> },
+    /// A windowed sinc kernel that is evaluated at the fractional position of every output sample.
+    Interpolation { ratio: f64, cutoff: f64, half_width: f64 },
+}
+
+impl Filter {
+    fn decimation(factor: usize) -> Self {
+        let cutoff = 1.0 / factor as f64;
+        let half_width = DECIMATION_ZERO_CROSSINGS * factor;
+        let mut taps = (0..=2 * half_width)
+            .map(|k| {
+                let distance = k as f64 - half_width as f64;
+                cutoff * sinc(cutoff * distance) * window(distance / (half_width + 1) as f64)
+            })
+            .collect::<Vec<f64>>();
+        // Normalizing keeps the level of low frequencies unchanged despite the short filter.
+        let sum = taps.iter().sum::<f64>();
+        taps.iter_mut().for_each(|tap| *tap /= sum);
+
+        Filter::Decimation { factor, taps }
+    }
+
+    fn new(input_rate: u32, output_rate: u32) -> Self {
+        let factor = input_rate / output_rate;
+        if factor * output_rate == input_rate && factor <= MAX_DECIMATION_FACTOR {
+            return Filter::decimation(factor as usize);
+        }
+
+        let ratio = input_rate as f64 / output_rate as f64;
+        // When downsampling, the filter cutoff has to move down to the new Nyquist frequency.
+        let cutoff = (1.0 / ratio).min(1.0);
+        Filter::Interpolation { ratio, cutoff, half_width: KERNEL_ZERO_CROSSINGS as f64 / cutoff }
+    }
+
+    /// The position of output sample `i` in input samples.
+    fn center(&self, i: u64) -> f64 {
+        match self {
+            Filter::Decimation { factor, .. } => (i * *factor as u64) as f64,
+            Filter::Interpolation { ratio, .. } => i as f64 * ratio,
+        }
+    }
+
+    /// The number of input samples on either side of the center that an output sample depends on.
+    fn half_width(&self) -> f64 {
+        match self {
+            Filter::Decimation { taps, .. } => (taps.len() / 2) as f64,
+            Filter::Interpolation { half_width, .. } => *half_width,
+        }
+    }
+
+    /// Calculates output sample `i` from `input`, whose first sample has the index `offset` in the
+    /// whole audio. Samples outside of `input` count as silence.
+    fn output_sample(&self, input: &[f32], offset: u64, i: u64) -> f32 {
+        let center = self.center(i);
+        let half_width = self.half_width();
+        let first = ((center - half_width).ceil().max(0.0) as u64).max(offset);
+        let last = ((center + half_width).floor() as u64).min(offset + input.len() as u64 - 1);
+
+        (first..=last).map(|k| {
+            let weight = match self {
+                Filter::Decimation { taps, .. } => taps[(k as f64 + half_width - center) as usize],
+                Filter::Interpolation { cutoff, .. } => {
+                    let distance = k as f64 - center;
+                    cutoff * sinc(cutoff * distance) * window(distance / half_width)
+                }
+            };
+
+            input[(k - offset) as usize] as f64 * weight
+        }).sum::<f64>() as f32
+    }
+}
+
+/// Keeps every `factor`th sample of the low-pass filtered audio.
+fn decimate(input: &[f32], factor: usize) -> Vec<f32> {
+    let filter = Filter::decimation(factor);
+
+    (0..(input.len() / factor) as u64).map(|i| filter.output_sample(input, 0, i)).collect()
+}
+
+/// Downsamples audio to a third of its sample rate, e.g. from 48 kHz to 16 kHz.
+pub fn decimate_3(input: &[f32]) -> Vec<f32> {
+    decimate(input, 3)
+}
+
+/// Resamples audio from `input_rate` to `output_rate`.
+///
+/// Input rates that are at most [`MAX_DECIMATION_FACTOR`] times the output rate are decimated,
+/// which is much cheaper than interpolating.
+pub fn resample(input: &[f32], input_rate: u32, output_rate: u32) -> Vec<f32> {
+    if input_rate == output_rate || input.is_empty() {
+        return input.to_vec();
+    }
+    let filter = Filter::new(input_rate, output_rate);
+    let output_len = (input.len() as f64 * output_rate as f64 / input_rate as f64).floor() as u64;
+
+    (0..output_len).map(|i| filter.output_sample(input, 0, i)).collect()
+}
+
+/// Resamples audio from `input_rate` to the sample rate of the CREPE model.
+pub fn resample_to_16k(input: &[f32], input_rate: u32) -> Vec<f32> {
+    resample(input, input_rate, SAMPLE_RATE)
+}
+
+/// Like [`resample_to_16k`], but for integer samples as they are captured from audio devices.
+pub fn resample_i16_to_16k(input: &[i16], input_rate: u32) -> Vec<i16> {
+    if input_rate == SAMPLE_RATE {
+        return input.to_vec();
+    }
+
+    let input = input.iter().map(|sample| *sample as f32).collect::<Vec<f32>>();
+    resample_to_16k(&input, input_rate).into_iter().map(to_i16).collect()
+}
+
+fn to_i16(sample: f32) -> i16 {
+    sample.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+/// Resamples audio that arrives in pieces, e.g. from an audio callback, as one continuous stream.
+///
+/// Resampling every piece on its own would cut the filter off at the edges of each piece. Instead,
+/// an output sample is only returned once all input samples its filter reaches are known, so the
+/// output trails the input by the half width of the filter, and the input samples that later
+/// output samples still need are kept until the next piece arrives.
+///
+/// The default resampler passes audio at the output rate through unchanged.
+#[derive(Debug, Clone, Default)]
+pub struct StreamingResampler {
+    /// `None` if the input is already at the output rate.
+    filter: Option<Filter>,
+    /// The input samples that the next output samples depend on.
+    history: Vec<f32>,
+    /// The index of the first sample of `history` in the whole stream.
+    history_start: u64,
+    /// The index of the next output sample in the whole stream.
+    next_output: u64,
+}
+
+impl StreamingResampler {
+    /// Creates a resampler from `input_rate` to `output_rate`.
+    pub fn new(input_rate: u32, output_rate: u32) -> Self {
+        StreamingResampler {
+            filter: Some(Filter::new(input_rate, output_rate)).filter(|_| input_rate != output_rate),
+            history: Vec::new(),
+            history_start: 0,
+            next_output: 0,
+        }
+    }
+
+    /// Creates a resampler from `input_rate` to the sample rate of the CREPE model.
+    pub fn to_16k(input_rate: u32) -> Self {
+        StreamingResampler::new(input_rate, SAMPLE_RATE)
+    }
+
+    /// Adds the next piece of input and returns the output samples that are complete.
+    pub fn push(&mut self, input: &[f32]) -> Vec<f32> {
+        let Some(filter) = self.filter.as_ref() else {
+            return input.to_vec();
+        };
+
+        self.history.extend_from_slice(input);
+        let end = self.history_start + self.history.len() as u64;
+        let mut output = Vec::new();
+        while filter.center(self.next_output) + filter.half_width() < end as f64 {
+            output.push(filter.output_sample(&self.history, self.history_start, self.next_output));
+            self.next_output += 1;
+        }
+
+        let keep_from = ((filter.center(self.next_output) - filter.half_width()).ceil().max(0.0) as u64).clamp(self.history_start, end);
+        self.history.drain(..(keep_from - self.history_start) as usize);
+        self.history_start = keep_from;
+
+        output
+    }
+
+    /// Forgets the input so far, e.g. after a gap in the audio, so that the next piece starts a new
+    /// stream.
+    pub fn clear(&mut self) {
+        self.history.clear();
+        self.history_start = 0;
+        self.next_output = 0;
+    }
+
+    /// Like [`StreamingResampler::push`], but for integer samples as they are captured from audio
+    /// devices.
+    pub fn push_i16(&mut self, input: &[i16]) -> Vec<i16> {
+        if self.filter.is_none() {
+            return input.to_vec();
+        }
+
+        let input = input.iter().map(|sample| *sample as f32).collect::<Vec<f32>>();
+        self.push(&input).into_iter().map(to_i16).collect()
+    }
+}
+
+/// Collects mono audio that arrives in pieces of any size, e.g. from an audio callback, into
+/// chunks of `N` samples at the model's [`SAMPLE_RATE`].
+///
+/// Both the overlay and the headless mode capture audio through it, so the chunks continue each
+/// other without gaps in either.
+#[derive(Debug, Clone, Default)]
+pub struct ChunkCollector<const N: usize> {
+    resampler: StreamingResampler,
+    /// Resampled audio that does not make up a whole chunk yet.
+    buffer: Vec<i16>,
+}
+
+impl<const N: usize> ChunkCollector<N> {
+    /// Creates a collector for audio captured at `sample_rate` Hz.
+    pub fn new(sample_rate: u32) -> Self {
+        ChunkCollector {
+            resampler: StreamingResampler::to_16k(sample_rate),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Adds captured audio and returns the chunks that are complete, oldest first.
+    pub fn push(&mut self, samples: &[i16]) -> Vec<[i16; N]> {
+        self.buffer.extend(self.resampler.push_i16(samples));
+
+        let mut chunks = Vec::new();
+        while self.buffer.len() >= N {
+            chunks.push(self.buffer[..N].try_into().unwrap());
+            self.buffer.drain(..N);
+        }
+
+        chunks
+    }
+
+    /// The number of resampled samples that were captured after the most recent chunk.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Discards the audio so far, e.g. after a gap in the audio, so that the next chunk only
+    /// contains audio that is pushed from now on.
+    pub fn clear(&mut self) {
+        self.resampler.clear();
+        self.buffer.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crepe::SAMPLES_PER_STEP;
+    use crate::resample::*;
+
+    fn sine(frequency: f32, sample_rate: u32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (2.0 * std::f32::consts::PI * frequency * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    /// Returns the magnitude of a DFT bin.
+    fn bin_magnitude(samples: &[f32], bin: usize) -> f32 {
+        let n = samples.len();
+        let (re, im) = samples.iter().enumerate().fold((0.0, 0.0), |(re, im), (i, sample)| {
+            let phase = 2.0 * std::f32::consts::PI * (bin * i % n) as f32 / n as f32;
+            (re + sample * phase.cos(), im - sample * phase.sin())
+        });
+
+        (re * re + im * im).sqrt()
+    }
+
+    /// Returns the frequency of the largest DFT bin.
+    fn dominant_frequency(samples: &[f32], sample_rate: u32) -> f32 {
+        let n = samples.len();
+        let bin = (1..n / 2)
+            .max_by(|a, b| bin_magnitude(samples, *a).total_cmp(&bin_magnitude(samples, *b)))
+            .unwrap();
+
+        bin as f32 * sample_rate as f32 / n as f32
+    }
+
+    #[test]
+    fn test_same_rate_is_identity() {
+        let input = sine(440.0, SAMPLE_RATE, 100);
+
+        assert_eq!(resample_to_16k(&input, SAMPLE_RATE), input);
+    }
+
+    #[test]
+    fn test_output_length() {
+        assert_eq!(resample_to_16k(&[0.0; 48_000], 48_000).len(), 16_000);
+        assert_eq!(resample_to_16k(&[0.0; 44_100], 44_100).len(), 16_000);
+    }
+
+    #[test]
+    fn test_decimation_preserves_tone_and_attenuates_alias() {
+        let tone = sine(2000.0, 48_000, 3 * 1024);
+        // Without filtering, 17 kHz would alias to 1 kHz at 16 kHz.
+        let alias = sine(17_000.0, 48_000, 3 * 1024);
+        let input = tone.iter().zip(&alias).map(|(tone, alias)| tone + alias).collect::<Vec<f32>>();
+
+        let output = decimate_3(&input);
+        assert_eq!(output.len(), 1024);
+        assert_eq!(resample_to_16k(&input, 48_000), output);
+        // The DFT bins are 15.625 Hz wide, so both frequencies fall exactly on a bin.
+        let tone_level = bin_magnitude(&output, 128);
+        let alias_level = bin_magnitude(&output, 64);
+        assert!((tone_level / 512.0 - 1.0).abs() < 0.05, "tone level {}", tone_level);
+        assert!(alias_level < tone_level / 1000.0, "alias level {}", alias_level);
+    }
+
+    #[test]
+    fn test_downsampling_preserves_dominant_frequency() {
+        let input = sine(1000.0, 48_000, 3 * 1024);
+
+        let output = resample_to_16k(&input, 48_000);
+        assert_eq!(output.len(), 1024);
+        // The DFT bins are 15.625 Hz wide.
+        assert!((dominant_frequency(&output, SAMPLE_RATE) - 1000.0).abs() < 16.0);
+    }
+
+    #[test]
+    fn test_streaming_matches_resampling_at_once() {
+        for input_rate in [48_000, 44_100] {
+            let input = sine(1000.0, input_rate, 4800);
+            let at_once = resample_to_16k(&input, input_rate);
+
+            let mut resampler = StreamingResampler::to_16k(input_rate);
+            let mut streamed = Vec::new();
+            // Pieces of irregular size, like audio callbacks may deliver them.
+            let mut sizes = [1, 479, 480, 7, 1500].into_iter().cycle();
+            let mut rest = &input[..];
+            while !rest.is_empty() {
+                let (piece, after) = rest.split_at(sizes.next().unwrap().min(rest.len()));
+                streamed.extend(resampler.push(piece));
+                rest = after;
+            }
+
+            // Only the end is missing, since its filter reaches past the input.
+            assert!(streamed.len() < at_once.len() && streamed.len() + 20 > at_once.len(), "{} of {} samples", streamed.len(), at_once.len());
+            assert_eq!(streamed, at_once[..streamed.len()]);
+        }
+    }
+
+    #[test]
+    fn test_streaming_at_same_rate_passes_input_through() {
+        let mut resampler = StreamingResampler::to_16k(SAMPLE_RATE);
+
+        assert_eq!(resampler.push_i16(&[1, 2, 3]), [1, 2, 3]);
+        assert_eq!(resampler.push_i16(&[4]), [4]);
+    }
+
+    #[test]
+    fn test_collects_pieces_into_chunks() {
+        let mut collector = ChunkCollector::<SAMPLES_PER_STEP>::new(SAMPLE_RATE);
+
+        assert!(collector.push(&[1; 1000]).is_empty());
+        let chunks = collector.push(&[2; 1100]);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0][..1000], [1; 1000]);
+        assert_eq!(chunks[0][1000..], [2; 24]);
+        assert!(collector.push(&[3; 971]).is_empty());
+        assert_eq!(collector.push(&[3; 1]).len(), 1);
+    }
+
+    #[test]
+    fn test_collects_resampled_chunks() {
+        let mut collector = ChunkCollector::<SAMPLES_PER_STEP>::new(48_000);
+
+        let chunks = (0..20)
+            .flat_map(|_| collector.push(&[1000; 480]))
+            .collect::<Vec<[i16; SAMPLES_PER_STEP]>>();
+        // 9600 samples at 48 kHz are 3200 samples at 16 kHz, which are three full chunks.
+        assert_eq!(chunks.len(), 3);
+        // The chunks continue each other without the edges of the pieces showing.
+        assert!(chunks[1..].iter().flatten().all(|sample| *sample == 1000));
+    }
+}
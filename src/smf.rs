@@ -0,0 +1,131 @@
+use midly::num::{u15, u24, u28, u4, u7};
+use midly::{Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind};
+use crate::crepe::Prediction;
+use crate::notes::{frequency_to_midi, DEFAULT_A4_HZ};
+
+/// The resolution of the written file in ticks per quarter note.
+const TICKS_PER_QUARTER: u16 = 480;
+
+/// The tempo of the written file in microseconds per quarter note, which is 120 BPM.
+const MICROSECONDS_PER_QUARTER: u32 = 500_000;
+
+const VELOCITY: u8 = 100;
+
+/// Converts a time in seconds into ticks at the tempo of the written file.
+fn seconds_to_ticks(seconds: f64) -> u32 {
+    let ticks_per_second = TICKS_PER_QUARTER as f64 * 1_000_000.0 / MICROSECONDS_PER_QUARTER as f64;
+    (seconds * ticks_per_second).round() as u32
+}
+
+/// Transcribes predictions made `frame_interval_s` seconds apart into a type 0 Standard MIDI File.
+///
+/// Consecutive predictions of the same note become a single sustained note, predictions below
+/// `min_confidence` become rests.
+pub fn predictions_to_smf(preds: &[Prediction], frame_interval_s: f64, min_confidence: f32) -> Vec<u8> {
+    let notes = preds.iter()
+        .map(|prediction| (prediction.confidence >= min_confidence && prediction.frequency > 0.0)
+            .then(|| frequency_to_midi(prediction.frequency, DEFAULT_A4_HZ).round().clamp(0.0, 127.0) as u8));
+
+    // Events are collected with their absolute time in ticks and turned into deltas afterward.
+    let mut events = vec![(0, TrackEventKind::Meta(MetaMessage::Tempo(u24::new(MICROSECONDS_PER_QUARTER))))];
+    let mut active_note = None;
+    for (i, note) in notes.chain(std::iter::once(None)).enumerate() {
+        if note == active_note {
+            continue;
+        }
+
+        let ticks = seconds_to_ticks(i as f64 * frame_interval_s);
+        if let Some(key) = active_note {
+            events.push((ticks, note_event(MidiMessage::NoteOff { key: u7::new(key), vel: u7::new(0) })));
+        }
+        if let Some(key) = note {
+            events.push((ticks, note_event(MidiMessage::NoteOn { key: u7::new(key), vel: u7::new(VELOCITY) })));
+        }
+        active_note = note;
+    }
+    let end = events.last().map_or(0, |(ticks, _)| *ticks);
+    events.push((end, TrackEventKind::Meta(MetaMessage::EndOfTrack)));
+
+    let mut previous_ticks = 0;
+    let track = events.into_iter()
+        .map(|(ticks, kind)| {
+            let delta = ticks - previous_ticks;
+            previous_ticks = ticks;
+            TrackEvent { delta: u28::new(delta), kind }
+        })
+        .collect();
+
+    let mut smf = Smf::new(Header::new(Format::SingleTrack, Timing::Metrical(u15::new(TICKS_PER_QUARTER))));
+    smf.tracks.push(track);
+    let mut bytes = Vec::new();
+    smf.write_std(&mut bytes).expect("writing into a Vec cannot fail");
+
+    bytes
+}
+
+fn note_event<'a>(message: MidiMessage) -> TrackEventKind<'a> {
+    TrackEventKind::Midi { channel: u4::new(0), message }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::smf::*;
+    use crate::test_util::{prediction, STEP};
+
+    /// Parses a written file and returns its note events with their absolute time in ticks.
+    fn note_events(bytes: &[u8]) -> Vec<(u32, MidiMessage)> {
+        let smf = Smf::parse(bytes).unwrap();
+        assert_eq!(smf.header.format, Format::SingleTrack);
+        assert_eq!(smf.tracks.len(), 1);
+
+        let mut ticks = 0;
+        smf.tracks[0].iter()
+            .filter_map(|event| {
+                ticks += event.delta.as_int();
+                match event.kind {
+                    TrackEventKind::Midi { message, .. } => Some((ticks, message)),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_held_note_is_single_note() {
+        let predictions = vec![prediction(440.0, 0.9); 10];
+
+        let events = note_events(&predictions_to_smf(&predictions, STEP, 0.5));
+        assert_eq!(events, vec![
+            (0, MidiMessage::NoteOn { key: u7::new(69), vel: u7::new(VELOCITY) }),
+            (seconds_to_ticks(10.0 * STEP), MidiMessage::NoteOff { key: u7::new(69), vel: u7::new(0) }),
+        ]);
+        assert_eq!(seconds_to_ticks(10.0 * STEP), 614);
+    }
+
+    #[test]
+    fn test_rests_and_note_changes() {
+        let predictions = [
+            prediction(440.0, 0.9),
+            prediction(440.0, 0.2),
+            prediction(494.0, 0.9),
+            prediction(523.0, 0.9),
+        ];
+
+        let events = note_events(&predictions_to_smf(&predictions, STEP, 0.5));
+        assert_eq!(events, vec![
+            (0, MidiMessage::NoteOn { key: u7::new(69), vel: u7::new(VELOCITY) }),
+            (seconds_to_ticks(STEP), MidiMessage::NoteOff { key: u7::new(69), vel: u7::new(0) }),
+            (seconds_to_ticks(2.0 * STEP), MidiMessage::NoteOn { key: u7::new(71), vel: u7::new(VELOCITY) }),
+            (seconds_to_ticks(3.0 * STEP), MidiMessage::NoteOff { key: u7::new(71), vel: u7::new(0) }),
+            (seconds_to_ticks(3.0 * STEP), MidiMessage::NoteOn { key: u7::new(72), vel: u7::new(VELOCITY) }),
+            (seconds_to_ticks(4.0 * STEP), MidiMessage::NoteOff { key: u7::new(72), vel: u7::new(0) }),
+        ]);
+    }
+
+    #[test]
+    fn test_no_confident_predictions_writes_empty_track() {
+        let predictions = [prediction(440.0, 0.1); 5];
+
+        assert!(note_events(&predictions_to_smf(&predictions, STEP, 0.5)).is_empty());
+    }
+}
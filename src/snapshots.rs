@@ -0,0 +1,126 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use crate::crepe::Prediction;
+use crate::notes::{frequency_to_note_checked, Note};
+
+const HEADER: &str = "time_s,note,frequency_hz,cents_offset";
+
+/// A reading that was captured, e.g. after tuning a string.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Snapshot {
+    /// Seconds since the start of the audio stream at which the reading was captured.
+    pub time_s: f64,
+    pub frequency: f32,
+    /// The nearest note and how many cents the frequency is off from it.
+    pub note: Note,
+}
+
+/// The readings captured during a session, in the order they were captured.
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotList {
+    snapshots: Vec<Snapshot>,
+}
+
+impl SnapshotList {
+    pub fn new() -> Self {
+        SnapshotList::default()
+    }
+
+    /// Captures `prediction` made at `time_s` seconds if it is at least as confident as
+    /// `min_confidence` and has a valid frequency, calculating its note relative to `a4_hz`.
+    ///
+    /// Returns whether the reading was captured.
+    pub fn capture(&mut self, time_s: f64, prediction: &Prediction, min_confidence: f32, a4_hz: f32) -> bool {
+        if prediction.confidence < min_confidence {
+            return false;
+        }
+        let Some(note) = frequency_to_note_checked(prediction.frequency, a4_hz) else {
+            return false;
+        };
+
+        self.snapshots.push(Snapshot {
+            time_s,
+            frequency: prediction.frequency,
+            note,
+        });
+        true
+    }
+
+    pub fn snapshots(&self) -> &[Snapshot] {
+        &self.snapshots
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+
+    /// Writes the captured readings as CSV to `out`, one row per reading.
+    pub fn write_csv<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, "{}", HEADER)?;
+        for snapshot in &self.snapshots {
+            writeln!(
+                out,
+                "{:.3},{}{},{:.2},{:.1}",
+                snapshot.time_s, snapshot.note.name, snapshot.note.octave, snapshot.frequency, snapshot.note.cents_offset,
+            )?;
+        }
+
+        out.flush()
+    }
+
+    /// Writes the captured readings to a CSV file at `path`, overwriting any existing file.
+    pub fn export_csv(&self, path: &Path) -> io::Result<()> {
+        self.write_csv(BufWriter::new(File::create(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::snapshots::*;
+    use crate::test_util::prediction;
+
+    #[test]
+    fn test_add_and_clear() {
+        let mut list = SnapshotList::new();
+
+        assert!(list.capture(1.0, &prediction(440.0, 0.9), 0.5, 440.0));
+        assert!(list.capture(2.5, &prediction(329.0, 0.8), 0.5, 440.0));
+        assert_eq!(list.snapshots().len(), 2);
+        assert_eq!(list.snapshots()[0].note.name, "A");
+        assert_eq!(list.snapshots()[1].note.name, "E");
+        assert_eq!(list.snapshots()[1].time_s, 2.5);
+
+        list.clear();
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn test_unconfident_readings_are_not_captured() {
+        let mut list = SnapshotList::new();
+
+        assert!(!list.capture(1.0, &prediction(440.0, 0.4), 0.5, 440.0));
+        assert!(!list.capture(1.0, &prediction(f32::NAN, 0.9), 0.5, 440.0));
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn test_csv_rows() {
+        let mut list = SnapshotList::new();
+        list.capture(1.0, &prediction(440.0, 0.9), 0.5, 440.0);
+        list.capture(2.0, &prediction(110.0, 0.9), 0.5, 440.0);
+
+        let mut out = Vec::new();
+        list.write_csv(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().lines().collect::<Vec<&str>>(), vec![
+            HEADER,
+            "1.000,A4,440.00,0.0",
+            "2.000,A2,110.00,0.0",
+        ]);
+    }
+}
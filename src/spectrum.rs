@@ -0,0 +1,99 @@
+use std::sync::Arc;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use crate::crepe::{SAMPLES_PER_STEP, SAMPLE_RATE};
+
+/// The number of samples that each spectrum is calculated from, which is one chunk of audio.
+pub const FFT_SIZE: usize = SAMPLES_PER_STEP;
+
+/// The number of frequency bins of a spectrum, from 0 Hz up to half the sample rate.
+pub const SPECTRUM_BINS: usize = FFT_SIZE / 2 + 1;
+
+/// The level in dBFS that bins without any energy are set to instead of negative infinity.
+pub const MIN_DB: f32 = -150.0;
+
+/// The magnitude of every frequency bin of a chunk of audio in dBFS.
+pub type Spectrum = [f32; SPECTRUM_BINS];
+
+/// Returns the center frequency of a spectrum bin in Hz.
+pub fn bin_frequency(bin: usize) -> f32 {
+    bin as f32 * SAMPLE_RATE as f32 / FFT_SIZE as f32
+}
+
+/// Calculates the magnitude spectrum of chunks of audio.
+pub struct SpectrumAnalyzer {
+    /// The FFT plan, which is kept across chunks since planning takes much longer than running it.
+    fft: Arc<dyn Fft<f32>>,
+    /// The Hann window that every chunk is weighted with to reduce spectral leakage.
+    window: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl Default for SpectrumAnalyzer {
+    fn default() -> Self {
+        SpectrumAnalyzer::new()
+    }
+}
+
+impl SpectrumAnalyzer {
+    pub fn new() -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(FFT_SIZE);
+        let window = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FFT_SIZE as f32).cos())
+            .collect();
+        let scratch = vec![Complex::default(); fft.get_inplace_scratch_len()];
+
+        SpectrumAnalyzer {
+            fft,
+            window,
+            buffer: vec![Complex::default(); FFT_SIZE],
+            scratch,
+        }
+    }
+
+    /// Calculates the spectrum of a chunk of audio, scaled so that a sine at full scale peaks at
+    /// about 0 dBFS.
+    pub fn spectrum(&mut self, audio: &[i16; FFT_SIZE]) -> Spectrum {
+        for ((value, sample), weight) in self.buffer.iter_mut().zip(audio).zip(&self.window) {
+            *value = Complex::new(*sample as f32 / i16::MAX as f32 * weight, 0.0);
+        }
+        self.fft.process_with_scratch(&mut self.buffer, &mut self.scratch);
+
+        // The Hann window halves the amplitude and the energy of a sine is split between the
+        // positive and negative frequencies.
+        let scale = 4.0 / FFT_SIZE as f32;
+        std::array::from_fn(|bin| (20.0 * (self.buffer[bin].norm() * scale).log10()).max(MIN_DB))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use crate::spectrum::*;
+
+    #[test]
+    fn test_sine_peaks_in_its_bin() {
+        let audio = std::array::from_fn(|i| {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            ((2.0 * std::f32::consts::PI * 1000.0 * t).sin() * i16::MAX as f32) as i16
+        });
+        let spectrum = SpectrumAnalyzer::new().spectrum(&audio);
+
+        let peak_bin = (0..SPECTRUM_BINS)
+            .max_by(|a, b| spectrum[*a].total_cmp(&spectrum[*b]))
+            .unwrap();
+        assert_eq!(peak_bin, 64);
+        assert_eq!(bin_frequency(peak_bin), 1000.0);
+        assert_relative_eq!(spectrum[peak_bin], 0.0, epsilon = 0.1);
+        // Bins far away from the sine only contain leakage.
+        assert!(spectrum[32] < -60.0);
+    }
+
+    #[test]
+    fn test_silence_is_clamped() {
+        let spectrum = SpectrumAnalyzer::new().spectrum(&[0; FFT_SIZE]);
+
+        assert!(spectrum.iter().all(|db| *db == MIN_DB));
+    }
+}
@@ -0,0 +1,404 @@
+use std::collections::VecDeque;
+use crate::crepe::Prediction;
+use crate::notes::{cents_to_hz, frequency_to_note, frequency_to_note_checked, hz_to_cents, Note, NoteSpec};
+
+/// The number of frames the most likely note is picked from by default, about a second of the
+/// live display.
+pub const DEFAULT_NOTE_MODE_FRAMES: usize = 16;
+
+/// The width in cents of every bin of a [`CentsHistogram`].
+pub const CENTS_BIN_WIDTH: f32 = 5.0;
+
+/// The number of bins of a [`CentsHistogram`], which cover the offsets from -50 to +50 cents.
+pub const CENTS_BINS: usize = 20;
+
+/// The number of cents the average offset may be off from zero while still counting as in tune.
+pub const TENDENCY_TOLERANCE_CENTS: f32 = 5.0;
+
+/// Summary of the pitch of a held note over a number of predictions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PitchStats {
+    /// The average frequency, averaged in cents so that it is not biased toward higher pitches.
+    pub mean_hz: f32,
+    /// The standard deviation of the pitch in cents, which shows how steadily the note was held.
+    pub cents_std: f32,
+    /// The note closest to the average frequency.
+    pub nearest_note: Note,
+    /// How many cents the average frequency is off from the nearest note.
+    pub mean_cents_offset: f32,
+    /// The number of predictions the statistics were calculated from.
+    pub frame_count: usize,
+}
+
+/// Collects confident predictions, e.g. while a note is sung, to summarize how well it was held.
+#[derive(Debug, Clone)]
+pub struct StatsCollector {
+    min_confidence: f32,
+    a4_hz: f32,
+    /// The collected pitches in cents relative to A4.
+    cents: Vec<f32>,
+}
+
+impl StatsCollector {
+    /// Creates a collector that ignores predictions below `min_confidence` and calculates notes
+    /// relative to `a4_hz`.
+    pub fn new(min_confidence: f32, a4_hz: f32) -> Self {
+        StatsCollector {
+            min_confidence,
+            a4_hz,
+            cents: Vec::new(),
+        }
+    }
+
+    /// Adds a prediction if it is confident and has a valid frequency.
+    pub fn push(&mut self, prediction: &Prediction) {
+        let is_valid = prediction.frequency.is_finite() && prediction.frequency > 0.0;
+        if is_valid && prediction.confidence >= self.min_confidence {
+            self.cents.push(hz_to_cents(prediction.frequency, self.a4_hz));
+        }
+    }
+
+    /// The number of predictions that were collected so far.
+    pub fn frame_count(&self) -> usize {
+        self.cents.len()
+    }
+
+    /// Calculates the statistics of the collected predictions, or `None` if none were collected.
+    pub fn stats(&self) -> Option<PitchStats> {
+        if self.cents.is_empty() {
+            return None;
+        }
+
+        let count = self.cents.len() as f32;
+        let mean_cents = self.cents.iter().sum::<f32>() / count;
+        let variance = self.cents.iter()
+            .map(|cents| (cents - mean_cents) * (cents - mean_cents))
+            .sum::<f32>() / count;
+        let mean_hz = cents_to_hz(mean_cents, self.a4_hz);
+        let nearest_note = frequency_to_note(mean_hz, self.a4_hz);
+
+        Some(PitchStats {
+            mean_hz,
+            cents_std: variance.sqrt(),
+            nearest_note,
+            mean_cents_offset: nearest_note.cents_offset,
+            frame_count: self.cents.len(),
+        })
+    }
+}
+
+/// The note that was detected most often in a window of recent frames.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoteMode {
+    pub note: NoteSpec,
+    /// The fraction of the frames in the window that the note was detected in.
+    pub share: f32,
+}
+
+/// Tracks the most likely note over a sliding window of frames, which is much steadier to read
+/// than the note of every single frame.
+#[derive(Debug, Clone)]
+pub struct NoteModeTracker {
+    window: usize,
+    /// The nearest note of every frame in the window, `None` for frames without a confident pitch.
+    notes: VecDeque<Option<NoteSpec>>,
+}
+
+impl Default for NoteModeTracker {
+    fn default() -> Self {
+        NoteModeTracker::new(DEFAULT_NOTE_MODE_FRAMES)
+    }
+}
+
+impl NoteModeTracker {
+    /// Creates a tracker over the last `window` frames.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` is zero.
+    pub fn new(window: usize) -> Self {
+        assert!(window > 0, "note mode window must not be empty");
+
+        NoteModeTracker {
+            window,
+            notes: VecDeque::with_capacity(window),
+        }
+    }
+
+    /// Adds the prediction of the next frame, `None` if it had no pitch. Predictions below
+    /// `min_confidence` count as frames without a note, the note is calculated relative to `a4_hz`.
+    pub fn push(&mut self, prediction: Option<&Prediction>, min_confidence: f32, a4_hz: f32) {
+        let note = prediction
+            .filter(|prediction| prediction.confidence >= min_confidence)
+            .filter(|prediction| prediction.frequency.is_finite() && prediction.frequency > 0.0)
+            .map(|prediction| NoteSpec::nearest(prediction.frequency, a4_hz));
+
+        if self.notes.len() == self.window {
+            self.notes.pop_front();
+        }
+        self.notes.push_back(note);
+    }
+
+    /// The most frequent note in the window, or `None` if no frame in it had a confident pitch.
+    ///
+    /// If notes are detected equally often, the one that was detected most recently wins.
+    pub fn mode(&self) -> Option<NoteMode> {
+        let mut counts: Vec<(NoteSpec, usize, usize)> = Vec::new();
+        for (i, note) in self.notes.iter().enumerate() {
+            let Some(note) = note else {
+                continue;
+            };
+            match counts.iter_mut().find(|(counted, _, _)| counted == note) {
+                Some((_, count, last_seen)) => {
+                    *count += 1;
+                    *last_seen = i;
+                }
+                None => counts.push((*note, 1, i)),
+            }
+        }
+
+        counts.into_iter()
+            .max_by_key(|(_, count, last_seen)| (*count, *last_seen))
+            .map(|(note, count, _)| NoteMode {
+                note,
+                share: count as f32 / self.notes.len() as f32,
+            })
+    }
+}
+
+/// Whether a pitch is below, within or above the tolerance around a note, or whether a player
+/// tends to play above or below the notes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tendency {
+    Flat,
+    InTune,
+    Sharp,
+}
+
+impl Tendency {
+    /// Classifies an offset from a note in cents, up to `tolerance_cents` either way is in tune.
+    pub fn of(cents: f32, tolerance_cents: f32) -> Tendency {
+        if cents < -tolerance_cents {
+            Tendency::Flat
+        } else if cents > tolerance_cents {
+            Tendency::Sharp
+        } else {
+            Tendency::InTune
+        }
+    }
+}
+
+/// Counts how many cents confident frames are off from their nearest note over a session, to see
+/// whether a player is consistently sharp or flat.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CentsHistogram {
+    counts: [usize; CENTS_BINS],
+    /// The sum of the offsets of all counted frames, for the mean.
+    cents_sum: f64,
+}
+
+impl CentsHistogram {
+    /// Adds the prediction of a frame, `None` if it had no pitch. Predictions below
+    /// `min_confidence` are ignored, the offset is calculated relative to `a4_hz`.
+    pub fn push(&mut self, prediction: Option<&Prediction>, min_confidence: f32, a4_hz: f32) {
+        let note = prediction
+            .filter(|prediction| prediction.confidence >= min_confidence)
+            .and_then(|prediction| frequency_to_note_checked(prediction.frequency, a4_hz));
+        if let Some(note) = note {
+            self.push_cents(note.cents_offset);
+        }
+    }
+
+    /// Adds the offset of a frame from its nearest note in cents.
+    pub fn push_cents(&mut self, cents: f32) {
+        self.counts[Self::bin(cents)] += 1;
+        self.cents_sum += cents as f64;
+    }
+
+    /// The index of the bin that an offset in cents is counted in, offsets outside of ±50 cents
+    /// are counted in the outermost bins.
+    pub fn bin(cents: f32) -> usize {
+        ((cents + 50.0) / CENTS_BIN_WIDTH).floor().clamp(0.0, (CENTS_BINS - 1) as f32) as usize
+    }
+
+    /// The offset in cents at the center of a bin.
+    pub fn bin_center(bin: usize) -> f32 {
+        -50.0 + (bin as f32 + 0.5) * CENTS_BIN_WIDTH
+    }
+
+    /// The number of frames counted in every bin, from the flattest to the sharpest.
+    pub fn counts(&self) -> &[usize; CENTS_BINS] {
+        &self.counts
+    }
+
+    /// The number of frames that were counted.
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    /// The average offset of all counted frames, `None` if none were counted.
+    pub fn mean_cents(&self) -> Option<f32> {
+        let total = self.total();
+        (total > 0).then(|| (self.cents_sum / total as f64) as f32)
+    }
+
+    /// Whether the frames were sharp or flat on average, `None` if none were counted.
+    pub fn tendency(&self) -> Option<Tendency> {
+        self.mean_cents().map(|mean| Tendency::of(mean, TENDENCY_TOLERANCE_CENTS))
+    }
+
+    /// Removes all counted frames, e.g. to start a new session.
+    pub fn clear(&mut self) {
+        *self = CentsHistogram::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use crate::notes::shift_by_cents;
+    use crate::stats::*;
+    use crate::test_util::prediction;
+
+    #[test]
+    fn test_slightly_sharp_a4() {
+        let mut collector = StatsCollector::new(0.5, 440.0);
+        for cents in [0.0, 10.0, 20.0, 10.0] {
+            collector.push(&prediction(shift_by_cents(440.0, cents), 0.9));
+        }
+
+        let stats = collector.stats().unwrap();
+        assert_eq!(stats.frame_count, 4);
+        assert_relative_eq!(stats.mean_hz, shift_by_cents(440.0, 10.0), epsilon = 1e-2);
+        // The deviations from the mean are -10, 0, 10 and 0 cents.
+        assert_relative_eq!(stats.cents_std, 50f32.sqrt(), epsilon = 1e-2);
+        assert_eq!(stats.nearest_note.name, "A");
+        assert_eq!(stats.nearest_note.octave, 4);
+        assert_relative_eq!(stats.mean_cents_offset, 10.0, epsilon = 1e-2);
+    }
+
+    #[test]
+    fn test_flat_note_has_negative_offset() {
+        let mut collector = StatsCollector::new(0.5, 440.0);
+        for _ in 0..5 {
+            collector.push(&prediction(shift_by_cents(220.0, -30.0), 0.9));
+        }
+
+        let stats = collector.stats().unwrap();
+        assert_relative_eq!(stats.cents_std, 0.0, epsilon = 1e-2);
+        assert_eq!(stats.nearest_note.octave, 3);
+        assert_relative_eq!(stats.mean_cents_offset, -30.0, epsilon = 1e-2);
+    }
+
+    #[test]
+    fn test_note_mode() {
+        let mut tracker = NoteModeTracker::new(10);
+        for frequency in [440.0, 466.16, 440.0, 440.0, 466.16, 440.0, 440.0, 466.16, 440.0, 440.0] {
+            tracker.push(Some(&prediction(frequency, 0.9)), 0.5, 440.0);
+        }
+
+        let mode = tracker.mode().unwrap();
+        assert_eq!(mode.note.midi, 69);
+        assert_eq!(mode.note.note().name, "A");
+        assert_relative_eq!(mode.share, 0.7);
+    }
+
+    #[test]
+    fn test_note_mode_window_slides() {
+        let mut tracker = NoteModeTracker::new(4);
+        assert_eq!(tracker.mode(), None);
+
+        for _ in 0..3 {
+            tracker.push(Some(&prediction(440.0, 0.9)), 0.5, 440.0);
+        }
+        for _ in 0..2 {
+            tracker.push(Some(&prediction(220.0, 0.9)), 0.5, 440.0);
+        }
+        // Two frames of each note are tied, the more recent one wins.
+        let mode = tracker.mode().unwrap();
+        assert_eq!(mode.note.midi, 57);
+        assert_relative_eq!(mode.share, 0.5);
+
+        tracker.push(Some(&prediction(220.0, 0.9)), 0.5, 440.0);
+        assert_relative_eq!(tracker.mode().unwrap().share, 0.75);
+
+        tracker.push(Some(&prediction(220.0, 0.2)), 0.5, 440.0);
+        tracker.push(None, 0.5, 440.0);
+        assert_relative_eq!(tracker.mode().unwrap().share, 0.5);
+        tracker.push(None, 0.5, 440.0);
+        tracker.push(None, 0.5, 440.0);
+        assert_eq!(tracker.mode(), None);
+    }
+
+    #[test]
+    fn test_unconfident_and_invalid_predictions_are_ignored() {
+        let mut collector = StatsCollector::new(0.5, 440.0);
+        assert_eq!(collector.stats(), None);
+
+        collector.push(&prediction(440.0, 0.2));
+        collector.push(&prediction(f32::NAN, 0.9));
+        collector.push(&prediction(0.0, 0.9));
+        assert_eq!(collector.frame_count(), 0);
+        assert_eq!(collector.stats(), None);
+
+        collector.push(&prediction(440.0, 0.5));
+        assert_eq!(collector.frame_count(), 1);
+    }
+
+    #[test]
+    fn test_cents_histogram_bins() {
+        let mut histogram = CentsHistogram::default();
+        assert_eq!(histogram.mean_cents(), None);
+        assert_eq!(histogram.tendency(), None);
+
+        for cents in [-50.0, -47.5, -0.1, 0.0, 4.9, 5.0, 12.0, 49.9, 50.0, 80.0] {
+            histogram.push_cents(cents);
+        }
+        let mut expected = [0; CENTS_BINS];
+        expected[0] = 2;
+        expected[9] = 1;
+        expected[10] = 2;
+        expected[11] = 1;
+        expected[12] = 1;
+        expected[19] = 3;
+        assert_eq!(histogram.counts(), &expected);
+        assert_eq!(histogram.total(), 10);
+        assert_relative_eq!(CentsHistogram::bin_center(0), -47.5);
+        assert_relative_eq!(CentsHistogram::bin_center(10), 2.5);
+
+        histogram.clear();
+        assert_eq!(histogram.total(), 0);
+    }
+
+    #[test]
+    fn test_cents_histogram_tendency() {
+        let mut histogram = CentsHistogram::default();
+        for cents in [10.0, 20.0, -10.0] {
+            histogram.push(Some(&prediction(shift_by_cents(440.0, cents), 0.9)), 0.5, 440.0);
+        }
+        // Unconfident frames and frames without a pitch are not counted.
+        histogram.push(Some(&prediction(shift_by_cents(440.0, -40.0), 0.2)), 0.5, 440.0);
+        histogram.push(None, 0.5, 440.0);
+
+        assert_eq!(histogram.total(), 3);
+        assert_relative_eq!(histogram.mean_cents().unwrap(), 20.0 / 3.0, epsilon = 1e-2);
+        assert_eq!(histogram.tendency(), Some(Tendency::Sharp));
+
+        histogram.push_cents(-30.0);
+        assert_eq!(histogram.tendency(), Some(Tendency::InTune));
+        histogram.push_cents(-30.0);
+        assert_eq!(histogram.tendency(), Some(Tendency::Flat));
+    }
+
+    #[test]
+    fn test_tendency_of_offset() {
+        assert_eq!(Tendency::of(-12.0, 5.0), Tendency::Flat);
+        assert_eq!(Tendency::of(-5.0, 5.0), Tendency::InTune);
+        assert_eq!(Tendency::of(0.0, 5.0), Tendency::InTune);
+        assert_eq!(Tendency::of(5.0, 5.0), Tendency::InTune);
+        assert_eq!(Tendency::of(5.1, 5.0), Tendency::Sharp);
+        // A wider tolerance accepts larger errors.
+        assert_eq!(Tendency::of(-12.0, 15.0), Tendency::InTune);
+        assert_eq!(Tendency::of(40.0, 15.0), Tendency::Sharp);
+    }
+}
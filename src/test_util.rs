@@ -0,0 +1,17 @@
+//! Fixtures shared by the tests of several modules.
+use crate::crepe::{Prediction, SAMPLES_PER_STEP, SAMPLE_RATE};
+
+/// The time between two predictions of the live display.
+pub const STEP: f64 = 0.064;
+
+pub fn prediction(frequency: f32, confidence: f32) -> Prediction {
+    Prediction { frequency, confidence }
+}
+
+/// A chunk of a sine wave at `frequency` Hz with an amplitude of 10000.
+pub fn sine_chunk(frequency: f32) -> [i16; SAMPLES_PER_STEP] {
+    std::array::from_fn(|i| {
+        let t = i as f32 / SAMPLE_RATE as f32;
+        ((2.0 * std::f32::consts::PI * frequency * t).sin() * 10_000.0) as i16
+    })
+}
@@ -0,0 +1,115 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Generates a sine tone sample by sample, e.g. to play a reference note.
+#[derive(Debug, Clone)]
+pub struct SineGenerator {
+    sample_rate: f32,
+    /// The position within the current period, from 0 to 1.
+    phase: f32,
+}
+
+impl SineGenerator {
+    pub fn new(sample_rate: f32) -> Self {
+        SineGenerator {
+            sample_rate,
+            phase: 0.0,
+        }
+    }
+
+    /// Returns the next sample of a sine at `frequency` Hz with an amplitude of `volume`.
+    ///
+    /// The phase carries over from the previous sample, so changing the frequency or volume
+    /// between samples does not click.
+    pub fn next_sample(&mut self, frequency: f32, volume: f32) -> f32 {
+        let sample = (2.0 * std::f32::consts::PI * self.phase).sin() * volume;
+        self.phase = (self.phase + frequency / self.sample_rate).fract();
+
+        sample
+    }
+
+    /// Fills `out` with interleaved frames of `channels` samples, playing the same sine on every
+    /// channel.
+    pub fn fill(&mut self, out: &mut [f32], channels: usize, frequency: f32, volume: f32) {
+        for frame in out.chunks_exact_mut(channels.max(1)) {
+            frame.fill(self.next_sample(frequency, volume));
+        }
+    }
+}
+
+/// The frequency and volume of a tone that is played on another thread, which can be changed
+/// while it plays.
+#[derive(Debug)]
+pub struct ToneControl {
+    frequency: AtomicU32,
+    volume: AtomicU32,
+}
+
+impl ToneControl {
+    pub fn new(frequency: f32, volume: f32) -> Self {
+        ToneControl {
+            frequency: AtomicU32::new(frequency.to_bits()),
+            volume: AtomicU32::new(volume.to_bits()),
+        }
+    }
+
+    pub fn frequency(&self) -> f32 {
+        f32::from_bits(self.frequency.load(Ordering::Relaxed))
+    }
+
+    pub fn set_frequency(&self, frequency: f32) {
+        self.frequency.store(frequency.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn volume(&self) -> f32 {
+        f32::from_bits(self.volume.load(Ordering::Relaxed))
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        self.volume.store(volume.to_bits(), Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use crate::tone::*;
+
+    #[test]
+    fn test_sine_period() {
+        // 440 Hz at 44.1 kHz has a period of about 100.23 samples.
+        let mut generator = SineGenerator::new(44_100.0);
+        let samples = (0..44_100).map(|_| generator.next_sample(440.0, 0.5)).collect::<Vec<f32>>();
+
+        let rising_crossings = samples.windows(2)
+            .enumerate()
+            .filter(|(_, pair)| pair[0] < 0.0 && pair[1] >= 0.0)
+            .map(|(i, pair)| i as f32 + pair[0] / (pair[0] - pair[1]))
+            .collect::<Vec<f32>>();
+        for pair in rising_crossings.windows(2) {
+            assert_relative_eq!(pair[1] - pair[0], 44_100.0 / 440.0, epsilon = 0.01);
+        }
+        assert_eq!(rising_crossings.len(), 439);
+        assert_relative_eq!(samples.iter().fold(0.0f32, |max, sample| max.max(*sample)), 0.5, epsilon = 0.001);
+    }
+
+    #[test]
+    fn test_fill_repeats_sample_on_every_channel() {
+        let mut generator = SineGenerator::new(8.0);
+        let mut out = [0.0; 8];
+        generator.fill(&mut out, 2, 2.0, 1.0);
+
+        for (sample, expected) in out.iter().zip([0.0, 0.0, 1.0, 1.0, 0.0, 0.0, -1.0, -1.0]) {
+            assert_relative_eq!(*sample, expected, epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_control_round_trip() {
+        let control = ToneControl::new(440.0, 0.2);
+        control.set_frequency(261.63);
+        control.set_volume(0.8);
+
+        assert_eq!(control.frequency(), 261.63);
+        assert_eq!(control.volume(), 0.8);
+    }
+}
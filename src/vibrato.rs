@@ -0,0 +1,209 @@
+use std::collections::VecDeque;
+use crate::crepe::{SAMPLES_PER_STEP, SAMPLE_RATE};
+
+/// The number of pitch values per second of the live display, which analyzes consecutive chunks
+/// that do not overlap.
+const DEFAULT_FRAME_RATE: f32 = SAMPLE_RATE as f32 / SAMPLES_PER_STEP as f32;
+
+/// The number of seconds a note has to be held before its vibrato is measured.
+const DEFAULT_WINDOW_SECONDS: f32 = 1.5;
+
+/// The smallest depth in cents that counts as vibrato, anything less is a steady note.
+const MIN_DEPTH_CENTS: f32 = 10.0;
+
+/// The range of rates in Hz that count as vibrato, slower or faster oscillations are likely drift
+/// or noise.
+const RATE_RANGE_HZ: std::ops::RangeInclusive<f32> = 3.0..=10.0;
+
+/// The distance in cents between two consecutive pitch values that is taken as a new note.
+const NOTE_CHANGE_CENTS: f32 = 100.0;
+
+/// The rate and depth of the vibrato of a held note.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vibrato {
+    /// The number of oscillations per second.
+    pub rate_hz: f32,
+    /// The distance in cents between the highest and lowest pitch of an oscillation.
+    pub depth_cents: f32,
+}
+
+/// Removes the straight line that fits the values best, so that only the oscillation around a
+/// slowly drifting pitch remains.
+fn detrend(values: &[f32]) -> Vec<f32> {
+    let count = values.len() as f32;
+    let mean_x = (count - 1.0) / 2.0;
+    let mean_y = values.iter().sum::<f32>() / count;
+    let covariance = values.iter()
+        .enumerate()
+        .map(|(x, y)| (x as f32 - mean_x) * (y - mean_y))
+        .sum::<f32>();
+    let variance = (0..values.len())
+        .map(|x| (x as f32 - mean_x) * (x as f32 - mean_x))
+        .sum::<f32>();
+    let slope = if variance > 0.0 { covariance / variance } else { 0.0 };
+
+    values.iter()
+        .enumerate()
+        .map(|(x, y)| y - mean_y - slope * (x as f32 - mean_x))
+        .collect()
+}
+
+/// Estimates the vibrato of pitch values in cents that were measured `frame_rate` times per
+/// second, or returns `None` if they do not oscillate like vibrato.
+///
+/// The rate is measured from the zero crossings of the detrended pitch. The depth is derived from
+/// its RMS, which unlike the highest and lowest value does not depend on whether the frames
+/// happened to catch the peaks of the oscillation.
+pub fn analyze_vibrato(cents: &[f32], frame_rate: f32) -> Option<Vibrato> {
+    if cents.len() < 4 {
+        return None;
+    }
+
+    let oscillation = detrend(cents);
+    let rms = (oscillation.iter().map(|cents| cents * cents).sum::<f32>() / oscillation.len() as f32).sqrt();
+    // The peak-to-peak distance of a sine wave.
+    let depth_cents = 2.0 * std::f32::consts::SQRT_2 * rms;
+    if depth_cents < MIN_DEPTH_CENTS {
+        return None;
+    }
+
+    // Linearly interpolated frame positions at which the oscillation crosses zero.
+    let crossings = oscillation.windows(2)
+        .enumerate()
+        .filter(|(_, pair)| (pair[0] < 0.0) != (pair[1] < 0.0))
+        .map(|(i, pair)| i as f32 + pair[0] / (pair[0] - pair[1]))
+        .collect::<Vec<f32>>();
+    let (Some(first), Some(last)) = (crossings.first(), crossings.last()) else {
+        return None;
+    };
+    if crossings.len() < 3 {
+        return None;
+    }
+
+    // Every oscillation crosses zero twice.
+    let rate_hz = (crossings.len() - 1) as f32 / 2.0 / ((last - first) / frame_rate);
+
+    RATE_RANGE_HZ.contains(&rate_hz).then_some(Vibrato { rate_hz, depth_cents })
+}
+
+/// Collects the pitch of a held note and measures its vibrato once it was held long enough.
+#[derive(Debug, Clone)]
+pub struct VibratoAnalyzer {
+    frame_rate: f32,
+    /// The number of pitch values the vibrato is measured over.
+    window: usize,
+    cents: VecDeque<f32>,
+}
+
+impl Default for VibratoAnalyzer {
+    fn default() -> Self {
+        VibratoAnalyzer::new(DEFAULT_FRAME_RATE, DEFAULT_WINDOW_SECONDS)
+    }
+}
+
+impl VibratoAnalyzer {
+    /// Creates an analyzer for `frame_rate` pitch values per second that measures the vibrato of
+    /// the most recent `window_seconds` seconds.
+    pub fn new(frame_rate: f32, window_seconds: f32) -> Self {
+        VibratoAnalyzer {
+            frame_rate,
+            window: (frame_rate * window_seconds).round().max(1.0) as usize,
+            cents: VecDeque::new(),
+        }
+    }
+
+    /// Adds the pitch of the next frame in cents, `None` if it had no confident pitch.
+    ///
+    /// A frame without pitch or a jump to another note starts over, since the note is not held.
+    pub fn push(&mut self, cents: Option<f32>) {
+        let Some(cents) = cents else {
+            self.cents.clear();
+            return;
+        };
+        if self.cents.back().is_some_and(|last| (cents - last).abs() > NOTE_CHANGE_CENTS) {
+            self.cents.clear();
+        }
+
+        self.cents.push_back(cents);
+        while self.cents.len() > self.window {
+            self.cents.pop_front();
+        }
+    }
+
+    /// Measures the vibrato, or returns `None` if no note was held for the whole window or it has
+    /// no vibrato.
+    pub fn analyze(&self) -> Option<Vibrato> {
+        if self.cents.len() < self.window {
+            return None;
+        }
+
+        let cents = self.cents.iter().copied().collect::<Vec<f32>>();
+        analyze_vibrato(&cents, self.frame_rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use crate::vibrato::*;
+
+    /// Pitch values of a note that drifts upward by `drift` cents per second with a vibrato.
+    fn modulated(rate_hz: f32, amplitude_cents: f32, drift: f32, frame_rate: f32, seconds: f32) -> Vec<f32> {
+        (0..(frame_rate * seconds) as usize)
+            .map(|i| {
+                let t = i as f32 / frame_rate;
+                (2.0 * std::f32::consts::PI * rate_hz * t).sin() * amplitude_cents + drift * t
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_6_hz_vibrato() {
+        let vibrato = analyze_vibrato(&modulated(6.0, 30.0, 0.0, 100.0, 2.0), 100.0).unwrap();
+
+        assert_relative_eq!(vibrato.rate_hz, 6.0, epsilon = 0.1);
+        assert_relative_eq!(vibrato.depth_cents, 60.0, epsilon = 2.0);
+    }
+
+    #[test]
+    fn test_drift_is_removed() {
+        let vibrato = analyze_vibrato(&modulated(6.0, 30.0, 40.0, 100.0, 2.0), 100.0).unwrap();
+
+        assert_relative_eq!(vibrato.rate_hz, 6.0, epsilon = 0.1);
+        assert_relative_eq!(vibrato.depth_cents, 60.0, epsilon = 2.0);
+        assert_eq!(analyze_vibrato(&modulated(6.0, 0.0, 40.0, 100.0, 2.0), 100.0), None);
+    }
+
+    #[test]
+    fn test_vibrato_at_live_frame_rate() {
+        let mut analyzer = VibratoAnalyzer::default();
+        let cents = modulated(6.0, 30.0, 10.0, DEFAULT_FRAME_RATE, 3.0);
+
+        for (i, cents) in cents.iter().enumerate() {
+            analyzer.push(Some(*cents));
+            if i + 1 < analyzer.window {
+                assert_eq!(analyzer.analyze(), None);
+            }
+        }
+        let vibrato = analyzer.analyze().unwrap();
+        assert_relative_eq!(vibrato.rate_hz, 6.0, epsilon = 0.5);
+        assert_relative_eq!(vibrato.depth_cents, 60.0, epsilon = 6.0);
+    }
+
+    #[test]
+    fn test_interrupted_note_starts_over() {
+        let mut analyzer = VibratoAnalyzer::new(100.0, 1.0);
+        for cents in modulated(6.0, 30.0, 0.0, 100.0, 1.0) {
+            analyzer.push(Some(cents));
+        }
+        assert!(analyzer.analyze().is_some());
+
+        analyzer.push(None);
+        assert_eq!(analyzer.analyze(), None);
+        for cents in modulated(6.0, 30.0, 0.0, 100.0, 1.0) {
+            analyzer.push(Some(cents));
+        }
+        analyzer.push(Some(500.0));
+        assert_eq!(analyzer.analyze(), None);
+    }
+}
@@ -0,0 +1,248 @@
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::path::Path;
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use crate::crepe::{frame_time, CrepeModel, TimedPrediction, SAMPLES_PER_STEP, SAMPLE_RATE};
+use crate::resample::resample_to_16k;
+
+/// Errors that can occur while analyzing or recording a WAV file.
+#[derive(Debug)]
+pub enum WavError {
+    /// The file is not a WAV file or uses a sample format other than 16-bit integer or 32-bit float.
+    UnsupportedFormat(String),
+    Io(io::Error),
+    /// The file does not contain any samples.
+    Empty,
+}
+
+impl Display for WavError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WavError::UnsupportedFormat(message) => write!(f, "Unsupported WAV format: {}", message),
+            WavError::Io(e) => write!(f, "Error accessing WAV file: {}", e),
+            WavError::Empty => write!(f, "WAV file contains no samples"),
+        }
+    }
+}
+
+impl std::error::Error for WavError {}
+
+impl From<hound::Error> for WavError {
+    fn from(e: hound::Error) -> Self {
+        match e {
+            hound::Error::IoError(e) => WavError::Io(e),
+            e => WavError::UnsupportedFormat(e.to_string()),
+        }
+    }
+}
+
+/// Reads a WAV file and returns its samples in the range `-1.0..=1.0` along with the sample rate.
+///
+/// Files with multiple channels are downmixed to mono by averaging all channels.
+pub fn read_wav_mono(path: &Path) -> Result<(Vec<f32>, u32), WavError> {
+    let mut reader = WavReader::open(path)?;
+    let spec = reader.spec();
+
+    let samples = match (spec.sample_format, spec.bits_per_sample) {
+        (SampleFormat::Int, 16) => reader.samples::<i16>()
+            .map(|sample| sample.map(|sample| sample as f32 / i16::MAX as f32))
+            .collect::<Result<Vec<f32>, hound::Error>>()?,
+        (SampleFormat::Float, 32) => reader.samples::<f32>()
+            .collect::<Result<Vec<f32>, hound::Error>>()?,
+        (format, bits) => return Err(WavError::UnsupportedFormat(format!("{} bit {:?} samples", bits, format))),
+    };
+    if samples.is_empty() {
+        return Err(WavError::Empty);
+    }
+
+    let channels = spec.channels.max(1) as usize;
+    let mono = samples.chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect();
+
+    Ok((mono, spec.sample_rate))
+}
+
+/// Splits audio into consecutive chunks for the model, padding the last one with silence.
+fn to_chunks(samples: &[f32]) -> Vec<[i16; SAMPLES_PER_STEP]> {
+    samples.chunks(SAMPLES_PER_STEP)
+        .map(|chunk| {
+            let mut frame = [0; SAMPLES_PER_STEP];
+            for (target, sample) in frame.iter_mut().zip(chunk) {
+                *target = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            }
+
+            frame
+        })
+        .collect()
+}
+
+/// Records mono audio at the model's [`SAMPLE_RATE`] into a 16-bit WAV file.
+#[derive(Default)]
+pub struct WavRecorder {
+    writer: Option<WavWriter<BufWriter<File>>>,
+}
+
+impl WavRecorder {
+    /// Starts recording into a new file at `path`, finishing any recording that is in progress.
+    pub fn start(&mut self, path: &Path) -> Result<(), WavError> {
+        self.stop()?;
+
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        self.writer = Some(WavWriter::create(path, spec)?);
+
+        Ok(())
+    }
+
+    /// Appends samples to the recording, does nothing if no recording is in progress.
+    pub fn push(&mut self, samples: &[i16]) -> Result<(), WavError> {
+        if let Some(writer) = self.writer.as_mut() {
+            for sample in samples {
+                writer.write_sample(*sample)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finishes the recording in progress, if any, and writes the final WAV header.
+    pub fn stop(&mut self) -> Result<(), WavError> {
+        if let Some(writer) = self.writer.take() {
+            writer.finalize()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.writer.is_some()
+    }
+}
+
+/// Reads a WAV file and splits it into consecutive chunks at the model's [`SAMPLE_RATE`].
+pub fn read_wav_chunks(path: &Path) -> Result<Vec<[i16; SAMPLES_PER_STEP]>, WavError> {
+    let (samples, sample_rate) = read_wav_mono(path)?;
+
+    Ok(to_chunks(&resample_to_16k(&samples, sample_rate)))
+}
+
+/// Runs pitch prediction on every chunk of a WAV file, along with the time each chunk starts at.
+pub fn analyze_wav(path: &Path, model: &CrepeModel) -> Result<Vec<TimedPrediction>, WavError> {
+    let chunks = read_wav_chunks(path)?;
+
+    Ok(model.predict_batch(&chunks)
+        .into_iter()
+        .enumerate()
+        .map(|(chunk, prediction)| TimedPrediction {
+            time_s: frame_time(chunk, SAMPLES_PER_STEP),
+            prediction,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use approx::assert_relative_eq;
+    use ort::session::Session;
+    use crate::wav::*;
+
+    fn write_test_wav(name: &str, spec: WavSpec, samples: &[i16]) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for sample in samples {
+            writer.write_sample(*sample).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        path
+    }
+
+    fn spec(channels: u16, sample_rate: u32) -> WavSpec {
+        WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        }
+    }
+
+    #[test]
+    fn test_read_wav_downmixes_channels() {
+        let path = write_test_wav("pitch-overlay-test-stereo.wav", spec(2, 16_000), &[1000, 3000, -1000, -3000]);
+
+        let (samples, sample_rate) = read_wav_mono(&path).unwrap();
+        assert_eq!(sample_rate, 16_000);
+        assert_eq!(samples.len(), 2);
+        assert_relative_eq!(samples[0], 2000.0 / i16::MAX as f32);
+        assert_relative_eq!(samples[1], -2000.0 / i16::MAX as f32);
+    }
+
+    #[test]
+    fn test_read_wav_rejects_empty_file() {
+        let path = write_test_wav("pitch-overlay-test-empty.wav", spec(1, 16_000), &[]);
+
+        assert!(matches!(read_wav_mono(&path), Err(WavError::Empty)));
+    }
+
+    #[test]
+    fn test_to_chunks_pads_last_chunk() {
+        let chunks = to_chunks(&vec![0.5; SAMPLES_PER_STEP + 10]);
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1][9], (0.5 * i16::MAX as f32) as i16);
+        assert_eq!(chunks[1][10], 0);
+    }
+
+    #[test]
+    fn test_recorder_round_trip() {
+        let path = std::env::temp_dir().join("pitch-overlay-test-recording.wav");
+        let samples = (0..3000).map(|i| (i * 7 % 2000 - 1000) as i16).collect::<Vec<i16>>();
+
+        let mut recorder = WavRecorder::default();
+        recorder.push(&[1, 2, 3]).unwrap();
+        recorder.start(&path).unwrap();
+        assert!(recorder.is_recording());
+        recorder.push(&samples[..1000]).unwrap();
+        recorder.push(&samples[1000..]).unwrap();
+        recorder.stop().unwrap();
+        assert!(!recorder.is_recording());
+
+        let mut reader = WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().sample_rate, SAMPLE_RATE);
+        assert_eq!(reader.spec().channels, 1);
+        let recorded = reader.samples::<i16>().collect::<Result<Vec<i16>, hound::Error>>().unwrap();
+        assert_eq!(recorded, samples);
+    }
+
+    #[test]
+    #[ignore = "requires crepe-full.onnx in the working directory"]
+    fn test_analyze_sine_wav() {
+        let samples = (0..SAMPLE_RATE)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                ((2.0 * std::f32::consts::PI * 440.0 * t).sin() * 10_000.0) as i16
+            })
+            .collect::<Vec<i16>>();
+        let path = write_test_wav("pitch-overlay-test-sine.wav", spec(1, SAMPLE_RATE), &samples);
+        let session = Session::builder().unwrap().commit_from_file("crepe-full.onnx").unwrap();
+        let model = CrepeModel::new(session).unwrap();
+
+        let predictions = analyze_wav(&path, &model).unwrap();
+        assert_relative_eq!(predictions[1].time_s, SAMPLES_PER_STEP as f64 / SAMPLE_RATE as f64);
+        let mut frequencies = predictions.into_iter()
+            .map(|timed| timed.prediction.frequency)
+            .collect::<Vec<f32>>();
+        frequencies.sort_by(f32::total_cmp);
+        let median = frequencies[frequencies.len() / 2];
+
+        assert!((median - 440.0).abs() < 2.0, "median frequency was {}", median);
+    }
+}
@@ -0,0 +1,151 @@
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use serde_json::json;
+use tungstenite::Message;
+use crate::crepe::Prediction;
+use crate::notes::frequency_to_note_checked;
+
+/// The number of messages that can wait to be sent to a client before new ones are dropped.
+const QUEUE_SIZE: usize = 64;
+
+/// Encodes a prediction made at `time` seconds as a JSON object with the fields `time`, `freq`,
+/// `confidence`, `note` and `cents`.
+///
+/// The note and its cents offset are calculated relative to `a4_hz` and are `null` if the
+/// frequency is not positive.
+pub fn encode_prediction(time: f64, prediction: &Prediction, a4_hz: f32) -> String {
+    let note = frequency_to_note_checked(prediction.frequency, a4_hz);
+
+    json!({
+        "time": time,
+        "freq": prediction.frequency,
+        "confidence": prediction.confidence,
+        "note": note.map(|note| format!("{}{}", note.name, note.octave)),
+        "cents": note.map(|note| note.cents_offset),
+    }).to_string()
+}
+
+/// Broadcasts predictions as JSON messages to every connected WebSocket client, e.g. to show the
+/// pitch in a browser overlay.
+///
+/// Connections are accepted on a background thread and every client is sent its messages from a
+/// thread of its own, so that broadcasting never blocks the caller.
+pub struct PredictionServer {
+    local_addr: SocketAddr,
+    clients: Arc<Mutex<Vec<SyncSender<String>>>>,
+}
+
+impl PredictionServer {
+    /// Starts listening for WebSocket clients on `addr`.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let clients = Arc::new(Mutex::new(Vec::new()));
+
+        let accepted_clients = Arc::clone(&clients);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let clients = Arc::clone(&accepted_clients);
+                        thread::spawn(move || serve_client(stream, &clients));
+                    }
+                    Err(e) => println!("Error accepting WebSocket connection: {}", e),
+                }
+            }
+        });
+
+        Ok(PredictionServer {
+            local_addr,
+            clients,
+        })
+    }
+
+    /// The address the server listens on, which has the actual port if it was bound to port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// The number of clients that are connected.
+    pub fn client_count(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    /// Queues a prediction made at `time` seconds to be sent to every connected client, with its
+    /// note calculated relative to `a4_hz`. Clients that too many messages are already waiting
+    /// for miss it.
+    pub fn broadcast(&self, time: f64, prediction: &Prediction, a4_hz: f32) {
+        let message = encode_prediction(time, prediction, a4_hz);
+        self.clients.lock().unwrap().retain(|client| {
+            !matches!(client.try_send(message.clone()), Err(TrySendError::Disconnected(_)))
+        });
+    }
+}
+
+/// Completes the WebSocket handshake with a client and sends it the broadcast messages until it
+/// disconnects.
+fn serve_client(stream: TcpStream, clients: &Mutex<Vec<SyncSender<String>>>) {
+    let mut websocket = match tungstenite::accept(stream) {
+        Ok(websocket) => websocket,
+        Err(e) => {
+            println!("Error accepting WebSocket connection: {}", e);
+            return;
+        }
+    };
+
+    let (sender, receiver) = sync_channel::<String>(QUEUE_SIZE);
+    clients.lock().unwrap().push(sender);
+    // Dropping the receiver removes the client from the broadcast list on the next broadcast.
+    for message in receiver {
+        if websocket.send(Message::text(message)).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use crate::notes::DEFAULT_A4_HZ;
+    use crate::websocket::*;
+
+    #[test]
+    fn test_encoded_prediction() {
+        let message = encode_prediction(1.5, &Prediction { frequency: 440.0, confidence: 0.75 }, DEFAULT_A4_HZ);
+
+        let value = serde_json::from_str::<serde_json::Value>(&message).unwrap();
+        assert_eq!(value["time"], 1.5);
+        assert_eq!(value["freq"], 440.0);
+        assert_eq!(value["confidence"], 0.75);
+        assert_eq!(value["note"], "A4");
+        assert_eq!(value["cents"], 0.0);
+
+        let message = encode_prediction(0.0, &Prediction { frequency: f32::NAN, confidence: 0.0 }, DEFAULT_A4_HZ);
+        let value = serde_json::from_str::<serde_json::Value>(&message).unwrap();
+        assert!(value["note"].is_null());
+        assert!(value["cents"].is_null());
+    }
+
+    #[test]
+    fn test_client_receives_prediction() {
+        let server = PredictionServer::bind("127.0.0.1:0").unwrap();
+        let (mut client, _) = tungstenite::connect(format!("ws://{}", server.local_addr())).unwrap();
+
+        // The client is only added once the server thread has finished the handshake.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while server.client_count() == 0 {
+            assert!(Instant::now() < deadline, "client was not registered");
+            thread::sleep(Duration::from_millis(10));
+        }
+        server.broadcast(2.0, &Prediction { frequency: 220.0, confidence: 0.9 }, DEFAULT_A4_HZ);
+
+        let message = client.read().unwrap();
+        let value = serde_json::from_str::<serde_json::Value>(message.to_text().unwrap()).unwrap();
+        assert_eq!(value["time"], 2.0);
+        assert_eq!(value["freq"], 220.0);
+        assert_eq!(value["note"], "A3");
+    }
+}